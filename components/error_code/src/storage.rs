@@ -22,6 +22,11 @@ define_error_codes!(
     KEY_IS_LOCKED => ("KeyIsLocked", "", ""),
     MAX_TIMESTAMP_NOT_SYNCED => ("MaxTimestampNotSynced", "", ""),
     FLASHBACK_NOT_PREPARED => ("FlashbackNotPrepared", "", ""),
+    FLASHBACK_CONFLICT => ("FlashbackConflict", "", ""),
+    FLASHBACK_NO_DATA => ("FlashbackNoData", "", ""),
+    FLASHBACK_NEWER_WRITES => ("FlashbackNewerWrites", "", ""),
+    FLASHBACK_BELOW_GC_SAFE_POINT => ("FlashbackBelowGcSafePoint", "", ""),
+    FLASHBACK_STALLED => ("FlashbackStalled", "", ""),
     DEADLINE_EXCEEDED => ("DeadlineExceeded", "", ""),
     API_VERSION_NOT_MATCHED => ("ApiVersionNotMatched", "", ""),
     INVALID_KEY_MODE => ("InvalidKeyMode", "", ""),
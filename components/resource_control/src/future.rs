@@ -289,6 +289,8 @@ mod tests {
             "".into(),
             f64::INFINITY,
             1000.0,
+            f64::INFINITY,
+            f64::INFINITY,
             0,
             true,
         ));
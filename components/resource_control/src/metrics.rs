@@ -42,6 +42,25 @@ lazy_static! {
     )
     .unwrap();
 
+    pub static ref BACKGROUND_CONSUMED_RATE_VEC: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_resource_control_background_consumed_rate",
+        "The per-second consumed rate of background resource groups per resource type in the last adjust period",
+        &["resource_group", "type"]
+    )
+    .unwrap();
+    pub static ref BACKGROUND_WAIT_RATE_VEC: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_resource_control_background_wait_rate",
+        "The per-second wait duration of background resource groups per resource type in the last adjust period",
+        &["resource_group", "type"]
+    )
+    .unwrap();
+    pub static ref LOW_LOAD_FAST_PATH_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_resource_control_low_load_fast_path_total",
+        "The number of times the low-load fast path skipped the quota adjustment per resource type",
+        &["type"]
+    )
+    .unwrap();
+
     pub static ref BACKGROUND_TASK_RESOURCE_UTILIZATION_VEC: IntGaugeVec = register_int_gauge_vec!(
         "tikv_resource_control_bg_resource_utilization",
         "The total resource utilization percentage of background tasks",
@@ -51,9 +70,11 @@ lazy_static! {
 }
 
 pub fn deregister_metrics(name: &str) {
-    for ty in ["cpu", "io"] {
+    for ty in ["cpu", "io", "net", "mem"] {
         _ = BACKGROUND_QUOTA_LIMIT_VEC.remove_label_values(&[name, ty]);
         _ = BACKGROUND_RESOURCE_CONSUMPTION.remove_label_values(&[name, ty]);
+        _ = BACKGROUND_CONSUMED_RATE_VEC.remove_label_values(&[name, ty]);
+        _ = BACKGROUND_WAIT_RATE_VEC.remove_label_values(&[name, ty]);
     }
     _ = BACKGROUND_TASKS_WAIT_DURATION.remove_label_values(&[name]);
 }
@@ -61,6 +61,10 @@ pub struct ResourceGroupManager {
     pub(crate) resource_groups: DashMap<String, ResourceGroup>,
     // the count of all groups, a fast path because call `DashMap::len` is a little slower.
     group_count: AtomicU64,
+    // the count of groups owning a background resource limiter, so the quota
+    // adjust worker can skip scanning the whole group map on clusters where
+    // no group has any background setting.
+    background_group_count: AtomicU64,
     registry: RwLock<Vec<Arc<ResourceController>>>,
     // auto incremental version generator used for mark the background
     // resource limiter has changed.
@@ -84,6 +88,8 @@ impl ResourceGroupManager {
                 p.as_str().to_owned(),
                 f64::INFINITY,
                 f64::INFINITY,
+                f64::INFINITY,
+                f64::INFINITY,
                 0,
                 false,
             ))
@@ -91,6 +97,7 @@ impl ResourceGroupManager {
         let manager = Self {
             resource_groups: Default::default(),
             group_count: AtomicU64::new(0),
+            background_group_count: AtomicU64::new(0),
             registry: Default::default(),
             version_generator: AtomicU64::new(0),
             priority_limiters,
@@ -117,6 +124,11 @@ impl ResourceGroupManager {
         self.group_count.load(Ordering::Relaxed)
     }
 
+    #[inline]
+    pub fn get_background_group_count(&self) -> u64 {
+        self.background_group_count.load(Ordering::Relaxed)
+    }
+
     fn get_ru_setting(rg: &PbResourceGroup, is_read: bool) -> u64 {
         match (rg.get_mode(), is_read) {
             // RU mode, read and write use the same setting.
@@ -155,14 +167,20 @@ impl ResourceGroupManager {
             .get(&rg.name)
             .and_then(|g| g.limiter.clone());
         let limiter = self.build_resource_limiter(&rg, prev_limiter);
+        let has_limiter = limiter.is_some();
 
-        if self
+        let prev_group = self
             .resource_groups
-            .insert(group_name, ResourceGroup::new(rg, limiter))
-            .is_none()
-        {
+            .insert(group_name, ResourceGroup::new(rg, limiter));
+        if prev_group.is_none() {
             self.group_count.fetch_add(1, Ordering::Relaxed);
         }
+        let had_limiter = prev_group.is_some_and(|g| g.limiter.is_some());
+        if has_limiter && !had_limiter {
+            self.background_group_count.fetch_add(1, Ordering::Relaxed);
+        } else if !has_limiter && had_limiter {
+            self.background_group_count.fetch_sub(1, Ordering::Relaxed);
+        }
     }
 
     fn build_resource_limiter(
@@ -177,6 +195,8 @@ impl ResourceGroupManager {
                     rg.name.clone(),
                     f64::INFINITY,
                     f64::INFINITY,
+                    f64::INFINITY,
+                    f64::INFINITY,
                     version,
                     true,
                 )))
@@ -191,15 +211,19 @@ impl ResourceGroupManager {
         self.registry.read().iter().for_each(|controller| {
             controller.remove_resource_group(group_name.as_bytes());
         });
-        if self.resource_groups.remove(&group_name).is_some() {
+        if let Some((_, group)) = self.resource_groups.remove(&group_name) {
             deregister_metrics(name);
             info!("remove resource group"; "name"=> name);
             self.group_count.fetch_sub(1, Ordering::Relaxed);
+            if group.limiter.is_some() {
+                self.background_group_count.fetch_sub(1, Ordering::Relaxed);
+            }
         }
     }
 
     pub fn retain(&self, mut f: impl FnMut(&String, &PbResourceGroup) -> bool) {
         let mut removed_names = vec![];
+        let mut removed_with_limiter = 0;
         self.resource_groups.retain(|k, v| {
             // avoid remove default group.
             if k == DEFAULT_RESOURCE_GROUP_NAME {
@@ -208,6 +232,9 @@ impl ResourceGroupManager {
             let ret = f(k, &v.group);
             if !ret {
                 removed_names.push(k.clone());
+                if v.limiter.is_some() {
+                    removed_with_limiter += 1;
+                }
                 deregister_metrics(k);
             }
             ret
@@ -220,6 +247,8 @@ impl ResourceGroupManager {
             });
             self.group_count
                 .fetch_sub(removed_names.len() as u64, Ordering::Relaxed);
+            self.background_group_count
+                .fetch_sub(removed_with_limiter, Ordering::Relaxed);
         }
     }
 
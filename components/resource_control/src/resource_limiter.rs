@@ -1,12 +1,15 @@
 // Copyright 2023 TiKV Project Authors. Licensed under Apache-2.0.
 
 use std::{
-    fmt,
-    sync::atomic::{AtomicU64, Ordering},
+    array, fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
     time::{Duration, Instant},
 };
 
-use file_system::IoBytes;
+use file_system::{IoBytes, IoType};
 use futures::compat::Future01CompatExt;
 use prometheus::Histogram;
 use strum::EnumCount;
@@ -19,13 +22,29 @@ use crate::metrics::PRIORITY_WAIT_DURATION_VEC;
 pub enum ResourceType {
     Cpu,
     Io,
+    Net,
+    // Memory is not a rate the way CPU/IO are: the limit derived from RSS
+    // pressure throttles the admission of allocation-heavy background tasks
+    // instead of pacing a consumption rate.
+    Mem,
 }
 
 impl ResourceType {
+    pub fn all() -> [ResourceType; ResourceType::COUNT] {
+        [
+            ResourceType::Cpu,
+            ResourceType::Io,
+            ResourceType::Net,
+            ResourceType::Mem,
+        ]
+    }
+
     pub fn as_str(&self) -> &str {
         match *self {
             ResourceType::Cpu => "cpu",
             ResourceType::Io => "io",
+            ResourceType::Net => "net",
+            ResourceType::Mem => "mem",
         }
     }
 }
@@ -40,6 +59,11 @@ pub struct ResourceLimiter {
     _name: String,
     version: u64,
     limiters: [QuotaLimiter; ResourceType::COUNT],
+    // optional per-`IoType` sub-limiters on top of the aggregate io limiter,
+    // initialized once the group opts into IoType-granular limits. The
+    // default of a single aggregate limiter is kept for groups that never
+    // opt in.
+    io_type_limiters: OnceLock<[QuotaLimiter; IoType::COUNT]>,
     // whether the resource limiter is a background limiter or priority limiter.
     is_background: bool,
     // the wait duration histogram for prioitry limiter.
@@ -57,11 +81,15 @@ impl ResourceLimiter {
         name: String,
         cpu_limit: f64,
         io_limit: f64,
+        net_limit: f64,
+        mem_limit: f64,
         version: u64,
         is_background: bool,
     ) -> Self {
         let cpu_limiter = QuotaLimiter::new(cpu_limit);
         let io_limiter = QuotaLimiter::new(io_limit);
+        let net_limiter = QuotaLimiter::new(net_limit);
+        let mem_limiter = QuotaLimiter::new(mem_limit);
         // high priority tasks does not triggers wait, so no need to generate an empty
         // metrics.
         let wait_histogram = if !is_background && name != TaskPriority::High.as_str() {
@@ -76,7 +104,8 @@ impl ResourceLimiter {
         Self {
             _name: name,
             version,
-            limiters: [cpu_limiter, io_limiter],
+            limiters: [cpu_limiter, io_limiter, net_limiter, mem_limiter],
+            io_type_limiters: OnceLock::new(),
             is_background,
             wait_histogram,
         }
@@ -99,6 +128,37 @@ impl ResourceLimiter {
         wait_dur
     }
 
+    /// Opt the group into per-`IoType` rate limits on top of the aggregate
+    /// io limiter, e.g. to throttle its compaction reads harder than its
+    /// flush writes. The sub-limiters start unlimited and are tuned by the
+    /// quota adjustment worker on its next tick; enabling is idempotent.
+    pub fn enable_io_type_limits(&self) {
+        self.io_type_limiters
+            .get_or_init(|| array::from_fn(|_| QuotaLimiter::new(f64::INFINITY)));
+    }
+
+    pub fn io_type_limits_enabled(&self) -> bool {
+        self.io_type_limiters.get().is_some()
+    }
+
+    /// Like [`Self::consume`], but additionally charges the sub-limiter of
+    /// `io_type` when the group opted into IoType-granular limits. The
+    /// returned wait is the larger of the aggregate and the sub-limiter
+    /// waits.
+    pub fn consume_with_io_type(
+        &self,
+        cpu_time: Duration,
+        io_bytes: IoBytes,
+        io_type: IoType,
+        wait: bool,
+    ) -> Duration {
+        let agg_dur = self.consume(cpu_time, io_bytes, wait);
+        let Some(sub) = self.get_io_type_limiter(io_type) else {
+            return agg_dur;
+        };
+        agg_dur.max(sub.consume_io(io_bytes, wait))
+    }
+
     pub async fn async_consume(&self, cpu_time: Duration, io_bytes: IoBytes) -> Duration {
         let dur = self.consume(cpu_time, io_bytes, true);
         if !dur.is_zero() {
@@ -115,6 +175,12 @@ impl ResourceLimiter {
         &self.limiters[ty as usize]
     }
 
+    // `None` until the group opts into IoType-granular limits.
+    #[inline]
+    pub(crate) fn get_io_type_limiter(&self, io_type: IoType) -> Option<&QuotaLimiter> {
+        self.io_type_limiters.get().map(|ls| &ls[io_type as usize])
+    }
+
     pub(crate) fn get_limit_statistics(&self, ty: ResourceType) -> GroupStatistics {
         let (total_consumed, total_wait_dur_us, read_consumed, write_consumed, request_count) =
             self.limiters[ty as usize].get_statistics();
@@ -223,6 +289,9 @@ pub struct GroupStatistics {
     pub request_count: u64,
 }
 
+// The subtraction saturates on each field so a non-monotonic sample (e.g.
+// after a limiter is recreated under the same group name) yields a zero
+// delta instead of a huge wrapped value.
 impl std::ops::Sub for GroupStatistics {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
@@ -251,3 +320,42 @@ impl std::ops::Div<f64> for GroupStatistics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_statistics_saturating_sub() {
+        let big = GroupStatistics {
+            version: 1,
+            total_consumed: 1000,
+            total_wait_dur_us: 500,
+            read_consumed: 300,
+            write_consumed: 200,
+            request_count: 10,
+        };
+        let small = GroupStatistics {
+            version: 1,
+            total_consumed: 100,
+            total_wait_dur_us: 50,
+            read_consumed: 30,
+            write_consumed: 20,
+            request_count: 1,
+        };
+        let delta = big - small;
+        assert_eq!(delta.total_consumed, 900);
+        assert_eq!(delta.total_wait_dur_us, 450);
+        assert_eq!(delta.read_consumed, 270);
+        assert_eq!(delta.write_consumed, 180);
+        assert_eq!(delta.request_count, 9);
+
+        // a non-monotonic sample saturates to zero instead of wrapping.
+        let delta = small - big;
+        assert_eq!(delta.total_consumed, 0);
+        assert_eq!(delta.total_wait_dur_us, 0);
+        assert_eq!(delta.read_consumed, 0);
+        assert_eq!(delta.write_consumed, 0);
+        assert_eq!(delta.request_count, 0);
+    }
+}
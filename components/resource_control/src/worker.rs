@@ -3,19 +3,30 @@
 use std::{
     array,
     collections::{HashMap, HashSet},
+    future::Future,
     io::Result as IoResult,
-    sync::Arc,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
 use file_system::{fetch_io_bytes, IoBytes, IoType};
+use futures::{
+    compat::Future01CompatExt,
+    future::{self, Either},
+};
 use prometheus::Histogram;
-use strum::EnumCount;
+use serde::{Deserialize, Serialize};
+use strum::{EnumCount, IntoEnumIterator};
 use tikv_util::{
     debug,
     resource_control::{TaskPriority, DEFAULT_RESOURCE_GROUP_NAME},
-    sys::{cpu_time::ProcessStat, SysQuota},
+    sys::{cpu_time::ProcessStat, get_global_memory_usage, SysQuota},
     time::Instant,
+    timer::GLOBAL_TIMER_HANDLE,
     warn,
     yatp_pool::metrics::YATP_POOL_SCHEDULE_WAIT_DURATION_VEC,
 };
@@ -28,87 +39,1032 @@ use crate::{
 
 pub const BACKGROUND_LIMIT_ADJUST_DURATION: Duration = Duration::from_secs(10);
 
+// the default ratio of current used resource below which the load is
+// considered low and the adjustment can be skipped.
+const DEFAULT_LOW_LOAD_RATIO: f64 = 0.1;
+// the number of consecutive observations on the other side of the low-load
+// threshold required before the fast-path state flips.
+const DEFAULT_LOW_LOAD_DEBOUNCE: usize = 3;
+// the number of consecutive cpu stat failures after which the error is
+// propagated instead of reusing the last successful sample.
+const MAX_CPU_STAT_FAILURES: usize = 3;
+
+// the default weight of the newest sample when EMA smoothing is enabled.
+const DEFAULT_EMA_ALPHA: f64 = 0.5;
+
+// the default fraction of the free resource that background tasks are
+// allowed to use, the rest is reserved as headroom for foreground traffic.
+const DEFAULT_HEADROOM_FACTOR: f64 = 0.8;
+
+// the default fraction of the accumulated starvation debt carried over
+// into the next tick, the rest is forgiven so ancient under-allocation
+// fades out.
+const DEFAULT_STARVATION_DEBT_DECAY: f64 = 0.5;
+
+// the default weight of the aggregate wait seconds when the wait-based
+// throttle relief is enabled.
+const DEFAULT_WAIT_RELIEF_FACTOR: f64 = 1.0;
+
+// the fraction of the accumulated integral error kept per tick when the
+// integral term of the distribution is enabled.
+const DEFAULT_INTEGRAL_DECAY: f64 = 0.5;
+
+// the default multiple of a group's rolling peak consumed rate its limit
+// is capped at when the peak-derived ceiling is enabled.
+const DEFAULT_PEAK_CAP_RATIO: f64 = 1.5;
+// the fraction of the stored rolling peak kept per tick, so an old burst
+// ages out of the window instead of capping the group on it forever.
+const DEFAULT_PEAK_DECAY: f64 = 0.9;
+
+// the default minimal duration between two "get resource statistics
+// failed" warnings of the same resource type, so a persistently failing
+// provider does not flood the logs every tick.
+const DEFAULT_PROVIDER_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+// the default number of consecutive under-utilized ticks required before
+// the negative feedback trims a group's chronically unused limit.
+const DEFAULT_UNDER_UTIL_TICKS: usize = 3;
+
+// the default minimum window the io rate is computed over; the rate of a
+// shorter window is dominated by sampling noise.
+const DEFAULT_IO_SAMPLE_WINDOW: Duration = Duration::from_secs(1);
+
 const MICROS_PER_SEC: f64 = 1_000_000.0;
+
+// The statistics and limiters of the different resource types are
+// denominated in different raw units (cpu microseconds, io bytes per
+// second) while `ru_quota` is denominated in abstract resource units, and
+// all of them travel through the distribution as `f64` rates. The generic
+// distribution itself necessarily stays unit-less since the unit depends
+// on the resource type at runtime, but every seam whose unit is fixed at
+// compile time converts through the wrappers below, so a conversion cannot
+// be forgotten or applied twice.
+
+/// An amount of abstract resource units, the currency `ru_quota` and the
+/// consumed group statistics are denominated in.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Ru(pub f64);
+
+impl Ru {
+    /// Convert into the raw unit of a resource type, mapping one RU onto
+    /// `cost_factor` raw units (see
+    /// [`GroupQuotaAdjustWorker::set_ru_cost_factor`]).
+    pub fn to_raw(self, cost_factor: f64) -> f64 {
+        self.0 * cost_factor
+    }
+}
+
+/// An amount of cpu time in microseconds, the raw unit of the cpu
+/// statistics and limiters.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct CpuMicros(pub f64);
+
+impl CpuMicros {
+    pub fn from_secs(secs: f64) -> Self {
+        Self(secs * MICROS_PER_SEC)
+    }
+
+    pub fn as_secs(self) -> f64 {
+        self.0 / MICROS_PER_SEC
+    }
+}
+
+/// An io rate in bytes per second, the raw unit of the io statistics and
+/// limiters.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct IoBytesPerSec(pub f64);
+
+impl IoBytesPerSec {
+    /// The average rate of `bytes` observed over a window of
+    /// `window_secs`.
+    pub fn over_window(bytes: u64, window_secs: f64) -> Self {
+        Self(bytes as f64 / window_secs)
+    }
+}
 // the minimal schedule wait duration due to the overhead of queue.
 // We should exclude this cause when calculate the estimated total wait
 // duration.
 const MINIMAL_SCHEDULE_WAIT_SECS: f64 = 0.000_005; //5us
 
+#[derive(Clone, Copy)]
 pub struct ResourceUsageStats {
     total_quota: f64,
     current_used: f64,
+    // the window `current_used` was actually averaged over, for providers
+    // deriving it from their own counter diff (e.g. the IO counters are
+    // diffed since `prev_io_ts`) whose window may differ from the caller's
+    // adjust tick. `None` means the sample carries no window of its own.
+    window_secs: Option<f64>,
+}
+
+/// The self-reported health of a stats provider, so a genuinely unlimited
+/// resource can be told apart from a provider that cannot read its
+/// underlying metric.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderHealth {
+    Healthy,
+    /// the provider is still returning stats but some of its sources are
+    /// failing, so the samples may be stale or incomplete.
+    Degraded(String),
 }
 
 pub trait ResourceStatsProvider {
     fn get_current_stats(&mut self, _t: ResourceType) -> IoResult<ResourceUsageStats>;
+
+    /// Report the health of the underlying stat sources. The default
+    /// implementation assumes a provider without failure tracking is always
+    /// healthy.
+    fn health(&self) -> ProviderHealth {
+        ProviderHealth::Healthy
+    }
+
+    /// Fetch the stats of all resource types in one batch. Implementations
+    /// may override this to read the underlying counters only once. The
+    /// result is kept per type so one failing source does not hide the
+    /// others.
+    fn get_all_stats(&mut self) -> [IoResult<ResourceUsageStats>; ResourceType::COUNT] {
+        ResourceType::all().map(|t| self.get_current_stats(t))
+    }
+
+    /// The per-`IoType` IO rates (bytes per second) observed since the
+    /// previous call, used to split a group's io limit across its
+    /// per-`IoType` sub-limiters. Providers without a per-type source
+    /// report no traffic, which leaves the sub-limits unlimited.
+    fn get_io_breakdown(&mut self) -> [IoBytes; IoType::COUNT] {
+        [IoBytes::default(); IoType::COUNT]
+    }
 }
 
 pub struct SysQuotaGetter {
-    process_stat: ProcessStat,
+    // `None` on platforms and sandboxes where the process cpu stats cannot
+    // be read; the cpu quota is then reported as unlimited and only io is
+    // adjusted.
+    process_stat: Option<ProcessStat>,
     prev_io_stats: [IoBytes; IoType::COUNT],
     prev_io_ts: Instant,
+    // the minimum window the io rate is computed over; an elapsed time
+    // below it reuses `cached_io_rate` instead of deriving a noisy rate
+    // from a tiny counter delta.
+    min_io_sample_window: Duration,
+    // the last io (rate, window_secs) computed over a full window.
+    cached_io_rate: Option<(f64, f64)>,
     io_bandwidth: f64,
+    // `IoType`s whose traffic is foreground by definition (e.g. WAL and
+    // raft writes) and should not count against the background IO budget.
+    excluded_io_types: [bool; IoType::COUNT],
+    prev_net_stats: NetBytes,
+    prev_net_ts: Instant,
+    net_bandwidth: f64,
+    // the cgroup v2 directory of the current process, injectable for tests.
+    cgroup_path: PathBuf,
+    prev_cgroup_io_bytes: u64,
+    // the counters observed by the last `get_io_breakdown` call. They are
+    // tracked separately from `prev_io_stats` so the breakdown does not
+    // disturb the summed quota computation.
+    prev_io_breakdown: [IoBytes; IoType::COUNT],
+    prev_io_breakdown_ts: Instant,
+    // the last successful cpu usage sample and the consecutive failure
+    // count, to ride over transient `/proc` read errors.
+    last_cpu_usage: Option<f64>,
+    cpu_stat_failures: usize,
+    // the engine-reported cumulative internal compaction io bytes, `None`
+    // leaves compaction io counted like any other background traffic.
+    compaction_io_source: Option<Box<dyn Fn() -> u64 + Send>>,
+    compaction_io_accounting: CompactionIoAccounting,
+    prev_compaction_io_bytes: u64,
+    // the last compaction io rate computed over a full window, reused on
+    // the cached-rate fast path like `cached_io_rate`.
+    cached_compaction_io_rate: f64,
 }
 
-impl ResourceStatsProvider for SysQuotaGetter {
-    fn get_current_stats(&mut self, ty: ResourceType) -> IoResult<ResourceUsageStats> {
-        match ty {
-            ResourceType::Cpu => {
-                let total_quota = SysQuota::cpu_cores_quota();
-                self.process_stat.cpu_usage().map(|u| ResourceUsageStats {
-                    // cpu is measured in us.
-                    total_quota: total_quota * MICROS_PER_SEC,
-                    current_used: u * MICROS_PER_SEC,
-                })
+/// How the engine's internal compaction IO is accounted against the IO
+/// budget the worker distributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionIoAccounting {
+    /// subtract the compaction io rate from the total quota, reserving the
+    /// bandwidth up front before anything is handed out.
+    Reserve,
+    /// subtract the compaction io rate from the measured usage, so the
+    /// groups may be assigned bandwidth the compactions are already using.
+    Exclude,
+}
+
+const DEFAULT_CGROUP_PATH: &str = "/sys/fs/cgroup";
+
+// Parse the cgroup v2 `io.max` content, e.g. "8:0 rbps=1048576 wbps=max
+// riops=max wiops=max", and return the summed bandwidth throttle of all
+// device lines. `None` is returned when no bandwidth throttle is configured.
+fn parse_cgroup_io_max(content: &str) -> Option<f64> {
+    let mut total = 0.0;
+    let mut has_limit = false;
+    for line in content.lines() {
+        for item in line.split_whitespace().skip(1) {
+            let Some((key, value)) = item.split_once('=') else {
+                continue;
+            };
+            if (key == "rbps" || key == "wbps")
+                && value != "max"
+                && let Ok(value) = value.parse::<u64>()
+            {
+                total += value as f64;
+                has_limit = true;
             }
-            ResourceType::Io => {
-                let mut stats = ResourceUsageStats {
-                    total_quota: self.io_bandwidth,
-                    current_used: 0.0,
-                };
-                let now = Instant::now_coarse();
-                let dur = now.saturating_duration_since(self.prev_io_ts).as_secs_f64();
-                if dur < 0.1 {
-                    return Ok(stats);
+        }
+    }
+    has_limit.then_some(total)
+}
+
+// Parse the cgroup v2 `io.stat` content and return the accumulated
+// rbytes + wbytes over all device lines.
+fn parse_cgroup_io_stat(content: &str) -> u64 {
+    let mut total = 0;
+    for line in content.lines() {
+        for item in line.split_whitespace().skip(1) {
+            let Some((key, value)) = item.split_once('=') else {
+                continue;
+            };
+            if key == "rbytes" || key == "wbytes" {
+                total += value.parse::<u64>().unwrap_or(0);
+            }
+        }
+    }
+    total
+}
+
+// Sum the per-`IoType` counter deltas between two snapshots, skipping the
+// excluded types so e.g. foreground writes do not count against the
+// background IO budget.
+fn sum_io_delta(
+    prev: &[IoBytes; IoType::COUNT],
+    cur: &[IoBytes; IoType::COUNT],
+    excluded: &[bool; IoType::COUNT],
+) -> u64 {
+    prev.iter()
+        .zip(cur.iter())
+        .enumerate()
+        .filter(|(i, _)| !excluded[*i])
+        .map(|(_, (s, new_s))| {
+            let delta = *new_s - *s;
+            delta.read + delta.write
+        })
+        .sum()
+}
+
+// Turn two per-`IoType` counter snapshots into per-type rates (bytes per
+// second) over the given duration.
+fn compute_io_breakdown(
+    prev: &[IoBytes; IoType::COUNT],
+    cur: &[IoBytes; IoType::COUNT],
+    dur_secs: f64,
+) -> [IoBytes; IoType::COUNT] {
+    let mut rates = [IoBytes::default(); IoType::COUNT];
+    for ((rate, prev), cur) in rates.iter_mut().zip(prev.iter()).zip(cur.iter()) {
+        let delta = *cur - *prev;
+        rate.read = (delta.read as f64 / dur_secs) as u64;
+        rate.write = (delta.write as f64 / dur_secs) as u64;
+    }
+    rates
+}
+
+impl SysQuotaGetter {
+    // Read the cgroup v2 `io.max` to get the container-level IO throttle if
+    // present.
+    fn cgroup_io_max(&self) -> Option<f64> {
+        let content = std::fs::read_to_string(self.cgroup_path.join("io.max")).ok()?;
+        parse_cgroup_io_max(&content)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct NetBytes {
+    rx: u64,
+    tx: u64,
+}
+
+// Fetch the accumulated network rx/tx bytes of all non-loopback interfaces
+// from `/proc/net/dev`. Returns an error on platforms or environments where
+// the counter is unavailable.
+fn fetch_net_bytes() -> IoResult<NetBytes> {
+    let content = std::fs::read_to_string("/proc/net/dev")?;
+    let mut total = NetBytes::default();
+    // the first 2 lines are headers.
+    for line in content.lines().skip(2) {
+        let Some((iface, stats)) = line.split_once(':') else {
+            continue;
+        };
+        if iface.trim() == "lo" {
+            continue;
+        }
+        let cols: Vec<_> = stats.split_whitespace().collect();
+        // rx bytes is the 1st column and tx bytes is the 9th column.
+        if cols.len() >= 9 {
+            total.rx += cols[0].parse::<u64>().unwrap_or(0);
+            total.tx += cols[8].parse::<u64>().unwrap_or(0);
+        }
+    }
+    Ok(total)
+}
+
+// Open the process cpu stat source, falling back to `None` on platforms and
+// sandboxes where it is unavailable, so the workers start in io-only mode
+// instead of panicking.
+fn try_cur_proc_stat() -> Option<ProcessStat> {
+    match ProcessStat::cur_proc_stat() {
+        Ok(process_stat) => Some(process_stat),
+        Err(e) => {
+            warn!(
+                "process cpu stats are unavailable, the cpu quota is treated as unlimited and only io is adjusted";
+                "err" => ?e
+            );
+            None
+        }
+    }
+}
+
+impl SysQuotaGetter {
+    /// Update the configured disk bandwidth budget online. A zero value
+    /// means unlimited. The next stats fetch picks up the new value.
+    pub fn set_io_bandwidth(&mut self, io_bandwidth: u64) {
+        self.io_bandwidth = io_bandwidth as f64;
+    }
+
+    /// Set the minimum window the io rate is computed over. While the
+    /// elapsed time since the previous sample is below it, the last rate
+    /// computed over a full window is reused instead of deriving a noisy
+    /// rate from a tiny counter delta. A window below the historical 100ms
+    /// floor is clamped to it.
+    pub fn set_min_io_sample_window(&mut self, window: Duration) {
+        self.min_io_sample_window = window.max(Duration::from_millis(100));
+    }
+
+    /// Mark the given `IoType`s as foreground-only so their traffic is
+    /// subtracted from the measured IO usage, e.g. `IoType::ForegroundWrite`
+    /// for WAL writes. The exclusion only applies to the process-level
+    /// per-type counters; the container-level cgroup counter has no per-type
+    /// breakdown and keeps accounting all traffic.
+    pub fn set_excluded_io_types(&mut self, types: &[IoType]) {
+        self.excluded_io_types = [false; IoType::COUNT];
+        for t in types {
+            self.excluded_io_types[*t as usize] = true;
+        }
+    }
+
+    /// Register a source of the engine's cumulative internal compaction IO
+    /// bytes (e.g. the RocksDB compaction read/write statistics) together
+    /// with how the derived rate is accounted. Compaction IO is not
+    /// attributable to any resource group but still occupies the disk
+    /// bandwidth the worker hands out, so it is either reserved out of the
+    /// total quota or excluded from the measured usage.
+    pub fn set_compaction_io_source(
+        &mut self,
+        source: impl Fn() -> u64 + Send + 'static,
+        accounting: CompactionIoAccounting,
+    ) {
+        // prime the counter so the first window does not observe the whole
+        // history of the engine as a burst.
+        self.prev_compaction_io_bytes = source();
+        self.compaction_io_source = Some(Box::new(source));
+        self.compaction_io_accounting = accounting;
+    }
+
+    // Apply the configured compaction io accounting onto one io sample.
+    fn apply_compaction_io(&self, stats: &mut ResourceUsageStats, compaction_rate: f64) {
+        if compaction_rate <= f64::EPSILON {
+            return;
+        }
+        match self.compaction_io_accounting {
+            CompactionIoAccounting::Reserve => {
+                stats.total_quota = (stats.total_quota - compaction_rate).max(0.0);
+            }
+            CompactionIoAccounting::Exclude => {
+                stats.current_used = (stats.current_used - compaction_rate).max(0.0);
+            }
+        }
+    }
+
+    fn cpu_stats(&mut self) -> IoResult<ResourceUsageStats> {
+        // Without a process stat source the cpu quota is reported as
+        // unlimited, so the worker leaves the cpu limiters alone and only
+        // adjusts io.
+        let Some(process_stat) = self.process_stat.as_mut() else {
+            return Ok(ResourceUsageStats {
+                total_quota: f64::INFINITY,
+                current_used: 0.0,
+                window_secs: None,
+            });
+        };
+        let sample = process_stat.cpu_usage();
+        self.cpu_stats_with_sample(sample)
+    }
+
+    fn cpu_stats_with_sample(&mut self, sample: IoResult<f64>) -> IoResult<ResourceUsageStats> {
+        let usage = match sample {
+            Ok(u) => {
+                self.cpu_stat_failures = 0;
+                self.last_cpu_usage = Some(u);
+                u
+            }
+            Err(e) => {
+                // a transient `/proc` read hiccup should not freeze the cpu
+                // adjustment for a whole tick, reuse the last successful
+                // sample unless the source keeps failing.
+                self.cpu_stat_failures += 1;
+                match self.last_cpu_usage {
+                    Some(u) if self.cpu_stat_failures < MAX_CPU_STAT_FAILURES => {
+                        warn!("get process cpu usage failed, reuse the last sample"; "err" => ?e, "failures" => self.cpu_stat_failures);
+                        u
+                    }
+                    _ => return Err(e),
                 }
-                let new_io_stats = fetch_io_bytes();
-                let total_io_used = self
-                    .prev_io_stats
-                    .iter()
-                    .zip(new_io_stats.iter())
-                    .map(|(s, new_s)| {
-                        let delta = *new_s - *s;
-                        delta.read + delta.write
-                    })
-                    .sum::<u64>();
-                self.prev_io_stats = new_io_stats;
-                self.prev_io_ts = now;
+            }
+        };
+        Ok(ResourceUsageStats {
+            // cpu is measured in us.
+            total_quota: CpuMicros::from_secs(SysQuota::cpu_cores_quota()).0,
+            current_used: CpuMicros::from_secs(usage).0,
+            // the sampler already reports a rate, there is no window to
+            // attach.
+            window_secs: None,
+        })
+    }
+
+    fn io_stats(&mut self, now: Instant) -> IoResult<ResourceUsageStats> {
+        // prefer the container-level throttle from cgroup v2 `io.max`
+        // over the statically configured bandwidth.
+        let cgroup_io_max = self.cgroup_io_max();
+        // an unconfigured bandwidth (0) means the disk throughput is
+        // unlimited, not that there is no quota at all.
+        let io_bandwidth = if self.io_bandwidth <= f64::EPSILON {
+            f64::INFINITY
+        } else {
+            self.io_bandwidth
+        };
+        let mut stats = ResourceUsageStats {
+            total_quota: cgroup_io_max.unwrap_or(io_bandwidth),
+            current_used: 0.0,
+            window_secs: None,
+        };
+        let dur = now.saturating_duration_since(self.prev_io_ts).as_secs_f64();
+        if dur < self.min_io_sample_window.as_secs_f64() {
+            // the rate over a tiny window is dominated by noise, report the
+            // last rate computed over a full window instead.
+            if let Some((rate, window_secs)) = self.cached_io_rate {
+                stats.current_used = rate;
+                stats.window_secs = Some(window_secs);
+            }
+            self.apply_compaction_io(&mut stats, self.cached_compaction_io_rate);
+            return Ok(stats);
+        }
+        let total_io_used = if cgroup_io_max.is_some()
+            && let Ok(content) = std::fs::read_to_string(self.cgroup_path.join("io.stat"))
+        {
+            // when the container is throttled, account the IO against
+            // the same container-level counter.
+            let cur_io_bytes = parse_cgroup_io_stat(&content);
+            let delta = cur_io_bytes.saturating_sub(self.prev_cgroup_io_bytes);
+            self.prev_cgroup_io_bytes = cur_io_bytes;
+            delta
+        } else {
+            let new_io_stats = fetch_io_bytes();
+            let total = sum_io_delta(&self.prev_io_stats, &new_io_stats, &self.excluded_io_types);
+            self.prev_io_stats = new_io_stats;
+            total
+        };
+        self.prev_io_ts = now;
+
+        stats.current_used = IoBytesPerSec::over_window(total_io_used, dur).0;
+        stats.window_secs = Some(dur);
+        self.cached_io_rate = Some((stats.current_used, dur));
+
+        // derive the engine's internal compaction io rate over the same
+        // window and account it per the configured policy.
+        let compaction_rate = if let Some(source) = &self.compaction_io_source {
+            let cur_bytes = source();
+            let delta = cur_bytes.saturating_sub(self.prev_compaction_io_bytes);
+            self.prev_compaction_io_bytes = cur_bytes;
+            IoBytesPerSec::over_window(delta, dur).0
+        } else {
+            0.0
+        };
+        self.cached_compaction_io_rate = compaction_rate;
+        self.apply_compaction_io(&mut stats, compaction_rate);
+        Ok(stats)
+    }
 
-                stats.current_used = total_io_used as f64 / dur;
-                Ok(stats)
+    fn net_stats(&mut self, now: Instant) -> IoResult<ResourceUsageStats> {
+        let mut stats = ResourceUsageStats {
+            total_quota: self.net_bandwidth,
+            current_used: 0.0,
+            window_secs: None,
+        };
+        let dur = now
+            .saturating_duration_since(self.prev_net_ts)
+            .as_secs_f64();
+        if dur < 0.1 {
+            return Ok(stats);
+        }
+        let new_net_stats = match fetch_net_bytes() {
+            Ok(s) => s,
+            Err(_) => {
+                // network counter is unavailable, report a zero quota so
+                // the caller falls into the unlimited path.
+                stats.total_quota = 0.0;
+                return Ok(stats);
             }
+        };
+        let total_net_used = new_net_stats
+            .rx
+            .saturating_sub(self.prev_net_stats.rx)
+            .saturating_add(new_net_stats.tx.saturating_sub(self.prev_net_stats.tx));
+        self.prev_net_stats = new_net_stats;
+        self.prev_net_ts = now;
+
+        stats.current_used = total_net_used as f64 / dur;
+        stats.window_secs = Some(dur);
+        Ok(stats)
+    }
+
+    fn mem_stats(&self) -> IoResult<ResourceUsageStats> {
+        // memory is not a rate, report the instantaneous RSS against
+        // the memory limit so background admission is throttled under
+        // memory pressure.
+        Ok(ResourceUsageStats {
+            total_quota: SysQuota::memory_limit_in_bytes() as f64,
+            current_used: get_global_memory_usage() as f64,
+            window_secs: None,
+        })
+    }
+}
+
+impl ResourceStatsProvider for SysQuotaGetter {
+    fn get_current_stats(&mut self, ty: ResourceType) -> IoResult<ResourceUsageStats> {
+        match ty {
+            ResourceType::Cpu => self.cpu_stats(),
+            ResourceType::Io => self.io_stats(Instant::now_coarse()),
+            ResourceType::Net => self.net_stats(Instant::now_coarse()),
+            ResourceType::Mem => self.mem_stats(),
         }
     }
+
+    fn get_all_stats(&mut self) -> [IoResult<ResourceUsageStats>; ResourceType::COUNT] {
+        // derive all rate based stats from a single timestamp so the samples
+        // do not skew between each other.
+        let now = Instant::now_coarse();
+        [
+            self.cpu_stats(),
+            self.io_stats(now),
+            self.net_stats(now),
+            self.mem_stats(),
+        ]
+    }
+
+    fn health(&self) -> ProviderHealth {
+        if self.cpu_stat_failures > 0 {
+            return ProviderHealth::Degraded(format!(
+                "process cpu stat read failed {} consecutive times",
+                self.cpu_stat_failures
+            ));
+        }
+        if !self.cgroup_path.exists() {
+            return ProviderHealth::Degraded(format!(
+                "cgroup directory {} is missing, container-level io limits are ignored",
+                self.cgroup_path.display()
+            ));
+        }
+        ProviderHealth::Healthy
+    }
+
+    // the per-type counters can be distinguished here (e.g. compaction
+    // reads from foreground writes), while the base quota computation keeps
+    // using the counters summed over all types.
+    fn get_io_breakdown(&mut self) -> [IoBytes; IoType::COUNT] {
+        let now = Instant::now_coarse();
+        let dur = now
+            .saturating_duration_since(self.prev_io_breakdown_ts)
+            .as_secs_f64();
+        if dur < 0.1 {
+            return [IoBytes::default(); IoType::COUNT];
+        }
+        let new_io_stats = fetch_io_bytes();
+        let breakdown = compute_io_breakdown(&self.prev_io_breakdown, &new_io_stats, dur);
+        self.prev_io_breakdown = new_io_stats;
+        self.prev_io_breakdown_ts = now;
+        breakdown
+    }
 }
 
 pub struct GroupQuotaAdjustWorker<R> {
     prev_stats_by_group: [HashMap<String, GroupStatistics>; ResourceType::COUNT],
-    last_adjust_time: Instant,
+    // the per-group under-served rate carried over from the previous ticks,
+    // i.e. how far below its `ru_quota`-proportional fair share each group
+    // was assigned. The debt decays every tick and is converted into extra
+    // distribution weight so a previously starved group catches up.
+    starvation_debt: [HashMap<String, f64>; ResourceType::COUNT],
+    // the fraction of the starvation debt kept per tick, `None` means the
+    // debt-based fairness is disabled and only the current tick's stats
+    // drive the distribution.
+    starvation_debt_decay: Option<f64>,
+    // whether the quota-short pass rotates its starting index every tick.
+    // The greedy order otherwise always hands the slack released by the
+    // light head groups to the same groups right behind them, so the tail
+    // never gets a chance to burst above its fair share.
+    starvation_rotation: bool,
+    // the per-type starting index the next quota-short pass begins at.
+    rotation_cursors: [usize; ResourceType::COUNT],
+    // the weight of the aggregate wait seconds in the wait-based throttle
+    // relief, `None` means heavy waiting does not widen the available quota.
+    wait_relief_factor: Option<f64>,
+    // the last effective adjustment per resource type; every type runs on
+    // its own timer so their cadences are independent.
+    last_adjust_time: [Instant; ResourceType::COUNT],
     resource_ctl: Arc<ResourceGroupManager>,
-    is_last_time_low_load: [bool; ResourceType::COUNT],
+    // whether the low-load fast path is active per resource type.
+    low_load_active: [bool; ResourceType::COUNT],
+    // the streak of observations on the opposite side of the low-load
+    // threshold; the active state only flips after `low_load_debounce`
+    // consecutive such observations, so a workload hovering around the
+    // threshold does not flap between fast path and full recompute.
+    low_load_streaks: [usize; ResourceType::COUNT],
+    low_load_debounce: usize,
     resource_quota_getter: R,
+    low_load_ratio: f64,
+    last_adjustments: Vec<GroupAdjustment>,
+    // the aggregate inputs of the most recent distribution per resource
+    // type, `None` until a full distribution of that type has run.
+    last_adjustment_summaries: [Option<ResourceAdjustmentSummary>; ResourceType::COUNT],
+    // the max ratio a group's rate limit can change by in one adjustment,
+    // `None` means the change is unbounded.
+    max_change_ratio: Option<f64>,
+    // the EMA weight of the newest usage sample, `None` means smoothing is
+    // disabled and the raw sample is used.
+    ema_alpha: Option<f64>,
+    // the smoothed used resource per type, NaN until the first sample.
+    smoothed_used: [f64; ResourceType::COUNT],
+    // the fraction of the free resource handed to background tasks.
+    headroom_factor: f64,
+    // the conversion factor from one consumed RU to the raw unit of each
+    // resource type (cpu us, io bytes). 1.0 treats the consumed statistics
+    // as raw units directly.
+    ru_cost_factor: [f64; ResourceType::COUNT],
+    // the relative per-type weights blending the combined pressure score,
+    // see `pressure_score`. Only the ratio between them matters.
+    pressure_weights: [f64; ResourceType::COUNT],
+    // per-group minimum guaranteed rates so scarcity never starves a
+    // critical background group completely.
+    min_rate_floors: [HashMap<String, f64>; ResourceType::COUNT],
+    // per-group pinned rates the adjustment must not touch; a pinned group
+    // is excluded from the distribution and its rate is reserved from the
+    // available quota up front.
+    fixed_rate_overrides: [HashMap<String, f64>; ResourceType::COUNT],
+    // per-class floors and caps keyed by the workload class name, applied
+    // to every group of the class, see [`ClassPolicy`].
+    class_policies: HashMap<String, ClassPolicy>,
+    // absolute per-type ceilings on the summed background rate, to protect
+    // shared infrastructure regardless of the computed quota.
+    max_total_background_rate: [Option<f64>; ResourceType::COUNT],
+    // when set, the worker computes the adjustment decisions and records
+    // them into the snapshot but does not touch any limiter.
+    dry_run: bool,
+    // when set, the worker keeps refreshing the per-group statistics
+    // baselines and the adjustment timers every tick but leaves all
+    // limiters untouched, see [`Self::pause`].
+    paused: bool,
+    // the cadence of the adjustment per resource type. CPU is cheap to
+    // sample frequently while e.g. the io stats from `/proc` are noisier
+    // and benefit from a longer window, so every type is gated on its own
+    // timer. The minimal interval between two effective adjustments of a
+    // type is derived from its own cadence, so both stay consistent.
+    adjust_interval: [Duration; ResourceType::COUNT],
+    // invoked with (group name, resource type, old limit, new limit) right
+    // before a changed rate limit is applied; unchanged limits do not fire
+    // the callback.
+    on_limit_change: Option<Box<dyn Fn(&str, ResourceType, f64, f64) + Send>>,
+    // raised by an external latency monitor while the foreground is under
+    // pressure; the worker then stops granting any group a higher limit
+    // than its current one, decreases still apply.
+    foreground_pressure: Arc<AtomicBool>,
+    // the gain of the integral contribution added on top of the purely
+    // proportional distribution, 0.0 disables the integral term entirely.
+    integral_gain: f64,
+    // the accumulated per-group error between the observed consumption and
+    // the assigned limit, decayed every tick while the integral term is
+    // enabled.
+    integral_errors: [HashMap<String, f64>; ResourceType::COUNT],
+    // the multiple of a group's rolling peak consumed rate its assigned
+    // limit is capped at, `None` means the peak-derived ceiling is
+    // disabled.
+    peak_cap_ratio: Option<f64>,
+    // the rolling peak of the observed per-group consumed rate, decayed
+    // every tick while the peak-derived ceiling is enabled.
+    observed_peaks: [HashMap<String, f64>; ResourceType::COUNT],
+    // raised by a manual uniform override; the next `adjust_quota` tick is
+    // skipped so the automatic adjustment does not immediately undo it.
+    suppress_next_adjust: bool,
+    // how aggressively the quota-short branch scales the groups down, see
+    // [`ScaleDownPolicy`].
+    scale_down_policy: ScaleDownPolicy,
+    // the cumulative per-group duration the assigned limit stayed below the
+    // `ru_quota`-proportional fair share while the group demanded more, for
+    // SLA reporting.
+    throttled_durations: [HashMap<String, Duration>; ResourceType::COUNT],
+    // the ratio of the assigned limit below which a tick counts as
+    // under-utilized, `None` means chronically unused limits are not
+    // trimmed.
+    under_util_ratio: Option<f64>,
+    // the consecutive under-utilized ticks required before the trim.
+    under_util_ticks: usize,
+    // the per-group streak of consecutive under-utilized ticks.
+    under_util_streaks: [HashMap<String, usize>; ResourceType::COUNT],
+    // over how many ticks a group newly entering throttling is ramped from
+    // its recent consumption toward the computed limit, zero disables the
+    // soft start.
+    soft_start_ticks: usize,
+    // the per-group ticks left on an active soft-start ramp.
+    soft_start_remaining: [HashMap<String, usize>; ResourceType::COUNT],
+    // the leftover of the distribution pool after the most recent
+    // adjustment of each type, i.e. the quota the fair-share caps and
+    // trims held back rather than assigned to any group.
+    unallocated_quota: [f64; ResourceType::COUNT],
+    // the max number of background groups one tick recomputes, zero means
+    // every group is processed every tick. A capped tick only handles a
+    // window of the name-sorted group order, rotating across ticks so
+    // every group is still adjusted once per full cycle.
+    max_groups_per_tick: usize,
+    // the position in the name-sorted order the next capped tick resumes
+    // from.
+    group_rotation_cursor: usize,
+    // when each group's counters were last diffed per resource type. A
+    // group sitting out capped ticks accumulates several ticks worth of
+    // consumption, so its delta is normalized over its own sampling gap
+    // instead of the tick interval.
+    group_sample_times: [HashMap<String, Instant>; ResourceType::COUNT],
+    // the minimal duration between two provider-failure warnings of the
+    // same resource type; failures within the gate are silenced but still
+    // counted into `provider_failure_counts`.
+    provider_warn_interval: Duration,
+    // when the last provider-failure warning of each type was logged,
+    // `None` until the first one fires.
+    last_provider_warn: [Option<Instant>; ResourceType::COUNT],
+    // the cumulative number of provider failures per resource type,
+    // incremented on every failed tick regardless of the warning gate.
+    provider_failure_counts: [u64; ResourceType::COUNT],
+    // invoked with the resource type whenever a provider-failure warning
+    // actually passes the gate and is logged.
+    on_provider_warn: Option<Box<dyn Fn(ResourceType) + Send>>,
+    // the time source of all the worker's interval checks, overridable so
+    // tests can drive the ticks deterministically instead of backdating
+    // `last_adjust_time`.
+    clock: Box<dyn Fn() -> Instant + Send>,
+}
+
+/// The decision made for one group and resource type in the most recent
+/// `adjust_quota` call, for debugging and diagnosis endpoints.
+#[derive(Debug, Clone)]
+pub struct GroupAdjustment {
+    pub name: String,
+    pub resource_type: ResourceType,
+    /// the rate limit assigned by the worker.
+    pub rate_limit: f64,
+    /// the observed consumed rate during the last period.
+    pub consumed_rate: f64,
+    /// the observed wait duration during the last period.
+    pub wait_dur_us: u64,
+}
+
+/// The aggregate inputs the most recent distribution of one resource type
+/// was computed from, complementing the per-group [`GroupAdjustment`]
+/// entries so a given distribution can be reconstructed exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResourceAdjustmentSummary {
+    /// the total quota reported by the stats provider.
+    pub total_quota: f64,
+    /// the observed overall usage, after the EMA smoothing when enabled.
+    pub current_used: f64,
+    /// the summed consumed rate of all background groups.
+    pub background_consumed_total: f64,
+    /// the final pool handed to the distribution, i.e. after the headroom
+    /// and utilization clamps and with the pinned rates and declared
+    /// floors already reserved.
+    pub available_quota: f64,
+}
+
+/// The resource policy of one workload class, applied to every background
+/// group whose class matches: `min_rate` guarantees each group of the class
+/// at least that rate and `max_rate` caps each group's assigned limit, per
+/// resource type. An unset entry leaves the global behavior in charge. A
+/// group's class is the first job type of its background settings (e.g.
+/// "br"), so related groups share one policy without declaring the floors
+/// and caps group by group; an explicit per-group floor declared via
+/// `set_group_min_rate` still takes precedence over the class floor.
+#[derive(Debug, Clone, Default)]
+pub struct ClassPolicy {
+    pub min_rate: [Option<f64>; ResourceType::COUNT],
+    pub max_rate: [Option<f64>; ResourceType::COUNT],
+}
+
+/// How the distribution computes a group's share when the total demand
+/// exceeds the available quota. The policy only changes the quota-short
+/// branch, a pool large enough for every demand is distributed the same way
+/// regardless of the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleDownPolicy {
+    /// every group is capped at its weight-proportional fair share.
+    Linear,
+    /// a group whose demand overshoots its fair share is cut the harder the
+    /// further it overshoots: its share is additionally scaled by
+    /// `(share / demand) ^ exponent`, and the yielded quota stays in the
+    /// pool for the groups handled after it. An exponent of 0 degenerates
+    /// to `Linear`.
+    Convex { exponent: f64 },
+}
+
+/// A serializable dump of the worker's effective tuning knobs, e.g. for a
+/// support bundle, complementing the per-group adjustment snapshot. The
+/// per-type values are keyed by the resource type name, and the per-group
+/// maps only list the types that have any entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkerConfig {
+    pub adjust_interval: HashMap<String, Duration>,
+    pub headroom_factor: f64,
+    pub low_load_ratio: f64,
+    pub low_load_debounce: usize,
+    pub starvation_debt_decay: Option<f64>,
+    pub starvation_rotation: bool,
+    pub wait_relief_factor: Option<f64>,
+    pub ema_alpha: Option<f64>,
+    pub max_change_ratio: Option<f64>,
+    pub integral_gain: f64,
+    pub peak_cap_ratio: Option<f64>,
+    pub under_util_ratio: Option<f64>,
+    pub under_util_ticks: usize,
+    pub soft_start_ticks: usize,
+    pub max_groups_per_tick: usize,
+    pub scale_down_policy: ScaleDownPolicy,
+    pub provider_warn_interval: Duration,
+    pub dry_run: bool,
+    pub paused: bool,
+    pub ru_cost_factor: HashMap<String, f64>,
+    pub pressure_weights: HashMap<String, f64>,
+    pub min_rate_floors: HashMap<String, HashMap<String, f64>>,
+    pub fixed_rate_overrides: HashMap<String, HashMap<String, f64>>,
+    pub class_min_rates: HashMap<String, HashMap<String, f64>>,
+    pub class_max_rates: HashMap<String, HashMap<String, f64>>,
+    pub max_total_background_rate: HashMap<String, f64>,
+}
+
+/// One group's raw consumption counters as captured in a baseline
+/// snapshot of the debug dump.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupStatisticsDump {
+    pub version: u64,
+    pub total_consumed: u64,
+    pub total_wait_dur_us: u64,
+    pub read_consumed: u64,
+    pub write_consumed: u64,
+    pub request_count: u64,
+}
+
+impl From<GroupStatistics> for GroupStatisticsDump {
+    fn from(stats: GroupStatistics) -> Self {
+        Self {
+            version: stats.version,
+            total_consumed: stats.total_consumed,
+            total_wait_dur_us: stats.total_wait_dur_us,
+            read_consumed: stats.read_consumed,
+            write_consumed: stats.write_consumed,
+            request_count: stats.request_count,
+        }
+    }
+}
+
+/// One entry of the last adjustment snapshot in the debug dump, with the
+/// resource type flattened into its name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupAdjustmentDump {
+    pub name: String,
+    pub resource_type: String,
+    pub rate_limit: f64,
+    pub consumed_rate: f64,
+    pub wait_dur_us: u64,
+}
+
+impl From<&GroupAdjustment> for GroupAdjustmentDump {
+    fn from(a: &GroupAdjustment) -> Self {
+        Self {
+            name: a.name.clone(),
+            resource_type: a.resource_type.as_str().to_owned(),
+            rate_limit: a.rate_limit,
+            consumed_rate: a.consumed_rate,
+            wait_dur_us: a.wait_dur_us,
+        }
+    }
+}
+
+/// A serializable dump of the worker's full internal state for a support
+/// bundle, complementing [`WorkerConfig`] (which only covers the tuning
+/// knobs) with the runtime state: the per-group counter baselines, the
+/// accumulated feedback state and the timers. The per-type arrays are
+/// keyed by the resource type name. Monotonic timestamps are reported as
+/// seconds elapsed and NaN placeholders as `None`, so the dump always
+/// serializes cleanly to JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkerDebugState {
+    pub config: WorkerConfig,
+    pub prev_stats_by_group: HashMap<String, HashMap<String, GroupStatisticsDump>>,
+    pub secs_since_last_adjust: HashMap<String, f64>,
+    pub low_load_active: HashMap<String, bool>,
+    pub low_load_streaks: HashMap<String, usize>,
+    pub smoothed_used: HashMap<String, Option<f64>>,
+    pub starvation_debt: HashMap<String, HashMap<String, f64>>,
+    pub integral_errors: HashMap<String, HashMap<String, f64>>,
+    pub observed_peaks: HashMap<String, HashMap<String, f64>>,
+    pub throttled_durations: HashMap<String, HashMap<String, Duration>>,
+    pub under_util_streaks: HashMap<String, HashMap<String, usize>>,
+    pub soft_start_remaining: HashMap<String, HashMap<String, usize>>,
+    pub unallocated_quota: HashMap<String, f64>,
+    pub rotation_cursors: HashMap<String, usize>,
+    pub group_rotation_cursor: usize,
+    pub suppress_next_adjust: bool,
+    pub provider_failure_counts: HashMap<String, u64>,
+    pub secs_since_provider_warn: HashMap<String, Option<f64>>,
+    pub last_adjustments: Vec<GroupAdjustmentDump>,
+    pub last_adjustment_summaries: HashMap<String, Option<ResourceAdjustmentSummary>>,
+}
+
+/// The outcome of one `adjust_quota` tick, so callers can track provider
+/// failure rates or skipped ticks instead of relying on logs alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustOutcome {
+    /// the limits were recomputed.
+    Adjusted,
+    /// the limits were recomputed, but the stats provider reported itself
+    /// degraded; the reason is available via
+    /// [`GroupQuotaAdjustWorker::provider_health`].
+    AdjustedDegraded,
+    /// the tick fired before any per-type adjust interval elapsed.
+    SkippedShortInterval,
+    /// there is no background resource group to adjust.
+    SkippedNoGroups,
+    /// the tick right after a manual uniform override, skipped so the
+    /// override is not immediately undone.
+    SkippedManualOverride,
+    /// the worker is paused; the statistics baselines and the adjustment
+    /// timers were refreshed but no limiter was touched.
+    SkippedPaused,
+    /// the stats provider failed for the given resource type. Other
+    /// resource types are still adjusted on a best-effort basis.
+    ProviderError(ResourceType),
 }
 
 impl GroupQuotaAdjustWorker<SysQuotaGetter> {
     pub fn new(resource_ctl: Arc<ResourceGroupManager>, io_bandwidth: u64) -> Self {
         let resource_quota_getter = SysQuotaGetter {
-            process_stat: ProcessStat::cur_proc_stat().unwrap(),
+            process_stat: try_cur_proc_stat(),
             prev_io_stats: [IoBytes::default(); IoType::COUNT],
             prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
             io_bandwidth: io_bandwidth as f64,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: fetch_net_bytes().unwrap_or_default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: PathBuf::from(DEFAULT_CGROUP_PATH),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
         };
         Self::with_quota_getter(resource_ctl, resource_quota_getter)
     }
+
+    /// Update the disk bandwidth budget used by the quota getter, the new
+    /// value takes effect on the next adjustment tick.
+    pub fn set_io_bandwidth(&mut self, io_bandwidth: u64) {
+        self.resource_quota_getter.set_io_bandwidth(io_bandwidth);
+    }
+
+    /// Register the engine's internal compaction IO source on the quota
+    /// getter, see [`SysQuotaGetter::set_compaction_io_source`].
+    pub fn set_compaction_io_source(
+        &mut self,
+        source: impl Fn() -> u64 + Send + 'static,
+        accounting: CompactionIoAccounting,
+    ) {
+        self.resource_quota_getter
+            .set_compaction_io_source(source, accounting);
+    }
 }
 
 impl<R: ResourceStatsProvider> GroupQuotaAdjustWorker<R> {
@@ -119,845 +1075,6242 @@ impl<R: ResourceStatsProvider> GroupQuotaAdjustWorker<R> {
         let prev_stats_by_group = array::from_fn(|_| HashMap::default());
         Self {
             prev_stats_by_group,
-            last_adjust_time: Instant::now_coarse(),
+            starvation_debt: array::from_fn(|_| HashMap::default()),
+            starvation_debt_decay: None,
+            starvation_rotation: false,
+            rotation_cursors: array::from_fn(|_| 0),
+            wait_relief_factor: None,
+            last_adjust_time: [Instant::now_coarse(); ResourceType::COUNT],
             resource_ctl,
             resource_quota_getter,
-            is_last_time_low_load: array::from_fn(|_| false),
+            low_load_active: array::from_fn(|_| false),
+            low_load_streaks: array::from_fn(|_| 0),
+            low_load_debounce: DEFAULT_LOW_LOAD_DEBOUNCE,
+            low_load_ratio: DEFAULT_LOW_LOAD_RATIO,
+            last_adjustments: Vec::new(),
+            last_adjustment_summaries: array::from_fn(|_| None),
+            max_change_ratio: None,
+            ema_alpha: None,
+            smoothed_used: [f64::NAN; ResourceType::COUNT],
+            headroom_factor: DEFAULT_HEADROOM_FACTOR,
+            ru_cost_factor: [1.0; ResourceType::COUNT],
+            pressure_weights: [1.0; ResourceType::COUNT],
+            min_rate_floors: array::from_fn(|_| HashMap::default()),
+            fixed_rate_overrides: array::from_fn(|_| HashMap::default()),
+            class_policies: HashMap::default(),
+            max_total_background_rate: array::from_fn(|_| None),
+            dry_run: false,
+            paused: false,
+            adjust_interval: [BACKGROUND_LIMIT_ADJUST_DURATION; ResourceType::COUNT],
+            on_limit_change: None,
+            foreground_pressure: Arc::default(),
+            integral_gain: 0.0,
+            integral_errors: array::from_fn(|_| HashMap::default()),
+            peak_cap_ratio: None,
+            observed_peaks: array::from_fn(|_| HashMap::default()),
+            suppress_next_adjust: false,
+            scale_down_policy: ScaleDownPolicy::Linear,
+            throttled_durations: array::from_fn(|_| HashMap::default()),
+            under_util_ratio: None,
+            under_util_ticks: DEFAULT_UNDER_UTIL_TICKS,
+            under_util_streaks: array::from_fn(|_| HashMap::default()),
+            soft_start_ticks: 0,
+            soft_start_remaining: array::from_fn(|_| HashMap::default()),
+            unallocated_quota: [0.0; ResourceType::COUNT],
+            max_groups_per_tick: 0,
+            group_rotation_cursor: 0,
+            group_sample_times: array::from_fn(|_| HashMap::default()),
+            provider_warn_interval: DEFAULT_PROVIDER_WARN_INTERVAL,
+            last_provider_warn: array::from_fn(|_| None),
+            provider_failure_counts: [0; ResourceType::COUNT],
+            on_provider_warn: None,
+            clock: Box::new(Instant::now_coarse),
         }
     }
 
-    pub fn adjust_quota(&mut self) {
-        let now = Instant::now_coarse();
-        let dur_secs = now
-            .saturating_duration_since(self.last_adjust_time)
-            .as_secs_f64();
-        // a conservative check, skip adjustment if the duration is too short.
-        if dur_secs < 1.0 {
-            return;
-        }
-        self.last_adjust_time = now;
+    /// The shared flag an external latency monitor raises while the
+    /// foreground latency spikes. While it is set, the adjustment only ever
+    /// lowers the background rate limits: a limit the formula would raise is
+    /// kept at its current value until the pressure clears.
+    pub fn foreground_pressure(&self) -> Arc<AtomicBool> {
+        self.foreground_pressure.clone()
+    }
 
-        let mut background_util_limit = self
-            .resource_ctl
-            .get_resource_group(DEFAULT_RESOURCE_GROUP_NAME)
-            .map_or(0, |r| {
-                r.group.get_background_settings().get_utilization_limit()
-            });
-        if background_util_limit == 0 {
-            background_util_limit = 100;
-        }
+    /// Register a callback invoked with (group name, resource type, old
+    /// limit, new limit) whenever the worker applies a rate limit that
+    /// differs from the current one. Unchanged limits are not reported.
+    pub fn set_on_limit_change(
+        &mut self,
+        cb: impl Fn(&str, ResourceType, f64, f64) + Send + 'static,
+    ) {
+        self.on_limit_change = Some(Box::new(cb));
+    }
 
-        BACKGROUND_TASK_RESOURCE_UTILIZATION_VEC
-            .with_label_values(&["limit"])
-            .set(background_util_limit as i64);
+    /// Set the cadence of the adjustment loop for every resource type at
+    /// once. Externally-driven single-group adjustments are debounced by a
+    /// tenth of it, clamped to at least 100ms.
+    pub fn set_adjust_interval(&mut self, interval: Duration) {
+        self.adjust_interval = [interval; ResourceType::COUNT];
+    }
 
-        let mut background_groups: Vec<_> = self
-            .resource_ctl
-            .resource_groups
-            .iter()
-            .filter_map(|kv| {
-                let g = kv.value();
-                g.limiter.as_ref().map(|limiter| GroupStats {
-                    name: g.group.name.clone(),
-                    ru_quota: g.get_ru_quota() as f64,
-                    limiter: limiter.clone(),
-                    stats_per_sec: GroupStatistics::default(),
-                    expect_cost_rate: 0.0,
-                })
-            })
-            .collect();
-        if background_groups.is_empty() {
-            return;
-        }
+    /// Set the adjustment cadence of a single resource type, leaving the
+    /// other types on their own timers. CPU is cheap to sample frequently
+    /// while e.g. the io stats from `/proc` are noisier and benefit from a
+    /// longer window between two adjustments.
+    pub fn set_adjust_interval_of(&mut self, resource_type: ResourceType, interval: Duration) {
+        self.adjust_interval[resource_type as usize] = interval;
+    }
 
-        self.do_adjust(
-            ResourceType::Cpu,
-            dur_secs,
-            background_util_limit,
-            &mut background_groups,
-        );
-        self.do_adjust(
-            ResourceType::Io,
-            dur_secs,
-            background_util_limit,
-            &mut background_groups,
-        );
+    // the minimal duration between two effective adjustments of the given
+    // resource type, used to debounce externally-driven calls like
+    // [`Self::adjust_group`]. The regular loop waits for the full interval
+    // instead; this is only a lower clamp derived from the cadence so a
+    // faster cadence also reacts faster.
+    fn min_adjust_interval(&self, resource_type: ResourceType) -> Duration {
+        (self.adjust_interval[resource_type as usize] / 10).max(Duration::from_millis(100))
+    }
 
-        // clean up deleted group stats
-        if self.prev_stats_by_group[0].len() != background_groups.len() {
-            let name_set: HashSet<_> =
-                HashSet::from_iter(background_groups.iter().map(|g| &g.name));
-            for stat_map in &mut self.prev_stats_by_group {
-                stat_map.retain(|k, _v| !name_set.contains(k));
+    /// Reset the transient state of the worker. Callers should invoke this
+    /// when the role that runs the adjustment is re-acquired, so statistics
+    /// accumulated before the transition do not produce a bogus delta on
+    /// the first post-transition tick.
+    pub fn reset(&mut self) {
+        for stat_map in &mut self.prev_stats_by_group {
+            stat_map.clear();
+        }
+        for debt_map in &mut self.starvation_debt {
+            debt_map.clear();
+        }
+        for error_map in &mut self.integral_errors {
+            error_map.clear();
+        }
+        for peak_map in &mut self.observed_peaks {
+            peak_map.clear();
+        }
+        for throttled_map in &mut self.throttled_durations {
+            throttled_map.clear();
+        }
+        for streak_map in &mut self.under_util_streaks {
+            streak_map.clear();
+        }
+        for ramp_map in &mut self.soft_start_remaining {
+            ramp_map.clear();
+        }
+        for sample_map in &mut self.group_sample_times {
+            sample_map.clear();
+        }
+        self.unallocated_quota = [0.0; ResourceType::COUNT];
+        self.group_rotation_cursor = 0;
+        let now = (self.clock)();
+        // re-prime the baselines with the current statistics so the first
+        // post-reset tick only observes consumption happening after it.
+        for kv in self.resource_ctl.resource_groups.iter() {
+            let g = kv.value();
+            let Some(limiter) = g.limiter.as_ref() else {
+                continue;
+            };
+            if !limiter.is_background() {
+                continue;
+            }
+            for t in ResourceType::all() {
+                self.prev_stats_by_group[t as usize]
+                    .insert(g.group.name.clone(), limiter.get_limit_statistics(t));
+                self.group_sample_times[t as usize].insert(g.group.name.clone(), now);
             }
         }
+        self.low_load_active = array::from_fn(|_| false);
+        self.low_load_streaks = array::from_fn(|_| 0);
+        self.smoothed_used = [f64::NAN; ResourceType::COUNT];
+        self.last_adjustments.clear();
+        self.last_adjustment_summaries = array::from_fn(|_| None);
+        self.last_adjust_time = [now; ResourceType::COUNT];
+        self.suppress_next_adjust = false;
+        self.rotation_cursors = array::from_fn(|_| 0);
+        self.last_provider_warn = array::from_fn(|_| None);
     }
 
-    fn do_adjust(
-        &mut self,
-        resource_type: ResourceType,
-        dur_secs: f64,
-        utilization_limit: u64,
-        bg_group_stats: &mut [GroupStats],
-    ) {
-        let resource_stats = match self.resource_quota_getter.get_current_stats(resource_type) {
-            Ok(r) => r,
-            Err(e) => {
-                warn!("get resource statistics info failed, skip adjust"; "type" => ?resource_type, "err" => ?e);
-                return;
-            }
-        };
-        // if total resource quota is unlimited, set all groups' limit to unlimited.
-        if resource_stats.total_quota <= f64::EPSILON {
-            for g in bg_group_stats {
-                g.limiter
-                    .get_limiter(resource_type)
-                    .set_rate_limit(f64::INFINITY);
+    /// Toggle dry-run mode. In dry-run the worker still observes statistics
+    /// and records its decisions into the adjustment snapshot, but leaves
+    /// all limiters untouched.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Pause the adjustment without losing the accumulated state, e.g. to
+    /// keep the limits fixed during a planned migration. A paused worker
+    /// still refreshes the per-group statistics baselines and the
+    /// adjustment timers on every tick, so the first tick after
+    /// [`Self::resume`] only observes the consumption since the previous
+    /// tick instead of the whole paused window.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resume the adjustment after a [`Self::pause`].
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Instantly throttle every group that owns a background limiter to one
+    /// uniform `rate` for both cpu and io, e.g. to quiesce all background
+    /// work during a maintenance window. The next `adjust_quota` tick is
+    /// suppressed so the automatic adjustment does not immediately undo the
+    /// manual override; the regular adjustment resumes on the tick after
+    /// that.
+    pub fn set_uniform_limit(&mut self, rate: f64) {
+        for kv in self.resource_ctl.resource_groups.iter() {
+            let g = kv.value();
+            let Some(limiter) = g.limiter.as_ref() else {
+                continue;
+            };
+            if !limiter.is_background() {
+                continue;
             }
-            return;
+            limiter.get_limiter(ResourceType::Cpu).set_rate_limit(rate);
+            limiter.get_limiter(ResourceType::Io).set_rate_limit(rate);
         }
+        self.suppress_next_adjust = true;
+    }
 
-        let mut total_ru_quota = 0.0;
-        let mut background_consumed_total = 0.0;
-        let mut has_wait = false;
-        for g in bg_group_stats.iter_mut() {
-            total_ru_quota += g.ru_quota;
-            let total_stats = g.limiter.get_limit_statistics(resource_type);
-            let last_stats = self.prev_stats_by_group[resource_type as usize]
-                .insert(g.name.clone(), total_stats)
-                .unwrap_or_default();
-            // version changes means this is a brand new limiter, so no need to sub the old
-            // statistics.
-            let stats_delta = if total_stats.version == last_stats.version {
-                total_stats - last_stats
-            } else {
-                total_stats
-            };
-            BACKGROUND_RESOURCE_CONSUMPTION
-                .with_label_values(&[&g.name, resource_type.as_str()])
-                .inc_by(stats_delta.total_consumed);
-            if resource_type == ResourceType::Cpu {
-                BACKGROUND_TASKS_WAIT_DURATION
-                    .with_label_values(&[&g.name])
-                    .inc_by(stats_delta.total_wait_dur_us);
-            }
+    /// Compute the adjustment decisions against the current stats without
+    /// applying them, and return the per-group decisions. This is useful to
+    /// preview the effect of tuning knobs like `set_headroom_factor` or
+    /// `set_low_load_ratio` on a live instance.
+    pub fn adjust_quota_preview(&mut self) -> Vec<GroupAdjustment> {
+        let dry_run = self.dry_run;
+        self.dry_run = true;
+        self.adjust_quota();
+        self.dry_run = dry_run;
+        self.last_adjustment_snapshot()
+    }
 
-            let stats_per_sec = stats_delta / dur_secs;
-            background_consumed_total += stats_per_sec.total_consumed as f64;
-            g.stats_per_sec = stats_per_sec;
-            if stats_per_sec.total_wait_dur_us > 0 {
-                has_wait = true;
+    /// Declare a minimum guaranteed rate for a group. The floors are
+    /// reserved from the available quota before the remainder is
+    /// distributed, so declaring them cannot over-allocate the total quota.
+    /// A non-positive `min_rate` removes the floor.
+    pub fn set_group_min_rate(&mut self, name: &str, resource_type: ResourceType, min_rate: f64) {
+        if min_rate <= 0.0 {
+            self.min_rate_floors[resource_type as usize].remove(name);
+        } else {
+            self.min_rate_floors[resource_type as usize].insert(name.to_owned(), min_rate);
+        }
+    }
+
+    /// Pin a group to a fixed rate limit so it runs at a deterministic
+    /// rate, e.g. for a reproducible benchmark. A pinned group does not
+    /// participate in the adjustment at all: its fixed rate is subtracted
+    /// from the available quota up front and re-applied verbatim on every
+    /// tick. A non-positive `rate` removes the pin and returns the group to
+    /// the regular distribution.
+    pub fn set_group_fixed_rate(&mut self, name: &str, resource_type: ResourceType, rate: f64) {
+        if rate <= 0.0 {
+            self.fixed_rate_overrides[resource_type as usize].remove(name);
+        } else {
+            self.fixed_rate_overrides[resource_type as usize].insert(name.to_owned(), rate);
+        }
+    }
+
+    /// Set an absolute ceiling on the summed background rate of one
+    /// resource type. After distribution the assigned limits are scaled
+    /// down proportionally so their sum never exceeds the ceiling. A
+    /// non-positive rate removes the ceiling.
+    pub fn set_max_total_background_rate(&mut self, resource_type: ResourceType, rate: f64) {
+        self.max_total_background_rate[resource_type as usize] = (rate > 0.0).then_some(rate);
+    }
+
+    /// Register the policy of one workload class, see [`ClassPolicy`].
+    /// Non-positive or non-finite rates in the policy are dropped, like a
+    /// non-positive rate removes a per-group floor.
+    pub fn set_class_policy(&mut self, class: &str, mut policy: ClassPolicy) {
+        for rate in policy.min_rate.iter_mut().chain(policy.max_rate.iter_mut()) {
+            if let Some(r) = *rate {
+                if !(r > 0.0 && r.is_finite()) {
+                    *rate = None;
+                }
             }
         }
+        self.class_policies.insert(class.to_owned(), policy);
+    }
 
-        let background_util =
-            (background_consumed_total / resource_stats.total_quota * 100.0) as u64;
-        BACKGROUND_TASK_RESOURCE_UTILIZATION_VEC
-            .with_label_values(&[resource_type.as_str()])
-            .set(background_util as i64);
+    /// Remove the policy of one workload class; its groups fall back to
+    /// the global behavior and their own per-group settings.
+    pub fn remove_class_policy(&mut self, class: &str) {
+        self.class_policies.remove(class);
+    }
 
-        // fast path if process cpu is low
-        let is_low_load = resource_stats.current_used <= (resource_stats.total_quota * 0.1);
-        if is_low_load && !has_wait && self.is_last_time_low_load[resource_type as usize] {
+    /// Set the conversion factor from one consumed RU to the raw unit of a
+    /// resource type. The default of 1.0 matches the historical behavior of
+    /// treating the consumed statistics as raw units (cpu us, io bytes)
+    /// directly. A non-positive factor is ignored.
+    pub fn set_ru_cost_factor(&mut self, resource_type: ResourceType, factor: f64) {
+        if factor <= 0.0 {
+            warn!("ru cost factor must be positive, ignore it"; "factor" => factor);
             return;
         }
-        self.is_last_time_low_load[resource_type as usize] = is_low_load;
+        self.ru_cost_factor[resource_type as usize] = factor;
+    }
 
-        let util_limit_percent = (utilization_limit as f64 / 100.0).min(1.0);
-        // the available resource for background tasks is defined as:
-        // (total_resource_quota - foreground_task_used). foreground_task_used
-        // resource is calculated by: (resource_current_total_used -
-        // background_consumed_total). We reserve 20% of the free resources for
-        // foreground tasks in case the fore ground traffics increases.
-        let mut available_resource_rate = ((resource_stats.total_quota
-            - resource_stats.current_used
-            + background_consumed_total)
-            * 0.8)
-            .min(resource_stats.total_quota * util_limit_percent)
-            .max(resource_stats.total_quota * 0.1);
-        let mut total_expected_cost = 0.0;
-        for g in bg_group_stats.iter_mut() {
-            let mut rate_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
-            if rate_limit.is_infinite() {
-                rate_limit = 0.0;
-            }
-            let group_expected_cost = g.stats_per_sec.total_consumed as f64
-                + g.stats_per_sec.total_wait_dur_us as f64 / MICROS_PER_SEC * rate_limit;
-            g.expect_cost_rate = group_expected_cost;
-            total_expected_cost += group_expected_cost;
+    /// Return the effective RU-to-resource conversion factor of one type.
+    pub fn ru_cost_factor(&self, resource_type: ResourceType) -> f64 {
+        self.ru_cost_factor[resource_type as usize]
+    }
+
+    /// Set the relative weight of each resource type in the combined
+    /// [`Self::pressure_score`]. Both default to 1.0, i.e. cpu and io
+    /// pressure count equally; only the ratio between the weights matters
+    /// since the score is normalized by their sum. Negative or non-finite
+    /// weights, or two zero weights, are ignored.
+    pub fn set_pressure_weights(&mut self, cpu: f64, io: f64) {
+        if !(cpu >= 0.0 && io >= 0.0 && cpu.is_finite() && io.is_finite() && cpu + io > 0.0) {
+            warn!("pressure weights must be non-negative and not all zero, ignore them"; "cpu" => cpu, "io" => io);
+            return;
         }
-        // sort groups by the expect_cost_rate per ru
-        bg_group_stats.sort_by(|g1, g2| {
-            (g1.expect_cost_rate / g1.ru_quota)
-                .partial_cmp(&(g2.expect_cost_rate / g2.ru_quota))
-                .unwrap()
-        });
+        self.pressure_weights[ResourceType::Cpu as usize] = cpu;
+        self.pressure_weights[ResourceType::Io as usize] = io;
+    }
 
-        // quota is enough, group is allowed to got more resource then its share by ru.
-        // e.g. Given a totol resource of 10000, and ("name", ru_quota, expected_rate)
-        // of:  (rg1, 2000, 3000), (rg2, 3000, 1000), (rg3, 5000, 5000)
-        // then after the previous sort, the order is rg2, rg3, rg1 and the handle order
-        // is rg1, rg3, rg2 so the final rate limit assigned is: (rg1, 3000),
-        // (rg3, 5833(7000/6*5)), (rg2, 1166(7000/6*1))
-        if total_expected_cost <= available_resource_rate {
-            for g in bg_group_stats.iter().rev() {
-                let limit = g
-                    .expect_cost_rate
-                    .max(available_resource_rate / total_ru_quota * g.ru_quota);
-                g.limiter.get_limiter(resource_type).set_rate_limit(limit);
-                BACKGROUND_QUOTA_LIMIT_VEC
-                    .with_label_values(&[&g.name, resource_type.as_str()])
-                    .set(limit as i64);
-                available_resource_rate -= limit;
-                total_ru_quota -= g.ru_quota;
-            }
+    /// Set the fraction of the free resource that background tasks may use,
+    /// the rest is reserved for foreground traffic increases. The input
+    /// should be within `(0.0, 1.0]`, an invalid value is ignored.
+    pub fn set_headroom_factor(&mut self, factor: f64) {
+        if !(factor > 0.0 && factor <= 1.0) {
+            warn!("headroom factor is out of range (0.0, 1.0], ignore it"; "factor" => factor);
             return;
         }
+        self.headroom_factor = factor;
+    }
 
-        // quota is not enough, assign by share
-        // e.g. Given a totol resource of 10000, and ("name", ru_quota, expected_rate)
-        // of:  (rg1, 2000, 1000), (rg2, 3000, 5000), (rg3, 5000, 7000)
-        // then after the previous sort, the order is rg1, rg3, rg2, and handle order is
-        // rg1, rg3, rg2 so the final rate limit assigned is: (rg1, 1000), (rg3,
-        // 5250(9000/12*7)), (rg2, 3750(9000/12*5))
-        for g in bg_group_stats {
-            let limit = g
-                .expect_cost_rate
-                .min(available_resource_rate / total_ru_quota * g.ru_quota);
-            g.limiter.get_limiter(resource_type).set_rate_limit(limit);
-            BACKGROUND_QUOTA_LIMIT_VEC
-                .with_label_values(&[&g.name, resource_type.as_str()])
-                .set(limit as i64);
-            available_resource_rate -= limit;
-            total_ru_quota -= g.ru_quota;
+    /// Enable carrying under-served quota over to the following ticks. Each
+    /// tick the gap between a group's `ru_quota`-proportional fair share and
+    /// its assigned limit is added to a per-group debt that decays by
+    /// `decay` and is converted into extra distribution weight, so a group
+    /// that was starved before is biased toward catching up. `decay` is the
+    /// fraction of the debt kept per tick and should be within `[0.0, 1.0)`,
+    /// an invalid value falls back to the default 0.5.
+    pub fn set_starvation_debt_decay(&mut self, mut decay: f64) {
+        if !(0.0..1.0).contains(&decay) {
+            warn!("starvation debt decay is out of range [0.0, 1.0), use the default"; "decay" => decay);
+            decay = DEFAULT_STARVATION_DEBT_DECAY;
         }
+        self.starvation_debt_decay = Some(decay);
     }
-}
 
-struct GroupStats {
-    name: String,
-    limiter: Arc<ResourceLimiter>,
-    ru_quota: f64,
-    stats_per_sec: GroupStatistics,
-    expect_cost_rate: f64,
-}
+    /// Enable or disable rotating the starting index of the quota-short
+    /// distribution pass every tick. The greedy pass walks the groups in a
+    /// fixed sorted order, so the slack released by light groups always
+    /// flows to the same groups right behind them and the others can be
+    /// perpetually capped at their fair share. Rotating who gets first pick
+    /// spreads the leftover quota over all the groups across the ticks.
+    pub fn set_starvation_rotation(&mut self, enabled: bool) {
+        self.starvation_rotation = enabled;
+        if !enabled {
+            self.rotation_cursors = array::from_fn(|_| 0);
+        }
+    }
 
-/// PriorityLimiterAdjustWorker automically adjust the quota of each priority
-/// limiter based on the statistics data during a certain period of time.
-/// In general, caller should call this function in a fixed interval.
-pub struct PriorityLimiterAdjustWorker<R> {
-    resource_ctl: Arc<ResourceGroupManager>,
-    trackers: [PriorityLimiterStatsTracker; TaskPriority::PRIORITY_COUNT],
-    resource_quota_getter: R,
-    last_adjust_time: Instant,
-    is_last_low_cpu: bool,
-    is_last_single_group: bool,
-}
+    /// Enable the wait-based throttle relief: when background groups spent
+    /// time waiting on their limiters while the machine still has free
+    /// capacity, the available quota is widened proportionally to the
+    /// aggregate wait (at most doubled, and still bounded by the regular
+    /// clamps), so recovery from over-throttling does not drag on for
+    /// several ticks. The factor weights the aggregate wait seconds; an
+    /// invalid factor falls back to the default.
+    pub fn set_wait_relief_factor(&mut self, mut factor: f64) {
+        if !(factor > 0.0 && factor.is_finite()) {
+            warn!("wait relief factor must be positive, use the default"; "factor" => factor);
+            factor = DEFAULT_WAIT_RELIEF_FACTOR;
+        }
+        self.wait_relief_factor = Some(factor);
+    }
 
-impl PriorityLimiterAdjustWorker<SysQuotaGetter> {
-    pub fn new(resource_ctl: Arc<ResourceGroupManager>) -> Self {
-        let resource_quota_getter = SysQuotaGetter {
-            process_stat: ProcessStat::cur_proc_stat().unwrap(),
-            prev_io_stats: [IoBytes::default(); IoType::COUNT],
-            prev_io_ts: Instant::now_coarse(),
-            io_bandwidth: f64::INFINITY,
-        };
-        Self::with_quota_getter(resource_ctl, resource_quota_getter)
+    /// Enable exponential moving average smoothing of the observed resource
+    /// usage. `alpha` is the weight of the newest sample and should be within
+    /// `(0.0, 1.0]`, an invalid value falls back to the default 0.5.
+    pub fn set_ema_alpha(&mut self, mut alpha: f64) {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            warn!("ema alpha is out of range (0.0, 1.0], use the default"; "alpha" => alpha);
+            alpha = DEFAULT_EMA_ALPHA;
+        }
+        self.ema_alpha = Some(alpha);
     }
-}
 
-impl<R: ResourceStatsProvider> PriorityLimiterAdjustWorker<R> {
-    fn with_quota_getter(
-        resource_ctl: Arc<ResourceGroupManager>,
-        resource_quota_getter: R,
-    ) -> Self {
-        let limiters = resource_ctl.get_priority_resource_limiters();
-        let priorities = TaskPriority::priorities();
-        let trackers = std::array::from_fn(|i| {
-            PriorityLimiterStatsTracker::new(limiters[i].clone(), priorities[i].as_str())
-        });
-        Self {
-            resource_ctl,
-            trackers,
-            resource_quota_getter,
-            last_adjust_time: Instant::now_coarse(),
-            is_last_low_cpu: true,
-            is_last_single_group: true,
+    /// Set the gain of the integral term in the distribution. The purely
+    /// proportional distribution follows the instantaneous expected cost
+    /// only, so it consistently lags a ramping workload. With a positive
+    /// gain, each group additionally accumulates the error between its
+    /// observed consumption and the assigned limit (decayed every tick), and
+    /// `gain` times that error is added on top of the proportionally
+    /// computed limit, so a consistently under-served group converges
+    /// faster. The default gain of 0 keeps the purely proportional behavior;
+    /// a negative or non-finite gain is ignored.
+    pub fn set_integral_gain(&mut self, gain: f64) {
+        if !(gain >= 0.0 && gain.is_finite()) {
+            warn!("integral gain must be non-negative, ignore it"; "gain" => gain);
+            return;
         }
+        self.integral_gain = gain;
     }
-    pub fn adjust(&mut self) {
-        let now = Instant::now_coarse();
-        let dur = now.saturating_duration_since(self.last_adjust_time);
-        if dur < Duration::from_secs(1) {
-            warn!("adjust duration too small, skip adjustment."; "dur" => ?dur);
+
+    /// Enable the peak-derived ceiling: each group's assigned limit is
+    /// capped at `ratio` times its rolling peak observed consumed rate, so
+    /// a group that historically never came close to its share does not
+    /// hoard quota other groups could use. The stored peak decays every
+    /// tick, and a group whose demand grows raises it again and ramps up
+    /// over a few ticks. An invalid ratio falls back to the default.
+    pub fn set_peak_cap_ratio(&mut self, mut ratio: f64) {
+        if !(ratio >= 1.0 && ratio.is_finite()) {
+            warn!("peak cap ratio should be >= 1.0, use the default"; "ratio" => ratio);
+            ratio = DEFAULT_PEAK_CAP_RATIO;
+        }
+        self.peak_cap_ratio = Some(ratio);
+    }
+
+    /// Enable the negative feedback on chronically unused limits: once a
+    /// group's consumed rate stays below `ratio` of its assigned limit for
+    /// `ticks` consecutive ticks, its limit is trimmed toward the observed
+    /// usage instead of granted in full, freeing the unused quota for the
+    /// other groups. The trimmed limit keeps the threshold's worth of
+    /// headroom above the usage and never drops below the low-load share of
+    /// the total quota, so an idle group can still ramp back up. A
+    /// non-positive or non-finite `ratio` disables the feedback.
+    pub fn set_under_utilization_feedback(&mut self, ratio: f64, ticks: usize) {
+        if !(ratio > 0.0 && ratio.is_finite()) {
+            self.under_util_ratio = None;
             return;
         }
-        self.last_adjust_time = now;
+        self.under_util_ratio = Some(ratio.min(1.0));
+        self.under_util_ticks = ticks.max(1);
+    }
 
-        // fast path for only the default resource group which means resource
-        // control is not used at all.
-        let group_count = self.resource_ctl.get_group_count();
-        if group_count == 1 {
-            if self.is_last_single_group {
-                return;
+    /// Set over how many ticks a group whose limit transitions from
+    /// infinite to finite is ramped from its recent consumption toward the
+    /// computed limit, instead of jumping straight to it and shocking the
+    /// in-flight background tasks. Zero (the default) disables the soft
+    /// start and active ramps are dropped.
+    pub fn set_soft_start_ticks(&mut self, ticks: usize) {
+        self.soft_start_ticks = ticks;
+        if ticks == 0 {
+            for ramp_map in &mut self.soft_start_remaining {
+                ramp_map.clear();
             }
-            self.is_last_single_group = true;
-            self.trackers.iter().skip(1).for_each(|t| {
-                t.limiter
-                    .get_limiter(ResourceType::Cpu)
-                    .set_rate_limit(f64::INFINITY)
-            });
-            return;
         }
-        self.is_last_single_group = false;
+    }
 
-        let stats: [_; TaskPriority::PRIORITY_COUNT] =
-            array::from_fn(|i| self.trackers[i].get_and_update_last_stats(dur.as_secs_f64()));
+    /// Bound how many background groups one `adjust_quota` tick recomputes.
+    /// On clusters with thousands of background groups building and sorting
+    /// the whole group list every tick becomes a CPU cost of its own, so a
+    /// capped tick only processes a window of the name-sorted order and the
+    /// window rotates across ticks: each tick's cost is bounded while every
+    /// group is still adjusted once per full cycle. Zero (the default)
+    /// disables the cap.
+    pub fn set_max_groups_per_tick(&mut self, cap: usize) {
+        self.max_groups_per_tick = cap;
+        self.group_rotation_cursor = 0;
+    }
 
-        let process_cpu_stats = match self
-            .resource_quota_getter
-            .get_current_stats(ResourceType::Cpu)
+    /// Set how aggressively the quota-short branch scales the groups down,
+    /// see [`ScaleDownPolicy`]. A `Convex` policy with a negative or
+    /// non-finite exponent is ignored.
+    pub fn set_scale_down_policy(&mut self, policy: ScaleDownPolicy) {
+        if let ScaleDownPolicy::Convex { exponent } = policy
+            && !(exponent >= 0.0 && exponent.is_finite())
         {
-            Ok(s) => s,
-            Err(e) => {
-                warn!("get process total cpu failed; skip adjusment."; "err" => ?e);
-                return;
-            }
-        };
-
-        if process_cpu_stats.current_used < process_cpu_stats.total_quota * 0.3 {
-            if self.is_last_low_cpu {
-                return;
-            }
-            self.is_last_low_cpu = true;
-            self.trackers.iter().skip(1).for_each(|t| {
-                t.limiter
-                    .get_limiter(ResourceType::Cpu)
-                    .set_rate_limit(f64::INFINITY);
-                // 0 represent infinity
-                PRIORITY_QUOTA_LIMIT_VEC
-                    .get_metric_with_label_values(&[t.priority])
-                    .unwrap()
-                    .set(0);
-            });
+            warn!("convex scale-down exponent must be non-negative, ignore it"; "exponent" => exponent);
             return;
         }
-        self.is_last_low_cpu = false;
+        self.scale_down_policy = policy;
+    }
 
-        let total_cpus: f64 = stats.iter().map(|s| s.cpu_secs).sum();
-        let max_cpus = stats.iter().map(|s| s.cpu_secs).fold(0.0, f64::max);
-        // there is only 1 active priority, do not restrict.
-        if total_cpus * 0.99 <= max_cpus {
-            self.trackers
-                .iter()
-                .skip(1)
-                .for_each(|t: &PriorityLimiterStatsTracker| {
-                    t.limiter
-                        .get_limiter(ResourceType::Cpu)
-                        .set_rate_limit(f64::INFINITY)
-                });
+    /// Bound how much a group's rate limit can change in a single
+    /// adjustment: the new limit is clamped into
+    /// `[current / ratio, current * ratio]` unless the current limit is
+    /// infinite. This smooths abrupt limit swings caused by noisy samples.
+    pub fn set_max_change_ratio(&mut self, ratio: f64) {
+        if !(ratio >= 1.0) {
+            warn!("max change ratio should be >= 1.0, ignore it"; "ratio" => ratio);
             return;
         }
+        self.max_change_ratio = Some(ratio);
+    }
 
-        let cpu_duration: [_; TaskPriority::PRIORITY_COUNT] = array::from_fn(|i| stats[i].cpu_secs);
-        let real_cpu_total: f64 = cpu_duration.iter().sum();
+    fn clamp_limit_change(&self, current: f64, new_limit: f64) -> f64 {
+        let Some(ratio) = self.max_change_ratio else {
+            return new_limit;
+        };
+        if current.is_infinite() || !new_limit.is_finite() {
+            return new_limit;
+        }
+        new_limit.clamp(current / ratio, current * ratio)
+    }
 
-        let available_quota_percentage = self
-            .resource_ctl
-            .get_config()
-            .value()
-            .priority_ctl_strategy
-            .to_resource_util_percentage();
-        let expect_pool_cpu_total = real_cpu_total
-            * (process_cpu_stats.total_quota * available_quota_percentage)
-            / process_cpu_stats.current_used;
-        let mut limits = [0.0; 2];
-        let level_expected: [_; TaskPriority::PRIORITY_COUNT] =
-            array::from_fn(|i| stats[i].cpu_secs + stats[i].wait_secs);
-        // substract the cpu time usage for priority high.
-        let mut expect_cpu_time_total = expect_pool_cpu_total - level_expected[0];
+    /// Returns the per-group decisions made by the most recent `adjust_quota`
+    /// call.
+    pub fn last_adjustment_snapshot(&self) -> Vec<GroupAdjustment> {
+        self.last_adjustments.clone()
+    }
 
-        // still reserve a minimal cpu quota
-        let minimal_quota = process_cpu_stats.total_quota / MICROS_PER_SEC * 0.1;
-        for i in 1..self.trackers.len() {
-            if expect_cpu_time_total < minimal_quota {
-                expect_cpu_time_total = minimal_quota;
+    /// Returns the aggregate inputs of the most recent distribution of one
+    /// resource type, so the per-group decisions in
+    /// [`Self::last_adjustment_snapshot`] can be traced back to the quota
+    /// they were computed from. `None` is returned while no full
+    /// distribution of the type has run yet, e.g. when the resource is
+    /// unlimited or the low-load fast path was taken.
+    pub fn last_adjustment_summary(
+        &self,
+        resource_type: ResourceType,
+    ) -> Option<ResourceAdjustmentSummary> {
+        self.last_adjustment_summaries[resource_type as usize]
+    }
+
+    /// Returns the leftover of the distribution pool after the most recent
+    /// adjustment of one resource type: the quota the fair-share caps and
+    /// trims held back rather than assigned to any group, i.e. the headroom
+    /// still available to background tasks on top of the reserved share.
+    /// Zero while no distribution of the type has run yet.
+    pub fn unallocated_quota(&self, resource_type: ResourceType) -> f64 {
+        self.unallocated_quota[resource_type as usize]
+    }
+
+    /// A combined cpu+io pressure score in `[0, 1]` for one group, for
+    /// admission control loops that want a single "how throttled should I
+    /// be" scalar instead of two separate rate limits. Per resource type
+    /// the pressure is the larger of the machine-level scarcity of the
+    /// most recent distribution (`current_used` over `current_used` plus
+    /// `available_quota`, so a pool dwarfed by the usage reads as high
+    /// pressure) and the group's own saturation of its assigned limit
+    /// (its consumed rate over the limit, zero while it runs unthrottled).
+    /// The per-type pressures are blended by the weights set via
+    /// [`Self::set_pressure_weights`], equal by default. Returns `None`
+    /// until the group has been adjusted at least once.
+    pub fn pressure_score(&self, name: &str) -> Option<f64> {
+        let mut weighted = 0.0;
+        let mut weight_total = 0.0;
+        let mut adjusted = false;
+        for t in ResourceType::all() {
+            let weight = self.pressure_weights[t as usize];
+            if weight <= 0.0 {
+                continue;
             }
-            let limit = expect_cpu_time_total * MICROS_PER_SEC;
-            self.trackers[i]
-                .limiter
-                .get_limiter(ResourceType::Cpu)
-                .set_rate_limit(limit);
-            PRIORITY_QUOTA_LIMIT_VEC
-                .get_metric_with_label_values(&[self.trackers[i].priority])
-                .unwrap()
-                .set(limit as i64);
-            limits[i - 1] = limit;
-            expect_cpu_time_total -= level_expected[i];
+            let Some(adjustment) = self
+                .last_adjustments
+                .iter()
+                .find(|a| a.name == name && a.resource_type == t)
+            else {
+                continue;
+            };
+            adjusted = true;
+            let scarcity = self.last_adjustment_summaries[t as usize].map_or(0.0, |s| {
+                let supply = s.current_used + s.available_quota;
+                if supply <= f64::EPSILON {
+                    0.0
+                } else {
+                    (s.current_used / supply).clamp(0.0, 1.0)
+                }
+            });
+            let saturation = if adjustment.rate_limit.is_finite() && adjustment.rate_limit > 0.0 {
+                (adjustment.consumed_rate / adjustment.rate_limit).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            weighted += weight * scarcity.max(saturation);
+            weight_total += weight;
         }
-        debug!("adjsut cpu limiter by priority"; "cpu_quota" => process_cpu_stats.total_quota,
-            "process_cpu" => process_cpu_stats.current_used, "expected_cpu" => ?level_expected,
-            "cpu_costs" => ?cpu_duration, "limits" => ?limits,
-            "limit_cpu_total" => expect_pool_cpu_total, "pool_cpu_cost" => real_cpu_total);
+        (adjusted && weight_total > 0.0).then(|| weighted / weight_total)
     }
-}
 
-#[derive(Debug)]
-struct LimiterStats {
-    // QuotaLimiter consumed cpu secs in total
-    cpu_secs: f64,
-    // QuotaLimiter waited secs in total.
-    wait_secs: f64,
-}
+    /// Returns the cumulative duration the group spent capped below its
+    /// `ru_quota`-proportional fair share while demanding more, accrued one
+    /// adjustment window at a time, e.g. for SLA reporting. An unknown
+    /// group reports zero; [`Self::reset`] clears the counters.
+    pub fn get_throttled_duration(&self, name: &str, resource_type: ResourceType) -> Duration {
+        self.throttled_durations[resource_type as usize]
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
 
-struct HistogramTracker {
-    metrics: Histogram,
-    last_sum: f64,
-    last_count: u64,
-}
+    /// Returns the names of the background groups whose limiter of the
+    /// given resource type currently holds a finite rate limit, i.e. the
+    /// groups that are actively throttled. Groups running unthrottled (an
+    /// infinite limit) and groups without a background limiter are not
+    /// listed. The order of the names is unspecified.
+    pub fn throttled_groups(&self, resource_type: ResourceType) -> Vec<String> {
+        self.resource_ctl
+            .resource_groups
+            .iter()
+            .filter_map(|kv| {
+                let g = kv.value();
+                let limiter = g.limiter.as_ref()?;
+                if !limiter.is_background() {
+                    return None;
+                }
+                limiter
+                    .get_limiter(resource_type)
+                    .get_rate_limit()
+                    .is_finite()
+                    .then(|| g.group.name.clone())
+            })
+            .collect()
+    }
 
-impl HistogramTracker {
-    fn new(metrics: Histogram) -> Self {
-        let last_sum = metrics.get_sample_sum();
-        let last_count = metrics.get_sample_count();
-        Self {
-            metrics,
-            last_sum,
-            last_count,
+    /// Returns the effective configuration of the worker as a serializable
+    /// struct, reflecting the current values of all the tuning knobs.
+    pub fn config(&self) -> WorkerConfig {
+        let per_group = |maps: &[HashMap<String, f64>; ResourceType::COUNT]| {
+            ResourceType::all()
+                .into_iter()
+                .filter(|t| !maps[*t as usize].is_empty())
+                .map(|t| (t.as_str().to_owned(), maps[t as usize].clone()))
+                .collect()
+        };
+        let per_class = |policies: &HashMap<String, ClassPolicy>,
+                         pick: fn(&ClassPolicy) -> &[Option<f64>; ResourceType::COUNT]| {
+            ResourceType::all()
+                .into_iter()
+                .filter_map(|t| {
+                    let rates: HashMap<String, f64> = policies
+                        .iter()
+                        .filter_map(|(class, policy)| {
+                            pick(policy)[t as usize].map(|rate| (class.clone(), rate))
+                        })
+                        .collect();
+                    (!rates.is_empty()).then(|| (t.as_str().to_owned(), rates))
+                })
+                .collect::<HashMap<_, _>>()
+        };
+        WorkerConfig {
+            adjust_interval: ResourceType::all()
+                .into_iter()
+                .map(|t| (t.as_str().to_owned(), self.adjust_interval[t as usize]))
+                .collect(),
+            headroom_factor: self.headroom_factor,
+            low_load_ratio: self.low_load_ratio,
+            low_load_debounce: self.low_load_debounce,
+            starvation_debt_decay: self.starvation_debt_decay,
+            starvation_rotation: self.starvation_rotation,
+            wait_relief_factor: self.wait_relief_factor,
+            ema_alpha: self.ema_alpha,
+            max_change_ratio: self.max_change_ratio,
+            integral_gain: self.integral_gain,
+            peak_cap_ratio: self.peak_cap_ratio,
+            under_util_ratio: self.under_util_ratio,
+            under_util_ticks: self.under_util_ticks,
+            soft_start_ticks: self.soft_start_ticks,
+            max_groups_per_tick: self.max_groups_per_tick,
+            scale_down_policy: self.scale_down_policy,
+            provider_warn_interval: self.provider_warn_interval,
+            dry_run: self.dry_run,
+            paused: self.paused,
+            ru_cost_factor: ResourceType::all()
+                .into_iter()
+                .map(|t| (t.as_str().to_owned(), self.ru_cost_factor[t as usize]))
+                .collect(),
+            pressure_weights: ResourceType::all()
+                .into_iter()
+                .map(|t| (t.as_str().to_owned(), self.pressure_weights[t as usize]))
+                .collect(),
+            min_rate_floors: per_group(&self.min_rate_floors),
+            fixed_rate_overrides: per_group(&self.fixed_rate_overrides),
+            class_min_rates: per_class(&self.class_policies, |policy| &policy.min_rate),
+            class_max_rates: per_class(&self.class_policies, |policy| &policy.max_rate),
+            max_total_background_rate: ResourceType::all()
+                .into_iter()
+                .filter_map(|t| {
+                    self.max_total_background_rate[t as usize]
+                        .map(|rate| (t.as_str().to_owned(), rate))
+                })
+                .collect(),
         }
     }
 
-    fn get_and_upate_statistics(&mut self) -> (f64, u64) {
-        let cur_sum = self.metrics.get_sample_sum();
-        let cur_count = self.metrics.get_sample_count();
-        let res = (cur_sum - self.last_sum, cur_count - self.last_count);
-        self.last_sum = cur_sum;
-        self.last_count = cur_count;
-        res
+    /// Capture the worker's full internal state into one serializable blob
+    /// for a support bundle, so field issues can be diagnosed from a debug
+    /// endpoint without a coredump. Nothing is redacted: the dump only
+    /// contains group names and numbers that are visible through the
+    /// regular metrics anyway.
+    pub fn debug_dump(&self) -> WorkerDebugState {
+        fn per_group<V: Clone>(
+            maps: &[HashMap<String, V>; ResourceType::COUNT],
+        ) -> HashMap<String, HashMap<String, V>> {
+            ResourceType::all()
+                .into_iter()
+                .map(|t| (t.as_str().to_owned(), maps[t as usize].clone()))
+                .collect()
+        }
+        fn per_type<V>(values: [V; ResourceType::COUNT]) -> HashMap<String, V> {
+            ResourceType::all()
+                .into_iter()
+                .zip(values)
+                .map(|(t, v)| (t.as_str().to_owned(), v))
+                .collect()
+        }
+        let now = (self.clock)();
+        WorkerDebugState {
+            config: self.config(),
+            prev_stats_by_group: per_type(array::from_fn(|i| {
+                self.prev_stats_by_group[i]
+                    .iter()
+                    .map(|(name, stats)| (name.clone(), GroupStatisticsDump::from(*stats)))
+                    .collect()
+            })),
+            secs_since_last_adjust: per_type(
+                self.last_adjust_time
+                    .map(|t| now.saturating_duration_since(t).as_secs_f64()),
+            ),
+            low_load_active: per_type(self.low_load_active),
+            low_load_streaks: per_type(self.low_load_streaks),
+            smoothed_used: per_type(self.smoothed_used.map(|v| (!v.is_nan()).then_some(v))),
+            starvation_debt: per_group(&self.starvation_debt),
+            integral_errors: per_group(&self.integral_errors),
+            observed_peaks: per_group(&self.observed_peaks),
+            throttled_durations: per_group(&self.throttled_durations),
+            under_util_streaks: per_group(&self.under_util_streaks),
+            soft_start_remaining: per_group(&self.soft_start_remaining),
+            unallocated_quota: per_type(self.unallocated_quota),
+            rotation_cursors: per_type(self.rotation_cursors),
+            group_rotation_cursor: self.group_rotation_cursor,
+            suppress_next_adjust: self.suppress_next_adjust,
+            provider_failure_counts: per_type(self.provider_failure_counts),
+            secs_since_provider_warn: per_type(
+                self.last_provider_warn
+                    .map(|t| t.map(|t| now.saturating_duration_since(t).as_secs_f64())),
+            ),
+            last_adjustments: self.last_adjustments.iter().map(Into::into).collect(),
+            last_adjustment_summaries: per_type(self.last_adjustment_summaries),
+        }
     }
-}
 
-struct PriorityLimiterStatsTracker {
-    priority: &'static str,
-    limiter: Arc<ResourceLimiter>,
-    last_stats: GroupStatistics,
-    // unified-read-pool and schedule-worker-pool wait duration metrics.
-    task_wait_dur_trakcers: [HistogramTracker; 2],
-}
+    /// Returns the self-reported health of the underlying stats provider,
+    /// e.g. for an alerting endpoint.
+    pub fn provider_health(&self) -> ProviderHealth {
+        self.resource_quota_getter.health()
+    }
 
-impl PriorityLimiterStatsTracker {
-    fn new(limiter: Arc<ResourceLimiter>, priority: &'static str) -> Self {
-        let task_wait_dur_trakcers =
-            ["unified-read-pool", "sched-worker-priority"].map(|pool_name| {
-                HistogramTracker::new(
-                    YATP_POOL_SCHEDULE_WAIT_DURATION_VEC
-                        .get_metric_with_label_values(&[pool_name, priority])
-                        .unwrap(),
-                )
-            });
-        let last_stats = limiter.get_limit_statistics(ResourceType::Cpu);
-        Self {
-            priority,
-            limiter,
-            last_stats,
-            task_wait_dur_trakcers,
-        }
+    /// Set the minimal duration between two "get resource statistics
+    /// failed" warnings of the same resource type. Failures silenced by the
+    /// gate are still counted into [`Self::provider_failure_count`].
+    pub fn set_provider_warn_interval(&mut self, interval: Duration) {
+        self.provider_warn_interval = interval;
     }
 
-    fn get_and_update_last_stats(&mut self, dur_secs: f64) -> LimiterStats {
-        let cur_stats = self.limiter.get_limit_statistics(ResourceType::Cpu);
-        let stats_delta = cur_stats - self.last_stats;
-        self.last_stats = cur_stats;
-        PRIORITY_CPU_TIME_VEC
-            .with_label_values(&[self.priority])
-            .inc_by(stats_delta.total_consumed);
-        let stats_per_sec = stats_delta / dur_secs;
+    /// Register a callback invoked with the resource type whenever a
+    /// provider-failure warning passes the rate limit gate and is actually
+    /// logged, e.g. to forward it to an alerting channel.
+    pub fn set_on_provider_warn(&mut self, cb: impl Fn(ResourceType) + Send + 'static) {
+        self.on_provider_warn = Some(Box::new(cb));
+    }
 
-        let wait_stats: [_; 2] =
-            array::from_fn(|i| self.task_wait_dur_trakcers[i].get_and_upate_statistics());
-        let schedule_wait_dur_secs = wait_stats.iter().map(|s| s.0).sum::<f64>() / dur_secs;
-        let expected_wait_dur_secs =
-            stats_per_sec.request_count as f64 * MINIMAL_SCHEDULE_WAIT_SECS;
-        let normed_schedule_wait_dur_secs =
-            (schedule_wait_dur_secs - expected_wait_dur_secs).max(0.0);
-        LimiterStats {
-            cpu_secs: stats_per_sec.total_consumed as f64 / MICROS_PER_SEC,
-            wait_secs: stats_per_sec.total_wait_dur_us as f64 / MICROS_PER_SEC
-                + normed_schedule_wait_dur_secs,
-        }
+    /// The cumulative number of stats provider failures of one resource
+    /// type, counted on every failed tick regardless of the warning gate.
+    pub fn provider_failure_count(&self, resource_type: ResourceType) -> u64 {
+        self.provider_failure_counts[resource_type as usize]
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    /// Replace the time source used for all the worker's interval checks
+    /// (the per-type adjustment timers and the provider warning gate). The
+    /// default is the coarse monotonic clock; tests inject a mock clock to
+    /// drive the ticks deterministically instead of backdating
+    /// `last_adjust_time`. Note [`Self::reset`] re-stamps the adjustment
+    /// timers through the clock, so call it after switching the source.
+    pub fn set_clock(&mut self, clock: impl Fn() -> Instant + Send + 'static) {
+        self.clock = Box::new(clock);
+    }
 
-    use super::*;
-    use crate::{resource_group::tests::*, resource_limiter::QuotaLimiter};
+    /// Set the ratio of used resource below which the worker treats the
+    /// load as low. The input should be within `(0.0, 1.0)`, an invalid
+    /// value is clamped into this range.
+    pub fn set_low_load_ratio(&mut self, mut ratio: f64) {
+        if !(ratio > 0.0 && ratio < 1.0) {
+            warn!("low load ratio is out of range (0.0, 1.0), clamp it"; "ratio" => ratio);
+            ratio = if ratio.is_nan() {
+                DEFAULT_LOW_LOAD_RATIO
+            } else {
+                ratio.clamp(f64::EPSILON, 1.0 - f64::EPSILON)
+            };
+        }
+        self.low_load_ratio = ratio;
+    }
 
-    struct TestResourceStatsProvider {
-        cpu_total: f64,
-        cpu_used: f64,
-        io_total: f64,
-        io_used: f64,
+    /// Set the number of consecutive observations required to enter or
+    /// leave the low-load fast path. The minimum effective value is 1.
+    pub fn set_low_load_debounce(&mut self, debounce: usize) {
+        self.low_load_debounce = debounce.max(1);
     }
 
-    impl TestResourceStatsProvider {
-        fn new(cpu_total: f64, io_total: f64) -> Self {
-            Self {
-                cpu_total,
-                cpu_used: 0.0,
-                io_total,
-                io_used: 0.0,
-            }
-        }
+    /// Run the adjustment loop on an async runtime, ticking at the fastest
+    /// per-type cadence until `shutdown` resolves. A type whose own interval
+    /// has not elapsed yet simply skips the tick.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        let interval = self.adjust_interval.into_iter().min().unwrap();
+        self.run_with_interval(interval, shutdown).await
     }
 
-    impl ResourceStatsProvider for TestResourceStatsProvider {
-        fn get_current_stats(&mut self, t: ResourceType) -> IoResult<ResourceUsageStats> {
-            match t {
-                ResourceType::Cpu => Ok(ResourceUsageStats {
-                    total_quota: self.cpu_total * MICROS_PER_SEC,
-                    current_used: self.cpu_used * MICROS_PER_SEC,
-                }),
-                ResourceType::Io => Ok(ResourceUsageStats {
-                    total_quota: self.io_total,
-                    current_used: self.io_used,
-                }),
+    /// Same as [`Self::run`] but with a custom tick interval, mainly for
+    /// tests.
+    pub async fn run_with_interval(
+        mut self,
+        interval: Duration,
+        shutdown: impl Future<Output = ()>,
+    ) {
+        let mut shutdown = Box::pin(shutdown);
+        loop {
+            let delay = Box::pin(
+                GLOBAL_TIMER_HANDLE
+                    .delay(std::time::Instant::now() + interval)
+                    .compat(),
+            );
+            match future::select(delay, shutdown).await {
+                Either::Left((_, not_shutdown)) => {
+                    shutdown = not_shutdown;
+                    self.adjust_quota();
+                }
+                Either::Right(_) => return,
             }
         }
     }
 
-    #[test]
-    fn test_adjust_resource_limiter() {
-        let resource_ctl = Arc::new(ResourceGroupManager::default());
-        let rg1 = new_resource_group_ru("test".into(), 1000, 14);
-        resource_ctl.add_resource_group(rg1);
-        assert!(
-            resource_ctl
-                .get_background_resource_limiter("test", "br")
-                .is_none()
-        );
-
-        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
-        let mut worker =
-            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
-
-        let default_bg =
-            new_background_resource_group_ru("default".into(), 100000, 8, vec!["br".into()]);
-        resource_ctl.add_resource_group(default_bg);
-        assert!(
-            resource_ctl
-                .get_background_resource_limiter("default", "lightning")
-                .is_none()
-        );
-        let limiter = resource_ctl
-            .get_background_resource_limiter("default", "br")
-            .unwrap();
-        assert!(
-            limiter
-                .get_limiter(ResourceType::Cpu)
-                .get_rate_limit()
-                .is_infinite()
-        );
-        assert!(
-            limiter
-                .get_limiter(ResourceType::Io)
-                .get_rate_limit()
-                .is_infinite()
-        );
+    pub fn adjust_quota(&mut self) -> AdjustOutcome {
+        if self.suppress_next_adjust {
+            self.suppress_next_adjust = false;
+            return AdjustOutcome::SkippedManualOverride;
+        }
+        let now = (self.clock)();
+        // a per-type check: every type runs on its own timer and is only
+        // adjusted once its own full interval has elapsed, so e.g. the noisy
+        // io stats can run on a longer window than the cheap cpu sampling
+        // even though the loop ticks at the fastest cadence.
+        let dur_secs: [Option<f64>; ResourceType::COUNT] = array::from_fn(|i| {
+            let dur_secs = now
+                .saturating_duration_since(self.last_adjust_time[i])
+                .as_secs_f64();
+            (dur_secs >= self.adjust_interval[i].as_secs_f64()).then_some(dur_secs)
+        });
+        if dur_secs.iter().all(|dur| dur.is_none()) {
+            return AdjustOutcome::SkippedShortInterval;
+        }
+        for (i, dur) in dur_secs.iter().enumerate() {
+            if dur.is_some() {
+                self.last_adjust_time[i] = now;
+            }
+        }
 
-        fn reset_quota_limiter(limiter: &QuotaLimiter) {
-            let limit = limiter.get_rate_limit();
-            if limit.is_finite() {
-                limiter.set_rate_limit(f64::INFINITY);
-                limiter.set_rate_limit(limit);
+        // While paused the limiters must stay untouched, but the statistics
+        // baselines keep tracking the current counters (like [`Self::reset`]
+        // re-primes them), so the consumption of the whole paused window
+        // does not show up as one huge delta on the first resumed tick.
+        if self.paused {
+            for kv in self.resource_ctl.resource_groups.iter() {
+                let g = kv.value();
+                let Some(limiter) = g.limiter.as_ref() else {
+                    continue;
+                };
+                if !limiter.is_background() {
+                    continue;
+                }
+                for t in ResourceType::all() {
+                    self.prev_stats_by_group[t as usize]
+                        .insert(g.group.name.clone(), limiter.get_limit_statistics(t));
+                    self.group_sample_times[t as usize].insert(g.group.name.clone(), now);
+                }
             }
+            return AdjustOutcome::SkippedPaused;
         }
 
-        fn reset_limiter(limiter: &Arc<ResourceLimiter>) {
-            reset_quota_limiter(limiter.get_limiter(ResourceType::Cpu));
-            reset_quota_limiter(limiter.get_limiter(ResourceType::Io));
+        // the common case on clusters without any background setting: the
+        // manager maintains a counter of limiter-bearing groups, so there is
+        // no need to scan the whole (possibly huge) group map every tick
+        // just to find out it filters down to nothing.
+        if self.resource_ctl.get_background_group_count() == 0 {
+            return AdjustOutcome::SkippedNoGroups;
         }
 
-        let reset_quota = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>,
-                           cpu: f64,
-                           io: f64,
-                           dur: Duration| {
-            worker.resource_quota_getter.cpu_used = cpu;
-            worker.resource_quota_getter.io_used = io;
-            let now = Instant::now_coarse();
-            worker.last_adjust_time = now - dur;
-        };
+        let mut background_util_limit = self
+            .resource_ctl
+            .get_resource_group(DEFAULT_RESOURCE_GROUP_NAME)
+            .map_or(0, |r| {
+                r.group.get_background_settings().get_utilization_limit()
+            });
+        if background_util_limit == 0 {
+            background_util_limit = 100;
+        }
 
-        #[track_caller]
-        fn check(val: f64, expected: f64) {
-            assert!(
-                expected * 0.99 < val && val < expected * 1.01,
-                "actual: {}, expected: {}",
-                val,
-                expected
-            );
+        BACKGROUND_TASK_RESOURCE_UTILIZATION_VEC
+            .with_label_values(&["limit"])
+            .set(background_util_limit as i64);
+
+        let mut background_groups: Vec<_> = self
+            .resource_ctl
+            .resource_groups
+            .iter()
+            .filter_map(|kv| {
+                let g = kv.value();
+                let limiter = g.limiter.as_ref()?;
+                // a group may own a limiter purely for accounting, only
+                // background-tagged limiters are throttled here.
+                if !limiter.is_background() {
+                    return None;
+                }
+                let ru_quota = g.get_ru_quota() as f64;
+                // skip groups without any ru quota, they do not participate in
+                // the distribution and dividing by their quota produces NaN.
+                if ru_quota <= 0.0 {
+                    return None;
+                }
+                let weight = ru_quota * priority_factor(g.group.priority);
+                let class = g
+                    .group
+                    .get_background_settings()
+                    .get_job_types()
+                    .first()
+                    .cloned();
+                Some(GroupStats {
+                    name: g.group.name.clone(),
+                    class,
+                    ru_quota,
+                    weight,
+                    adjusted_weight: weight,
+                    limiter: limiter.clone(),
+                    stats_per_sec: GroupStatistics::default(),
+                    expect_cost_rate: 0.0,
+                })
+            })
+            .collect();
+        if background_groups.is_empty() {
+            return AdjustOutcome::SkippedNoGroups;
         }
 
-        #[track_caller]
-        fn check_limiter(limiter: &Arc<ResourceLimiter>, cpu: f64, io: IoBytes) {
-            check(
-                limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
-                cpu * MICROS_PER_SEC,
-            );
-            check(
-                limiter.get_limiter(ResourceType::Io).get_rate_limit(),
-                (io.read + io.write) as f64,
+        // bound the per-tick cost on clusters with a huge number of
+        // background groups: only a window of at most `max_groups_per_tick`
+        // groups is recomputed this tick, rotating through the name-sorted
+        // order so every group is adjusted once per full cycle. The full
+        // name set is kept aside for the deleted-group cleanup below, which
+        // must not mistake the groups outside the window for deleted ones.
+        let mut all_group_names = None;
+        if self.max_groups_per_tick > 0 && background_groups.len() > self.max_groups_per_tick {
+            all_group_names = Some(
+                background_groups
+                    .iter()
+                    .map(|g| g.name.clone())
+                    .collect::<HashSet<_>>(),
             );
-            reset_limiter(limiter);
+            // the group map iterates in no stable order, so sort by name to
+            // make the rotation deterministic across ticks.
+            background_groups.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+            let total = background_groups.len();
+            self.group_rotation_cursor %= total;
+            background_groups.rotate_left(self.group_rotation_cursor);
+            background_groups.truncate(self.max_groups_per_tick);
+            self.group_rotation_cursor =
+                (self.group_rotation_cursor + self.max_groups_per_tick) % total;
         }
 
-        reset_quota(&mut worker, 0.0, 0.0, Duration::from_secs(1));
-        worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            6.4,
-            IoBytes {
-                read: 4000,
-                write: 4000,
-            },
-        );
+        // fetch the stats of all resource types in one batch so the samples
+        // share the same timestamp.
+        let all_stats = self.resource_quota_getter.get_all_stats();
+        let mut provider_error = None;
+        for (resource_type, stats) in ResourceType::all().into_iter().zip(all_stats) {
+            // this type's own interval has not elapsed yet, leave it as is.
+            let Some(dur_secs) = dur_secs[resource_type as usize] else {
+                continue;
+            };
+            match stats {
+                Ok(stats) => {
+                    self.do_adjust(
+                        resource_type,
+                        stats,
+                        now,
+                        dur_secs,
+                        background_util_limit,
+                        &mut background_groups,
+                    );
+                    // the per-`IoType` sub-limits derive from the aggregate
+                    // io limits assigned just above.
+                    if resource_type == ResourceType::Io {
+                        self.distribute_io_type_limits(&background_groups);
+                    }
+                }
+                Err(e) => {
+                    self.provider_failure_counts[resource_type as usize] += 1;
+                    // a persistently failing provider would log every tick,
+                    // so gate the warning per type; the counter above still
+                    // records every failure for metrics.
+                    let last_warn = &mut self.last_provider_warn[resource_type as usize];
+                    if last_warn.map_or(true, |last| {
+                        now.saturating_duration_since(last) >= self.provider_warn_interval
+                    }) {
+                        warn!("get resource statistics info failed, skip adjust"; "type" => ?resource_type, "err" => ?e);
+                        if let Some(cb) = &self.on_provider_warn {
+                            cb(resource_type);
+                        }
+                        *last_warn = Some(now);
+                    }
+                    provider_error.get_or_insert(resource_type);
+                }
+            }
+        }
 
-        reset_quota(&mut worker, 4.0, 2000.0, Duration::from_millis(500));
-        worker.adjust_quota();
-        check_limiter(
+        // clean up deleted group stats
+        let total_group_count = all_group_names
+            .as_ref()
+            .map_or(background_groups.len(), HashSet::len);
+        if self.prev_stats_by_group[0].len() != total_group_count {
+            let name_set: HashSet<&String> = match &all_group_names {
+                Some(names) => names.iter().collect(),
+                None => background_groups.iter().map(|g| &g.name).collect(),
+            };
+            for stat_map in &mut self.prev_stats_by_group {
+                stat_map.retain(|k, _v| name_set.contains(k));
+            }
+            for debt_map in &mut self.starvation_debt {
+                debt_map.retain(|k, _v| name_set.contains(k));
+            }
+            for peak_map in &mut self.observed_peaks {
+                peak_map.retain(|k, _v| name_set.contains(k));
+            }
+            for throttled_map in &mut self.throttled_durations {
+                throttled_map.retain(|k, _v| name_set.contains(k));
+            }
+            for streak_map in &mut self.under_util_streaks {
+                streak_map.retain(|k, _v| name_set.contains(k));
+            }
+            for ramp_map in &mut self.soft_start_remaining {
+                ramp_map.retain(|k, _v| name_set.contains(k));
+            }
+            for sample_map in &mut self.group_sample_times {
+                sample_map.retain(|k, _v| name_set.contains(k));
+            }
+            for error_map in &mut self.integral_errors {
+                error_map.retain(|k, _v| name_set.contains(k));
+            }
+        }
+
+        match provider_error {
+            Some(t) => AdjustOutcome::ProviderError(t),
+            // a degraded provider still returns stats, but flag the tick so
+            // callers can alert on possibly stale samples.
+            None => match self.resource_quota_getter.health() {
+                ProviderHealth::Healthy => AdjustOutcome::Adjusted,
+                ProviderHealth::Degraded(_) => AdjustOutcome::AdjustedDegraded,
+            },
+        }
+    }
+
+    /// Force an immediate recomputation of one group's limits, e.g. to
+    /// debug a misbehaving group without waiting for the next tick. The
+    /// distribution considers only this group against the full available
+    /// quota while all other groups keep their current limits, so the
+    /// result is an upper bound of what the regular tick would assign. The
+    /// other groups' baselines are left untouched; the next regular tick
+    /// normalizes their deltas over each group's own sampling gap, so their
+    /// per-second rates stay accurate. Returns the per-type decisions, or
+    /// `None` when the group is not a known background group or the last
+    /// adjustment was too recent.
+    pub fn adjust_group(&mut self, name: &str) -> Option<Vec<GroupAdjustment>> {
+        let now = (self.clock)();
+        // like `adjust_quota`, every resource type is gated on its own timer.
+        let dur_secs: [Option<f64>; ResourceType::COUNT] = array::from_fn(|i| {
+            let dur_secs = now
+                .saturating_duration_since(self.last_adjust_time[i])
+                .as_secs_f64();
+            (dur_secs >= self.min_adjust_interval(ResourceType::all()[i]).as_secs_f64())
+                .then_some(dur_secs)
+        });
+        if dur_secs.iter().all(|dur| dur.is_none()) {
+            return None;
+        }
+
+        let (ru_quota, weight, class, limiter) = {
+            let g = self.resource_ctl.get_resource_group(name)?;
+            let limiter = g.limiter.clone()?;
+            if !limiter.is_background() {
+                return None;
+            }
+            let ru_quota = g.get_ru_quota() as f64;
+            if ru_quota <= 0.0 {
+                return None;
+            }
+            let class = g
+                .group
+                .get_background_settings()
+                .get_job_types()
+                .first()
+                .cloned();
+            (
+                ru_quota,
+                ru_quota * priority_factor(g.group.priority),
+                class,
+                limiter,
+            )
+        };
+        for (i, dur) in dur_secs.iter().enumerate() {
+            if dur.is_some() {
+                self.last_adjust_time[i] = now;
+            }
+        }
+
+        let mut background_util_limit = self
+            .resource_ctl
+            .get_resource_group(DEFAULT_RESOURCE_GROUP_NAME)
+            .map_or(0, |r| {
+                r.group.get_background_settings().get_utilization_limit()
+            });
+        if background_util_limit == 0 {
+            background_util_limit = 100;
+        }
+
+        let mut group_stats = [GroupStats {
+            name: name.to_owned(),
+            class,
+            ru_quota,
+            weight,
+            adjusted_weight: weight,
+            limiter,
+            stats_per_sec: GroupStatistics::default(),
+            expect_cost_rate: 0.0,
+        }];
+        let all_stats = self.resource_quota_getter.get_all_stats();
+        for (resource_type, stats) in ResourceType::all().into_iter().zip(all_stats) {
+            let Some(dur_secs) = dur_secs[resource_type as usize] else {
+                continue;
+            };
+            match stats {
+                Ok(stats) => {
+                    self.do_adjust(
+                        resource_type,
+                        stats,
+                        now,
+                        dur_secs,
+                        background_util_limit,
+                        &mut group_stats,
+                    );
+                    if resource_type == ResourceType::Io {
+                        self.distribute_io_type_limits(&group_stats);
+                    }
+                }
+                Err(e) => {
+                    warn!("get resource statistics info failed, skip adjust"; "type" => ?resource_type, "err" => ?e);
+                }
+            }
+        }
+        Some(
+            self.last_adjustments
+                .iter()
+                .filter(|a| a.name == name)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn set_groups_unlimited(
+        &mut self,
+        resource_type: ResourceType,
+        bg_group_stats: &mut [GroupStats],
+    ) {
+        for g in bg_group_stats {
+            // a pinned group keeps its fixed rate even when the resource
+            // itself is unlimited.
+            let target = self.fixed_rate_overrides[resource_type as usize]
+                .get(&g.name)
+                .copied()
+                .unwrap_or(f64::INFINITY);
+            if !self.dry_run {
+                let old_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
+                if let Some(cb) = &self.on_limit_change
+                    && (target - old_limit).abs() > f64::EPSILON
+                {
+                    cb(&g.name, resource_type, old_limit, target);
+                }
+                g.limiter.get_limiter(resource_type).set_rate_limit(target);
+            }
+            self.last_adjustments.push(GroupAdjustment {
+                name: g.name.clone(),
+                resource_type,
+                rate_limit: target,
+                consumed_rate: 0.0,
+                wait_dur_us: 0,
+            });
+        }
+    }
+
+    // Split the just-assigned aggregate io limit of every opted-in group
+    // across its per-`IoType` sub-limiters, proportionally to the
+    // process-wide per-type traffic observed by the getter. A type without
+    // observed traffic is left unlimited so its first burst is only paced by
+    // the aggregate limiter, and an infinite aggregate limit resets all
+    // sub-limits to unlimited as well.
+    fn distribute_io_type_limits(&mut self, bg_group_stats: &[GroupStats]) {
+        if self.dry_run
+            || bg_group_stats
+                .iter()
+                .all(|g| !g.limiter.io_type_limits_enabled())
+        {
+            return;
+        }
+        let breakdown = self.resource_quota_getter.get_io_breakdown();
+        let rates = breakdown.map(|b| (b.read + b.write) as f64);
+        let total: f64 = rates.iter().sum();
+        for g in bg_group_stats {
+            if !g.limiter.io_type_limits_enabled() {
+                continue;
+            }
+            let limit = g.limiter.get_limiter(ResourceType::Io).get_rate_limit();
+            for io_type in IoType::iter() {
+                let sub = g.limiter.get_io_type_limiter(io_type).unwrap();
+                let rate = rates[io_type as usize];
+                if !limit.is_finite() || total <= f64::EPSILON || rate <= f64::EPSILON {
+                    sub.set_rate_limit(f64::INFINITY);
+                } else {
+                    sub.set_rate_limit(limit * rate / total);
+                }
+            }
+        }
+    }
+
+    fn do_adjust(
+        &mut self,
+        resource_type: ResourceType,
+        mut resource_stats: ResourceUsageStats,
+        now: Instant,
+        dur_secs: f64,
+        utilization_limit: u64,
+        bg_group_stats: &mut [GroupStats],
+    ) {
+        // smooth the instantaneous usage with an EMA to reduce thrashing on
+        // bursty workloads. The first sample initializes the EMA directly.
+        if let Some(alpha) = self.ema_alpha {
+            let prev = self.smoothed_used[resource_type as usize];
+            let smoothed = if prev.is_nan() {
+                resource_stats.current_used
+            } else {
+                alpha * resource_stats.current_used + (1.0 - alpha) * prev
+            };
+            self.smoothed_used[resource_type as usize] = smoothed;
+            resource_stats.current_used = smoothed;
+        }
+        self.last_adjustments
+            .retain(|a| a.resource_type != resource_type);
+        self.last_adjustment_summaries[resource_type as usize] = None;
+        // an infinite total quota means the resource is explicitly
+        // unconfigured, so all groups are unlimited.
+        if resource_stats.total_quota.is_infinite() {
+            self.set_groups_unlimited(resource_type, bg_group_stats);
+            return;
+        }
+        // a zero total quota means the stats provider cannot measure the
+        // resource, treat it as unlimited as well.
+        if resource_stats.total_quota <= f64::EPSILON {
+            self.set_groups_unlimited(resource_type, bg_group_stats);
+            return;
+        }
+
+        // The group counters below are diffed over this adjust tick, but the
+        // provider may have averaged `current_used` over its own measurement
+        // window. Normalize the group deltas with the provider's window when
+        // it reports one, so the consumed rates compared against the quota
+        // describe the same period as `current_used`.
+        let measure_dur_secs = resource_stats
+            .window_secs
+            .filter(|window| *window > 0.0)
+            .unwrap_or(dur_secs);
+
+        let mut total_weight = 0.0;
+        let mut total_ru_quota = 0.0;
+        let mut background_consumed_total = 0.0;
+        let mut has_wait = false;
+        let mut total_wait_rate_us = 0.0;
+        for g in bg_group_stats.iter_mut() {
+            // pinned groups are outside the distribution, so they contribute
+            // no weight to it.
+            if !self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name) {
+                total_weight += g.weight;
+                total_ru_quota += g.ru_quota;
+            }
+            let total_stats = g.limiter.get_limit_statistics(resource_type);
+            let stats_delta = match self.prev_stats_by_group[resource_type as usize]
+                .insert(g.name.clone(), total_stats)
+            {
+                // the first observation of a group only primes the baseline:
+                // counting everything it accumulated before registration as
+                // one tick's consumption would massively overstate its rate.
+                None => GroupStatistics::default(),
+                // version changes means this is a brand new limiter, so no need to sub the old
+                // statistics.
+                Some(last_stats) if total_stats.version == last_stats.version => {
+                    total_stats - last_stats
+                }
+                Some(_) => total_stats,
+            };
+            BACKGROUND_RESOURCE_CONSUMPTION
+                .with_label_values(&[&g.name, resource_type.as_str()])
+                .inc_by(stats_delta.total_consumed);
+            if resource_type == ResourceType::Cpu {
+                BACKGROUND_TASKS_WAIT_DURATION
+                    .with_label_values(&[&g.name])
+                    .inc_by(stats_delta.total_wait_dur_us);
+            }
+
+            // with `max_groups_per_tick` capping the scan, a group may sit
+            // out several ticks between two visits and its counter delta
+            // spans its own sampling gap rather than this tick's interval;
+            // normalize over the gap in that case to keep the rate honest.
+            // An uncapped tick samples every group exactly at `dur_secs`,
+            // which keeps the provider-window normalization in effect.
+            let group_dur_secs = self.group_sample_times[resource_type as usize]
+                .insert(g.name.clone(), now)
+                .map_or(measure_dur_secs, |last| {
+                    let gap = now.saturating_duration_since(last).as_secs_f64();
+                    if gap > dur_secs {
+                        gap
+                    } else {
+                        measure_dur_secs
+                    }
+                });
+            let stats_per_sec = stats_delta / group_dur_secs;
+            background_consumed_total += stats_per_sec.total_consumed as f64;
+            BACKGROUND_CONSUMED_RATE_VEC
+                .with_label_values(&[&g.name, resource_type.as_str()])
+                .set(stats_per_sec.total_consumed as i64);
+            BACKGROUND_WAIT_RATE_VEC
+                .with_label_values(&[&g.name, resource_type.as_str()])
+                .set(stats_per_sec.total_wait_dur_us as i64);
+            g.stats_per_sec = stats_per_sec;
+            self.update_observed_peak(
+                resource_type,
+                &g.name,
+                Ru(stats_per_sec.total_consumed as f64)
+                    .to_raw(self.ru_cost_factor[resource_type as usize]),
+            );
+            if stats_per_sec.total_wait_dur_us > 0 {
+                has_wait = true;
+            }
+            total_wait_rate_us += stats_per_sec.total_wait_dur_us as f64;
+        }
+
+        let background_util =
+            (background_consumed_total / resource_stats.total_quota * 100.0) as u64;
+        BACKGROUND_TASK_RESOURCE_UTILIZATION_VEC
+            .with_label_values(&[resource_type.as_str()])
+            .set(background_util as i64);
+
+        // fast path if process cpu is low
+        let is_low_load =
+            resource_stats.current_used <= (resource_stats.total_quota * self.low_load_ratio);
+        let idx = resource_type as usize;
+        if is_low_load != self.low_load_active[idx] {
+            self.low_load_streaks[idx] += 1;
+            if self.low_load_streaks[idx] >= self.low_load_debounce {
+                self.low_load_active[idx] = is_low_load;
+                self.low_load_streaks[idx] = 0;
+            }
+        } else {
+            self.low_load_streaks[idx] = 0;
+        }
+        if self.low_load_active[idx] && !has_wait {
+            LOW_LOAD_FAST_PATH_COUNTER_VEC
+                .with_label_values(&[resource_type.as_str()])
+                .inc();
+            return;
+        }
+
+        // while the foreground is under pressure, freeze all increases: each
+        // group's new limit is capped at its current one so the background
+        // footprint can only shrink until the pressure clears.
+        let foreground_pressure = self.foreground_pressure.load(Ordering::Relaxed);
+        let util_limit_percent = (utilization_limit as f64 / 100.0).min(1.0);
+        // the available resource for background tasks is defined as:
+        // (total_resource_quota - foreground_task_used). foreground_task_used
+        // resource is calculated by: (resource_current_total_used -
+        // background_consumed_total). We reserve part of the free resources
+        // for foreground tasks in case the fore ground traffics increases.
+        // `background_consumed_total` is added back on top of the free
+        // resource, so a momentarily low `current_used` could push the sum
+        // beyond the total quota and over-grant the next tick. Cap the result
+        // at the headroom share of the total quota as well.
+        // heavy waiting while the machine still has free capacity means the
+        // limits were too tight for the actual demand, so widen the free
+        // share proportionally to the aggregate wait (at most doubled); the
+        // clamps below still bound the result.
+        let wait_relief = match self.wait_relief_factor {
+            Some(factor)
+                if has_wait && resource_stats.current_used < resource_stats.total_quota =>
+            {
+                1.0 + (CpuMicros(total_wait_rate_us).as_secs() * factor).min(1.0)
+            }
+            _ => 1.0,
+        };
+        let mut available_resource_rate = ((resource_stats.total_quota
+            - resource_stats.current_used
+            + background_consumed_total)
+            * self.headroom_factor
+            * wait_relief)
+            .min(resource_stats.total_quota * util_limit_percent)
+            .min(resource_stats.total_quota * self.headroom_factor)
+            .max(resource_stats.total_quota * self.low_load_ratio);
+        // apply the pinned rates first: a pinned group is excluded from the
+        // distribution below and its fixed rate is taken out of the shared
+        // pool up front.
+        let mut pinned_rate_total = 0.0;
+        for g in bg_group_stats.iter() {
+            let Some(rate) = self.fixed_rate_overrides[resource_type as usize]
+                .get(&g.name)
+                .copied()
+            else {
+                continue;
+            };
+            pinned_rate_total += rate;
+            if !self.dry_run {
+                let old_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
+                if let Some(cb) = &self.on_limit_change
+                    && (rate - old_limit).abs() > f64::EPSILON
+                {
+                    cb(&g.name, resource_type, old_limit, rate);
+                }
+                g.limiter.get_limiter(resource_type).set_rate_limit(rate);
+                BACKGROUND_QUOTA_LIMIT_VEC
+                    .with_label_values(&[&g.name, resource_type.as_str()])
+                    .set(rate as i64);
+            }
+            self.last_adjustments.push(GroupAdjustment {
+                name: g.name.clone(),
+                resource_type,
+                rate_limit: rate,
+                consumed_rate: g.stats_per_sec.total_consumed as f64,
+                wait_dur_us: g.stats_per_sec.total_wait_dur_us,
+            });
+        }
+        available_resource_rate = (available_resource_rate - pinned_rate_total).max(0.0);
+        // reserve the declared minimum rates of the participating groups up
+        // front so distributing the remainder cannot over-allocate the quota.
+        let reserved_floor: f64 = bg_group_stats
+            .iter()
+            .filter(|g| !self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name))
+            .filter_map(|g| self.group_floor(resource_type, g))
+            .sum();
+        available_resource_rate = (available_resource_rate - reserved_floor).max(0.0);
+        self.last_adjustment_summaries[resource_type as usize] = Some(ResourceAdjustmentSummary {
+            total_quota: resource_stats.total_quota,
+            current_used: resource_stats.current_used,
+            background_consumed_total,
+            available_quota: available_resource_rate,
+        });
+        let mut total_expected_cost = 0.0;
+        let ru_cost_factor = self.ru_cost_factor[resource_type as usize];
+        for g in bg_group_stats.iter_mut() {
+            // pinned groups were already handled above and must not inflate
+            // the expected demand of the distribution.
+            if self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name) {
+                g.expect_cost_rate = 0.0;
+                continue;
+            }
+            let mut rate_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
+            if rate_limit.is_infinite() {
+                rate_limit = 0.0;
+            }
+            // the wait time spent at the current limit converts into the RU
+            // demand it would have consumed had it not been throttled.
+            let group_expected_cost = Ru(g.stats_per_sec.total_consumed as f64
+                + CpuMicros(g.stats_per_sec.total_wait_dur_us as f64).as_secs() * rate_limit)
+            .to_raw(ru_cost_factor);
+            g.expect_cost_rate = group_expected_cost;
+            total_expected_cost += group_expected_cost;
+        }
+        // bias this tick's distribution toward groups that were under-served
+        // before: the accumulated debt is converted into extra weight at this
+        // tick's rate-per-weight, so a starved group's share grows by roughly
+        // its outstanding debt.
+        let rate_per_weight = available_resource_rate / total_weight;
+        let mut total_adjusted_weight = 0.0;
+        for g in bg_group_stats.iter_mut() {
+            if self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name) {
+                g.adjusted_weight = g.weight;
+                continue;
+            }
+            let debt = self.starvation_debt[resource_type as usize]
+                .get(&g.name)
+                .copied()
+                .unwrap_or(0.0);
+            g.adjusted_weight = if debt > 0.0 && rate_per_weight > 0.0 {
+                g.weight + debt / rate_per_weight
+            } else {
+                g.weight
+            };
+            total_adjusted_weight += g.adjusted_weight;
+        }
+        let mut total_weight = total_adjusted_weight;
+        // the fair share baseline the debt is settled against is purely
+        // `ru_quota`-proportional, so a low-priority group starved by the
+        // priority weighting accrues debt as well.
+        let fair_share_per_ru = if total_ru_quota > 0.0 {
+            available_resource_rate / total_ru_quota
+        } else {
+            0.0
+        };
+        // sort groups by the expect_cost_rate per effective weight. Use
+        // `total_cmp` to be robust against NaN so the sort never panics.
+        bg_group_stats.sort_by(|g1, g2| {
+            (g1.expect_cost_rate / g1.adjusted_weight)
+                .total_cmp(&(g2.expect_cost_rate / g2.adjusted_weight))
+        });
+
+        // quota is enough, group is allowed to got more resource then its share by ru.
+        // e.g. Given a totol resource of 10000, and ("name", ru_quota, expected_rate)
+        // of:  (rg1, 2000, 3000), (rg2, 3000, 1000), (rg3, 5000, 5000)
+        // then after the previous sort, the order is rg2, rg3, rg1 and the handle order
+        // is rg1, rg3, rg2 so the final rate limit assigned is: (rg1, 3000),
+        // (rg3, 5833(7000/6*5)), (rg2, 1166(7000/6*1))
+        if total_expected_cost <= available_resource_rate {
+            for g in bg_group_stats.iter().rev() {
+                if self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name) {
+                    continue;
+                }
+                let old_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
+                // the integral term biases the limit of a group whose
+                // assigned limit lagged its observed consumption over the
+                // previous ticks, and shaves over-grants left unconsumed.
+                let integral_term = self.integral_gain
+                    * self.integral_errors[resource_type as usize]
+                        .get(&g.name)
+                        .copied()
+                        .unwrap_or(0.0);
+                // Subtracting the handled groups' weights can run
+                // `total_weight` down to (floating point) zero before the
+                // last group, and dividing by it would grant an infinite
+                // share. Hand the remaining pool over directly instead.
+                let share = if total_weight > f64::EPSILON {
+                    available_resource_rate / total_weight * g.adjusted_weight
+                } else {
+                    available_resource_rate.max(0.0)
+                };
+                let mut limit = self.clamp_limit_change(
+                    old_limit,
+                    (g.expect_cost_rate.max(share) + integral_term).max(0.0),
+                );
+                if foreground_pressure {
+                    limit = limit.min(old_limit);
+                }
+                // a group far below its historical demand is not granted the
+                // full share, the freed part stays in the pool for the others.
+                limit = self.peak_capped_limit(resource_type, &g.name, limit);
+                // the ceiling of the group's workload class, if any, applies
+                // on top of the group-level caps.
+                limit = self.class_capped_limit(resource_type, g, limit);
+                // likewise, a group that left its limit chronically unused is
+                // trimmed toward its observed usage.
+                let consumed_rate =
+                    Ru(g.stats_per_sec.total_consumed as f64).to_raw(ru_cost_factor);
+                self.update_under_util_streak(resource_type, &g.name, consumed_rate, old_limit);
+                limit = self.under_util_capped_limit(
+                    resource_type,
+                    &g.name,
+                    consumed_rate,
+                    resource_stats.total_quota,
+                    limit,
+                );
+                // a group entering throttling for the first time is eased
+                // toward the target instead of cut to it in one step.
+                limit =
+                    self.soft_start_limit(resource_type, &g.name, old_limit, consumed_rate, limit);
+                // the shared pool is charged with the unfloored limit; the
+                // floor itself was already reserved before distribution.
+                available_resource_rate -= limit;
+                total_weight -= g.adjusted_weight;
+                if let Some(floor) = self.group_floor(resource_type, g) {
+                    limit = limit.max(floor);
+                }
+                self.update_starvation_debt(
+                    resource_type,
+                    &g.name,
+                    fair_share_per_ru * g.ru_quota,
+                    limit,
+                );
+                self.update_throttled_duration(
+                    resource_type,
+                    &g.name,
+                    fair_share_per_ru * g.ru_quota,
+                    g.expect_cost_rate,
+                    limit,
+                    dur_secs,
+                );
+                self.update_integral_error(resource_type, &g.name, consumed_rate, limit);
+                if !self.dry_run {
+                    if let Some(cb) = &self.on_limit_change
+                        && (limit - old_limit).abs() > f64::EPSILON
+                    {
+                        cb(&g.name, resource_type, old_limit, limit);
+                    }
+                    g.limiter.get_limiter(resource_type).set_rate_limit(limit);
+                    BACKGROUND_QUOTA_LIMIT_VEC
+                        .with_label_values(&[&g.name, resource_type.as_str()])
+                        .set(limit as i64);
+                }
+                self.last_adjustments.push(GroupAdjustment {
+                    name: g.name.clone(),
+                    resource_type,
+                    rate_limit: limit,
+                    consumed_rate: g.stats_per_sec.total_consumed as f64,
+                    wait_dur_us: g.stats_per_sec.total_wait_dur_us,
+                });
+            }
+            // whatever the caps and trims above held back stays in the pool
+            // and is reported as the remaining headroom of this type.
+            self.unallocated_quota[resource_type as usize] = available_resource_rate.max(0.0);
+            self.apply_total_rate_cap(resource_type, bg_group_stats);
+            return;
+        }
+
+        // quota is not enough, assign by share
+        // e.g. Given a totol resource of 10000, and ("name", ru_quota, expected_rate)
+        // of:  (rg1, 2000, 1000), (rg2, 3000, 5000), (rg3, 5000, 7000)
+        // then after the previous sort, the order is rg1, rg3, rg2, and handle order is
+        // rg1, rg3, rg2 so the final rate limit assigned is: (rg1, 1000), (rg3,
+        // 5250(9000/12*7)), (rg2, 3750(9000/12*5))
+        //
+        // the greedy order above consistently hands the slack released by the
+        // light head groups to the same groups right behind them, so when
+        // enabled, the starting index rotates every tick to let a different
+        // group pick first over time.
+        let rotation = if self.starvation_rotation && !bg_group_stats.is_empty() {
+            let cursor = self.rotation_cursors[resource_type as usize];
+            self.rotation_cursors[resource_type as usize] = cursor.wrapping_add(1);
+            cursor % bg_group_stats.len()
+        } else {
+            0
+        };
+        for g in bg_group_stats
+            .iter()
+            .cycle()
+            .skip(rotation)
+            .take(bg_group_stats.len())
+        {
+            if self.fixed_rate_overrides[resource_type as usize].contains_key(&g.name) {
+                continue;
+            }
+            let old_limit = g.limiter.get_limiter(resource_type).get_rate_limit();
+            // under scarcity the integral term is what lets a group starved
+            // for several ticks grow beyond its instantaneous share.
+            let integral_term = self.integral_gain
+                * self.integral_errors[resource_type as usize]
+                    .get(&g.name)
+                    .copied()
+                    .unwrap_or(0.0);
+            // Like the quota-enough branch above, guard against a vanished
+            // total weight and hand the remaining pool over directly.
+            let share = if total_weight > f64::EPSILON {
+                available_resource_rate / total_weight * g.adjusted_weight
+            } else {
+                available_resource_rate.max(0.0)
+            };
+            let share = match self.scale_down_policy {
+                ScaleDownPolicy::Linear => share,
+                ScaleDownPolicy::Convex { exponent }
+                    if g.expect_cost_rate > share && share > 0.0 =>
+                {
+                    // the further the demand overshoots the fair share, the
+                    // harder the group is cut; the yielded quota stays in
+                    // the pool for the groups handled after it.
+                    share * (share / g.expect_cost_rate).powf(exponent)
+                }
+                ScaleDownPolicy::Convex { .. } => share,
+            };
+            let mut limit = self.clamp_limit_change(
+                old_limit,
+                (g.expect_cost_rate.min(share) + integral_term).max(0.0),
+            );
+            if foreground_pressure {
+                limit = limit.min(old_limit);
+            }
+            // the peak-derived ceiling applies under scarcity as well, the
+            // freed share flows to the remaining groups.
+            limit = self.peak_capped_limit(resource_type, &g.name, limit);
+            // so does the ceiling of the group's workload class.
+            limit = self.class_capped_limit(resource_type, g, limit);
+            // so does the trim of a chronically unused limit.
+            let consumed_rate = Ru(g.stats_per_sec.total_consumed as f64).to_raw(ru_cost_factor);
+            self.update_under_util_streak(resource_type, &g.name, consumed_rate, old_limit);
+            limit = self.under_util_capped_limit(
+                resource_type,
+                &g.name,
+                consumed_rate,
+                resource_stats.total_quota,
+                limit,
+            );
+            // the soft start applies under scarcity as well: the first
+            // finite limit after a group ran uncapped starts near its
+            // recent consumption rather than at the computed share.
+            limit = self.soft_start_limit(resource_type, &g.name, old_limit, consumed_rate, limit);
+            available_resource_rate -= limit;
+            total_weight -= g.adjusted_weight;
+            if let Some(floor) = self.group_floor(resource_type, g) {
+                limit = limit.max(floor);
+            }
+            self.update_starvation_debt(
+                resource_type,
+                &g.name,
+                fair_share_per_ru * g.ru_quota,
+                limit,
+            );
+            self.update_throttled_duration(
+                resource_type,
+                &g.name,
+                fair_share_per_ru * g.ru_quota,
+                g.expect_cost_rate,
+                limit,
+                dur_secs,
+            );
+            self.update_integral_error(resource_type, &g.name, consumed_rate, limit);
+            if !self.dry_run {
+                if let Some(cb) = &self.on_limit_change
+                    && (limit - old_limit).abs() > f64::EPSILON
+                {
+                    cb(&g.name, resource_type, old_limit, limit);
+                }
+                g.limiter.get_limiter(resource_type).set_rate_limit(limit);
+                BACKGROUND_QUOTA_LIMIT_VEC
+                    .with_label_values(&[&g.name, resource_type.as_str()])
+                    .set(limit as i64);
+            }
+            self.last_adjustments.push(GroupAdjustment {
+                name: g.name.clone(),
+                resource_type,
+                rate_limit: limit,
+                consumed_rate: g.stats_per_sec.total_consumed as f64,
+                wait_dur_us: g.stats_per_sec.total_wait_dur_us,
+            });
+        }
+        self.unallocated_quota[resource_type as usize] = available_resource_rate.max(0.0);
+        self.apply_total_rate_cap(resource_type, bg_group_stats);
+    }
+
+    // accumulate the integral error of one group for this tick: the old
+    // error decays and the gap between the observed consumption and the
+    // just-assigned limit is added on top. While the integral term is
+    // disabled the state is dropped instead, so a later enablement starts
+    // from a clean slate.
+    fn update_integral_error(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        consumed_rate: f64,
+        limit: f64,
+    ) {
+        if self.integral_gain == 0.0 {
+            self.integral_errors[resource_type as usize].remove(name);
+            return;
+        }
+        let error = self.integral_errors[resource_type as usize]
+            .entry(name.to_owned())
+            .or_insert(0.0);
+        *error = *error * DEFAULT_INTEGRAL_DECAY + (consumed_rate - limit);
+    }
+
+    // settle the debt of one group for this tick: the old debt decays and
+    // the part of the `ru_quota`-proportional fair share left unserved by
+    // the just-assigned limit is added on top.
+    fn update_starvation_debt(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        fair_share: f64,
+        limit: f64,
+    ) {
+        let Some(decay) = self.starvation_debt_decay else {
+            return;
+        };
+        let debt = self.starvation_debt[resource_type as usize]
+            .entry(name.to_owned())
+            .or_insert(0.0);
+        *debt = *debt * decay + (fair_share - limit).max(0.0);
+    }
+
+    // Charge the adjustment window that just closed to the group's
+    // throttled time whenever the newly assigned limit stays below its
+    // `ru_quota`-proportional fair share while the group demands more. A
+    // group idling below its fair share on its own is not throttled.
+    fn update_throttled_duration(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        fair_share: f64,
+        expect_cost_rate: f64,
+        limit: f64,
+        dur_secs: f64,
+    ) {
+        if limit >= fair_share || limit >= expect_cost_rate {
+            return;
+        }
+        *self.throttled_durations[resource_type as usize]
+            .entry(name.to_owned())
+            .or_default() += Duration::from_secs_f64(dur_secs);
+    }
+
+    // track the rolling peak consumed rate of one group: the stored peak
+    // decays every tick so an old burst ages out of the window, and the
+    // current rate raises it back whenever it is higher. While the
+    // peak-derived ceiling is disabled the state is dropped instead, so a
+    // later enablement starts from a clean slate.
+    fn update_observed_peak(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        consumed_rate: f64,
+    ) {
+        if self.peak_cap_ratio.is_none() {
+            self.observed_peaks[resource_type as usize].remove(name);
+            return;
+        }
+        let peak = self.observed_peaks[resource_type as usize]
+            .entry(name.to_owned())
+            .or_insert(0.0);
+        *peak = (*peak * DEFAULT_PEAK_DECAY).max(consumed_rate);
+    }
+
+    // the effective floor of one group: an explicit per-group floor wins,
+    // otherwise the floor of the group's workload class applies, if any.
+    fn group_floor(&self, resource_type: ResourceType, g: &GroupStats) -> Option<f64> {
+        self.min_rate_floors[resource_type as usize]
+            .get(&g.name)
+            .copied()
+            .or_else(|| {
+                self.class_policies.get(g.class.as_ref()?)?.min_rate[resource_type as usize]
+            })
+    }
+
+    // cap the limit at the ceiling of the group's workload class; a group
+    // without a class or a class without a cap is left as is.
+    fn class_capped_limit(&self, resource_type: ResourceType, g: &GroupStats, limit: f64) -> f64 {
+        let cap = g
+            .class
+            .as_ref()
+            .and_then(|class| self.class_policies.get(class))
+            .and_then(|policy| policy.max_rate[resource_type as usize]);
+        match cap {
+            Some(cap) => limit.min(cap),
+            None => limit,
+        }
+    }
+
+    // cap the limit at the peak-derived ceiling of one group. A group
+    // without any observed consumption yet is left uncapped, so a cold
+    // group still starts up at full speed.
+    fn peak_capped_limit(&self, resource_type: ResourceType, name: &str, limit: f64) -> f64 {
+        let Some(ratio) = self.peak_cap_ratio else {
+            return limit;
+        };
+        match self.observed_peaks[resource_type as usize].get(name) {
+            Some(peak) if *peak > 0.0 => limit.min(*peak * ratio),
+            _ => limit,
+        }
+    }
+
+    // track the consecutive ticks one group consumed below the configured
+    // share of its previously assigned limit. An infinite limit has no
+    // unused budget to reclaim and resets the streak. While the feedback is
+    // disabled the state is dropped instead, so a later enablement starts
+    // from a clean slate.
+    fn update_under_util_streak(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        consumed_rate: f64,
+        limit: f64,
+    ) {
+        let Some(ratio) = self.under_util_ratio else {
+            self.under_util_streaks[resource_type as usize].remove(name);
+            return;
+        };
+        let streak = self.under_util_streaks[resource_type as usize]
+            .entry(name.to_owned())
+            .or_insert(0);
+        if limit.is_finite() && limit > 0.0 && consumed_rate < limit * ratio {
+            *streak += 1;
+        } else {
+            *streak = 0;
+        }
+    }
+
+    // trim the limit of a group whose assigned limit went chronically
+    // unused down toward its observed usage; the freed part stays in the
+    // pool for the groups handled after it. The trimmed limit keeps the
+    // under-utilization threshold's worth of headroom above the usage and
+    // is floored at the low-load share of the total quota, so the group can
+    // still ramp back up once its demand returns.
+    fn under_util_capped_limit(
+        &self,
+        resource_type: ResourceType,
+        name: &str,
+        consumed_rate: f64,
+        total_quota: f64,
+        limit: f64,
+    ) -> f64 {
+        let Some(ratio) = self.under_util_ratio else {
+            return limit;
+        };
+        match self.under_util_streaks[resource_type as usize].get(name) {
+            Some(streak) if *streak >= self.under_util_ticks => {
+                let floor = total_quota * self.low_load_ratio;
+                limit.min((consumed_rate / ratio).max(floor))
+            }
+            _ => limit,
+        }
+    }
+
+    // ease a group whose limit just turned finite from its recent
+    // consumption toward the computed target over `soft_start_ticks` ticks,
+    // so newly throttled in-flight tasks are not cut to the target in one
+    // step. The ramp advances by one tick per call and lands exactly on the
+    // target on its last tick; a group transitioning back to an infinite
+    // limit mid-ramp simply restarts the ramp on its next finite limit.
+    fn soft_start_limit(
+        &mut self,
+        resource_type: ResourceType,
+        name: &str,
+        old_limit: f64,
+        consumed_rate: f64,
+        target: f64,
+    ) -> f64 {
+        let total = self.soft_start_ticks;
+        if total == 0 || !target.is_finite() {
+            return target;
+        }
+        let ramp_map = &mut self.soft_start_remaining[resource_type as usize];
+        if old_limit.is_infinite() {
+            ramp_map.insert(name.to_owned(), total);
+        }
+        let Some(left) = ramp_map.get_mut(name) else {
+            return target;
+        };
+        let fraction = (total + 1 - *left) as f64 / total as f64;
+        *left -= 1;
+        if *left == 0 {
+            ramp_map.remove(name);
+        }
+        consumed_rate + (target - consumed_rate) * fraction
+    }
+
+    // scale the just-assigned limits down proportionally when their sum
+    // exceeds the configured absolute ceiling of the resource type. Groups
+    // with an infinite limit are left alone, and so are pinned groups since
+    // their fixed rate is an explicit promise.
+    fn apply_total_rate_cap(&mut self, resource_type: ResourceType, bg_group_stats: &[GroupStats]) {
+        let Some(cap) = self.max_total_background_rate[resource_type as usize] else {
+            return;
+        };
+        let pinned = &self.fixed_rate_overrides[resource_type as usize];
+        let total: f64 = self
+            .last_adjustments
+            .iter()
+            .filter(|a| {
+                a.resource_type == resource_type
+                    && a.rate_limit.is_finite()
+                    && !pinned.contains_key(&a.name)
+            })
+            .map(|a| a.rate_limit)
+            .sum();
+        if total <= cap {
+            return;
+        }
+        let scale = cap / total;
+        for a in self
+            .last_adjustments
+            .iter_mut()
+            .filter(|a| {
+                a.resource_type == resource_type
+                    && a.rate_limit.is_finite()
+                    && !pinned.contains_key(&a.name)
+            })
+        {
+            a.rate_limit *= scale;
+        }
+        if self.dry_run {
+            return;
+        }
+        for g in bg_group_stats {
+            if pinned.contains_key(&g.name) {
+                continue;
+            }
+            let limiter = g.limiter.get_limiter(resource_type);
+            let limit = limiter.get_rate_limit();
+            if !limit.is_finite() {
+                continue;
+            }
+            let scaled = limit * scale;
+            if let Some(cb) = &self.on_limit_change {
+                cb(&g.name, resource_type, limit, scaled);
+            }
+            limiter.set_rate_limit(scaled);
+            BACKGROUND_QUOTA_LIMIT_VEC
+                .with_label_values(&[&g.name, resource_type.as_str()])
+                .set(scaled as i64);
+        }
+    }
+}
+
+// map the resource group priority to a fairness multiplier so a
+// high-priority background group receives a larger share of the available
+// quota at equal RU settings. The raw priority value (1..=16) falls into the
+// same (low, medium, high) buckets as `TaskPriority`:
+// low -> 0.5, medium (or unset) -> 1.0, high -> 2.0.
+fn priority_factor(priority: u32) -> f64 {
+    match TaskPriority::from(priority) {
+        TaskPriority::High => 2.0,
+        TaskPriority::Medium => 1.0,
+        TaskPriority::Low => 0.5,
+    }
+}
+
+struct GroupStats {
+    name: String,
+    // the workload class of the group, i.e. the first job type of its
+    // background settings; `None` when the group declares no job type.
+    class: Option<String>,
+    limiter: Arc<ResourceLimiter>,
+    ru_quota: f64,
+    // the effective weight used to split the available quota, i.e.
+    // `ru_quota * priority_factor(priority)`. `ru_quota` is kept raw for
+    // accounting purposes.
+    weight: f64,
+    // the distribution weight of the current tick, i.e. `weight` plus the
+    // extra weight converted from the group's starvation debt. Recomputed
+    // per resource type in `do_adjust`.
+    adjusted_weight: f64,
+    stats_per_sec: GroupStatistics,
+    expect_cost_rate: f64,
+}
+
+/// PriorityLimiterAdjustWorker automically adjust the quota of each priority
+/// limiter based on the statistics data during a certain period of time.
+/// In general, caller should call this function in a fixed interval.
+pub struct PriorityLimiterAdjustWorker<R> {
+    resource_ctl: Arc<ResourceGroupManager>,
+    trackers: [PriorityLimiterStatsTracker; TaskPriority::PRIORITY_COUNT],
+    resource_quota_getter: R,
+    last_adjust_time: Instant,
+    is_last_low_cpu: bool,
+    is_last_single_group: bool,
+}
+
+impl PriorityLimiterAdjustWorker<SysQuotaGetter> {
+    pub fn new(resource_ctl: Arc<ResourceGroupManager>) -> Self {
+        let resource_quota_getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: f64::INFINITY,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: fetch_net_bytes().unwrap_or_default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: PathBuf::from(DEFAULT_CGROUP_PATH),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        Self::with_quota_getter(resource_ctl, resource_quota_getter)
+    }
+}
+
+impl<R: ResourceStatsProvider> PriorityLimiterAdjustWorker<R> {
+    fn with_quota_getter(
+        resource_ctl: Arc<ResourceGroupManager>,
+        resource_quota_getter: R,
+    ) -> Self {
+        let limiters = resource_ctl.get_priority_resource_limiters();
+        let priorities = TaskPriority::priorities();
+        let trackers = std::array::from_fn(|i| {
+            PriorityLimiterStatsTracker::new(limiters[i].clone(), priorities[i].as_str())
+        });
+        Self {
+            resource_ctl,
+            trackers,
+            resource_quota_getter,
+            last_adjust_time: Instant::now_coarse(),
+            is_last_low_cpu: true,
+            is_last_single_group: true,
+        }
+    }
+    pub fn adjust(&mut self) {
+        let now = Instant::now_coarse();
+        let dur = now.saturating_duration_since(self.last_adjust_time);
+        if dur < Duration::from_secs(1) {
+            warn!("adjust duration too small, skip adjustment."; "dur" => ?dur);
+            return;
+        }
+        self.last_adjust_time = now;
+
+        // fast path for only the default resource group which means resource
+        // control is not used at all.
+        let group_count = self.resource_ctl.get_group_count();
+        if group_count == 1 {
+            if self.is_last_single_group {
+                return;
+            }
+            self.is_last_single_group = true;
+            self.trackers.iter().skip(1).for_each(|t| {
+                t.limiter
+                    .get_limiter(ResourceType::Cpu)
+                    .set_rate_limit(f64::INFINITY)
+            });
+            return;
+        }
+        self.is_last_single_group = false;
+
+        let stats: [_; TaskPriority::PRIORITY_COUNT] =
+            array::from_fn(|i| self.trackers[i].get_and_update_last_stats(dur.as_secs_f64()));
+
+        let process_cpu_stats = match self
+            .resource_quota_getter
+            .get_current_stats(ResourceType::Cpu)
+        {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("get process total cpu failed; skip adjusment."; "err" => ?e);
+                return;
+            }
+        };
+
+        if process_cpu_stats.current_used < process_cpu_stats.total_quota * 0.3 {
+            if self.is_last_low_cpu {
+                return;
+            }
+            self.is_last_low_cpu = true;
+            self.trackers.iter().skip(1).for_each(|t| {
+                t.limiter
+                    .get_limiter(ResourceType::Cpu)
+                    .set_rate_limit(f64::INFINITY);
+                // 0 represent infinity
+                PRIORITY_QUOTA_LIMIT_VEC
+                    .get_metric_with_label_values(&[t.priority])
+                    .unwrap()
+                    .set(0);
+            });
+            return;
+        }
+        self.is_last_low_cpu = false;
+
+        let total_cpus: f64 = stats.iter().map(|s| s.cpu_secs).sum();
+        let max_cpus = stats.iter().map(|s| s.cpu_secs).fold(0.0, f64::max);
+        // there is only 1 active priority, do not restrict.
+        if total_cpus * 0.99 <= max_cpus {
+            self.trackers
+                .iter()
+                .skip(1)
+                .for_each(|t: &PriorityLimiterStatsTracker| {
+                    t.limiter
+                        .get_limiter(ResourceType::Cpu)
+                        .set_rate_limit(f64::INFINITY)
+                });
+            return;
+        }
+
+        let cpu_duration: [_; TaskPriority::PRIORITY_COUNT] = array::from_fn(|i| stats[i].cpu_secs);
+        let real_cpu_total: f64 = cpu_duration.iter().sum();
+
+        let available_quota_percentage = self
+            .resource_ctl
+            .get_config()
+            .value()
+            .priority_ctl_strategy
+            .to_resource_util_percentage();
+        let expect_pool_cpu_total = real_cpu_total
+            * (process_cpu_stats.total_quota * available_quota_percentage)
+            / process_cpu_stats.current_used;
+        let mut limits = [0.0; 2];
+        let level_expected: [_; TaskPriority::PRIORITY_COUNT] =
+            array::from_fn(|i| stats[i].cpu_secs + stats[i].wait_secs);
+        // substract the cpu time usage for priority high.
+        let mut expect_cpu_time_total = expect_pool_cpu_total - level_expected[0];
+
+        // still reserve a minimal cpu quota
+        let minimal_quota = CpuMicros(process_cpu_stats.total_quota).as_secs() * 0.1;
+        for i in 1..self.trackers.len() {
+            if expect_cpu_time_total < minimal_quota {
+                expect_cpu_time_total = minimal_quota;
+            }
+            let limit = CpuMicros::from_secs(expect_cpu_time_total).0;
+            self.trackers[i]
+                .limiter
+                .get_limiter(ResourceType::Cpu)
+                .set_rate_limit(limit);
+            PRIORITY_QUOTA_LIMIT_VEC
+                .get_metric_with_label_values(&[self.trackers[i].priority])
+                .unwrap()
+                .set(limit as i64);
+            limits[i - 1] = limit;
+            expect_cpu_time_total -= level_expected[i];
+        }
+        debug!("adjsut cpu limiter by priority"; "cpu_quota" => process_cpu_stats.total_quota,
+            "process_cpu" => process_cpu_stats.current_used, "expected_cpu" => ?level_expected,
+            "cpu_costs" => ?cpu_duration, "limits" => ?limits,
+            "limit_cpu_total" => expect_pool_cpu_total, "pool_cpu_cost" => real_cpu_total);
+    }
+}
+
+#[derive(Debug)]
+struct LimiterStats {
+    // QuotaLimiter consumed cpu secs in total
+    cpu_secs: f64,
+    // QuotaLimiter waited secs in total.
+    wait_secs: f64,
+}
+
+struct HistogramTracker {
+    metrics: Histogram,
+    last_sum: f64,
+    last_count: u64,
+}
+
+impl HistogramTracker {
+    fn new(metrics: Histogram) -> Self {
+        let last_sum = metrics.get_sample_sum();
+        let last_count = metrics.get_sample_count();
+        Self {
+            metrics,
+            last_sum,
+            last_count,
+        }
+    }
+
+    fn get_and_upate_statistics(&mut self) -> (f64, u64) {
+        let cur_sum = self.metrics.get_sample_sum();
+        let cur_count = self.metrics.get_sample_count();
+        let res = (cur_sum - self.last_sum, cur_count - self.last_count);
+        self.last_sum = cur_sum;
+        self.last_count = cur_count;
+        res
+    }
+}
+
+struct PriorityLimiterStatsTracker {
+    priority: &'static str,
+    limiter: Arc<ResourceLimiter>,
+    last_stats: GroupStatistics,
+    // unified-read-pool and schedule-worker-pool wait duration metrics.
+    task_wait_dur_trakcers: [HistogramTracker; 2],
+}
+
+impl PriorityLimiterStatsTracker {
+    fn new(limiter: Arc<ResourceLimiter>, priority: &'static str) -> Self {
+        let task_wait_dur_trakcers =
+            ["unified-read-pool", "sched-worker-priority"].map(|pool_name| {
+                HistogramTracker::new(
+                    YATP_POOL_SCHEDULE_WAIT_DURATION_VEC
+                        .get_metric_with_label_values(&[pool_name, priority])
+                        .unwrap(),
+                )
+            });
+        let last_stats = limiter.get_limit_statistics(ResourceType::Cpu);
+        Self {
+            priority,
+            limiter,
+            last_stats,
+            task_wait_dur_trakcers,
+        }
+    }
+
+    fn get_and_update_last_stats(&mut self, dur_secs: f64) -> LimiterStats {
+        let cur_stats = self.limiter.get_limit_statistics(ResourceType::Cpu);
+        let stats_delta = cur_stats - self.last_stats;
+        self.last_stats = cur_stats;
+        PRIORITY_CPU_TIME_VEC
+            .with_label_values(&[self.priority])
+            .inc_by(stats_delta.total_consumed);
+        let stats_per_sec = stats_delta / dur_secs;
+
+        let wait_stats: [_; 2] =
+            array::from_fn(|i| self.task_wait_dur_trakcers[i].get_and_upate_statistics());
+        let schedule_wait_dur_secs = wait_stats.iter().map(|s| s.0).sum::<f64>() / dur_secs;
+        let expected_wait_dur_secs =
+            stats_per_sec.request_count as f64 * MINIMAL_SCHEDULE_WAIT_SECS;
+        let normed_schedule_wait_dur_secs =
+            (schedule_wait_dur_secs - expected_wait_dur_secs).max(0.0);
+        LimiterStats {
+            cpu_secs: CpuMicros(stats_per_sec.total_consumed as f64).as_secs(),
+            wait_secs: CpuMicros(stats_per_sec.total_wait_dur_us as f64).as_secs()
+                + normed_schedule_wait_dur_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{resource_group::tests::*, resource_limiter::QuotaLimiter};
+
+    struct TestResourceStatsProvider {
+        cpu_total: f64,
+        cpu_used: f64,
+        io_total: f64,
+        io_used: f64,
+        mem_total: f64,
+        mem_used: f64,
+        // return an error from `get_current_stats` for this resource type.
+        fail_type: Option<ResourceType>,
+        health: ProviderHealth,
+        // the measurement window attached to the emitted cpu/io samples.
+        window_secs: Option<f64>,
+        // the per-`IoType` rates reported by `get_io_breakdown`.
+        io_breakdown: [IoBytes; IoType::COUNT],
+    }
+
+    impl TestResourceStatsProvider {
+        fn new(cpu_total: f64, io_total: f64) -> Self {
+            Self {
+                cpu_total,
+                cpu_used: 0.0,
+                io_total,
+                io_used: 0.0,
+                mem_total: 0.0,
+                mem_used: 0.0,
+                fail_type: None,
+                health: ProviderHealth::Healthy,
+                window_secs: None,
+                io_breakdown: [IoBytes::default(); IoType::COUNT],
+            }
+        }
+    }
+
+    impl ResourceStatsProvider for TestResourceStatsProvider {
+        fn get_current_stats(&mut self, t: ResourceType) -> IoResult<ResourceUsageStats> {
+            if Some(t) == self.fail_type {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "stats provider failed",
+                ));
+            }
+            match t {
+                ResourceType::Cpu => Ok(ResourceUsageStats {
+                    total_quota: self.cpu_total * MICROS_PER_SEC,
+                    current_used: self.cpu_used * MICROS_PER_SEC,
+                    window_secs: self.window_secs,
+                }),
+                ResourceType::Io => Ok(ResourceUsageStats {
+                    total_quota: self.io_total,
+                    current_used: self.io_used,
+                    window_secs: self.window_secs,
+                }),
+                // report a zero quota so the worker keeps the net limiter
+                // unlimited in tests.
+                ResourceType::Net => Ok(ResourceUsageStats {
+                    total_quota: 0.0,
+                    current_used: 0.0,
+                    window_secs: None,
+                }),
+                ResourceType::Mem => Ok(ResourceUsageStats {
+                    total_quota: self.mem_total,
+                    current_used: self.mem_used,
+                    window_secs: None,
+                }),
+            }
+        }
+
+        fn health(&self) -> ProviderHealth {
+            self.health.clone()
+        }
+
+        fn get_io_breakdown(&mut self) -> [IoBytes; IoType::COUNT] {
+            self.io_breakdown
+        }
+    }
+
+    // Align the worker's per-type cadence with `dur` and backdate every
+    // adjustment timer by it, so the next `adjust_quota` call is due for
+    // every resource type.
+    fn elapse_adjust_interval<R: ResourceStatsProvider>(
+        worker: &mut GroupQuotaAdjustWorker<R>,
+        dur: Duration,
+    ) {
+        worker.set_adjust_interval(dur);
+        worker.last_adjust_time = [Instant::now_coarse() - dur; ResourceType::COUNT];
+    }
+
+    // A time-driven provider modeling a disk whose bandwidth degrades over
+    // time, e.g. throttling under thermal load: every advance of its internal
+    // clock decays the reported IO `total_quota` by a fixed factor, so tests
+    // can validate the worker tracks a moving ceiling instead of a static one.
+    struct DegradingDiskStatsProvider {
+        inner: TestResourceStatsProvider,
+        base_io_total: f64,
+        decay_per_tick: f64,
+        ticks: i32,
+    }
+
+    impl DegradingDiskStatsProvider {
+        fn new(cpu_total: f64, io_total: f64, decay_per_tick: f64) -> Self {
+            Self {
+                inner: TestResourceStatsProvider::new(cpu_total, io_total),
+                base_io_total: io_total,
+                decay_per_tick,
+                ticks: 0,
+            }
+        }
+
+        // Advance the internal clock by one tick, degrading the modeled disk
+        // bandwidth by another `decay_per_tick`.
+        fn advance_clock(&mut self) {
+            self.ticks += 1;
+        }
+    }
+
+    impl ResourceStatsProvider for DegradingDiskStatsProvider {
+        fn get_current_stats(&mut self, t: ResourceType) -> IoResult<ResourceUsageStats> {
+            self.inner.io_total = self.base_io_total * self.decay_per_tick.powi(self.ticks);
+            self.inner.get_current_stats(t)
+        }
+
+        fn health(&self) -> ProviderHealth {
+            self.inner.health()
+        }
+    }
+
+    #[test]
+    fn test_adjust_resource_limiter() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let rg1 = new_resource_group_ru("test".into(), 1000, 14);
+        resource_ctl.add_resource_group(rg1);
+        assert!(
+            resource_ctl
+                .get_background_resource_limiter("test", "br")
+                .is_none()
+        );
+
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // run the ticks on a 1s cadence, matching the durations `reset_quota`
+        // backdates the timers by.
+        worker.set_adjust_interval(Duration::from_secs(1));
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 100000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        assert!(
+            resource_ctl
+                .get_background_resource_limiter("default", "lightning")
+                .is_none()
+        );
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_infinite()
+        );
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Io)
+                .get_rate_limit()
+                .is_infinite()
+        );
+
+        fn reset_quota_limiter(limiter: &QuotaLimiter) {
+            let limit = limiter.get_rate_limit();
+            if limit.is_finite() {
+                limiter.set_rate_limit(f64::INFINITY);
+                limiter.set_rate_limit(limit);
+            }
+        }
+
+        fn reset_limiter(limiter: &Arc<ResourceLimiter>) {
+            reset_quota_limiter(limiter.get_limiter(ResourceType::Cpu));
+            reset_quota_limiter(limiter.get_limiter(ResourceType::Io));
+        }
+
+        let reset_quota = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>,
+                           cpu: f64,
+                           io: f64,
+                           dur: Duration| {
+            worker.resource_quota_getter.cpu_used = cpu;
+            worker.resource_quota_getter.io_used = io;
+            let now = Instant::now_coarse();
+            worker.last_adjust_time = [now - dur; ResourceType::COUNT];
+        };
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        #[track_caller]
+        fn check_limiter(limiter: &Arc<ResourceLimiter>, cpu: f64, io: IoBytes) {
+            check(
+                limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+                cpu * MICROS_PER_SEC,
+            );
+            check(
+                limiter.get_limiter(ResourceType::Io).get_rate_limit(),
+                (io.read + io.write) as f64,
+            );
+            reset_limiter(limiter);
+        }
+
+        reset_quota(&mut worker, 0.0, 0.0, Duration::from_secs(1));
+        worker.adjust_quota();
+        // the snapshot should reflect the limits just assigned.
+        let snapshot = worker.last_adjustment_snapshot();
+        let cpu_adjustment = snapshot
+            .iter()
+            .find(|a| a.name == "default" && a.resource_type == ResourceType::Cpu)
+            .unwrap();
+        check(cpu_adjustment.rate_limit, 6.4 * MICROS_PER_SEC);
+        let io_adjustment = snapshot
+            .iter()
+            .find(|a| a.name == "default" && a.resource_type == ResourceType::Io)
+            .unwrap();
+        check(io_adjustment.rate_limit, 8000.0);
+        check_limiter(
+            &limiter,
+            6.4,
+            IoBytes {
+                read: 4000,
+                write: 4000,
+            },
+        );
+
+        reset_quota(&mut worker, 4.0, 2000.0, Duration::from_millis(500));
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            6.4,
+            IoBytes {
+                read: 4000,
+                write: 4000,
+            },
+        );
+
+        reset_quota(&mut worker, 4.0, 2000.0, Duration::from_secs(1));
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            3.2,
+            IoBytes {
+                read: 3200,
+                write: 3200,
+            },
+        );
+
+        reset_quota(&mut worker, 6.0, 4000.0, Duration::from_secs(1));
+        limiter.consume(
+            Duration::from_secs(2),
+            IoBytes {
+                read: 1000,
+                write: 1000,
+            },
+            true,
+        );
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            3.2,
+            IoBytes {
+                read: 3200,
+                write: 3200,
+            },
+        );
+
+        reset_quota(&mut worker, 8.0, 9500.0, Duration::from_secs(1));
+        worker.adjust_quota();
+        check_limiter(
             &limiter,
-            6.4,
+            0.8,
+            IoBytes {
+                read: 500,
+                write: 500,
+            },
+        );
+
+        reset_quota(&mut worker, 7.5, 9500.0, Duration::from_secs(1));
+        limiter.consume(
+            Duration::from_secs(2),
+            IoBytes {
+                read: 1000,
+                write: 1000,
+            },
+            true,
+        );
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            2.0,
+            IoBytes {
+                read: 1000,
+                write: 1000,
+            },
+        );
+
+        reset_quota(&mut worker, 7.5, 9500.0, Duration::from_secs(5));
+        limiter.consume(
+            Duration::from_secs(10),
+            IoBytes {
+                read: 5000,
+                write: 5000,
+            },
+            true,
+        );
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            2.0,
+            IoBytes {
+                read: 1000,
+                write: 1000,
+            },
+        );
+
+        let default =
+            new_background_resource_group_ru("default".into(), 2000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default);
+        let new_limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+        assert_eq!(&*new_limiter as *const _, &*limiter as *const _);
+
+        let bg = new_background_resource_group_ru("background".into(), 1000, 15, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let bg_limiter = resource_ctl
+            .get_background_resource_limiter("background", "br")
+            .unwrap();
+
+        reset_quota(&mut worker, 5.0, 7000.0, Duration::from_secs(1));
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            1.6,
+            IoBytes {
+                read: 800,
+                write: 800,
+            },
+        );
+        check_limiter(
+            &bg_limiter,
+            0.8,
+            IoBytes {
+                read: 400,
+                write: 400,
+            },
+        );
+
+        reset_quota(&mut worker, 6.0, 5000.0, Duration::from_secs(1));
+        limiter.consume(
+            Duration::from_millis(1200),
+            IoBytes {
+                read: 600,
+                write: 600,
+            },
+            true,
+        );
+        bg_limiter.consume(
+            Duration::from_millis(1800),
+            IoBytes {
+                read: 900,
+                write: 900,
+            },
+            true,
+        );
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            1.2,
+            IoBytes {
+                read: 1800,
+                write: 1800,
+            },
+        );
+        check_limiter(
+            &bg_limiter,
+            2.8,
+            IoBytes {
+                read: 1400,
+                write: 1400,
+            },
+        );
+
+        let bg = new_resource_group_ru("background".into(), 1000, 15);
+        resource_ctl.add_resource_group(bg);
+
+        let new_bg =
+            new_background_resource_group_ru("background".into(), 1000, 15, vec!["br".into()]);
+        resource_ctl.add_resource_group(new_bg);
+        let new_bg_limiter = resource_ctl
+            .get_background_resource_limiter("background", "br")
+            .unwrap();
+        assert_ne!(&*bg_limiter as *const _, &*new_bg_limiter as *const _);
+        assert!(
+            new_bg_limiter
+                .get_limit_statistics(ResourceType::Cpu)
+                .version
+                > bg_limiter.get_limit_statistics(ResourceType::Cpu).version
+        );
+        let cpu_stats = new_bg_limiter.get_limit_statistics(ResourceType::Cpu);
+        assert_eq!(cpu_stats.total_consumed, 0);
+        assert_eq!(cpu_stats.total_wait_dur_us, 0);
+        let io_stats = new_bg_limiter.get_limit_statistics(ResourceType::Io);
+        assert_eq!(io_stats.total_consumed, 0);
+        assert_eq!(io_stats.total_wait_dur_us, 0);
+
+        reset_quota(&mut worker, 0.0, 0.0, Duration::from_secs(1));
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            4.27,
+            IoBytes {
+                read: 2667,
+                write: 2667,
+            },
+        );
+        check_limiter(
+            &new_bg_limiter,
+            2.13,
+            IoBytes {
+                read: 1334,
+                write: 1334,
+            },
+        );
+
+        reset_quota(&mut worker, 6.0, 5000.0, Duration::from_secs(1));
+        limiter.consume(
+            Duration::from_millis(1200),
+            IoBytes {
+                read: 600,
+                write: 600,
+            },
+            true,
+        );
+        new_bg_limiter.consume(
+            Duration::from_millis(1800),
+            IoBytes {
+                read: 900,
+                write: 900,
+            },
+            true,
+        );
+
+        worker.adjust_quota();
+        check_limiter(
+            &limiter,
+            2.2,
+            IoBytes {
+                read: 2133,
+                write: 2133,
+            },
+        );
+        check_limiter(
+            &new_bg_limiter,
+            1.8,
+            IoBytes {
+                read: 1066,
+                write: 1066,
+            },
+        );
+    }
+
+    #[test]
+    fn test_cgroup_io_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("io.max"),
+            "8:0 rbps=1000000 wbps=max riops=max wiops=max\n\
+             8:16 rbps=500000 wbps=250000 riops=max wiops=max\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("io.stat"),
+            "8:0 rbytes=1000 wbytes=2000 rios=10 wios=20\n8:16 rbytes=500 wbytes=250\n",
+        )
+        .unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse() - Duration::from_secs(2),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 100.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        // the container-level throttle is summed over all device lines.
+        let stats = getter.get_current_stats(ResourceType::Io).unwrap();
+        assert_eq!(stats.total_quota, 1_750_000.0);
+        // the usage is accounted through the container-level counter.
+        assert!(stats.current_used > 0.0);
+        assert_eq!(getter.prev_cgroup_io_bytes, 3750);
+
+        // without a bandwidth throttle, fall back to the configured bandwidth.
+        std::fs::write(dir.path().join("io.max"), "8:0 rbps=max wbps=max\n").unwrap();
+        getter.prev_io_ts = Instant::now_coarse() - Duration::from_secs(2);
+        let stats = getter.get_current_stats(ResourceType::Io).unwrap();
+        assert_eq!(stats.total_quota, 100.0);
+    }
+
+    #[test]
+    fn test_compaction_io_budget() {
+        use std::sync::atomic::AtomicU64;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("io.max"), "8:0 rbps=800 wbps=200\n").unwrap();
+        std::fs::write(dir.path().join("io.stat"), "8:0 rbytes=600 wbytes=400\n").unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse() - Duration::from_secs(2),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // the mock engine reports 400 bytes of internal compaction io over
+        // the 2s window, i.e. a 200 B/s rate reserved out of the 1000 B/s
+        // container throttle.
+        let compaction_bytes = Arc::new(AtomicU64::new(0));
+        let source = compaction_bytes.clone();
+        getter.set_compaction_io_source(
+            move || source.load(Ordering::Relaxed),
+            CompactionIoAccounting::Reserve,
+        );
+        compaction_bytes.store(400, Ordering::Relaxed);
+        let first = getter.get_current_stats(ResourceType::Io).unwrap();
+        check(first.total_quota, 800.0);
+        check(first.current_used, 1000.0 / 2.0);
+
+        // the cached-rate fast path keeps applying the last reservation.
+        let again = getter.get_current_stats(ResourceType::Io).unwrap();
+        check(again.total_quota, 800.0);
+        assert_eq!(again.current_used, first.current_used);
+
+        // excluding instead leaves the quota whole and subtracts the
+        // compaction rate from the measured usage.
+        let source = compaction_bytes.clone();
+        getter.set_compaction_io_source(
+            move || source.load(Ordering::Relaxed),
+            CompactionIoAccounting::Exclude,
+        );
+        compaction_bytes.store(800, Ordering::Relaxed);
+        std::fs::write(dir.path().join("io.stat"), "8:0 rbytes=1600 wbytes=400\n").unwrap();
+        getter.prev_io_ts = Instant::now_coarse() - Duration::from_secs(2);
+        let second = getter.get_current_stats(ResourceType::Io).unwrap();
+        assert_eq!(second.total_quota, 1000.0);
+        check(second.current_used, (1000.0 - 400.0) / 2.0);
+    }
+
+    #[test]
+    fn test_io_min_sample_window() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("io.max"),
+            "8:0 rbps=1000000 wbps=max riops=max wiops=max\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("io.stat"), "8:0 rbytes=1000 wbytes=2750\n").unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse() - Duration::from_secs(2),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // a full 2s window computes the rate from the counter delta.
+        let first = getter.get_current_stats(ResourceType::Io).unwrap();
+        check(first.current_used, 3750.0 / 2.0);
+
+        // rapid successive calls fall below the 1s default window: the
+        // cached rate is reused, the grown counter is left untouched.
+        std::fs::write(dir.path().join("io.stat"), "8:0 rbytes=4000 wbytes=6000\n").unwrap();
+        for _ in 0..3 {
+            let again = getter.get_current_stats(ResourceType::Io).unwrap();
+            assert_eq!(again.current_used, first.current_used);
+            assert_eq!(again.window_secs, first.window_secs);
+        }
+        assert_eq!(getter.prev_cgroup_io_bytes, 3750);
+
+        // once the window is met again the rate is recomputed from the
+        // whole accumulated delta.
+        getter.prev_io_ts = Instant::now_coarse() - Duration::from_secs(2);
+        let second = getter.get_current_stats(ResourceType::Io).unwrap();
+        check(second.current_used, 6250.0 / 2.0);
+
+        // a wider configured window keeps even a 2s sample on the cache.
+        getter.set_min_io_sample_window(Duration::from_secs(10));
+        std::fs::write(dir.path().join("io.stat"), "8:0 rbytes=9000 wbytes=11000\n").unwrap();
+        getter.prev_io_ts = Instant::now_coarse() - Duration::from_secs(2);
+        let third = getter.get_current_stats(ResourceType::Io).unwrap();
+        assert_eq!(third.current_used, second.current_used);
+    }
+
+    #[test]
+    fn test_set_io_bandwidth() {
+        let dir = tempfile::tempdir().unwrap();
+        let getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let mut worker = GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), getter);
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let first = limiter.get_limiter(ResourceType::Io).get_rate_limit();
+        assert!(first.is_finite());
+
+        // doubling the bandwidth budget doubles the assigned limit on the
+        // next tick.
+        worker.set_io_bandwidth(2000);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let second = limiter.get_limiter(ResourceType::Io).get_rate_limit();
+        assert!(
+            first * 2.0 * 0.99 < second && second < first * 2.0 * 1.01,
+            "first: {}, second: {}",
+            first,
+            second
+        );
+    }
+
+    #[test]
+    fn test_cpu_stats_ride_over_transient_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        let transient_err =
+            || std::io::Error::new(std::io::ErrorKind::Other, "proc read failed");
+
+        // a successful sample is cached.
+        let stats = getter.cpu_stats_with_sample(Ok(2.0)).unwrap();
+        assert_eq!(stats.current_used, 2.0 * MICROS_PER_SEC);
+
+        // a transient failure reuses the cached sample so the adjustment
+        // can proceed.
+        let stats = getter.cpu_stats_with_sample(Err(transient_err())).unwrap();
+        assert_eq!(stats.current_used, 2.0 * MICROS_PER_SEC);
+        assert_eq!(getter.cpu_stat_failures, 1);
+
+        // a success in between resets the failure count.
+        let stats = getter.cpu_stats_with_sample(Ok(3.0)).unwrap();
+        assert_eq!(stats.current_used, 3.0 * MICROS_PER_SEC);
+        assert_eq!(getter.cpu_stat_failures, 0);
+
+        // persistent failures are propagated after the threshold.
+        getter.cpu_stats_with_sample(Err(transient_err())).unwrap();
+        getter.cpu_stats_with_sample(Err(transient_err())).unwrap();
+        getter.cpu_stats_with_sample(Err(transient_err())).unwrap_err();
+    }
+
+    #[test]
+    fn test_cpu_stats_unavailable_io_only() {
+        let dir = tempfile::tempdir().unwrap();
+        // a platform without readable process cpu stats leaves the stat
+        // source empty instead of panicking on construction.
+        let getter = SysQuotaGetter {
+            process_stat: None,
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let mut worker = GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), getter);
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+
+        // the cpu quota is reported as unlimited, so the worker keeps the
+        // cpu limiter unlimited instead of freezing at a stale limit, while
+        // the configured io bandwidth is still adjusted.
+        let stats = worker
+            .resource_quota_getter
+            .get_current_stats(ResourceType::Cpu)
+            .unwrap();
+        assert!(stats.total_quota.is_infinite());
+        assert_eq!(stats.current_used, 0.0);
+
+        limiter
+            .get_limiter(ResourceType::Cpu)
+            .set_rate_limit(1000.0);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_infinite()
+        );
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Io)
+                .get_rate_limit()
+                .is_finite()
+        );
+    }
+
+    #[test]
+    fn test_io_breakdown() {
+        let mut prev = [IoBytes::default(); IoType::COUNT];
+        let mut cur = [IoBytes::default(); IoType::COUNT];
+        prev[IoType::Compaction as usize] = IoBytes {
+            read: 1000,
+            write: 500,
+        };
+        cur[IoType::Compaction as usize] = IoBytes {
+            read: 3000,
+            write: 1500,
+        };
+        cur[IoType::ForegroundWrite as usize] = IoBytes {
+            read: 0,
+            write: 4000,
+        };
+        let rates = compute_io_breakdown(&prev, &cur, 2.0);
+        assert_eq!(
+            rates[IoType::Compaction as usize],
+            IoBytes {
+                read: 1000,
+                write: 500
+            }
+        );
+        assert_eq!(
+            rates[IoType::ForegroundWrite as usize],
+            IoBytes {
+                read: 0,
+                write: 2000
+            }
+        );
+        assert_eq!(rates[IoType::Flush as usize], IoBytes::default());
+    }
+
+    #[test]
+    fn test_excluded_io_types() {
+        let prev = [IoBytes::default(); IoType::COUNT];
+        let mut cur = [IoBytes::default(); IoType::COUNT];
+        cur[IoType::Compaction as usize] = IoBytes {
+            read: 2000,
+            write: 1000,
+        };
+        cur[IoType::ForegroundWrite as usize] = IoBytes {
+            read: 0,
+            write: 4000,
+        };
+        cur[IoType::Flush as usize] = IoBytes {
+            read: 0,
+            write: 500,
+        };
+        // without exclusions everything counts.
+        let excluded = [false; IoType::COUNT];
+        assert_eq!(sum_io_delta(&prev, &cur, &excluded), 7500);
+        // foreground writes are subtracted from the measured usage.
+        let mut excluded = [false; IoType::COUNT];
+        excluded[IoType::ForegroundWrite as usize] = true;
+        assert_eq!(sum_io_delta(&prev, &cur, &excluded), 3500);
+
+        // the setter translates the type list into the exclusion mask.
+        let dir = tempfile::tempdir().unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse(),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 1000.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        getter.set_excluded_io_types(&[IoType::ForegroundWrite, IoType::ForegroundRead]);
+        assert!(getter.excluded_io_types[IoType::ForegroundWrite as usize]);
+        assert!(getter.excluded_io_types[IoType::ForegroundRead as usize]);
+        assert!(!getter.excluded_io_types[IoType::Compaction as usize]);
+        // a later call replaces the previous exclusion set.
+        getter.set_excluded_io_types(&[IoType::ForegroundWrite]);
+        assert!(!getter.excluded_io_types[IoType::ForegroundRead as usize]);
+    }
+
+    #[test]
+    fn test_zero_io_bandwidth_unlimited() {
+        // an unconfigured io bandwidth yields an infinite quota from the
+        // getter rather than a zero one.
+        let dir = tempfile::tempdir().unwrap();
+        let mut getter = SysQuotaGetter {
+            process_stat: try_cur_proc_stat(),
+            prev_io_stats: [IoBytes::default(); IoType::COUNT],
+            prev_io_ts: Instant::now_coarse() - Duration::from_secs(1),
+            min_io_sample_window: DEFAULT_IO_SAMPLE_WINDOW,
+            cached_io_rate: None,
+            io_bandwidth: 0.0,
+            excluded_io_types: [false; IoType::COUNT],
+            prev_net_stats: NetBytes::default(),
+            prev_net_ts: Instant::now_coarse(),
+            net_bandwidth: f64::INFINITY,
+            cgroup_path: dir.path().to_path_buf(),
+            prev_cgroup_io_bytes: 0,
+            prev_io_breakdown: [IoBytes::default(); IoType::COUNT],
+            prev_io_breakdown_ts: Instant::now_coarse(),
+            last_cpu_usage: None,
+            cpu_stat_failures: 0,
+            compaction_io_source: None,
+            compaction_io_accounting: CompactionIoAccounting::Reserve,
+            prev_compaction_io_bytes: 0,
+            cached_compaction_io_rate: 0.0,
+        };
+        let stats = getter.get_current_stats(ResourceType::Io).unwrap();
+        assert!(stats.total_quota.is_infinite());
+
+        // and the adjust worker short-circuits an infinite quota to
+        // unlimited group limits even under heavy usage.
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, f64::INFINITY);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        worker.resource_quota_getter.io_used = 9000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Io)
+                .get_rate_limit()
+                .is_infinite()
+        );
+    }
+
+    #[test]
+    fn test_prune_deleted_group_stats() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        // enable the integral term so the per-group error state is
+        // accumulated and must be pruned along with the other maps.
+        worker.set_integral_gain(0.1);
+
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(10); ResourceType::COUNT];
+        worker.adjust_quota();
+        assert!(worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg1"));
+        assert!(worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg2"));
+        assert!(worker.integral_errors[ResourceType::Cpu as usize].contains_key("rg1"));
+        assert!(worker.integral_errors[ResourceType::Cpu as usize].contains_key("rg2"));
+
+        // after the group is deleted, its stale stats should be pruned.
+        resource_ctl.remove_resource_group("rg2");
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(10); ResourceType::COUNT];
+        worker.adjust_quota();
+        assert!(worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg1"));
+        assert!(!worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg2"));
+        assert!(worker.integral_errors[ResourceType::Cpu as usize].contains_key("rg1"));
+        assert!(!worker.integral_errors[ResourceType::Cpu as usize].contains_key("rg2"));
+    }
+
+    #[test]
+    fn test_new_group_first_observation() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg_old = new_background_resource_group_ru("rg_old".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_old);
+        let limiter_old = resource_ctl
+            .get_background_resource_limiter("rg_old", "br")
+            .unwrap();
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // a group that already accumulated a lot of cpu time joins between
+        // two ticks.
+        let rg_new = new_background_resource_group_ru("rg_new".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_new);
+        let limiter_new = resource_ctl
+            .get_background_resource_limiter("rg_new", "br")
+            .unwrap();
+        limiter_new.consume(Duration::from_secs(100), IoBytes::default(), false);
+
+        // its first observation only primes the baseline instead of counting
+        // the pre-registration consumption as one tick's rate, so the 3.2
+        // cpu quota is still split evenly by weight.
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg_new"));
+        check(
+            limiter_new.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+        check(
+            limiter_old.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_async_adjust_loop() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(10); ResourceType::COUNT];
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let handle = std::thread::spawn(move || {
+            futures::executor::block_on(
+                worker.run_with_interval(Duration::from_millis(20), async {
+                    _ = rx.await;
+                }),
+            );
+        });
+        // wait until the first tick adjusts the limiter.
+        for _ in 0..100 {
+            if limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_finite()
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_finite()
+        );
+        // the loop exits cleanly on shutdown.
+        tx.send(()).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_skip_foreground_groups() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // a foreground group without background settings never gets a
+        // background limiter and must not be adjusted.
+        let rg_fg = new_resource_group_ru("rg_fg".into(), 1000, 8);
+        resource_ctl.add_resource_group(rg_fg);
+        let rg_bg = new_background_resource_group_ru("rg_bg".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_bg);
+        let bg_limiter = resource_ctl
+            .get_background_resource_limiter("rg_bg", "br")
+            .unwrap();
+
+        worker.resource_quota_getter.cpu_used = 6.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(
+            bg_limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_finite()
+        );
+        assert!(worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg_bg"));
+        assert!(!worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg_fg"));
+    }
+
+    #[test]
+    fn test_adjust_memory_pressure() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let mut test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        test_provider.mem_total = 1_000_000.0;
+        test_provider.mem_used = 950_000.0;
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Mem)
+                .get_rate_limit()
+                .is_infinite()
+        );
+
+        // under high RSS pressure only the quota floor remains for background
+        // admission.
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let limit = limiter.get_limiter(ResourceType::Mem).get_rate_limit();
+        assert!(limit.is_finite());
+        assert!(
+            100_000.0 * 0.99 < limit && limit < 100_000.0 * 1.01,
+            "actual: {}",
+            limit
+        );
+    }
+
+    #[test]
+    fn test_adjustment_metrics() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg = new_background_resource_group_ru("rg_metrics".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg_metrics", "br")
+            .unwrap();
+
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let limit = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert_eq!(
+            BACKGROUND_QUOTA_LIMIT_VEC
+                .with_label_values(&["rg_metrics", "cpu"])
+                .get(),
+            limit as i64
+        );
+        assert_eq!(
+            BACKGROUND_CONSUMED_RATE_VEC
+                .with_label_values(&["rg_metrics", "cpu"])
+                .get(),
+            0
+        );
+        assert_eq!(
+            BACKGROUND_WAIT_RATE_VEC
+                .with_label_values(&["rg_metrics", "cpu"])
+                .get(),
+            0
+        );
+
+        // low-load ticks take the fast path once the debounce window of
+        // three consecutive observations is filled.
+        let fast_path_before = LOW_LOAD_FAST_PATH_COUNTER_VEC
+            .with_label_values(&["cpu"])
+            .get();
+        worker.resource_quota_getter.cpu_used = 0.0;
+        for _ in 0..3 {
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        }
+        assert_eq!(
+            LOW_LOAD_FAST_PATH_COUNTER_VEC
+                .with_label_values(&["cpu"])
+                .get(),
+            fast_path_before + 1
+        );
+    }
+
+    #[test]
+    fn test_headroom_factor() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // invalid factors are ignored.
+        worker.set_headroom_factor(0.0);
+        worker.set_headroom_factor(1.5);
+        assert_eq!(worker.headroom_factor, DEFAULT_HEADROOM_FACTOR);
+        worker.set_headroom_factor(0.7);
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        // (8.0 - 4.0) * 0.7 cores instead of the default 0.8 headroom.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let limit = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(
+            2.8 * MICROS_PER_SEC * 0.99 < limit && limit < 2.8 * MICROS_PER_SEC * 1.01,
+            "actual: {}",
+            limit
+        );
+    }
+
+    #[test]
+    fn test_ema_smoothing() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // invalid alpha falls back to the default.
+        worker.set_ema_alpha(2.0);
+        assert_eq!(worker.ema_alpha, Some(DEFAULT_EMA_ALPHA));
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // the first sample initializes the EMA instead of blending against 0.
+        worker.resource_quota_getter.cpu_used = 0.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            6.4 * MICROS_PER_SEC,
+        );
+
+        // a sudden spike to full usage is smoothed to 4 cores, so the limit
+        // only drops to (8.0 - 4.0) * 0.8 cores instead of the quota floor.
+        worker.resource_quota_getter.cpu_used = 8.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_max_change_ratio() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // invalid ratio is ignored.
+        worker.set_max_change_ratio(0.5);
+        assert!(worker.max_change_ratio.is_none());
+        worker.set_max_change_ratio(2.0);
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        // the first tick with an idle process assigns the full available quota
+        // since the current limit is still infinite.
+        worker.resource_quota_getter.cpu_used = 0.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let first = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(first.is_finite());
+
+        // the second tick with a nearly saturated process would drop the limit
+        // to the quota floor, but the slew rate limits it to halving at most.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let second = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(
+            first / 2.0 * 0.99 < second && second < first / 2.0 * 1.01,
+            "first: {}, second: {}",
+            first,
+            second
+        );
+    }
+
+    #[test]
+    fn test_group_min_rate_floor() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        worker.set_group_min_rate("rg1", ResourceType::Cpu, 0.5 * MICROS_PER_SEC);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // with a nearly saturated process the available quota floors at 10%
+        // of the total (0.8 cpu). The 0.5 cpu floor of rg1 is reserved first,
+        // the remaining 0.3 cpu is split by ru, and rg1 is then raised to its
+        // floor while rg2 keeps its plain share.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.5 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.15 * MICROS_PER_SEC,
+        );
+
+        // a non-positive rate removes the floor.
+        worker.set_group_min_rate("rg1", ResourceType::Cpu, 0.0);
+        assert!(worker.min_rate_floors[ResourceType::Cpu as usize].is_empty());
+    }
+
+    #[test]
+    fn test_class_policy() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        fn cpu_policy(min: Option<f64>, max: Option<f64>) -> ClassPolicy {
+            let mut min_rate = [None; ResourceType::COUNT];
+            let mut max_rate = [None; ResourceType::COUNT];
+            min_rate[ResourceType::Cpu as usize] = min;
+            max_rate[ResourceType::Cpu as usize] = max;
+            ClassPolicy { min_rate, max_rate }
+        }
+        // two classes with different guarantees: every gc group is floored
+        // at 0.5 cpu, every br group at 0.25 cpu.
+        worker.set_class_policy("gc", cpu_policy(Some(0.5 * MICROS_PER_SEC), None));
+        worker.set_class_policy("br", cpu_policy(Some(0.25 * MICROS_PER_SEC), None));
+
+        let rg_gc = new_background_resource_group_ru("rg_gc".into(), 1000, 8, vec!["gc".into()]);
+        resource_ctl.add_resource_group(rg_gc);
+        let rg_br = new_background_resource_group_ru("rg_br".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_br);
+        let limiter_gc = resource_ctl
+            .get_background_resource_limiter("rg_gc", "gc")
+            .unwrap();
+        let limiter_br = resource_ctl
+            .get_background_resource_limiter("rg_br", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // with a nearly saturated process the pool floors at 10% of the
+        // total (0.8 cpu); both class floors (0.75 cpu in total) are
+        // reserved up front and the groups are then raised to them, so each
+        // group ends up at the floor of its own class without any per-group
+        // setting.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter_gc.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.5 * MICROS_PER_SEC,
+        );
+        check(
+            limiter_br.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.25 * MICROS_PER_SEC,
+        );
+
+        // an explicit per-group floor takes precedence over the class floor.
+        worker.set_group_min_rate("rg_gc", ResourceType::Cpu, 0.8 * MICROS_PER_SEC);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter_gc.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+        check(
+            limiter_br.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.25 * MICROS_PER_SEC,
+        );
+
+        // a class cap bounds the assigned limit: with plenty of quota (3.2
+        // cpu pool) the br group is held at its class ceiling while the gc
+        // group keeps its share-based grant.
+        worker.set_group_min_rate("rg_gc", ResourceType::Cpu, 0.0);
+        worker.remove_class_policy("gc");
+        worker.set_class_policy("br", cpu_policy(None, Some(0.2 * MICROS_PER_SEC)));
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter_br.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.2 * MICROS_PER_SEC,
+        );
+        assert!(
+            limiter_gc.get_limiter(ResourceType::Cpu).get_rate_limit() > 1.5 * MICROS_PER_SEC
+        );
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // with a nearly saturated process the available quota floors at 10%
+        // of the total and the idle group gets the whole 0.8 cpu pool.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+
+        // a paused tick leaves the limiter untouched no matter what was
+        // consumed in the meantime, but still moves the statistics baseline
+        // up to the current counters.
+        worker.pause();
+        limiter.consume(Duration::from_secs(100), IoBytes::default(), false);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedPaused);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+        assert_eq!(
+            worker.prev_stats_by_group[ResourceType::Cpu as usize]["rg1"].total_consumed,
+            limiter
+                .get_limit_statistics(ResourceType::Cpu)
+                .total_consumed
+        );
+
+        // the first resumed tick only sees the consumption since the paused
+        // tick, so the burst consumed while paused does not produce a demand
+        // spike and the limit stays at the plain pool share.
+        worker.resume();
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_group_fixed_rate_override() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        worker.set_group_fixed_rate("rg_pin", ResourceType::Cpu, 1.0 * MICROS_PER_SEC);
+
+        let rg_pin = new_background_resource_group_ru("rg_pin".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_pin);
+        let rg_auto =
+            new_background_resource_group_ru("rg_auto".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_auto);
+        let pinned_limiter = resource_ctl
+            .get_background_resource_limiter("rg_pin", "br")
+            .unwrap();
+        let auto_limiter = resource_ctl
+            .get_background_resource_limiter("rg_auto", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // the available quota is (8.0 - 4.0) * 0.8 cpu; the pinned 1.0 cpu is
+        // taken out up front, the remaining 2.2 cpu all goes to rg_auto.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            pinned_limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.0 * MICROS_PER_SEC,
+        );
+        check(
+            auto_limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.2 * MICROS_PER_SEC,
+        );
+
+        // a heavier foreground load shrinks rg_auto's share, but the pinned
+        // rate does not move with it.
+        worker.resource_quota_getter.cpu_used = 6.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            pinned_limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.0 * MICROS_PER_SEC,
+        );
+        check(
+            auto_limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.6 * MICROS_PER_SEC,
+        );
+
+        // a non-positive rate removes the pin.
+        worker.set_group_fixed_rate("rg_pin", ResourceType::Cpu, 0.0);
+        assert!(worker.fixed_rate_overrides[ResourceType::Cpu as usize].is_empty());
+    }
+
+    #[test]
+    fn test_ru_cost_factor() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // the default conversion treats consumed statistics as raw units.
+        assert_eq!(worker.ru_cost_factor(ResourceType::Cpu), 1.0);
+        // an invalid factor is ignored.
+        worker.set_ru_cost_factor(ResourceType::Cpu, -1.0);
+        assert_eq!(worker.ru_cost_factor(ResourceType::Cpu), 1.0);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter1.consume(Duration::from_secs(2), IoBytes::default(), false);
+            limiter2.consume(Duration::from_millis(500), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines; the first observation counts no consumption.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        // with the default factor the expected costs of 2 + 0.5 cpu are both
+        // below the even share of the (8 - 4 + 2.5) * 0.8 = 5.2 cpu pool, so
+        // each group takes half of it.
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.6 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.6 * MICROS_PER_SEC,
+        );
+
+        // doubling the factor doubles the expected costs to 4 + 1 cpu: rg1's
+        // inflated demand now exceeds its even share and squeezes rg2 down
+        // to the 1.2 cpu remainder.
+        worker.set_ru_cost_factor(ResourceType::Cpu, 2.0);
+        assert_eq!(worker.ru_cost_factor(ResourceType::Cpu), 2.0);
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            4.0 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.2 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_peak_derived_ceiling() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // an invalid ratio falls back to the default.
+        worker.set_peak_cap_ratio(0.5);
+        assert_eq!(worker.config().peak_cap_ratio, Some(DEFAULT_PEAK_CAP_RATIO));
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // the first tick primes the baseline; without any observed
+        // consumption the cold group is left uncapped and takes the full
+        // (8 - 4) * 0.8 = 3.2 cpu pool.
+        tick(&mut worker);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+
+        // the group only ever consumes 0.5 cpu: despite the pool having
+        // ample spare quota, its limit is capped at 1.5x that peak.
+        limiter.consume(Duration::from_millis(500), IoBytes::default(), false);
+        tick(&mut worker);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.75 * MICROS_PER_SEC,
+        );
+
+        // growing demand raises the rolling peak, so the cap ramps along
+        // with it: a 0.7 cpu tick lifts the ceiling to 1.05 cpu.
+        limiter.consume(Duration::from_millis(700), IoBytes::default(), false);
+        tick(&mut worker);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.05 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_under_utilization_feedback() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // an invalid ratio disables the feedback.
+        worker.set_under_utilization_feedback(-1.0, 2);
+        assert_eq!(worker.config().under_util_ratio, None);
+        // a total cap makes the trimmed quota visibly flow to the busy
+        // group: without it an idle grant in the quota-enough branch is
+        // surplus anyway.
+        worker.set_max_total_background_rate(ResourceType::Cpu, 4.0 * MICROS_PER_SEC);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // rg2 steadily consumes 3 cpu while rg1 idles.
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter2.consume(Duration::from_secs(3), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines, then reach the steady state: the raw grants
+        // of 3.0 (demand) and 2.6 (idle share) overshoot the 4.0 cap and
+        // are scaled by 4 / 5.6, so rg1's unused grant squeezes rg2 below
+        // its demand.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        tick(&mut worker);
+        tick(&mut worker);
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.0 * (4.0 / 5.6) * MICROS_PER_SEC,
+        );
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.6 * (4.0 / 5.6) * MICROS_PER_SEC,
+        );
+
+        // after two consecutive under-utilized ticks rg1's grant is trimmed
+        // to the low-load floor of 8 * 0.1 = 0.8 cpu, and the freed quota
+        // lets rg2 run at its full 3.0 cpu demand under the cap.
+        worker.set_under_utilization_feedback(0.5, 2);
+        tick(&mut worker);
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.0 * MICROS_PER_SEC,
+        );
+
+        // returning demand resets the streak and the trim lifts right away:
+        // both groups are granted the 3.2 cpu share and scaled to 2.0 each
+        // by the cap.
+        limiter1.consume(Duration::from_secs(2), IoBytes::default(), false);
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.0 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.0 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_soft_start_ramp() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        worker.set_soft_start_ticks(4);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // prime the baselines without assigning any limit yet, so the group
+        // still runs uncapped when the first real tick throttles it.
+        worker.reset();
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_infinite()
+        );
+
+        // the group steadily consumes 2 cpu; every tick the pool is
+        // (8 - 6 + 2) * 0.8 = 3.2 cpu and the single group would be granted
+        // it all.
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter.consume(Duration::from_secs(2), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 6.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit()
+        };
+
+        // the transition tick starts the ramp at a quarter of the way from
+        // the observed 2.0 cpu toward the 3.2 cpu target, so the first
+        // finite limit sits much closer to the actual usage than to the raw
+        // target.
+        let first = tick(&mut worker);
+        check(first, 2.3 * MICROS_PER_SEC);
+        assert!(
+            (first - 2.0 * MICROS_PER_SEC).abs() < (3.2 * MICROS_PER_SEC - first).abs(),
+            "actual: {}",
+            first
+        );
+        // the following ticks walk the rest of the way and land exactly on
+        // the target, after which the ramp state is dropped.
+        check(tick(&mut worker), 2.6 * MICROS_PER_SEC);
+        check(tick(&mut worker), 2.9 * MICROS_PER_SEC);
+        check(tick(&mut worker), 3.2 * MICROS_PER_SEC);
+        assert!(worker.soft_start_remaining[ResourceType::Cpu as usize].is_empty());
+        check(tick(&mut worker), 3.2 * MICROS_PER_SEC);
+    }
+
+    #[test]
+    fn test_unallocated_quota() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // no distribution has run yet.
+        assert_eq!(worker.unallocated_quota(ResourceType::Cpu), 0.0);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // rg2 steadily consumes 3 cpu while rg1 idles.
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter2.consume(Duration::from_secs(3), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines, then reach the steady state: the quota-enough
+        // branch hands the whole 5.6 cpu pool out (3.0 demand plus the 2.6
+        // idle share), leaving nothing unallocated.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        tick(&mut worker);
+        tick(&mut worker);
+        assert!(worker.unallocated_quota(ResourceType::Cpu) < 0.01 * MICROS_PER_SEC);
+
+        // once the under-utilization trim cuts rg1's idle grant to the
+        // low-load floor, the withheld share shows up as headroom: out of
+        // the effective 7 cpu (8 total - 4 used + 3 background), 1.4 is
+        // reserved by the headroom factor and 3.0 + 0.8 are assigned, so
+        // 1.8 cpu remains unallocated.
+        worker.set_under_utilization_feedback(0.5, 2);
+        tick(&mut worker);
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.8 * MICROS_PER_SEC,
+        );
+        check(
+            worker.unallocated_quota(ResourceType::Cpu),
+            (7.0 - 1.4 - 3.0 - 0.8) * MICROS_PER_SEC,
+        );
+        // the dump reports the same headroom.
+        check(
+            worker.debug_dump().unallocated_quota["cpu"],
+            1.8 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_max_groups_per_tick() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let mut test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        test_provider.cpu_used = 4.0;
+        test_provider.io_used = 5000.0;
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let names: Vec<String> = (0..5).map(|i| format!("bg{}", i)).collect();
+        for name in &names {
+            let rg = new_background_resource_group_ru(name.clone(), 1000, 8, vec!["br".into()]);
+            resource_ctl.add_resource_group(rg);
+        }
+        worker.set_max_groups_per_tick(2);
+
+        let mut adjusted = HashSet::new();
+        for tick in 0..5 {
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+            let snapshot = worker.last_adjustment_snapshot();
+            // no tick recomputes more groups than the cap, for any type.
+            for t in ResourceType::all() {
+                assert!(
+                    snapshot.iter().filter(|a| a.resource_type == t).count() <= 2,
+                    "tick {} exceeded the cap",
+                    tick
+                );
+            }
+            adjusted.extend(snapshot.into_iter().map(|a| a.name));
+            // one full cycle over 5 groups in windows of 2 takes 3 ticks.
+            if tick == 2 {
+                assert_eq!(adjusted.len(), names.len());
+            }
+        }
+        // every group ended up with an assigned limit.
+        for name in &names {
+            let limiter = resource_ctl
+                .get_background_resource_limiter(name, "br")
+                .unwrap();
+            assert!(
+                limiter
+                    .get_limiter(ResourceType::Cpu)
+                    .get_rate_limit()
+                    .is_finite(),
+                "{} was never adjusted",
+                name
+            );
+        }
+
+        // removing the cap goes back to recomputing every group each tick.
+        worker.set_max_groups_per_tick(0);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+        assert_eq!(
+            worker.last_adjustment_snapshot().len(),
+            names.len() * ResourceType::COUNT
+        );
+    }
+
+    #[test]
+    fn test_pressure_score() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let rg = new_background_resource_group_ru("bg".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("bg", "br")
+            .unwrap();
+
+        // no adjustment has run yet, so there is nothing to score.
+        assert_eq!(worker.pressure_score("bg"), None);
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>,
+                    cpu: f64,
+                    io: f64| {
+            limiter.consume(Duration::from_secs(1), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = cpu;
+            worker.resource_quota_getter.io_used = io;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines, then settle on moderate cpu and io load.
+        tick(&mut worker, 4.0, 5000.0);
+        tick(&mut worker, 4.0, 5000.0);
+        let relaxed = worker.pressure_score("bg").unwrap();
+        assert!(0.0 < relaxed && relaxed < 1.0, "relaxed: {}", relaxed);
+
+        // rising cpu contention raises the score.
+        tick(&mut worker, 7.0, 5000.0);
+        let cpu_pressed = worker.pressure_score("bg").unwrap();
+        assert!(
+            cpu_pressed > relaxed,
+            "cpu_pressed: {}, relaxed: {}",
+            cpu_pressed,
+            relaxed
+        );
+
+        // rising io contention raises it further.
+        tick(&mut worker, 7.0, 9000.0);
+        let io_pressed = worker.pressure_score("bg").unwrap();
+        assert!(
+            cpu_pressed < io_pressed && io_pressed <= 1.0,
+            "io_pressed: {}, cpu_pressed: {}",
+            io_pressed,
+            cpu_pressed
+        );
+
+        // an all-cpu weighting leaves the io contention out of the score.
+        worker.set_pressure_weights(1.0, 0.0);
+        assert!(worker.pressure_score("bg").unwrap() < io_pressed);
+
+        // an unknown group has no score under any weighting.
+        assert_eq!(worker.pressure_score("unknown"), None);
+    }
+
+    #[test]
+    fn test_integral_gain() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // an invalid gain is ignored.
+        worker.set_integral_gain(-1.0);
+        assert_eq!(worker.config().integral_gain, 0.0);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // rg2's demand of 5 cpu steadily exceeds its share of the scarce
+        // pool, the setup the integral term is supposed to speed up.
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter1.consume(Duration::from_secs(3), IoBytes::default(), false);
+            limiter2.consume(Duration::from_secs(5), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 8.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines; the first observation counts no consumption.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        worker.set_integral_gain(0.5);
+
+        // The demands of 3 + 5 cpu exceed the (8 - 8 + 8) * 0.8 = 6.4 cpu
+        // pool. Without any accumulated error this is the purely
+        // proportional result: rg1 takes its 3 cpu demand, rg2 gets the
+        // remainder and starts accruing an error of 5 - 3.4 = 1.6 cpu.
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.0 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.4 * MICROS_PER_SEC,
+        );
+
+        // The next tick adds 0.5 * 1.6 cpu on top of rg2's proportional
+        // share, so it converges toward its 5 cpu demand instead of staying
+        // pinned at 3.4 cpu like the purely proportional distribution would.
+        tick(&mut worker);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.0 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            4.2 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_scale_down_policy() {
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // Run the same scarce tick under the given policy and return the cpu
+        // limits assigned to the two groups.
+        fn run_scenario(policy: Option<ScaleDownPolicy>) -> (f64, f64) {
+            let resource_ctl = Arc::new(ResourceGroupManager::default());
+            let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+            let mut worker =
+                GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+            if let Some(policy) = policy {
+                worker.set_scale_down_policy(policy);
+            }
+            let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+            resource_ctl.add_resource_group(rg1);
+            let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+            resource_ctl.add_resource_group(rg2);
+            let limiter1 = resource_ctl
+                .get_background_resource_limiter("rg1", "br")
+                .unwrap();
+            let limiter2 = resource_ctl
+                .get_background_resource_limiter("rg2", "br")
+                .unwrap();
+
+            // prime the baselines; the first observation counts no
+            // consumption.
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+
+            // the demands of 3 + 5 cpu exceed the (8 - 8 + 8) * 0.8 = 6.4
+            // cpu pool.
+            limiter1.consume(Duration::from_secs(3), IoBytes::default(), false);
+            limiter2.consume(Duration::from_secs(5), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 8.0;
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+            (
+                limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+                limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            )
+        }
+
+        // Linear is the default: rg1 takes its 3 cpu demand, rg2 is capped
+        // at its fair share of the remainder.
+        let (linear1, linear2) = run_scenario(None);
+        check(linear1, 3.0 * MICROS_PER_SEC);
+        check(linear2, 3.4 * MICROS_PER_SEC);
+
+        // Under the convex policy rg1, whose 3 cpu demand stays within its
+        // 3.2 cpu fair share, is served the same, while the overshooting rg2
+        // is cut by a further (3.4 / 5) on top of its linear cap:
+        // 3.4 * 3.4 / 5 = 2.312.
+        let (convex1, convex2) = run_scenario(Some(ScaleDownPolicy::Convex { exponent: 1.0 }));
+        check(convex1, 3.0 * MICROS_PER_SEC);
+        check(convex2, 2.312 * MICROS_PER_SEC);
+        assert!(convex2 < linear2);
+
+        // an invalid convex exponent is ignored.
+        let mut worker = GroupQuotaAdjustWorker::with_quota_getter(
+            Arc::new(ResourceGroupManager::default()),
+            TestResourceStatsProvider::new(8.0, 10000.0),
+        );
+        worker.set_scale_down_policy(ScaleDownPolicy::Convex { exponent: f64::NAN });
+        assert_eq!(worker.config().scale_down_policy, ScaleDownPolicy::Linear);
+    }
+
+    #[test]
+    fn test_throttled_duration() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // rg1's high priority doubles its distribution weight while the fair
+        // share baseline stays purely ru-proportional, so under scarcity rg2
+        // is the one squeezed below its fair share.
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 16, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter1.consume(Duration::from_secs(5), IoBytes::default(), false);
+            limiter2.consume(Duration::from_secs(5), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 8.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // The prime tick observes no consumption, so even the group whose
+        // weight-proportional share is below its fair share demanded nothing
+        // and counts as idle, not throttled.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert_eq!(
+            worker.get_throttled_duration("rg2", ResourceType::Cpu),
+            Duration::ZERO
+        );
+
+        // Both demand 5 cpu out of the 6.4 cpu pool; the fair share of each
+        // is 3.2 cpu. rg1's doubled weight grants it ~4.27 cpu, above its
+        // fair share, while rg2 is capped at the remaining ~2.13 cpu below
+        // both its fair share and its demand, so each ~1s adjustment window
+        // is charged to rg2's throttled time.
+        tick(&mut worker);
+        assert_eq!(
+            worker.get_throttled_duration("rg1", ResourceType::Cpu),
+            Duration::ZERO
+        );
+        let throttled = worker.get_throttled_duration("rg2", ResourceType::Cpu);
+        assert!(
+            throttled >= Duration::from_secs(1) && throttled < Duration::from_millis(1100),
+            "{:?}",
+            throttled
+        );
+
+        tick(&mut worker);
+        let throttled = worker.get_throttled_duration("rg2", ResourceType::Cpu);
+        assert!(
+            throttled >= Duration::from_secs(2) && throttled < Duration::from_millis(2200),
+            "{:?}",
+            throttled
+        );
+
+        // the counters are part of the transient state.
+        worker.reset();
+        assert_eq!(
+            worker.get_throttled_duration("rg2", ResourceType::Cpu),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_provider_measurement_window() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>| {
+            limiter1.consume(Duration::from_secs(2), IoBytes::default(), false);
+            limiter2.consume(Duration::from_millis(500), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 4.0;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // prime the baselines; the first observation counts no consumption.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        // the provider averaged its `current_used` over a 2s window, so the
+        // deltas of this ~1s tick are normalized with the same window: the
+        // consumed rates halve to 1 + 0.25 cpu, the available quota becomes
+        // (8 - 4 + 1.25) * 0.8 = 4.2 cpu and each group takes an even 2.1
+        // cpu share.
+        worker.resource_quota_getter.window_secs = Some(2.0);
+        tick(&mut worker);
+        let consumed_rate = |worker: &GroupQuotaAdjustWorker<TestResourceStatsProvider>,
+                             name: &str| {
+            worker
+                .last_adjustment_snapshot()
+                .iter()
+                .find(|a| a.name == name && a.resource_type == ResourceType::Cpu)
+                .unwrap()
+                .consumed_rate
+        };
+        check(consumed_rate(&worker, "rg1"), 1.0 * MICROS_PER_SEC);
+        check(consumed_rate(&worker, "rg2"), 0.25 * MICROS_PER_SEC);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.1 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.1 * MICROS_PER_SEC,
+        );
+
+        // without a reported window the same consumption is averaged over
+        // the tick itself, matching the historical behavior: the rates
+        // double and the available quota grows to (8 - 4 + 2.5) * 0.8 = 5.2
+        // cpu.
+        worker.resource_quota_getter.window_secs = None;
+        tick(&mut worker);
+        check(consumed_rate(&worker, "rg1"), 2.0 * MICROS_PER_SEC);
+        check(consumed_rate(&worker, "rg2"), 0.5 * MICROS_PER_SEC);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.6 * MICROS_PER_SEC,
+        );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.6 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_foreground_pressure_freezes_increases() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let pressure = worker.foreground_pressure();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>, cpu: f64| {
+            worker.resource_quota_getter.cpu_used = cpu;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // without pressure the group takes the whole free share.
+        tick(&mut worker, 4.0);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+
+        // a decrease is still applied while the pressure flag is raised.
+        pressure.store(true, Ordering::Relaxed);
+        tick(&mut worker, 6.0);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+
+        // the formula would grant 3.2 cpu again, but increases are frozen
+        // until the pressure clears.
+        tick(&mut worker, 4.0);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+
+        pressure.store(false, Ordering::Relaxed);
+        tick(&mut worker, 4.0);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_degrading_disk_bandwidth() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        // The modeled disk starts at 10000 bytes/s and loses half of its
+        // bandwidth with every clock advance.
+        let test_provider = DegradingDiskStatsProvider::new(8.0, 10000.0, 0.5);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<DegradingDiskStatsProvider>, io: f64| {
+            worker.resource_quota_getter.inner.io_used = io;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // At full bandwidth the group gets the whole free share of the disk.
+        tick(&mut worker, 2000.0);
+        check(
+            limiter.get_limiter(ResourceType::Io).get_rate_limit(),
+            (10000.0 - 2000.0) * 0.8,
+        );
+
+        // The disk throttles to half of its bandwidth: even with the same
+        // foreground usage, the assigned limit shrinks with the ceiling.
+        worker.resource_quota_getter.advance_clock();
+        tick(&mut worker, 2000.0);
+        check(
+            limiter.get_limiter(ResourceType::Io).get_rate_limit(),
+            (5000.0 - 2000.0) * 0.8,
+        );
+
+        // Another degradation step leaves barely any headroom and the limit
+        // keeps tracking the falling ceiling.
+        worker.resource_quota_getter.advance_clock();
+        tick(&mut worker, 2000.0);
+        check(
+            limiter.get_limiter(ResourceType::Io).get_rate_limit(),
+            (2500.0 - 2000.0) * 0.8,
+        );
+    }
+
+    #[test]
+    fn test_worker_config_round_trip() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker = GroupQuotaAdjustWorker::with_quota_getter(resource_ctl, test_provider);
+        worker.set_headroom_factor(0.6);
+        worker.set_low_load_ratio(0.2);
+        worker.set_max_change_ratio(2.0);
+        worker.set_ru_cost_factor(ResourceType::Cpu, 2.0);
+        worker.set_group_min_rate("rg1", ResourceType::Cpu, 0.5 * MICROS_PER_SEC);
+        worker.set_group_fixed_rate("rg2", ResourceType::Io, 1024.0);
+        worker.set_max_total_background_rate(ResourceType::Cpu, 4.0 * MICROS_PER_SEC);
+
+        let config = worker.config();
+        assert_eq!(config.adjust_interval["cpu"], BACKGROUND_LIMIT_ADJUST_DURATION);
+        assert_eq!(config.headroom_factor, 0.6);
+        assert_eq!(config.low_load_ratio, 0.2);
+        assert_eq!(config.max_change_ratio, Some(2.0));
+        assert_eq!(config.starvation_debt_decay, None);
+        assert_eq!(config.ru_cost_factor["cpu"], 2.0);
+        assert_eq!(config.ru_cost_factor["io"], 1.0);
+        assert_eq!(config.min_rate_floors["cpu"]["rg1"], 0.5 * MICROS_PER_SEC);
+        // Only the types with any entry are listed in the per-group maps.
+        assert!(!config.min_rate_floors.contains_key("io"));
+        assert_eq!(config.fixed_rate_overrides["io"]["rg2"], 1024.0);
+        assert_eq!(
+            config.max_total_background_rate["cpu"],
+            4.0 * MICROS_PER_SEC
+        );
+
+        let json = serde_json::to_string(&config).unwrap();
+        let round_tripped: WorkerConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, config);
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // prime the baseline, then observe one tick of 1 cpu consumption.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.resource_quota_getter.io_used = 5000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        limiter.consume(Duration::from_secs(1), IoBytes::default(), false);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        let dump = worker.debug_dump();
+        assert_eq!(dump.config, worker.config());
+        // the baseline snapshot carries the cumulative counter of the tick.
+        assert_eq!(
+            dump.prev_stats_by_group["cpu"]["default"].total_consumed,
+            1_000_000
+        );
+        // the cpu decision of the last tick: the pool is
+        // (8 - 4 + 1) * 0.8 = 4 cpu and the single group takes it all.
+        let cpu_adjustment = dump
+            .last_adjustments
+            .iter()
+            .find(|a| a.resource_type == "cpu")
+            .unwrap();
+        assert_eq!(cpu_adjustment.name, "default");
+        check(cpu_adjustment.rate_limit, 4.0 * MICROS_PER_SEC);
+        check(cpu_adjustment.consumed_rate, 1.0 * MICROS_PER_SEC);
+        assert!(dump.secs_since_last_adjust["cpu"] < 1.0);
+        // the EMA is disabled, so there is no smoothed sample to report.
+        assert_eq!(dump.smoothed_used["cpu"], None);
+        assert!(!dump.suppress_next_adjust);
+        assert_eq!(dump.provider_failure_counts["io"], 0);
+        assert!(dump.starvation_debt["cpu"].is_empty());
+
+        // the dump always serializes cleanly despite the NaN placeholders.
+        let json = serde_json::to_string(&dump).unwrap();
+        assert!(json.contains("\"suppress-next-adjust\":false"));
+    }
+
+    #[test]
+    fn test_unit_conversions() {
+        assert_eq!(CpuMicros::from_secs(2.0).0, 2.0 * MICROS_PER_SEC);
+        assert_eq!(CpuMicros::from_secs(1.5).as_secs(), 1.5);
+        assert_eq!(CpuMicros(500_000.0).as_secs(), 0.5);
+        // the default cost factor of 1.0 keeps RU and raw units identical.
+        assert_eq!(Ru(100.0).to_raw(1.0), 100.0);
+        assert_eq!(Ru(100.0).to_raw(2.5), 250.0);
+        assert_eq!(IoBytesPerSec::over_window(4096, 2.0).0, 2048.0);
+    }
+
+    #[test]
+    fn test_adjust_quota_units_unchanged() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // the wrapped conversions must reproduce the historical formulas
+        // exactly: cpu (8 - 4) * 0.8 cores in microseconds, io
+        // (10000 - 5000) * 0.8 bytes per second.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.resource_quota_getter.io_used = 5000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+        check(limiter.get_limiter(ResourceType::Io).get_rate_limit(), 4000.0);
+    }
+
+    #[test]
+    fn test_adjust_with_zero_ru_quota() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        // a group with 0 ru quota must not make the adjustment panic.
+        let rg2 = new_background_resource_group_ru("rg2".into(), 0, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+
+        worker.resource_quota_getter.cpu_used = 6.0;
+        worker.resource_quota_getter.io_used = 5000.0;
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(10); ResourceType::COUNT];
+        worker.adjust_quota();
+        // the zero-quota group is skipped entirely.
+        assert!(!worker.prev_stats_by_group[ResourceType::Cpu as usize].contains_key("rg2"));
+    }
+
+    #[test]
+    fn test_vanishing_total_weight() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // the big group's weight swallows the small one in f64 entirely:
+        // 1e16 + 1.0 == 1e16, so once the big group is handled the remaining
+        // total weight is exactly zero and the naive division would grant
+        // the small group an infinite share.
+        let big = new_background_resource_group_ru(
+            "big".into(),
+            10_000_000_000_000_000,
+            8,
+            vec!["br".into()],
+        );
+        resource_ctl.add_resource_group(big);
+        let small = new_background_resource_group_ru("small".into(), 1, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(small);
+        let limiter_big = resource_ctl
+            .get_background_resource_limiter("big", "br")
+            .unwrap();
+        let limiter_small = resource_ctl
+            .get_background_resource_limiter("small", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // prime the consumption baselines.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        // pin down the current limits and bound the change ratio, so the big
+        // group cannot take the whole pool and a remainder is left over for
+        // the small one.
+        limiter_big
+            .get_limiter(ResourceType::Cpu)
+            .set_rate_limit(1.0 * MICROS_PER_SEC);
+        limiter_small
+            .get_limiter(ResourceType::Cpu)
+            .set_rate_limit(2.0 * MICROS_PER_SEC);
+        worker.set_max_change_ratio(2.0);
+
+        // the big group consumed 2 cpu, so it is handled first and runs the
+        // total weight down to exactly zero. Its clamped limit of 2 cpu
+        // leaves (8 - 4 + 2) * 0.8 - 2 = 2.8 cpu in the pool, which the
+        // small group is assigned directly instead of dividing by zero.
+        limiter_big.consume(Duration::from_secs(2), IoBytes::default(), false);
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+        check(
+            limiter_big.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            2.0 * MICROS_PER_SEC,
+        );
+        let small_limit = limiter_small.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(small_limit.is_finite(), "{}", small_limit);
+        check(small_limit, 2.8 * MICROS_PER_SEC);
+    }
+
+    #[test]
+    fn test_max_total_background_rate() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        worker.set_max_total_background_rate(ResourceType::Io, 1000.0);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        // the formula assigns (10000 - 5000) * 0.8 = 4000 in total, but the
+        // ceiling scales both groups down so the sum equals the cap.
+        worker.resource_quota_getter.io_used = 5000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let limit1 = limiter1.get_limiter(ResourceType::Io).get_rate_limit();
+        let limit2 = limiter2.get_limiter(ResourceType::Io).get_rate_limit();
+        let sum = limit1 + limit2;
+        assert!(
+            1000.0 * 0.99 < sum && sum < 1000.0 * 1.01,
+            "limit1: {}, limit2: {}",
+            limit1,
+            limit2
+        );
+        // the snapshot reflects the capped limits as well.
+        for a in worker
+            .last_adjustment_snapshot()
+            .iter()
+            .filter(|a| a.resource_type == ResourceType::Io)
+        {
+            assert!(450.0 < a.rate_limit && a.rate_limit < 550.0, "actual: {:?}", a);
+        }
+    }
+
+    #[test]
+    fn test_adjustment_summary() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 3000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // no distribution has run yet, so there is nothing to summarize.
+        assert!(worker.last_adjustment_summary(ResourceType::Cpu).is_none());
+
+        // prime the consumption baselines.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        limiter1.consume(
+            Duration::from_secs(2),
             IoBytes {
-                read: 4000,
-                write: 4000,
+                read: 1000,
+                write: 1000,
+            },
+            true,
+        );
+        limiter2.consume(
+            Duration::from_secs(4),
+            IoBytes {
+                read: 2000,
+                write: 2000,
             },
+            true,
+        );
+        worker.resource_quota_getter.cpu_used = 8.0;
+        worker.resource_quota_getter.io_used = 7000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        // cpu: the groups consumed 2 + 4 cpu while the machine is fully
+        // used, so the pool is (8 - 8 + 6) * 0.8 = 4.8 cpu and the demand
+        // of 6 cpu exceeds it.
+        let summary = worker.last_adjustment_summary(ResourceType::Cpu).unwrap();
+        check(summary.total_quota, 8.0 * MICROS_PER_SEC);
+        check(summary.current_used, 8.0 * MICROS_PER_SEC);
+        check(summary.background_consumed_total, 6.0 * MICROS_PER_SEC);
+        check(summary.available_quota, 4.8 * MICROS_PER_SEC);
+        // the aggregates are consistent with the per-group entries: the
+        // summed consumed rate matches, and under scarcity the assigned
+        // limits exactly exhaust the recorded pool.
+        let snapshot = worker.last_adjustment_snapshot();
+        let cpu_consumed: f64 = snapshot
+            .iter()
+            .filter(|a| a.resource_type == ResourceType::Cpu)
+            .map(|a| a.consumed_rate)
+            .sum();
+        check(cpu_consumed, summary.background_consumed_total);
+        let cpu_limit_total: f64 = snapshot
+            .iter()
+            .filter(|a| a.resource_type == ResourceType::Cpu)
+            .map(|a| a.rate_limit)
+            .sum();
+        check(cpu_limit_total, summary.available_quota);
+
+        // io: the pool of (10000 - 7000 + 6000) * 0.8 = 7200 covers the
+        // demand of 6000, and the quota-enough branch hands the whole pool
+        // out as well.
+        let summary = worker.last_adjustment_summary(ResourceType::Io).unwrap();
+        check(summary.total_quota, 10000.0);
+        check(summary.current_used, 7000.0);
+        check(summary.background_consumed_total, 6000.0);
+        check(summary.available_quota, 7200.0);
+        let io_limit_total: f64 = snapshot
+            .iter()
+            .filter(|a| a.resource_type == ResourceType::Io)
+            .map(|a| a.rate_limit)
+            .sum();
+        check(io_limit_total, summary.available_quota);
+
+        // the net quota is reported as zero, so the groups run unlimited
+        // and no distribution is summarized.
+        assert!(worker.last_adjustment_summary(ResourceType::Net).is_none());
+    }
+
+    #[test]
+    fn test_throttled_groups() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // a group without a background limiter is never listed.
+        resource_ctl.add_resource_group(new_resource_group_ru("fg".into(), 1000, 8));
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+
+        // all the limiters start unlimited.
+        assert!(worker.throttled_groups(ResourceType::Cpu).is_empty());
+        assert!(worker.throttled_groups(ResourceType::Io).is_empty());
+
+        // only rg1's cpu limiter is throttled.
+        limiter1
+            .get_limiter(ResourceType::Cpu)
+            .set_rate_limit(1000.0);
+        assert_eq!(worker.throttled_groups(ResourceType::Cpu), ["rg1"]);
+        assert!(worker.throttled_groups(ResourceType::Io).is_empty());
+
+        // an adjustment tick throttles both groups on cpu and io, while the
+        // unmeasured net stays unlimited.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.resource_quota_getter.io_used = 5000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let mut names = worker.throttled_groups(ResourceType::Cpu);
+        names.sort();
+        assert_eq!(names, ["rg1", "rg2"]);
+        let mut names = worker.throttled_groups(ResourceType::Io);
+        names.sort();
+        assert_eq!(names, ["rg1", "rg2"]);
+        assert!(worker.throttled_groups(ResourceType::Net).is_empty());
+
+        // lifting a limit removes the group from the list again.
+        limiter1
+            .get_limiter(ResourceType::Cpu)
+            .set_rate_limit(f64::INFINITY);
+        assert_eq!(worker.throttled_groups(ResourceType::Cpu), ["rg2"]);
+    }
+
+    #[test]
+    fn test_on_limit_change_callback() {
+        use std::sync::Mutex;
+
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let changes: Arc<Mutex<Vec<(String, ResourceType, f64, f64)>>> =
+            Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        worker.set_on_limit_change(move |name, ty, old, new| {
+            recorded.lock().unwrap().push((name.to_owned(), ty, old, new));
+        });
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+
+        // the first adjustment changes the cpu and io limits from infinite
+        // to a finite value and reports both.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        {
+            let changes = changes.lock().unwrap();
+            assert_eq!(changes.len(), 2, "changes: {:?}", changes);
+            let cpu = changes
+                .iter()
+                .find(|(_, ty, ..)| *ty == ResourceType::Cpu)
+                .unwrap();
+            assert_eq!(cpu.0, "default");
+            assert!(cpu.2.is_infinite());
+            assert!(
+                3.2 * MICROS_PER_SEC * 0.99 < cpu.3 && cpu.3 < 3.2 * MICROS_PER_SEC * 1.01,
+                "actual: {}",
+                cpu.3
+            );
+        }
+
+        // a tick computing the exact same limits does not fire the callback
+        // again.
+        changes.lock().unwrap().clear();
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(changes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reset_worker_state() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        // consume a large amount of cpu time before the (re-)acquisition of
+        // the adjusting role.
+        limiter.consume(Duration::from_secs(100), IoBytes::default(), true);
+        worker.reset();
+        assert!(worker.last_adjustments.is_empty());
+
+        // the first post-reset tick must not attribute the pre-reset
+        // consumption to the last period, so the group simply receives its
+        // share of the available quota instead of an inflated limit.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        let limit = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(
+            0.8 * MICROS_PER_SEC * 0.99 < limit && limit < 0.8 * MICROS_PER_SEC * 1.01,
+            "actual: {}",
+            limit
+        );
+    }
+
+    #[test]
+    fn test_set_adjust_interval() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // the default cadence keeps the historical 1s minimum.
+        assert_eq!(
+            worker.min_adjust_interval(ResourceType::Cpu),
+            Duration::from_secs(1)
+        );
+        // a very fast cadence is clamped to a 100ms minimum.
+        worker.set_adjust_interval(Duration::from_millis(100));
+        assert_eq!(
+            worker.min_adjust_interval(ResourceType::Cpu),
+            Duration::from_millis(100)
+        );
+
+        worker.set_adjust_interval(Duration::from_secs(2));
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+
+        // a tick well below the configured interval is skipped, a tick at
+        // the full 2s interval is applied.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_millis(100); ResourceType::COUNT];
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(2); ResourceType::COUNT];
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+    }
+
+    #[test]
+    fn test_per_type_adjust_intervals() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // cpu adjusts every second while io runs on a 100s cadence: every
+        // type is gated on its own full interval.
+        worker.set_adjust_interval_of(ResourceType::Cpu, Duration::from_secs(1));
+        worker.set_adjust_interval_of(ResourceType::Io, Duration::from_secs(100));
+
+        // one second elapsed on both timers: only cpu is due, io keeps its
+        // unlimited rate.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        worker.resource_quota_getter.io_used = 2000.0;
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(1); ResourceType::COUNT];
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Io)
+                .get_rate_limit()
+                .is_infinite()
         );
 
-        reset_quota(&mut worker, 4.0, 2000.0, Duration::from_secs(1));
+        // ten seconds on the io timer — a tenth of its cadence — is still
+        // not enough: the io limit stays unlimited.
+        worker.resource_quota_getter.cpu_used = 6.0;
+        worker.last_adjust_time[ResourceType::Io as usize] =
+            Instant::now_coarse() - Duration::from_secs(10);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Io)
+                .get_rate_limit()
+                .is_infinite()
+        );
+
+        // only once the full 100s io interval has elapsed does io get
+        // adjusted, while the cpu limit stays put even though the cpu usage
+        // changed, since the cpu timer was just reset above.
+        worker.last_adjust_time[ResourceType::Io as usize] =
+            Instant::now_coarse() - Duration::from_secs(100);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+        check(
+            limiter.get_limiter(ResourceType::Io).get_rate_limit(),
+            (10000.0 - 2000.0) * 0.8,
+        );
+        check(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            3.2 * MICROS_PER_SEC,
+        );
+    }
+
+    #[test]
+    fn test_set_uniform_limit() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 2000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // every background limiter is throttled to the uniform rate at once.
+        worker.set_uniform_limit(64.0);
+        for limiter in [&limiter1, &limiter2] {
+            check(limiter.get_limiter(ResourceType::Cpu).get_rate_limit(), 64.0);
+            check(limiter.get_limiter(ResourceType::Io).get_rate_limit(), 64.0);
+        }
+
+        // the tick right after the override is skipped, so the manual rates
+        // survive it.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedManualOverride);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            64.0,
+        );
+
+        // the regular adjustment resumes on the following tick and recomputes
+        // the limits.
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+        assert_ne!(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            64.0
+        );
+    }
+
+    #[test]
+    fn test_dry_run_preview() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        // apply one real adjustment first.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            3.2,
-            IoBytes {
-                read: 3200,
-                write: 3200,
-            },
+        let applied = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(applied.is_finite());
+
+        // the preview computes the decisions for the new load but leaves the
+        // limiter untouched, and the dry-run flag is restored afterwards.
+        worker.resource_quota_getter.cpu_used = 7.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        let preview = worker.adjust_quota_preview();
+        assert!(!worker.dry_run);
+        assert_eq!(
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            applied
+        );
+        let adj = preview
+            .iter()
+            .find(|a| a.name == "default" && a.resource_type == ResourceType::Cpu)
+            .unwrap();
+        // (8.0 - 7.0) * 0.8 cores would be assigned for the heavier load.
+        assert!(
+            0.8 * MICROS_PER_SEC * 0.99 < adj.rate_limit
+                && adj.rate_limit < 0.8 * MICROS_PER_SEC * 1.01,
+            "actual: {}",
+            adj.rate_limit
         );
+    }
 
-        reset_quota(&mut worker, 6.0, 4000.0, Duration::from_secs(1));
-        limiter.consume(
-            Duration::from_secs(2),
-            IoBytes {
-                read: 1000,
-                write: 1000,
-            },
-            true,
+    #[test]
+    fn test_adjust_single_group() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // an unknown group is rejected.
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert!(worker.adjust_group("unknown").is_none());
+
+        // a regular tick splits the 3.2 cpu quota between the two groups.
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+
+        // the on-demand recomputation considers rg1 alone against the full
+        // 6.4 cpu quota of the now idle process, while rg2 keeps its limit.
+        worker.resource_quota_getter.cpu_used = 0.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        let adjustments = worker.adjust_group("rg1").unwrap();
+        let cpu = adjustments
+            .iter()
+            .find(|a| a.resource_type == ResourceType::Cpu)
+            .unwrap();
+        check(cpu.rate_limit, 6.4 * MICROS_PER_SEC);
+        check(
+            limiter1.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            6.4 * MICROS_PER_SEC,
         );
+        check(
+            limiter2.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            1.6 * MICROS_PER_SEC,
+        );
+
+        // calling it again right away is skipped like a regular tick.
+        assert!(worker.adjust_group("rg1").is_none());
+    }
+
+    #[test]
+    fn test_priority_weighted_share() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // two groups with equal RU settings but different priorities.
+        let rg_low = new_background_resource_group_ru("rg_low".into(), 1000, 1, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_low);
+        let rg_high =
+            new_background_resource_group_ru("rg_high".into(), 1000, 16, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_high);
+        let limiter_low = resource_ctl
+            .get_background_resource_limiter("rg_low", "br")
+            .unwrap();
+        let limiter_high = resource_ctl
+            .get_background_resource_limiter("rg_high", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // with 0.8 cpu available, the shares split by the effective weights
+        // 1000 * 0.5 (low) and 1000 * 2.0 (high), so the high-priority group
+        // receives four times the limit of the low-priority one.
+        worker.resource_quota_getter.cpu_used = 7.5;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            3.2,
-            IoBytes {
-                read: 3200,
-                write: 3200,
-            },
+        check(
+            limiter_low.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.16 * MICROS_PER_SEC,
         );
+        check(
+            limiter_high.get_limiter(ResourceType::Cpu).get_rate_limit(),
+            0.64 * MICROS_PER_SEC,
+        );
+    }
 
-        reset_quota(&mut worker, 8.0, 9500.0, Duration::from_secs(1));
+    #[test]
+    fn test_starvation_debt_bias() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // invalid decay falls back to the default.
+        worker.set_starvation_debt_decay(1.5);
+        assert_eq!(
+            worker.starvation_debt_decay,
+            Some(DEFAULT_STARVATION_DEBT_DECAY)
+        );
+        worker.set_starvation_debt_decay(0.5);
+
+        // equal RU settings, but the low priority gives rg_low only a fifth
+        // of the effective weight, so under scarcity its assigned limit
+        // stays below its ru-proportional fair share tick after tick.
+        let rg_low = new_background_resource_group_ru("rg_low".into(), 1000, 1, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_low);
+        let rg_high =
+            new_background_resource_group_ru("rg_high".into(), 1000, 16, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg_high);
+        let limiter_low = resource_ctl
+            .get_background_resource_limiter("rg_low", "br")
+            .unwrap();
+        let limiter_high = resource_ctl
+            .get_background_resource_limiter("rg_high", "br")
+            .unwrap();
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        // prime the baselines; the first observation counts no consumption.
+        worker.resource_quota_getter.cpu_used = 8.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            0.8,
-            IoBytes {
-                read: 500,
-                write: 500,
-            },
+
+        let mut tick = || {
+            // both groups demand more than the scarce quota every tick. The
+            // wait recording is disabled so the expected cost of each tick
+            // stays exactly at the consumed rate.
+            limiter_low.consume(Duration::from_secs(2), IoBytes::default(), false);
+            limiter_high.consume(Duration::from_secs(9), IoBytes::default(), false);
+            worker.resource_quota_getter.cpu_used = 8.0;
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+            limiter_low.get_limiter(ResourceType::Cpu).get_rate_limit()
+        };
+
+        // the first tick splits the clamped 6.4 cpu quota purely by effective
+        // weight, capping rg_low at 6.4 * 500 / 2500 = 1.28 cpu although its
+        // ru-proportional fair share would be 3.2 cpu, so it accrues debt.
+        let first = tick();
+        check(first, 1.28 * MICROS_PER_SEC);
+
+        // the debt is converted into extra weight on the next tick, which
+        // lifts the starved group's share enough to serve its full 2 cpu
+        // demand.
+        let second = tick();
+        check(second, 2.0 * MICROS_PER_SEC);
+        assert!(second > first);
+    }
+
+    #[test]
+    fn test_starvation_rotation() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        worker.set_starvation_rotation(true);
+        assert!(worker.config().starvation_rotation);
+
+        // one light and two heavy groups with equal weights: the slack the
+        // light group leaves in the pool only flows to the groups handled
+        // after it, so without the rotation the same head pick order decides
+        // who bursts above the fair share every tick.
+        let names = ["rg1", "rg2", "rg3"];
+        for name in names {
+            let rg = new_background_resource_group_ru(name.into(), 1000, 8, vec!["br".into()]);
+            resource_ctl.add_resource_group(rg);
+        }
+        let limiters: Vec<_> = names
+            .iter()
+            .map(|name| {
+                resource_ctl
+                    .get_background_resource_limiter(name, "br")
+                    .unwrap()
+            })
+            .collect();
+
+        // prime the baselines; the first observation counts no consumption.
+        worker.resource_quota_getter.io_used = 10000.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+
+        // every tick the pool is (10000 - 10000 + 5500) * 0.8 = 4400 io
+        // against a demand of 5500, so the quota-short branch runs and the
+        // light rg1 releases 4400 / 3 - 500 of slack each time.
+        let mut max_fair_ratio = [0.0_f64; 3];
+        for _ in 0..names.len() {
+            limiters[0].consume(Duration::ZERO, IoBytes { read: 500, write: 0 }, false);
+            for limiter in &limiters[1..] {
+                limiter.consume(Duration::ZERO, IoBytes { read: 2500, write: 0 }, false);
+            }
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+            let fair = worker
+                .last_adjustment_summary(ResourceType::Io)
+                .unwrap()
+                .available_quota
+                / 3.0;
+            for adjustment in worker.last_adjustment_snapshot() {
+                if adjustment.resource_type != ResourceType::Io {
+                    continue;
+                }
+                let idx = names
+                    .iter()
+                    .position(|name| *name == adjustment.name)
+                    .unwrap();
+                max_fair_ratio[idx] = max_fair_ratio[idx].max(adjustment.rate_limit / fair);
+            }
+        }
+
+        // the light group never exceeds its own demand, which stays below
+        // the fair share.
+        assert!(max_fair_ratio[0] < 1.0, "{:?}", max_fair_ratio);
+        // each heavy group got an above-fair-share allocation on some tick.
+        assert!(max_fair_ratio[1] > 1.2, "{:?}", max_fair_ratio);
+        assert!(max_fair_ratio[2] > 1.2, "{:?}", max_fair_ratio);
+        // the tick starting right behind the light group hands one heavy
+        // group nearly the whole slack on top of its fair share, a burst the
+        // fixed order never produces (it always splits the slack in two).
+        assert!(
+            max_fair_ratio[1].max(max_fair_ratio[2]) > 1.5,
+            "{:?}",
+            max_fair_ratio
+        );
+    }
+
+    #[test]
+    fn test_wait_relief() {
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+
+        fn run(relief: bool) -> f64 {
+            let resource_ctl = Arc::new(ResourceGroupManager::default());
+            let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+            let mut worker =
+                GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+            if relief {
+                // an invalid factor falls back to the default.
+                worker.set_wait_relief_factor(-1.0);
+                assert_eq!(worker.wait_relief_factor, Some(DEFAULT_WAIT_RELIEF_FACTOR));
+            }
+            let rg = new_background_resource_group_ru("rg".into(), 1000, 8, vec!["br".into()]);
+            resource_ctl.add_resource_group(rg);
+            let limiter = resource_ctl
+                .get_background_resource_limiter("rg", "br")
+                .unwrap();
+
+            // a saturated process leaves only the 0.8 cpu quota floor.
+            worker.resource_quota_getter.cpu_used = 7.5;
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+
+            // the group demands far more than its tight limit, so it spends
+            // over a second waiting on the limiter during the next period.
+            limiter.consume(Duration::from_secs(2), IoBytes::default(), true);
+            worker.resource_quota_getter.cpu_used = 6.0;
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+            limiter.get_limiter(ResourceType::Cpu).get_rate_limit()
+        }
+
+        // without the signal the limit only recovers to the plain available
+        // quota of (8 - 6 + 2) * 0.8 cpu.
+        let without = run(false);
+        check(without, 3.2 * MICROS_PER_SEC);
+        // the aggregate wait of over a second doubles the free share, which
+        // the headroom clamp then caps at 6.4 cpu.
+        let with = run(true);
+        check(with, 6.4 * MICROS_PER_SEC);
+        assert!(with > without);
+    }
+
+    #[test]
+    fn test_adjust_outcome() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // the adjust interval has not elapsed yet.
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+
+        // no background group is registered.
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedNoGroups);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+
+        // a failing provider is reported, while the healthy resource types
+        // are still adjusted.
+        worker.resource_quota_getter.fail_type = Some(ResourceType::Io);
+        worker.resource_quota_getter.cpu_used = 2.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(
+            worker.adjust_quota(),
+            AdjustOutcome::ProviderError(ResourceType::Io)
+        );
+        let limit = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(
+            4.8 * MICROS_PER_SEC * 0.99 < limit && limit < 4.8 * MICROS_PER_SEC * 1.01,
+            "actual: {}",
+            limit
         );
+    }
+
+    #[test]
+    fn test_no_background_group_fast_path() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        fn backdate(worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>) {
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+        }
+
+        // a cluster full of foreground-only groups: none of them owns a
+        // limiter, so the cached count lets `adjust_quota` bail out without
+        // scanning the whole group map.
+        for i in 0..100 {
+            resource_ctl.add_resource_group(new_resource_group_ru(format!("fg{}", i), 100, 8));
+        }
+        assert_eq!(resource_ctl.get_group_count(), 101);
+        assert_eq!(resource_ctl.get_background_group_count(), 0);
+        backdate(&mut worker);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedNoGroups);
+
+        // the count follows the limiter through the whole group lifecycle.
+        let bg = new_background_resource_group_ru("bg".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg.clone());
+        assert_eq!(resource_ctl.get_background_group_count(), 1);
+        backdate(&mut worker);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+
+        // updating a group that already owns a limiter keeps the count
+        // stable, while dropping its background setting releases it.
+        resource_ctl.add_resource_group(bg.clone());
+        assert_eq!(resource_ctl.get_background_group_count(), 1);
+        resource_ctl.add_resource_group(new_resource_group_ru("bg".into(), 1000, 8));
+        assert_eq!(resource_ctl.get_background_group_count(), 0);
+        backdate(&mut worker);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedNoGroups);
+
+        // both removal paths decrement the count as well.
+        resource_ctl.add_resource_group(bg.clone());
+        resource_ctl.remove_resource_group("bg");
+        assert_eq!(resource_ctl.get_background_group_count(), 0);
+        resource_ctl.add_resource_group(bg);
+        resource_ctl.retain(|name, _| name.as_str() != "bg");
+        assert_eq!(resource_ctl.get_background_group_count(), 0);
+        backdate(&mut worker);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedNoGroups);
+    }
+
+    #[test]
+    fn test_provider_warn_rate_limit() {
+        use std::sync::Mutex;
+
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let warns: Arc<Mutex<Vec<ResourceType>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = warns.clone();
+        worker.set_on_provider_warn(move |ty| {
+            recorded.lock().unwrap().push(ty);
+        });
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
 
-        reset_quota(&mut worker, 7.5, 9500.0, Duration::from_secs(1));
-        limiter.consume(
-            Duration::from_secs(2),
-            IoBytes {
-                read: 1000,
-                write: 1000,
-            },
-            true,
-        );
-        worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            2.0,
-            IoBytes {
-                read: 1000,
-                write: 1000,
-            },
-        );
+        // many consecutive failing ticks within the default one-minute
+        // window log (and report) the warning exactly once, while every
+        // failure is still counted.
+        worker.resource_quota_getter.fail_type = Some(ResourceType::Io);
+        for _ in 0..10 {
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            assert_eq!(
+                worker.adjust_quota(),
+                AdjustOutcome::ProviderError(ResourceType::Io)
+            );
+        }
+        assert_eq!(*warns.lock().unwrap(), vec![ResourceType::Io]);
+        assert_eq!(worker.provider_failure_count(ResourceType::Io), 10);
+        assert_eq!(worker.provider_failure_count(ResourceType::Cpu), 0);
 
-        reset_quota(&mut worker, 7.5, 9500.0, Duration::from_secs(5));
-        limiter.consume(
-            Duration::from_secs(10),
-            IoBytes {
-                read: 5000,
-                write: 5000,
-            },
-            true,
-        );
+        // a zero interval disables the gate and warns on every failure.
+        worker.set_provider_warn_interval(Duration::ZERO);
+        for _ in 0..3 {
+            elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        }
+        assert_eq!(warns.lock().unwrap().len(), 4);
+        assert_eq!(worker.provider_failure_count(ResourceType::Io), 13);
+
+        // a reset clears the gate, so the failure persisting across a role
+        // transition is logged once more.
+        worker.set_provider_warn_interval(Duration::from_secs(60));
+        worker.reset();
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            2.0,
-            IoBytes {
-                read: 1000,
-                write: 1000,
-            },
-        );
+        assert_eq!(warns.lock().unwrap().len(), 5);
+    }
 
-        let default =
-            new_background_resource_group_ru("default".into(), 2000, 8, vec!["br".into()]);
-        resource_ctl.add_resource_group(default);
-        let new_limiter = resource_ctl
+    #[test]
+    fn test_mock_clock_drives_ticks() {
+        use std::sync::atomic::AtomicU64;
+
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        worker.resource_quota_getter.cpu_used = 4.0;
+
+        // a hand-advanced mock clock drives the interval checks
+        // deterministically instead of backdating `last_adjust_time`.
+        let base = Instant::now_coarse();
+        let offset_ms = Arc::new(AtomicU64::new(0));
+        let tick = offset_ms.clone();
+        worker.set_clock(move || base + Duration::from_millis(tick.load(Ordering::Relaxed)));
+        worker.set_adjust_interval(Duration::from_secs(1));
+        // re-stamp the adjustment timers through the mock clock.
+        worker.reset();
+
+        // a 1s cadence gates every tick on its full interval.
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+        offset_ms.store(999, Ordering::Relaxed);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+        offset_ms.store(1000, Ordering::Relaxed);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+
+        // an accepted tick re-stamps the timers at the mock time, so the
+        // next tick is gated relative to it.
+        offset_ms.store(1500, Ordering::Relaxed);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::SkippedShortInterval);
+        offset_ms.store(2000, Ordering::Relaxed);
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+    }
+
+    #[test]
+    fn test_io_type_granular_limits() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
             .get_background_resource_limiter("default", "br")
             .unwrap();
-        assert_eq!(&*new_limiter as *const _, &*limiter as *const _);
+        assert!(!limiter.io_type_limits_enabled());
+        limiter.enable_io_type_limits();
+        assert!(limiter.io_type_limits_enabled());
 
-        let bg = new_background_resource_group_ru("background".into(), 1000, 15, vec!["br".into()]);
-        resource_ctl.add_resource_group(bg);
-        let bg_limiter = resource_ctl
-            .get_background_resource_limiter("background", "br")
+        // the observed traffic is 3/4 compaction reads and 1/4 flush writes,
+        // so the assigned io limit of (10000 - 5000) * 0.8 = 4000 is split
+        // 3000/1000 across the two types; types without observed traffic
+        // stay unlimited.
+        worker.resource_quota_getter.io_used = 5000.0;
+        worker.resource_quota_getter.io_breakdown[IoType::Compaction as usize] = IoBytes {
+            read: 3000,
+            write: 0,
+        };
+        worker.resource_quota_getter.io_breakdown[IoType::Flush as usize] = IoBytes {
+            read: 0,
+            write: 1000,
+        };
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::Adjusted);
+
+        #[track_caller]
+        fn check(val: f64, expected: f64) {
+            assert!(
+                expected * 0.99 < val && val < expected * 1.01,
+                "actual: {}, expected: {}",
+                val,
+                expected
+            );
+        }
+        check(limiter.get_limiter(ResourceType::Io).get_rate_limit(), 4000.0);
+        let sub_limit = |ty: IoType| limiter.get_io_type_limiter(ty).unwrap().get_rate_limit();
+        check(sub_limit(IoType::Compaction), 3000.0);
+        check(sub_limit(IoType::Flush), 1000.0);
+        assert!(sub_limit(IoType::Other).is_infinite());
+
+        // a breakdown without any traffic resets the sub-limits to
+        // unlimited, the aggregate limiter alone keeps pacing the group.
+        worker.resource_quota_getter.io_breakdown = [IoBytes::default(); IoType::COUNT];
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        worker.adjust_quota();
+        assert!(sub_limit(IoType::Compaction).is_infinite());
+        assert!(sub_limit(IoType::Flush).is_infinite());
+        check(limiter.get_limiter(ResourceType::Io).get_rate_limit(), 4000.0);
+    }
+
+    #[test]
+    fn test_available_quota_upper_clamp() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        let rg1 = new_background_resource_group_ru("rg1".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg1);
+        let rg2 = new_background_resource_group_ru("rg2".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(rg2);
+        let limiter1 = resource_ctl
+            .get_background_resource_limiter("rg1", "br")
+            .unwrap();
+        let limiter2 = resource_ctl
+            .get_background_resource_limiter("rg2", "br")
             .unwrap();
 
-        reset_quota(&mut worker, 5.0, 7000.0, Duration::from_secs(1));
+        // prime the baselines first, the consumption of a group only counts
+        // from its second observation on.
+        worker.resource_quota_getter.io_used = 1500.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            1.6,
-            IoBytes {
-                read: 800,
-                write: 800,
-            },
-        );
-        check_limiter(
-            &bg_limiter,
-            0.8,
-            IoBytes {
-                read: 400,
-                write: 400,
-            },
-        );
 
-        reset_quota(&mut worker, 6.0, 5000.0, Duration::from_secs(1));
-        limiter.consume(
-            Duration::from_millis(1200),
+        // the background groups consumed far more io than the free quota
+        // while the momentary usage is low, so the raw available quota
+        // (10000 - 1500 + 24000) * 0.8 would exceed the total quota of
+        // 10000. The upper clamp caps it at 10000 * 0.8 instead.
+        limiter1.consume(
+            Duration::ZERO,
             IoBytes {
-                read: 600,
-                write: 600,
+                read: 6000,
+                write: 6000,
             },
-            true,
+            false,
         );
-        bg_limiter.consume(
-            Duration::from_millis(1800),
+        limiter2.consume(
+            Duration::ZERO,
             IoBytes {
-                read: 900,
-                write: 900,
+                read: 6000,
+                write: 6000,
             },
-            true,
+            false,
         );
+        worker.resource_quota_getter.io_used = 1500.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            1.2,
-            IoBytes {
-                read: 1800,
-                write: 1800,
-            },
+        let limit1 = limiter1.get_limiter(ResourceType::Io).get_rate_limit();
+        let limit2 = limiter2.get_limiter(ResourceType::Io).get_rate_limit();
+        let sum = limit1 + limit2;
+        assert!(
+            sum <= 10000.0 * DEFAULT_HEADROOM_FACTOR * 1.01,
+            "limit1: {}, limit2: {}",
+            limit1,
+            limit2
         );
-        check_limiter(
-            &bg_limiter,
-            2.8,
-            IoBytes {
-                read: 1400,
-                write: 1400,
-            },
+        assert!(
+            8000.0 * 0.99 < sum,
+            "limit1: {}, limit2: {}",
+            limit1,
+            limit2
         );
+    }
 
-        let bg = new_resource_group_ru("background".into(), 1000, 15);
-        resource_ctl.add_resource_group(bg);
+    #[test]
+    fn test_provider_health() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        assert_eq!(worker.provider_health(), ProviderHealth::Healthy);
 
-        let new_bg =
-            new_background_resource_group_ru("background".into(), 1000, 15, vec!["br".into()]);
-        resource_ctl.add_resource_group(new_bg);
-        let new_bg_limiter = resource_ctl
-            .get_background_resource_limiter("background", "br")
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
             .unwrap();
-        assert_ne!(&*bg_limiter as *const _, &*new_bg_limiter as *const _);
-        assert!(
-            new_bg_limiter
-                .get_limit_statistics(ResourceType::Cpu)
-                .version
-                > bg_limiter.get_limit_statistics(ResourceType::Cpu).version
-        );
-        let cpu_stats = new_bg_limiter.get_limit_statistics(ResourceType::Cpu);
-        assert_eq!(cpu_stats.total_consumed, 0);
-        assert_eq!(cpu_stats.total_wait_dur_us, 0);
-        let io_stats = new_bg_limiter.get_limit_statistics(ResourceType::Io);
-        assert_eq!(io_stats.total_consumed, 0);
-        assert_eq!(io_stats.total_wait_dur_us, 0);
 
-        reset_quota(&mut worker, 0.0, 0.0, Duration::from_secs(1));
-        worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            4.27,
-            IoBytes {
-                read: 2667,
-                write: 2667,
-            },
+        // a degraded provider still drives the adjustment, but the tick is
+        // flagged and the reason is exposed for alerting.
+        worker.resource_quota_getter.health =
+            ProviderHealth::Degraded("proc reads are failing".into());
+        worker.resource_quota_getter.cpu_used = 4.0;
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(worker.adjust_quota(), AdjustOutcome::AdjustedDegraded);
+        assert!(
+            limiter
+                .get_limiter(ResourceType::Cpu)
+                .get_rate_limit()
+                .is_finite()
         );
-        check_limiter(
-            &new_bg_limiter,
-            2.13,
-            IoBytes {
-                read: 1334,
-                write: 1334,
-            },
+        assert_eq!(
+            worker.provider_health(),
+            ProviderHealth::Degraded("proc reads are failing".into())
         );
 
-        reset_quota(&mut worker, 6.0, 5000.0, Duration::from_secs(1));
-        limiter.consume(
-            Duration::from_millis(1200),
-            IoBytes {
-                read: 600,
-                write: 600,
-            },
-            true,
-        );
-        new_bg_limiter.consume(
-            Duration::from_millis(1800),
-            IoBytes {
-                read: 900,
-                write: 900,
-            },
-            true,
+        // a provider error takes precedence over the degraded health.
+        worker.resource_quota_getter.fail_type = Some(ResourceType::Io);
+        elapse_adjust_interval(&mut worker, Duration::from_secs(1));
+        assert_eq!(
+            worker.adjust_quota(),
+            AdjustOutcome::ProviderError(ResourceType::Io)
         );
+    }
+
+    #[test]
+    fn test_low_load_debounce() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+        // the minimum effective debounce is 1.
+        worker.set_low_load_debounce(0);
+        assert_eq!(worker.low_load_debounce, 1);
+        worker.set_low_load_debounce(3);
+
+        let bg = new_background_resource_group_ru("default".into(), 1000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        let tick = |worker: &mut GroupQuotaAdjustWorker<TestResourceStatsProvider>, io: f64| {
+            worker.resource_quota_getter.io_used = io;
+            elapse_adjust_interval(worker, Duration::from_secs(1));
+            worker.adjust_quota();
+        };
+
+        // usage oscillating around the 10% threshold never fills the
+        // debounce window, so the fast path stays off and every tick still
+        // recomputes the limits.
+        for _ in 0..5 {
+            tick(&mut worker, 500.0);
+            tick(&mut worker, 1500.0);
+        }
+        assert!(!worker.low_load_active[ResourceType::Io as usize]);
+
+        // three consecutive low-load ticks enter the fast path, after which
+        // the limit is left untouched.
+        for _ in 0..3 {
+            tick(&mut worker, 500.0);
+        }
+        assert!(worker.low_load_active[ResourceType::Io as usize]);
+        let stable = limiter.get_limiter(ResourceType::Io).get_rate_limit();
+        tick(&mut worker, 500.0);
+        assert_eq!(limiter.get_limiter(ResourceType::Io).get_rate_limit(), stable);
 
+        // and three consecutive high-load ticks are required to leave it.
+        tick(&mut worker, 9000.0);
+        tick(&mut worker, 9000.0);
+        assert!(worker.low_load_active[ResourceType::Io as usize]);
+        tick(&mut worker, 9000.0);
+        assert!(!worker.low_load_active[ResourceType::Io as usize]);
+        assert_ne!(limiter.get_limiter(ResourceType::Io).get_rate_limit(), stable);
+    }
+
+    #[test]
+    fn test_set_low_load_ratio() {
+        let resource_ctl = Arc::new(ResourceGroupManager::default());
+        let test_provider = TestResourceStatsProvider::new(8.0, 10000.0);
+        let mut worker =
+            GroupQuotaAdjustWorker::with_quota_getter(resource_ctl.clone(), test_provider);
+
+        // invalid inputs are clamped into (0.0, 1.0).
+        worker.set_low_load_ratio(0.0);
+        assert!(worker.low_load_ratio > 0.0 && worker.low_load_ratio < 1.0);
+        worker.set_low_load_ratio(1.5);
+        assert!(worker.low_load_ratio > 0.0 && worker.low_load_ratio < 1.0);
+        worker.set_low_load_ratio(0.2);
+        assert_eq!(worker.low_load_ratio, 0.2);
+
+        let default_bg =
+            new_background_resource_group_ru("default".into(), 100000, 8, vec!["br".into()]);
+        resource_ctl.add_resource_group(default_bg);
+        let limiter = resource_ctl
+            .get_background_resource_limiter("default", "br")
+            .unwrap();
+
+        // cpu used is at 15%, which is low load under the new 0.2 ratio, but
+        // the first tick should still adjust the limit.
+        worker.resource_quota_getter.cpu_used = 1.2;
+        worker.last_adjust_time =
+            [Instant::now_coarse() - Duration::from_secs(10); ResourceType::COUNT];
         worker.adjust_quota();
-        check_limiter(
-            &limiter,
-            2.2,
-            IoBytes {
-                read: 2133,
-                write: 2133,
-            },
-        );
-        check_limiter(
-            &new_bg_limiter,
-            1.8,
-            IoBytes {
-                read: 1066,
-                write: 1066,
-            },
+        let limit = limiter.get_limiter(ResourceType::Cpu).get_rate_limit();
+        assert!(limit.is_finite());
+        // (8.0 - 1.2) * 0.8 cores for the only background group.
+        assert!(
+            5.44 * MICROS_PER_SEC * 0.99 < limit && limit < 5.44 * MICROS_PER_SEC * 1.01,
+            "actual: {}",
+            limit
         );
     }
 
@@ -1852,7 +1852,10 @@ pub async fn future_prepare_flashback_to_version<E: Engine, L: LockManager, F: K
         let (cb, f) = paired_future_callback();
         res = storage.sched_txn_command(req.clone().into(), cb);
         if matches!(res, Ok(())) {
-            res = f.await.unwrap_or_else(|e| Err(box_err!(e)));
+            res = f
+                .await
+                .unwrap_or_else(|e| Err(box_err!(e)))
+                .map(|_summary| ());
         }
     }
     let mut resp = PrepareFlashbackToVersionResponse::default();
@@ -1876,7 +1879,15 @@ pub async fn future_flashback_to_version<E: Engine, L: LockManager, F: KvFormat>
     let (cb, f) = paired_future_callback();
     let mut res = storage.sched_txn_command(req.clone().into(), cb);
     if matches!(res, Ok(())) {
-        res = f.await.unwrap_or_else(|e| Err(box_err!(e)));
+        // Log the summary as the confirmation receipt of the flashback.
+        res = f.await.unwrap_or_else(|e| Err(box_err!(e))).map(|summary| {
+            info!("flashback finished";
+                "region_id" => req.get_context().get_region_id(),
+                "start_ts" => req.get_start_ts(),
+                "commit_ts" => req.get_commit_ts(),
+                "summary" => ?summary,
+            );
+        });
     }
     if matches!(res, Ok(())) {
         // Only finish when flashback executed successfully.
@@ -152,6 +152,7 @@ make_auto_flush_static_metric! {
         flashback_to_version_read_write,
         flashback_to_version_rollback_lock,
         flashback_to_version_write,
+        flashback_estimate,
         flush,
         update_txn_status_cache,
         raw_get,
@@ -650,4 +651,19 @@ lazy_static! {
         "The count of running scheduler commands"
     )
     .unwrap();
+
+    pub static ref FLASHBACK_BATCH_READ_DURATION_HISTOGRAM_VEC: HistogramVec = register_histogram_vec!(
+        "tikv_storage_flashback_batch_read_duration_seconds",
+        "Bucketed histogram of the per-batch read duration of a flashback",
+        &["phase"],
+        exponential_buckets(0.00001, 2.0, 26).unwrap()
+    )
+    .unwrap();
+
+    pub static ref FLASHBACK_PROCESSED_KEYS_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_storage_flashback_processed_keys_total",
+        "Total number of locks rolled back and writes flashed back by a flashback",
+        &["phase"]
+    )
+    .unwrap();
 }
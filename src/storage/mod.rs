@@ -4243,9 +4243,18 @@ mod tests {
         mvcc::tests::{must_unlocked, must_written},
         test_util::*,
         txn::{
-            commands::{new_flashback_rollback_lock_cmd, new_flashback_write_cmd},
-            FLASHBACK_BATCH_SIZE,
+            commands::{
+                new_flashback_estimate_cmd, new_flashback_estimate_streaming_cmd,
+                new_flashback_locks_only_cmd, new_flashback_multi_range_cmd,
+                new_flashback_rollback_lock_cmd, new_flashback_sharded_write_cmds,
+                new_flashback_write_cmd, new_flashback_writes_only_cmd, FlashbackCancelToken,
+                FlashbackEstimatePartial, FlashbackEstimateSink, FlashbackObserver,
+                FlashbackProgress,
+            },
+            flashback_checkpoint_key, write_flashback_checkpoint, FLASHBACK_BATCH_SIZE,
+            FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
         },
+        types::FlashbackEstimateResult,
         *,
     };
     use crate::{
@@ -5568,10 +5577,18 @@ mod tests {
                 new_flashback_rollback_lock_cmd(
                     start_ts,
                     version,
+                    TimeStamp::zero(),
                     start_key.clone(),
                     end_key.clone(),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
                     Context::default(),
-                ),
+                )
+                .unwrap(),
                 expect_ok_callback(tx.clone(), 0),
             )
             .unwrap();
@@ -5582,16 +5599,276 @@ mod tests {
                     start_ts,
                     commit_ts,
                     version,
+                    TimeStamp::zero(),
                     start_key,
                     end_key,
+                    false,
+                    None,
+                    Vec::new(),
+                    // Most callers flash back to the very beginning, which
+                    // would trip the no-data guard otherwise.
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
                     Context::default(),
-                ),
+                )
+                .unwrap(),
                 expect_ok_callback(tx, 1),
             )
             .unwrap();
         rx.recv().unwrap();
     }
 
+    #[test]
+    fn test_flashback_observer() {
+        #[derive(Default)]
+        struct MockFlashbackObserver {
+            flashed_ranges: Mutex<Vec<(Vec<u8>, Option<Vec<u8>>)>>,
+        }
+
+        impl FlashbackObserver for MockFlashbackObserver {
+            fn on_flashback_done(&self, start_key: &Key, end_key: Option<&Key>) {
+                self.flashed_ranges.lock().push((
+                    start_key.as_encoded().clone(),
+                    end_key.map(|key| key.as_encoded().clone()),
+                ));
+            }
+        }
+
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let observer = Arc::new(MockFlashbackObserver::default());
+        storage
+            .get_scheduler()
+            .register_flashback_observer(observer.clone());
+
+        let mut ts = TimeStamp::zero();
+        let (tx, rx) = channel();
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::Put(
+                        (Key::from_raw(b"k"), b"v@1".to_vec()),
+                        Assertion::None,
+                    )],
+                    b"k".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+
+        // The prepare phase rewrites nothing yet, so it must not notify.
+        let start_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        assert!(observer.flashed_ranges.lock().is_empty());
+
+        // The commit at the end of the write phase makes the rewritten
+        // history visible and notifies the observer with the covered range.
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    start_ts,
+                    *ts.incr(),
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        assert_eq!(
+            *observer.flashed_ranges.lock(),
+            vec![(
+                Key::from_raw(b"k").as_encoded().clone(),
+                Some(Key::from_raw(b"z").as_encoded().clone()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_flashback_preserve_history() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // `k1` gets two versions and `k2` is only created after `version`.
+        for (key, value) in [
+            (b"k1", b"v@1".to_vec()),
+            (b"k1", b"v@3".to_vec()),
+            (b"k2", b"v@5".to_vec()),
+        ] {
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(Key::from_raw(key), value)],
+                        key.to_vec(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), 0),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![Key::from_raw(key)],
+                        ts,
+                        *ts.incr(),
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Flash back to version 2 preserving the history: only `k1` is
+        // restored to `v@1`, `k2` is left as it is.
+        let version = TimeStamp::from(2);
+        let start_ts = *ts.incr();
+        let commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k1"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    start_ts,
+                    commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k1"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    true,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let read_ts = *ts.incr();
+        expect_value(
+            b"v@1".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), read_ts))
+                .unwrap()
+                .0,
+        );
+        // `k2` was created after `version` but survives the preserve-history
+        // flashback, while a regular one would have deleted it.
+        expect_value(
+            b"v@5".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), read_ts))
+                .unwrap()
+                .0,
+        );
+        // The flashback only appends new versions, so the history rewound
+        // over is still readable below the flashback `commit_ts`.
+        expect_value(
+            b"v@3".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), 4.into()))
+                .unwrap()
+                .0,
+        );
+        // A regular flashback over the same range does remove `k2`.
+        let start_ts = *ts.incr();
+        let commit_ts = *ts.incr();
+        run_flashback_to_version(
+            &storage,
+            start_ts,
+            commit_ts,
+            version,
+            Key::from_raw(b"k1"),
+            Some(Key::from_raw(b"z")),
+        );
+        let read_ts = *ts.incr();
+        expect_value(
+            b"v@1".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), read_ts))
+                .unwrap()
+                .0,
+        );
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), read_ts))
+                .unwrap()
+                .0,
+        );
+    }
+
     #[test]
     fn test_flashback_to_version_lock() {
         let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
@@ -5778,45 +6055,2409 @@ mod tests {
     }
 
     #[test]
-    fn test_flashback_to_version_deleted_key() {
+    fn test_flashback_to_version_invalid_tso() {
         let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
             .build()
             .unwrap();
         let (tx, rx) = channel();
-        let mut ts = TimeStamp::zero();
-        let (k, v) = (Key::from_raw(b"k"), b"v".to_vec());
-        // Write a key.
+        // `commit_ts` must be greater than `start_ts`.
         storage
             .sched_txn_command(
-                commands::Prewrite::with_defaults(
-                    vec![Mutation::make_put(k.clone(), v.clone())],
-                    k.as_encoded().to_vec(),
-                    *ts.incr(),
-                ),
-                expect_ok_callback(tx.clone(), 0),
+                new_flashback_write_cmd(
+                    2.into(),
+                    2.into(),
+                    1.into(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_fail_callback(tx.clone(), 0, |e| match e {
+                    Error(box ErrorInner::Txn(TxnError(box TxnErrorInner::InvalidTxnTso {
+                        ..
+                    }))) => (),
+                    e => panic!("unexpected error chain: {:?}", e),
+                }),
             )
             .unwrap();
         rx.recv().unwrap();
+        // `version` must not be greater than `commit_ts`.
         storage
             .sched_txn_command(
-                commands::Commit::new(vec![k.clone()], ts, *ts.incr(), Context::default()),
-                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+                new_flashback_write_cmd(
+                    2.into(),
+                    3.into(),
+                    4.into(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_fail_callback(tx, 1, |e| match e {
+                    Error(box ErrorInner::Txn(TxnError(
+                        box TxnErrorInner::InvalidFlashbackVersion { .. },
+                    ))) => (),
+                    e => panic!("unexpected error chain: {:?}", e),
+                }),
             )
             .unwrap();
         rx.recv().unwrap();
-        expect_value(
-            v,
-            block_on(storage.get(Context::default(), k.clone(), ts))
+        // Neither flashback should have written anything.
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k"), 5.into()))
                 .unwrap()
                 .0,
         );
-        // Delete the key.
-        storage
-            .sched_txn_command(
-                commands::Prewrite::with_defaults(
-                    vec![Mutation::make_delete(k.clone())],
-                    k.as_encoded().to_vec(),
-                    *ts.incr(),
+    }
+
+    #[test]
+    fn test_flashback_below_gc_safe_point() {
+        // A version below the GC safe point reads potentially
+        // garbage-collected history, so the construction itself is rejected
+        // before anything is scheduled.
+        let err = new_flashback_rollback_lock_cmd(
+            15.into(),
+            5.into(),
+            // The mocked GC safe point, above the flashback version.
+            10.into(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+            false,
+            None,
+            Vec::new(),
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap_err();
+        match err {
+            TxnError(box TxnErrorInner::FlashbackBelowGcSafePoint {
+                version,
+                safe_point,
+            }) => {
+                assert_eq!(version, TimeStamp::from(5));
+                assert_eq!(safe_point, TimeStamp::from(10));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        // A version at the safe point is still usable.
+        new_flashback_rollback_lock_cmd(
+            15.into(),
+            10.into(),
+            10.into(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+            false,
+            None,
+            Vec::new(),
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap();
+        // A zero safe point means it is unknown and disables the check, e.g.
+        // on the gRPC path where the caller guarantees the version instead.
+        new_flashback_rollback_lock_cmd(
+            15.into(),
+            5.into(),
+            TimeStamp::zero(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+            false,
+            None,
+            Vec::new(),
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_flashback_to_version_cf_filter() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` twice and leave a lock on `k2`.
+        let (k1, v1, v2) = (Key::from_raw(b"k1"), b"v@1".to_vec(), b"v@2".to_vec());
+        for (i, value) in [&v1, &v2].into_iter().enumerate() {
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(k1.clone(), value.clone())],
+                        b"k1".to_vec(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![k1.clone()], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let version = 2.into();
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k2"), b"v@5".to_vec())],
+                    b"k2".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // A flashback restricted to `CF_LOCK` only rolls back the lock on `k2`
+        // and leaves the writes of `k1` untouched.
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    *ts.incr(),
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    Some(CF_LOCK),
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), *ts.incr()))
+                .unwrap()
+                .0,
+        );
+        expect_value(
+            v2,
+            block_on(storage.get(Context::default(), k1.clone(), ts))
+                .unwrap()
+                .0,
+        );
+        // A flashback restricted to `CF_WRITE` restores the writes of `k1`.
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    Some(CF_WRITE),
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    Some(CF_WRITE),
+                    Vec::new(),
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 5),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_value(
+            v1,
+            block_on(storage.get(Context::default(), k1, *ts.incr()))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_locks_only() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` twice and leave a lock on `k2`.
+        let (k1, v1, v2) = (Key::from_raw(b"k1"), b"v@1".to_vec(), b"v@2".to_vec());
+        for (i, value) in [&v1, &v2].into_iter().enumerate() {
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(k1.clone(), value.clone())],
+                        b"k1".to_vec(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![k1.clone()], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k2"), b"v@5".to_vec())],
+                    b"k2".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // A locks-only flashback rolls back the lock on `k2` with a single
+        // command and never rewrites the writes of `k1`.
+        storage
+            .sched_txn_command(
+                new_flashback_locks_only_cmd(
+                    *ts.incr(),
+                    2.into(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), *ts.incr()))
+                .unwrap()
+                .0,
+        );
+        expect_value(
+            v2,
+            block_on(storage.get(Context::default(), k1, ts))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_writes_only() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` twice and leave a lock on `k3`.
+        let (k1, v1, v2) = (Key::from_raw(b"k1"), b"v@1".to_vec(), b"v@2".to_vec());
+        for (i, value) in [&v1, &v2].into_iter().enumerate() {
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(k1.clone(), value.clone())],
+                        b"k1".to_vec(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![k1.clone()], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k3"), b"v@5".to_vec())],
+                    b"k3".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // A writes-only flashback restores `k1` to `v1` with a single command
+        // by chaining Prewrite -> FlashbackWrite -> Commit while leaving the
+        // lock on `k3` untouched.
+        storage
+            .sched_txn_command(
+                new_flashback_writes_only_cmd(
+                    *ts.incr(),
+                    *ts.incr(),
+                    2.into(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_value(
+            v1,
+            block_on(storage.get(Context::default(), k1, *ts.incr()))
+                .unwrap()
+                .0,
+        );
+        // The lock on `k3` is still there and blocks the read.
+        block_on(storage.get(Context::default(), Key::from_raw(b"k3"), ts)).unwrap_err();
+    }
+
+    #[test]
+    fn test_flashback_to_version_resume_from_checkpoint() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` ~ `k8` after the version to flash back to.
+        for i in 1..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            let value = format!("v@{}", i).as_bytes().to_vec();
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), value)],
+                        key.to_raw().unwrap(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Simulate an interrupted flashback by persisting a checkpoint
+        // claiming that the FlashbackWrite phase has processed everything
+        // before `k5`. The checkpoint is keyed by the first user key, which
+        // is the actual prewrite key of the flashback, and carries the
+        // identity of the flashback instance being retried.
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        let prewrite_key = Key::from_raw(b"k1");
+        mvcc::tests::write(
+            &storage.get_engine(),
+            &Context::default(),
+            vec![write_flashback_checkpoint(
+                FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+                flashback_start_ts,
+                TimeStamp::zero(),
+                &prewrite_key,
+                &Key::from_raw(b"k5"),
+            )],
+        );
+        // Re-creating the flashback should resume from the checkpoint rather
+        // than restarting, leaving `k2` ~ `k4` untouched.
+        run_flashback_to_version(
+            &storage,
+            flashback_start_ts,
+            flashback_commit_ts,
+            TimeStamp::zero(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+        );
+        let read_ts = *ts.incr();
+        // The prewrite key is still flashed back by the 2PC itself.
+        expect_none(
+            block_on(storage.get(Context::default(), prewrite_key.clone(), read_ts))
+                .unwrap()
+                .0,
+        );
+        for i in 2..=4 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_value(
+                format!("v@{}", i).as_bytes().to_vec(),
+                block_on(storage.get(Context::default(), key, read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+        for i in 5..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_none(
+                block_on(storage.get(Context::default(), key, read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+        // The checkpoint should have been deleted on completion.
+        let snapshot = storage.get_engine().snapshot(Default::default()).unwrap();
+        assert!(
+            snapshot
+                .get_cf(CF_DEFAULT, &flashback_checkpoint_key(&prewrite_key))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_ignore_stale_checkpoint() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` ~ `k8` after the version to flash back to.
+        for i in 1..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            let value = format!("v@{}", i).as_bytes().to_vec();
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), value)],
+                        key.to_raw().unwrap(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Persist a checkpoint left behind by an abandoned flashback with a
+        // different `start_ts`. Resuming from its cursor would leave `k2` ~
+        // `k4` untouched, so it must be ignored.
+        let stale_start_ts = *ts.incr();
+        let prewrite_key = Key::from_raw(b"k1");
+        mvcc::tests::write(
+            &storage.get_engine(),
+            &Context::default(),
+            vec![write_flashback_checkpoint(
+                FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+                stale_start_ts,
+                TimeStamp::zero(),
+                &prewrite_key,
+                &Key::from_raw(b"k5"),
+            )],
+        );
+        run_flashback_to_version(
+            &storage,
+            *ts.incr(),
+            *ts.incr(),
+            TimeStamp::zero(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+        );
+        // All the keys should have been flashed back from scratch.
+        let read_ts = *ts.incr();
+        for i in 1..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_none(
+                block_on(storage.get(Context::default(), key, read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+        // The stale checkpoint should have been purged on completion.
+        let snapshot = storage.get_engine().snapshot(Default::default()).unwrap();
+        assert!(
+            snapshot
+                .get_cf(CF_DEFAULT, &flashback_checkpoint_key(&prewrite_key))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_metrics() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Commit `k1` and `k2`, then leave a lock on `k3`.
+        for i in 1..=2 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), b"v".to_vec())],
+                        key.to_raw().unwrap(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k3"), b"v".to_vec())],
+                    b"k3".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx, 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let lock_counter =
+            metrics::FLASHBACK_PROCESSED_KEYS_COUNTER_VEC.with_label_values(&["flashback_lock"]);
+        let write_counter =
+            metrics::FLASHBACK_PROCESSED_KEYS_COUNTER_VEC.with_label_values(&["flashback_write"]);
+        let (locks_before, writes_before) = (lock_counter.get(), write_counter.get());
+        run_flashback_to_version(
+            &storage,
+            *ts.incr(),
+            *ts.incr(),
+            TimeStamp::zero(),
+            Key::from_raw(b"k"),
+            Some(Key::from_raw(b"z")),
+        );
+        // The lock on `k3` and the write of `k2` should have been counted.
+        // `k1` is the prewrite key, which is not scanned by the write phase.
+        assert!(lock_counter.get() >= locks_before + 1);
+        assert!(write_counter.get() >= writes_before + 1);
+    }
+
+    #[test]
+    fn test_flashback_to_version_cancel() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Write `k1` ~ `k8` after the version to flash back to.
+        for i in 1..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), b"v".to_vec())],
+                        key.to_raw().unwrap(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], ts, *ts.incr(), Context::default()),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Prepare the flashback normally.
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 9),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Cancel the flashback before the write phase starts scanning, so it
+        // should only commit the prewrite key and leave the rest untouched.
+        let cancel_token = FlashbackCancelToken::default();
+        cancel_token.cancel();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    cancel_token,
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 10),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let read_ts = *ts.incr();
+        // The prewrite key `k1` is still flashed back by the 2PC itself.
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), read_ts))
+                .unwrap()
+                .0,
+        );
+        // The rest of the range is left untouched.
+        for i in 2..=8 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_value(
+                b"v".to_vec(),
+                block_on(storage.get(Context::default(), key, read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+    }
+
+    #[test]
+    fn test_flashback_to_version_rate_limit() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), b"v".to_vec())],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], start_ts, commit_ts, Context::default()),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // A limiter whose IO quota is far below the bytes a single batch
+        // scans, so the chain has to wait between the batches.
+        let limiter = Arc::new(ResourceLimiter::new(
+            "flashback".to_string(),
+            f64::INFINITY,
+            1024.0,
+            f64::INFINITY,
+            f64::INFINITY,
+            0,
+            true,
+        ));
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        let begin = Instant::now();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    Some(limiter.clone()),
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    Some(limiter),
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 1),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // The write phase scans more than 2KB of encoded keys against a 1KB/s
+        // quota, so the flashback must have been throttled for a while.
+        assert!(begin.saturating_elapsed() >= Duration::from_millis(500));
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_none(
+                block_on(storage.get(Context::default(), key, *ts.incr()))
+                    .unwrap()
+                    .0,
+            );
+        }
+    }
+
+    #[test]
+    fn test_flashback_to_version_empty_range() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k1"), b"v@1".to_vec())],
+                    b"k1".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k1")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 0, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // A flashback over `[k, k)` completes directly without scanning or
+        // prewriting anything.
+        let progress = FlashbackProgress::default();
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"k")),
+                    false,
+                    None,
+                    Vec::new(),
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 1),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"k")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        assert_eq!(progress.processed_keys(), 0);
+        // `k1` is outside of the empty range and must be left untouched.
+        expect_value(
+            b"v@1".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), *ts.incr()))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_summary() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Commit `k1`..`k3` once and leave a lock on `k4`.
+        for (i, key) in [b"k1", b"k2", b"k3"].into_iter().enumerate() {
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(Key::from_raw(key), b"v".to_vec())],
+                        key.to_vec(),
+                        *ts.incr(),
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![Key::from_raw(key)],
+                        ts,
+                        *ts.incr(),
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k4"), b"v@4".to_vec())],
+                    b"k4".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 3),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Flash the whole range back to the very beginning with a shared
+        // progress handle, so the summary of the last command in the chain
+        // covers both phases.
+        let progress = FlashbackProgress::default();
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let (result_tx, result_rx) = channel();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    progress,
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                Box::new(move |res| result_tx.send(res.unwrap()).unwrap()),
+            )
+            .unwrap();
+        let summary = result_rx.recv().unwrap();
+        // `k1`..`k3` were flashed back, `k1` as the prewrite anchor committed
+        // at the very end, and the lock on `k4` was rolled back.
+        assert_eq!(summary.writes_flashed, 3);
+        assert_eq!(summary.locks_rolled_back, 1);
+        assert!(summary.scanned_bytes > 0);
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), *ts.incr()))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_checksum() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        let put = |key: &[u8], value: &[u8], i: i32, ts: &mut TimeStamp| {
+            let start_ts = *ts.incr();
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(Key::from_raw(key), value.to_vec())],
+                        key.to_vec(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            let commit_ts = *ts.incr();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![Key::from_raw(key)],
+                        start_ts,
+                        commit_ts,
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        };
+        // `k1` and `k2` exist at the flashback version; after it `k1` is
+        // overwritten and `k3` is created, while `k2` stays unchanged and is
+        // skipped by the flashback scan but still counts into the checksums.
+        put(b"k1", b"v1@old", 0, &mut ts);
+        put(b"k2", b"v2", 1, &mut ts);
+        let version = *ts.incr();
+        put(b"k1", b"v1@new", 2, &mut ts);
+        put(b"k3", b"v3", 3, &mut ts);
+        let progress = FlashbackProgress::with_checksum();
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let (result_tx, result_rx) = channel();
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    progress,
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                Box::new(move |res| result_tx.send(res.unwrap()).unwrap()),
+            )
+            .unwrap();
+        let summary = result_rx.recv().unwrap();
+        // `k1` was restored through the prewrite anchor and `k3` was flashed
+        // back to nothing, while the unchanged `k2` was left alone.
+        assert_eq!(summary.writes_flashed, 2);
+        // The checksum of the range at `version` accumulated by the read
+        // phase must match the one of the current contents recomputed at
+        // commit time.
+        assert!(summary.pre_checksum.is_some());
+        assert_eq!(summary.pre_checksum, summary.post_checksum);
+        let read_ts = *ts.incr();
+        expect_value(
+            b"v1@old".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k1"), read_ts))
+                .unwrap()
+                .0,
+        );
+        expect_value(
+            b"v2".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), read_ts))
+                .unwrap()
+                .0,
+        );
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k3"), read_ts))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_range_conflict() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k2"), b"v@1".to_vec())],
+                    b"k2".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k2")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let version = *ts.incr();
+        // Hold the latch on `k2` so the prepare phase below stays blocked on
+        // its prewrite command and its range registration stays active.
+        storage
+            .sched_txn_command::<()>(
+                commands::Pause::new(vec![Key::from_raw(b"k2")], 1000, Context::default()),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        let flashback_start_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k1"),
+                    Some(Key::from_raw(b"k3")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 3),
+            )
+            .unwrap();
+        // While the first flashback is still in flight, an overlapping one
+        // must be rejected right away...
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    *ts.incr(),
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k2"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_fail_callback(tx.clone(), 4, |e| match e {
+                    Error(box ErrorInner::Txn(TxnError(
+                        box TxnErrorInner::FlashbackConflict { .. },
+                    ))) => (),
+                    e => panic!("unexpected error chain: {:?}", e),
+                }),
+            )
+            .unwrap();
+        // ...while a disjoint one is still allowed to run.
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    *ts.incr(),
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"x"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 5),
+            )
+            .unwrap();
+        for _ in 0..4 {
+            rx.recv().unwrap();
+        }
+        // The registration is released once the chain finishes, so the same
+        // range can be flashed back again afterwards.
+        run_flashback_to_version(
+            &storage,
+            *ts.incr(),
+            *ts.incr(),
+            version,
+            Key::from_raw(b"k1"),
+            Some(Key::from_raw(b"k3")),
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_multi_ranges() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        let keys = [b"k1", b"k2", b"k4", b"k5", b"k6"];
+        // Write the initial version of all the keys, including `k4` which lies
+        // in the gap between the two flashback ranges.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    keys.iter()
+                        .map(|key| Mutation::make_put(Key::from_raw(*key), b"v@1".to_vec()))
+                        .collect(),
+                    b"k1".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    keys.iter().map(|key| Key::from_raw(*key)).collect(),
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let version = *ts.incr();
+        // Overwrite all the keys after `version`.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    keys.iter()
+                        .map(|key| Mutation::make_put(Key::from_raw(*key), b"v@4".to_vec()))
+                        .collect(),
+                    b"k1".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    keys.iter().map(|key| Key::from_raw(*key)).collect(),
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 3, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Leave a lock in the second range so the rollback phase has to cross
+        // the range boundary as well.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k6"), b"v@6".to_vec())],
+                    b"k6".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx, 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Flash back `[k1, k3)` and `[k5, k7)` under a single command.
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        let (result_tx, result_rx) = channel();
+        storage
+            .sched_txn_command(
+                new_flashback_multi_range_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    vec![
+                        (Key::from_raw(b"k1"), Key::from_raw(b"k3")),
+                        (Key::from_raw(b"k5"), Key::from_raw(b"k7")),
+                    ],
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                Box::new(move |res| result_tx.send(res.unwrap()).unwrap()),
+            )
+            .unwrap();
+        let summary = result_rx.recv().unwrap();
+        // `k1` (the shared anchor), `k2`, `k5` and `k6` were flashed back and
+        // the lock on `k6` was rolled back.
+        assert_eq!(summary.writes_flashed, 4);
+        assert_eq!(summary.locks_rolled_back, 1);
+        let read_ts = *ts.incr();
+        for key in [b"k1", b"k2", b"k5", b"k6"] {
+            expect_value(
+                b"v@1".to_vec(),
+                block_on(storage.get(Context::default(), Key::from_raw(key), read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+        // `k4` lies between the two ranges and must be left untouched.
+        expect_value(
+            b"v@4".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k4"), read_ts))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_progress() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Add (FLASHBACK_BATCH_SIZE + 1) lock records so the RollbackLock phase
+        // takes more than one batch.
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let start_ts = *ts.incr();
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Add (FLASHBACK_BATCH_SIZE + 1) write records so the FlashbackWrite
+        // phase takes more than one batch as well.
+        for i in FLASHBACK_BATCH_SIZE + 2..=FLASHBACK_BATCH_SIZE * 2 + 2 {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![key.clone()],
+                        start_ts,
+                        commit_ts,
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        // Share one progress handle across the whole flashback so it accumulates
+        // over both phases.
+        let progress = FlashbackProgress::default();
+        assert_eq!(progress.processed_keys(), 0);
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // All the locks should have been counted, modulo the boundary key of
+        // each batch being scanned twice.
+        let processed_after_locks = progress.processed_keys();
+        assert!(processed_after_locks >= FLASHBACK_BATCH_SIZE + 1);
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    progress.clone(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 1),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // The counter only ever grows, and the FlashbackWrite phase should have
+        // added at least the write records on top of the lock records.
+        let processed_after_writes = progress.processed_keys();
+        assert!(processed_after_writes >= processed_after_locks + FLASHBACK_BATCH_SIZE + 1);
+    }
+
+    #[test]
+    fn test_flashback_to_version_no_data() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // `k` is created only now, so nothing exists at version 0 the
+        // flashback below targets.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k"), b"v@1".to_vec())],
+                    b"k".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Finishing the flashback would wipe the whole range, so it is
+        // refused without `force`.
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_fail_callback(tx.clone(), 3, |e| match e {
+                    Error(box ErrorInner::Txn(TxnError(
+                        box TxnErrorInner::FlashbackNoData { .. },
+                    ))) => (),
+                    e => panic!("unexpected error chain: {:?}", e),
+                }),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Forcing the flashback wipes the range as asked.
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    TimeStamp::zero(),
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_none(
+            block_on(storage.get(Context::default(), Key::from_raw(b"k"), *ts.incr()))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_newer_writes() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k"), b"v@1".to_vec())],
+                    b"k".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let version = *ts.incr();
+        // Overwrite `k` after `version` so there is something to flash back.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k"), b"v@4".to_vec())],
+                    b"k".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 3, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Sneak a write past the flashback `commit_ts` after the preparing
+        // phase, simulating an anchor that failed to block new commits.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(Key::from_raw(b"k2"), b"v@8".to_vec())],
+                    b"k2".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 5),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    vec![Key::from_raw(b"k2")],
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 6, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Flashing back on top of the newer write would leave a mixed
+        // history, so it is refused without `force`.
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    false,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_fail_callback(tx.clone(), 7, |e| match e {
+                    Error(box ErrorInner::Txn(TxnError(
+                        box TxnErrorInner::FlashbackNewerWrites { .. },
+                    ))) => (),
+                    e => panic!("unexpected error chain: {:?}", e),
+                }),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Forcing the flashback finishes it while leaving the newer write
+        // untouched.
+        storage
+            .sched_txn_command(
+                new_flashback_write_cmd(
+                    flashback_start_ts,
+                    flashback_commit_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"k"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    true,
+                    false,
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx, 8),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let read_ts = *ts.incr();
+        expect_value(
+            b"v@1".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k"), read_ts))
+                .unwrap()
+                .0,
+        );
+        expect_value(
+            b"v@8".to_vec(),
+            block_on(storage.get(Context::default(), Key::from_raw(b"k2"), read_ts))
+                .unwrap()
+                .0,
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_sharded_write() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        let keys = [
+            b"a1", b"a2", b"a3", b"b1", b"b2", b"b3", b"c1", b"c2", b"c3",
+        ];
+        // Write the initial version of all the keys.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    keys.iter()
+                        .map(|key| Mutation::make_put(Key::from_raw(*key), b"v@1".to_vec()))
+                        .collect(),
+                    b"a1".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    keys.iter().map(|key| Key::from_raw(*key)).collect(),
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        let version = *ts.incr();
+        // Overwrite all the keys after `version`.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    keys.iter()
+                        .map(|key| Mutation::make_put(Key::from_raw(*key), b"v@4".to_vec()))
+                        .collect(),
+                    b"a1".to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 2),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(
+                    keys.iter().map(|key| Key::from_raw(*key)).collect(),
+                    ts,
+                    *ts.incr(),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 3, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Prepare the whole range as usual first.
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        storage
+            .sched_txn_command(
+                new_flashback_rollback_lock_cmd(
+                    flashback_start_ts,
+                    version,
+                    TimeStamp::zero(),
+                    Key::from_raw(b"a"),
+                    Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
+                    Context::default(),
+                )
+                .unwrap(),
+                expect_ok_callback(tx.clone(), 4),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Run the write phase as three shards split at `b` and `c`, all
+        // sharing one progress handle.
+        let progress = FlashbackProgress::default();
+        let shard_cmds = new_flashback_sharded_write_cmds(
+            flashback_start_ts,
+            flashback_commit_ts,
+            version,
+            TimeStamp::zero(),
+            Key::from_raw(b"a"),
+            Some(Key::from_raw(b"z")),
+            vec![Key::from_raw(b"b"), Key::from_raw(b"c")],
+            false,
+            progress.clone(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap();
+        assert_eq!(shard_cmds.len(), 3);
+        for (i, cmd) in shard_cmds.into_iter().enumerate() {
+            storage
+                .sched_txn_command(cmd, expect_ok_callback(tx.clone(), 5 + i as i32))
+                .unwrap();
+        }
+        for _ in 0..3 {
+            rx.recv().unwrap();
+        }
+        // The merged summary counts every key exactly once: the eight keys
+        // flashed back by the shard batches plus the anchor `a1` committed by
+        // the last shard to finish.
+        assert_eq!(progress.to_result().writes_flashed, keys.len());
+        let read_ts = *ts.incr();
+        for key in keys {
+            expect_value(
+                b"v@1".to_vec(),
+                block_on(storage.get(Context::default(), Key::from_raw(key), read_ts))
+                    .unwrap()
+                    .0,
+            );
+        }
+    }
+
+    #[test]
+    fn test_flashback_to_version_in_reverse() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Add a few lock records so the reverse RollbackLock phase has some
+        // work to do.
+        for i in 1..=3 {
+            let start_ts = *ts.incr();
+            let key = Key::from_raw(format!("a{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Add (FLASHBACK_BATCH_SIZE + 1) write records so the reverse
+        // FlashbackWrite phase takes more than one batch to terminate.
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![key.clone()],
+                        start_ts,
+                        commit_ts,
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let flashback_start_ts = *ts.incr();
+        let flashback_commit_ts = *ts.incr();
+        // Flashback twice to make sure the reverse flashback is idempotent as
+        // well. Both runs finishing proves the reverse traversal terminates.
+        for _ in 0..2 {
+            storage
+                .sched_txn_command(
+                    new_flashback_rollback_lock_cmd(
+                        flashback_start_ts,
+                        TimeStamp::zero(),
+                        TimeStamp::zero(),
+                        Key::from_raw(b"a"),
+                        Some(Key::from_raw(b"z")),
+                        true,
+                        None,
+                        Vec::new(),
+                        FlashbackProgress::default(),
+                        FlashbackCancelToken::default(),
+                        None,
+                        Context::default(),
+                    )
+                    .unwrap(),
+                    expect_ok_callback(tx.clone(), 0),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    new_flashback_write_cmd(
+                        flashback_start_ts,
+                        flashback_commit_ts,
+                        TimeStamp::zero(),
+                        TimeStamp::zero(),
+                        Key::from_raw(b"a"),
+                        Some(Key::from_raw(b"z")),
+                        true,
+                        None,
+                        Vec::new(),
+                        true,
+                        false,
+                        FlashbackProgress::default(),
+                        FlashbackCancelToken::default(),
+                        None,
+                        Context::default(),
+                    )
+                    .unwrap(),
+                    expect_ok_callback(tx.clone(), 1),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            for i in 1..=3 {
+                let key = Key::from_raw(format!("a{}", i).as_bytes());
+                expect_none(
+                    block_on(storage.get(Context::default(), key, *ts.incr()))
+                        .unwrap()
+                        .0,
+                );
+            }
+            for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+                let key = Key::from_raw(format!("k{}", i).as_bytes());
+                expect_none(
+                    block_on(storage.get(Context::default(), key, *ts.incr()))
+                        .unwrap()
+                        .0,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_flashback_estimate() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        // Keys committed before the flashback version are not touched by a
+        // flashback, so they should not be counted by the estimate either.
+        for i in 1..=5 {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(format!("b{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![key.clone()],
+                        start_ts,
+                        commit_ts,
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let version = ts;
+        // Add (FLASHBACK_BATCH_SIZE + 1) write records after `version` so the
+        // estimate takes more than one batch, all of which would be
+        // overwritten by a flashback.
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i as i32),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(
+                        vec![key.clone()],
+                        start_ts,
+                        commit_ts,
+                        Context::default(),
+                    ),
+                    expect_value_callback(tx.clone(), i as i32, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        // Leave a few locks that would be rolled back by a flashback.
+        for i in 1..=3 {
+            let start_ts = *ts.incr();
+            let key = Key::from_raw(format!("a{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(
+                            key.clone(),
+                            format!("v@{}", i).as_bytes().to_vec(),
+                        )],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let expected = FlashbackEstimateResult {
+            write_keys: FLASHBACK_BATCH_SIZE + 1,
+            lock_keys: 3,
+            approximate_bytes: (1..=3)
+                .map(|i| {
+                    Key::from_raw(format!("a{}", i).as_bytes())
+                        .as_encoded()
+                        .len()
+                })
+                .sum::<usize>()
+                + (1..=FLASHBACK_BATCH_SIZE + 1)
+                    .map(|i| {
+                        Key::from_raw(format!("k{}", i).as_bytes())
+                            .as_encoded()
+                            .len()
+                    })
+                    .sum::<usize>(),
+        };
+        storage
+            .sched_txn_command(
+                new_flashback_estimate_cmd(
+                    version,
+                    Key::from_raw(b"a"),
+                    Some(Key::from_raw(b"z")),
+                    Context::default(),
+                ),
+                expect_value_callback(tx.clone(), 0, expected),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // A real flashback then modifies exactly the estimated keys: the
+        // locks are rolled back and the newer writes are reverted, while the
+        // keys committed before `version` stay untouched.
+        run_flashback_to_version(
+            &storage,
+            *ts.incr(),
+            *ts.incr(),
+            version,
+            Key::from_raw(b"a"),
+            Some(Key::from_raw(b"z")),
+        );
+        for i in 1..=5 {
+            let key = Key::from_raw(format!("b{}", i).as_bytes());
+            expect_value(
+                format!("v@{}", i).as_bytes().to_vec(),
+                block_on(storage.get(Context::default(), key, *ts.incr()))
+                    .unwrap()
+                    .0,
+            );
+        }
+        for i in 1..=3 {
+            let key = Key::from_raw(format!("a{}", i).as_bytes());
+            expect_none(
+                block_on(storage.get(Context::default(), key, *ts.incr()))
+                    .unwrap()
+                    .0,
+            );
+        }
+        for i in 1..=FLASHBACK_BATCH_SIZE + 1 {
+            let key = Key::from_raw(format!("k{}", i).as_bytes());
+            expect_none(
+                block_on(storage.get(Context::default(), key, *ts.incr()))
+                    .unwrap()
+                    .0,
+            );
+        }
+    }
+
+    #[test]
+    fn test_flashback_estimate_streaming() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        let version = ts;
+        // Two write records on each side of the region boundary `k`, all
+        // newer than `version`, plus a few locks below the boundary.
+        for key in [b"b1" as &[u8], b"b2", b"k1", b"k2"] {
+            let start_ts = *ts.incr();
+            let commit_ts = *ts.incr();
+            let key = Key::from_raw(key);
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), b"v".to_vec())],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), 0),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+            storage
+                .sched_txn_command(
+                    commands::Commit::new(vec![key], start_ts, commit_ts, Context::default()),
+                    expect_value_callback(tx.clone(), 0, TxnStatus::committed(commit_ts)),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        for i in 1..=3 {
+            let start_ts = *ts.incr();
+            let key = Key::from_raw(format!("a{}", i).as_bytes());
+            storage
+                .sched_txn_command(
+                    commands::Prewrite::with_defaults(
+                        vec![Mutation::make_put(key.clone(), b"v".to_vec())],
+                        key.to_raw().unwrap(),
+                        start_ts,
+                    ),
+                    expect_ok_callback(tx.clone(), i),
+                )
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        let lock_bytes = (1..=3)
+            .map(|i| {
+                Key::from_raw(format!("a{}", i).as_bytes())
+                    .as_encoded()
+                    .len()
+            })
+            .sum::<usize>();
+        let write_bytes_below = Key::from_raw(b"b1").as_encoded().len()
+            + Key::from_raw(b"b2").as_encoded().len();
+        let write_bytes_above = Key::from_raw(b"k1").as_encoded().len()
+            + Key::from_raw(b"k2").as_encoded().len();
+        let expected = FlashbackEstimateResult {
+            write_keys: 4,
+            lock_keys: 3,
+            approximate_bytes: lock_bytes + write_bytes_below + write_bytes_above,
+        };
+        let partials = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let partials = partials.clone();
+            FlashbackEstimateSink::new(move |partial| partials.lock().unwrap().push(partial))
+        };
+        storage
+            .sched_txn_command(
+                new_flashback_estimate_streaming_cmd(
+                    version,
+                    Key::from_raw(b"a"),
+                    Some(Key::from_raw(b"z")),
+                    vec![Key::from_raw(b"k")],
+                    sink,
+                    Context::default(),
+                ),
+                expect_value_callback(tx, 0, expected),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        // Each scan phase reports both segments exactly once and in order:
+        // the lock scan first, then the write scan, and the partial counts
+        // sum to the final totals.
+        let partials = partials.lock().unwrap();
+        assert_eq!(
+            *partials,
+            vec![
+                FlashbackEstimatePartial {
+                    end_key: Some(Key::from_raw(b"k")),
+                    lock_keys: 3,
+                    write_keys: 0,
+                    approximate_bytes: lock_bytes,
+                },
+                FlashbackEstimatePartial {
+                    end_key: Some(Key::from_raw(b"z")),
+                    lock_keys: 0,
+                    write_keys: 0,
+                    approximate_bytes: 0,
+                },
+                FlashbackEstimatePartial {
+                    end_key: Some(Key::from_raw(b"k")),
+                    lock_keys: 0,
+                    write_keys: 2,
+                    approximate_bytes: write_bytes_below,
+                },
+                FlashbackEstimatePartial {
+                    end_key: Some(Key::from_raw(b"z")),
+                    lock_keys: 0,
+                    write_keys: 2,
+                    approximate_bytes: write_bytes_above,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flashback_to_version_deleted_key() {
+        let storage = TestStorageBuilderApiV1::new(MockLockManager::new())
+            .build()
+            .unwrap();
+        let (tx, rx) = channel();
+        let mut ts = TimeStamp::zero();
+        let (k, v) = (Key::from_raw(b"k"), b"v".to_vec());
+        // Write a key.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_put(k.clone(), v.clone())],
+                    k.as_encoded().to_vec(),
+                    *ts.incr(),
+                ),
+                expect_ok_callback(tx.clone(), 0),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        storage
+            .sched_txn_command(
+                commands::Commit::new(vec![k.clone()], ts, *ts.incr(), Context::default()),
+                expect_value_callback(tx.clone(), 1, TxnStatus::committed(ts)),
+            )
+            .unwrap();
+        rx.recv().unwrap();
+        expect_value(
+            v,
+            block_on(storage.get(Context::default(), k.clone(), ts))
+                .unwrap()
+                .0,
+        );
+        // Delete the key.
+        storage
+            .sched_txn_command(
+                commands::Prewrite::with_defaults(
+                    vec![Mutation::make_delete(k.clone())],
+                    k.as_encoded().to_vec(),
+                    *ts.incr(),
                 ),
                 expect_ok_callback(tx.clone(), 2),
             )
@@ -5896,10 +8537,18 @@ mod tests {
                 new_flashback_rollback_lock_cmd(
                     flashback_start_ts,
                     TimeStamp::zero(),
+                    TimeStamp::zero(),
                     Key::from_raw(b"k"),
                     Some(Key::from_raw(b"z")),
+                    false,
+                    None,
+                    Vec::new(),
+                    FlashbackProgress::default(),
+                    FlashbackCancelToken::default(),
+                    None,
                     Context::default(),
-                ),
+                )
+                .unwrap(),
                 expect_ok_callback(tx, 0),
             )
             .unwrap();
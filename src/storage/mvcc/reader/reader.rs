@@ -734,6 +734,59 @@ impl<S: EngineSnapshot> MvccReader<S> {
         Ok((locks, has_remain))
     }
 
+    /// The reverse version of `scan_locks_from_storage`: scan locks that
+    /// satisfy `filter(lock)` backward in the key range [start, end] and
+    /// return them in descending key order. Unlike the forward version, `end`
+    /// is inclusive since the reverse scan starts from it, so the caller
+    /// should exclude the out-of-range keys with `filter` if necessary. At
+    /// most `limit` locks will be returned. If `limit` is set to `0`, it
+    /// means unlimited.
+    ///
+    /// The return type is `(locks, has_remain)`. `has_remain` indicates
+    /// whether there MAY be remaining locks that can be scanned.
+    pub fn scan_locks_from_storage_reverse<F>(
+        &mut self,
+        start: Option<&Key>,
+        end: Option<&Key>,
+        filter: F,
+        limit: usize,
+    ) -> Result<(Vec<(Key, Lock)>, bool)>
+    where
+        F: Fn(&Key, &Lock) -> bool,
+    {
+        self.create_lock_cursor_if_not_exist()?;
+        let cursor = self.lock_cursor.as_mut().unwrap();
+        let ok = match end {
+            Some(x) => cursor.seek_for_prev(x, &mut self.statistics.lock)?,
+            None => cursor.seek_to_last(&mut self.statistics.lock),
+        };
+        if !ok {
+            return Ok((vec![], false));
+        }
+        let mut locks = Vec::with_capacity(limit);
+        let mut has_remain = false;
+        while cursor.valid()? {
+            let key = Key::from_encoded_slice(cursor.key(&mut self.statistics.lock));
+            if let Some(start) = start {
+                if key < *start {
+                    break;
+                }
+            }
+
+            let lock = Lock::parse(cursor.value(&mut self.statistics.lock))?;
+            if filter(&key, &lock) {
+                locks.push((key, lock));
+                if limit > 0 && locks.len() == limit {
+                    has_remain = true;
+                    break;
+                }
+            }
+            cursor.prev(&mut self.statistics.lock);
+        }
+        self.statistics.lock.processed_keys += locks.len();
+        Ok((locks, has_remain))
+    }
+
     /// Scan the writes to get all the latest user keys. This scan will skip
     /// `WriteType::Lock` and `WriteType::Rollback`, only return the key that
     /// has a latest `WriteType::Put` or `WriteType::Delete` record. The return
@@ -814,6 +867,98 @@ impl<S: EngineSnapshot> MvccReader<S> {
         Ok((keys, has_remain))
     }
 
+    /// The reverse version of `scan_latest_user_keys`: scan the writes
+    /// backward in the key range [start, end] and return the latest user keys
+    /// in descending order. Unlike the forward version, `end` is inclusive
+    /// since the reverse scan starts from it, so the caller should exclude
+    /// the out-of-range keys with `filter` if necessary.
+    ///
+    /// Since iterating backward visits the versions of a user key from the
+    /// oldest to the latest, this scan has to walk through all the versions
+    /// of each user key rather than seeking over them like the forward
+    /// version does.
+    pub fn scan_latest_user_keys_reverse<F>(
+        &mut self,
+        start: Option<&Key>,
+        end: Option<&Key>,
+        filter: F,
+        limit: usize,
+    ) -> Result<(Vec<Key>, bool)>
+    where
+        F: Fn(&Key /* user key */, TimeStamp /* latest `commit_ts` */) -> bool,
+    {
+        self.create_write_cursor()?;
+        let cursor = self.write_cursor.as_mut().unwrap();
+        let ok = match end {
+            // All the versions of `end` are encoded after the user key
+            // itself, so seek to the version with `TimeStamp::zero()` to
+            // cover them.
+            Some(x) => cursor.seek_for_prev(
+                &x.clone().append_ts(TimeStamp::zero()),
+                &mut self.statistics.write,
+            )?,
+            None => cursor.seek_to_last(&mut self.statistics.write),
+        };
+        if !ok {
+            return Ok((vec![], false));
+        }
+        let mut keys = Vec::with_capacity(limit);
+        let mut has_remain = false;
+        // The user key the cursor is currently walking through and the
+        // `commit_ts` of its latest `WriteType::Put` or `WriteType::Delete`
+        // record seen so far. A zero `commit_ts` means no such record has
+        // been seen yet.
+        let mut pending: Option<(Key, TimeStamp)> = None;
+        let check_pending =
+            |pending: &mut Option<(Key, TimeStamp)>, keys: &mut Vec<Key>| -> bool {
+                if let Some((user_key, latest_commit_ts)) = pending.take() {
+                    if !latest_commit_ts.is_zero() && filter(&user_key, latest_commit_ts) {
+                        keys.push(user_key);
+                        if limit > 0 && keys.len() == limit {
+                            return true;
+                        }
+                    }
+                }
+                false
+            };
+        while cursor.valid()? {
+            let key = Key::from_encoded_slice(cursor.key(&mut self.statistics.write));
+            let commit_ts = key.decode_ts()?;
+            let user_key = key.truncate_ts()?;
+            let out_of_range = matches!(start, Some(start) if user_key < *start);
+            // Moving to a smaller user key means all the versions of the
+            // pending user key have been visited.
+            if out_of_range || matches!(pending, Some((ref pending_key, _)) if *pending_key != user_key)
+            {
+                if check_pending(&mut pending, &mut keys) {
+                    has_remain = true;
+                    break;
+                }
+            }
+            if out_of_range {
+                break;
+            }
+            match WriteRef::parse(cursor.value(&mut self.statistics.write))?.write_type {
+                WriteType::Put | WriteType::Delete => pending = Some((user_key, commit_ts)),
+                // Do not overwrite the `commit_ts` of an older effective
+                // write, but still record the user key to detect the
+                // transition to the next one.
+                WriteType::Lock | WriteType::Rollback => {
+                    if pending.is_none() {
+                        pending = Some((user_key, TimeStamp::zero()));
+                    }
+                }
+            }
+            cursor.prev(&mut self.statistics.write);
+        }
+        if !has_remain && check_pending(&mut pending, &mut keys) {
+            has_remain = true;
+        }
+        self.statistics.write.processed_keys += keys.len();
+        resource_metering::record_read_keys(keys.len() as u32);
+        Ok((keys, has_remain))
+    }
+
     pub fn scan_keys(
         &mut self,
         mut start: Option<Key>,
@@ -1,8 +1,14 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
-use txn_types::{Key, Lock, LockType, TimeStamp, Write, WriteType};
+use std::cell::RefCell;
+
+use engine_traits::CF_DEFAULT;
+use tikv_kv::ScanMode;
+use tikv_util::deadline::Deadline;
+use txn_types::{Key, Lock, LockType, TimeStamp, Value, Write, WriteType};
 
 use crate::storage::{
+    kv::Modify,
     mvcc::{self, MvccReader, MvccTxn, SnapshotReader, MAX_TXN_WRITE_SIZE},
     txn::{self, actions::check_txn_status::rollback_lock, Result as TxnResult},
     Snapshot,
@@ -10,52 +16,493 @@ use crate::storage::{
 
 pub const FLASHBACK_BATCH_SIZE: usize = 256 + 1 /* To store the next key for multiple batches */;
 
+// The number of keys collected between two deadline checks while scanning a
+// flashback batch. A batch scan over a huge range may otherwise blow past
+// the request deadline before the scheduler gets a chance to check it again.
+pub const FLASHBACK_DEADLINE_CHECK_INTERVAL: usize = 32;
+
+// The maximum serialized size of the old writes a single batch is allowed to
+// restore. A batch limited only by the key count may still hold an
+// unpredictable amount of memory when the old writes carry large short
+// values, so it is additionally cut off once the size of the write records
+// collected for it exceeds this budget.
+pub const FLASHBACK_BATCH_MAX_BYTES: usize = 64 * 1024;
+
+// The suffix appended to the encoded flashback range start key to form the
+// key its checkpoint is persisted at. The checkpoint only lives in
+// `CF_DEFAULT` and has no corresponding write record, so it is invisible to
+// any MVCC read and will never collide with a real default CF entry, whose
+// encoded key always ends with an 8-byte `start_ts`.
+const FLASHBACK_CHECKPOINT_SUFFIX: &[u8] = b"!flashback_checkpoint";
+
+// Tags distinguishing which phase a persisted flashback checkpoint belongs
+// to, written as the first byte of the checkpoint value followed by the
+// `start_ts` and `version` identifying the flashback instance and the
+// encoded key the interrupted phase should resume from.
+pub const FLASHBACK_CHECKPOINT_ROLLBACK_LOCK: u8 = 0;
+pub const FLASHBACK_CHECKPOINT_FLASHBACK_WRITE: u8 = 1;
+
+// Phase tag plus the big-endian `start_ts` and `version` of the flashback
+// that wrote the checkpoint.
+const FLASHBACK_CHECKPOINT_PREFIX_LEN: usize = 1 + 8 + 8;
+
+pub fn flashback_checkpoint_key(start_key: &Key) -> Key {
+    let mut encoded = start_key.as_encoded().clone();
+    encoded.extend_from_slice(FLASHBACK_CHECKPOINT_SUFFIX);
+    Key::from_encoded(encoded)
+}
+
+/// Build a modify persisting that the given phase has processed everything
+/// before `next_key`, so an interrupted flashback is able to resume from it
+/// after a restart instead of redoing the whole range. The checkpoint also
+/// records the `start_ts` and `version` of the flashback writing it, scoping
+/// the recorded cursor to that single flashback instance.
+pub fn write_flashback_checkpoint(
+    phase: u8,
+    start_ts: TimeStamp,
+    version: TimeStamp,
+    start_key: &Key,
+    next_key: &Key,
+) -> Modify {
+    let mut value =
+        Vec::with_capacity(FLASHBACK_CHECKPOINT_PREFIX_LEN + next_key.as_encoded().len());
+    value.push(phase);
+    value.extend_from_slice(&start_ts.into_inner().to_be_bytes());
+    value.extend_from_slice(&version.into_inner().to_be_bytes());
+    value.extend_from_slice(next_key.as_encoded());
+    Modify::Put(CF_DEFAULT, flashback_checkpoint_key(start_key), value)
+}
+
+pub fn delete_flashback_checkpoint(start_key: &Key) -> Modify {
+    Modify::Delete(CF_DEFAULT, flashback_checkpoint_key(start_key))
+}
+
+/// Load the key to resume from persisted for the given phase, if any. A
+/// checkpoint belonging to a different phase, or to a different flashback —
+/// one whose recorded `start_ts`/`version` does not match — is ignored:
+/// resuming from the cursor of an abandoned flashback would skip locks and
+/// writes the current one has never processed. Since this runs on a read
+/// path the stale checkpoint is left in place here; the flashback that
+/// ignored it overwrites the key with its own first checkpoint and deletes
+/// it on completion.
+pub fn load_flashback_checkpoint(
+    snapshot: &impl Snapshot,
+    phase: u8,
+    start_ts: TimeStamp,
+    version: TimeStamp,
+    start_key: &Key,
+) -> TxnResult<Option<Key>> {
+    Ok(snapshot
+        .get_cf(CF_DEFAULT, &flashback_checkpoint_key(start_key))?
+        .and_then(|value| {
+            (value.len() >= FLASHBACK_CHECKPOINT_PREFIX_LEN
+                && value[0] == phase
+                && value[1..9] == start_ts.into_inner().to_be_bytes()
+                && value[9..17] == version.into_inner().to_be_bytes())
+            .then(|| Key::from_encoded_slice(&value[FLASHBACK_CHECKPOINT_PREFIX_LEN..]))
+        }))
+}
+
+/// Returns whether the encoded form of `key` starts with any of the excluded
+/// prefixes, i.e. whether the flashback has to leave the key untouched:
+/// neither its lock is rolled back nor its write is rewritten. Note that the
+/// prewrite anchor is resolved with the same exclusions applied (see
+/// [`get_first_user_key`]), since the anchor itself is rewritten when the
+/// flashback commits.
+pub fn key_is_excluded(key: &Key, exclude_prefixes: &[Key]) -> bool {
+    exclude_prefixes
+        .iter()
+        .any(|prefix| key.as_encoded().starts_with(prefix.as_encoded()))
+}
+
+/// Scan at most [`FLASHBACK_BATCH_SIZE`] locks to roll back. The scan is cut
+/// into chunks of [`FLASHBACK_DEADLINE_CHECK_INTERVAL`] keys with a deadline
+/// check in between: once the deadline trips, the batch stops early and the
+/// collected prefix is processed as a normal (just smaller) batch, so the
+/// chain resumes from its last key instead of overrunning the deadline.
 pub fn flashback_to_version_read_lock(
     reader: &mut MvccReader<impl Snapshot>,
     next_lock_key: Key,
     end_key: Option<&Key>,
     flashback_start_ts: TimeStamp,
+    exclude_prefixes: &[Key],
+    deadline: &Deadline,
 ) -> TxnResult<Vec<(Key, Lock)>> {
-    let result = reader.scan_locks_from_storage(
-        Some(&next_lock_key),
-        end_key,
-        // Skip the `prewrite_lock`. This lock will appear when retrying prepare
-        |_, lock| lock.ts != flashback_start_ts,
-        FLASHBACK_BATCH_SIZE,
-    );
-    let (key_locks, _) = result?;
+    let mut key_locks: Vec<(Key, Lock)> = Vec::with_capacity(FLASHBACK_BATCH_SIZE);
+    let mut next_lock_key = next_lock_key;
+    loop {
+        let chunk_limit =
+            FLASHBACK_DEADLINE_CHECK_INTERVAL.min(FLASHBACK_BATCH_SIZE - key_locks.len());
+        let (chunk, _) = reader.scan_locks_from_storage(
+            Some(&next_lock_key),
+            end_key,
+            // - Skip the `prewrite_lock`. This lock will appear when retrying
+            //   prepare.
+            // - Skip the keys under an excluded prefix, whose locks the
+            //   flashback leaves in place.
+            |key, lock| {
+                lock.ts != flashback_start_ts && !key_is_excluded(key, exclude_prefixes)
+            },
+            chunk_limit,
+        )?;
+        let exhausted = chunk.len() < chunk_limit;
+        key_locks.extend(chunk);
+        if exhausted || key_locks.len() >= FLASHBACK_BATCH_SIZE || deadline.check().is_err() {
+            break;
+        }
+        // Like the batch hand-over between two commands, the next chunk
+        // resumes from the last collected key itself, so pop it to avoid
+        // rolling it back twice.
+        next_lock_key = key_locks.pop().unwrap().0;
+    }
+    Ok(key_locks)
+}
+
+// The reverse version of `flashback_to_version_read_lock`: scan the locks
+// backward from `next_lock_key` toward `start_key` and return them in
+// descending key order. `next_lock_key` is `None` only for the first batch,
+// which starts the scan from `end_key`.
+pub fn flashback_to_version_read_lock_reverse(
+    reader: &mut MvccReader<impl Snapshot>,
+    next_lock_key: Option<&Key>,
+    start_key: &Key,
+    end_key: Option<&Key>,
+    flashback_start_ts: TimeStamp,
+    exclude_prefixes: &[Key],
+    deadline: &Deadline,
+) -> TxnResult<Vec<(Key, Lock)>> {
+    let mut key_locks: Vec<(Key, Lock)> = Vec::with_capacity(FLASHBACK_BATCH_SIZE);
+    let mut next_lock_key = next_lock_key.cloned();
+    loop {
+        let chunk_limit =
+            FLASHBACK_DEADLINE_CHECK_INTERVAL.min(FLASHBACK_BATCH_SIZE - key_locks.len());
+        let (chunk, _) = reader.scan_locks_from_storage_reverse(
+            Some(start_key),
+            next_lock_key.as_ref().or(end_key),
+            // - Skip the `prewrite_lock`. This lock will appear when retrying
+            //   prepare.
+            // - Skip `end_key` itself, since the reverse scan takes its upper
+            //   bound inclusively.
+            // - Skip the keys under an excluded prefix, whose locks the
+            //   flashback leaves in place.
+            |key, lock| {
+                lock.ts != flashback_start_ts
+                    && end_key.map_or(true, |end_key| key < end_key)
+                    && !key_is_excluded(key, exclude_prefixes)
+            },
+            chunk_limit,
+        )?;
+        let exhausted = chunk.len() < chunk_limit;
+        key_locks.extend(chunk);
+        if exhausted || key_locks.len() >= FLASHBACK_BATCH_SIZE || deadline.check().is_err() {
+            break;
+        }
+        // The popped smallest key becomes the inclusive upper bound the next
+        // chunk resumes from, mirroring the forward scan.
+        next_lock_key = key_locks.pop().map(|(key, _)| key);
+    }
     Ok(key_locks)
 }
 
+/// Cut the batch off once the serialized size of the old write records to
+/// restore exceeds `FLASHBACK_BATCH_MAX_BYTES`, keeping the key the budget is
+/// exceeded at as the start of the next batch. At least one key is always
+/// kept in the batch to guarantee the flashback makes progress. Note that the
+/// keys are visited out of the scan order, so the `reader` has to be a
+/// point-get one without any scan mode.
+pub fn truncate_flashback_batch_by_bytes(
+    reader: &mut MvccReader<impl Snapshot>,
+    keys: &mut Vec<Key>,
+    flashback_version: TimeStamp,
+) -> TxnResult<()> {
+    let mut batch_bytes = 0;
+    for (i, key) in keys.iter().enumerate() {
+        if batch_bytes >= FLASHBACK_BATCH_MAX_BYTES {
+            keys.truncate(i + 1);
+            break;
+        }
+        batch_bytes += key.as_encoded().len()
+            + reader
+                .get_write(key, flashback_version, None)?
+                .map_or(0, |write| write.as_ref().to_bytes().len());
+    }
+    Ok(())
+}
+
+/// Returns whether the prewritten `anchor` or any of `keys` has an MVCC
+/// version at or before `flashback_version`, i.e. whether the flashback
+/// leaves any data behind in the batch. Like
+/// [`truncate_flashback_batch_by_bytes`], the keys are visited out of the
+/// scan order, so the `reader` has to be a point-get one without any scan
+/// mode.
+pub fn check_flashback_version_has_data(
+    reader: &mut MvccReader<impl Snapshot>,
+    anchor: &Key,
+    keys: &[Key],
+    flashback_version: TimeStamp,
+) -> TxnResult<bool> {
+    for key in std::iter::once(anchor).chain(keys.iter()) {
+        if reader.get_write(key, flashback_version, None)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// The value of `key` visible at `ts`, resolved down to the user value so
+// two writes only differing in their timestamps compare equal. `None` means
+// the key has no visible value at `ts`, i.e. it does not exist or its
+// latest effective record is a delete.
+fn visible_value(
+    reader: &mut MvccReader<impl Snapshot>,
+    key: &Key,
+    ts: TimeStamp,
+) -> TxnResult<Option<Value>> {
+    match reader.get_write(key, ts, None)? {
+        Some(write) => Ok(Some(reader.load_data(key, write)?)),
+        None => Ok(None),
+    }
+}
+
+// Returns whether the current visible value of `key` already equals its
+// value at `flashback_version`, in which case rewriting the key would only
+// duplicate the data it already holds. The comparison is on the resolved
+// values, so a key rewritten with the identical value after the version is
+// recognized as unchanged even though its latest `commit_ts` differs. A
+// read failure conservatively reports the key as changed: it stays in the
+// batch and the write phase will re-read it and surface the error.
+fn key_unchanged_since_version(
+    reader: &mut MvccReader<impl Snapshot>,
+    key: &Key,
+    flashback_version: TimeStamp,
+) -> bool {
+    let current = visible_value(reader, key, TimeStamp::max());
+    let target = visible_value(reader, key, flashback_version);
+    matches!((current, target), (Ok(current), Ok(target)) if current == target)
+}
+
+// The checksum contribution of a single key-value pair. The callers combine
+// the contributions with XOR, so the accumulation order does not matter and
+// a reverse or sharded flashback sums up to the same range checksum as a
+// plain forward scan.
+fn key_value_checksum(key: &Key, value: &Value) -> u64 {
+    let mut digest = crc64fast::Digest::new();
+    digest.write(key.as_encoded());
+    digest.write(value);
+    digest.sum64()
+}
+
+/// The checksum contribution of `key` resolved at `ts`: the checksum of its
+/// visible value, or zero (the XOR identity) if the key has no visible value
+/// at `ts`, so an absent key and a deleted key contribute the same.
+pub fn flashback_key_checksum(
+    reader: &mut MvccReader<impl Snapshot>,
+    key: &Key,
+    ts: TimeStamp,
+) -> TxnResult<u64> {
+    Ok(match visible_value(reader, key, ts)? {
+        Some(value) => key_value_checksum(key, &value),
+        None => 0,
+    })
+}
+
+/// The smallest encoded key strictly greater than `key`, used to turn the
+/// inclusive batch boundaries of a reverse flashback scan into the half-open
+/// spans [`flashback_range_checksum`] takes.
+pub fn flashback_key_successor(key: &Key) -> Key {
+    let mut encoded = key.as_encoded().clone();
+    encoded.push(0);
+    Key::from_encoded(encoded)
+}
+
+/// The XOR-combined checksum of every visible key-value pair in
+/// `[start_key, end_key)` resolved at `ts`, excluding the keys under an
+/// excluded prefix since they keep their current value instead of being
+/// flashed back. Keys whose latest record is a `WriteType::Lock` or
+/// `WriteType::Rollback` are skipped: the flashback scan skips them the same
+/// way, so they are treated consistently on both sides of the comparison.
+///
+/// The cost is a write CF scan over the span plus a value lookup per key
+/// found, paid only when the caller opted into checksum verification.
+pub fn flashback_range_checksum(
+    reader: &mut MvccReader<impl Snapshot>,
+    point_reader: &mut MvccReader<impl Snapshot>,
+    start_key: &Key,
+    end_key: Option<&Key>,
+    ts: TimeStamp,
+    exclude_prefixes: &[Key],
+) -> TxnResult<u64> {
+    let mut checksum = 0;
+    let mut next_key = Some(start_key.clone());
+    while let Some(batch_start) = next_key {
+        let (keys, has_remain) = reader.scan_latest_user_keys(
+            Some(&batch_start),
+            end_key,
+            |_, _| true,
+            FLASHBACK_BATCH_SIZE,
+        )?;
+        // Unlike the flashback batches, the checksum must count each key
+        // exactly once, so the next chunk resumes strictly after the last
+        // key instead of rescanning it as the boundary.
+        next_key = if has_remain {
+            keys.last().map(flashback_key_successor)
+        } else {
+            None
+        };
+        for key in &keys {
+            if key_is_excluded(key, exclude_prefixes) {
+                continue;
+            }
+            checksum ^= flashback_key_checksum(point_reader, key, ts)?;
+        }
+    }
+    Ok(checksum)
+}
+
+/// Scan a batch of the latest visible user keys that need to be flashed back.
+/// Note that a key only created after `flashback_version` is collected as
+/// well: its latest `commit_ts` is newer than the version, so it passes the
+/// filter below even though it has no old version to restore, and
+/// [`flashback_to_version_write`] will put a `WriteType::Delete` at the
+/// flashback `commit_ts` for it instead of leaving it dangling.
+///
+/// A key whose current visible value already equals its value at
+/// `flashback_version` is filtered out as well: rewriting it would only
+/// amplify the writes without changing what any read observes. The check
+/// costs two point lookups per candidate key, paid exactly for the keys
+/// that would otherwise be rewritten, and needs the separate `point_reader`
+/// since the scanning reader's cursors only ever move forward.
 pub fn flashback_to_version_read_write(
     reader: &mut MvccReader<impl Snapshot>,
+    point_reader: &mut MvccReader<impl Snapshot>,
     next_write_key: Key,
     start_key: &Key,
     end_key: Option<&Key>,
     flashback_version: TimeStamp,
     flashback_commit_ts: TimeStamp,
-) -> TxnResult<Vec<Key>> {
-    // To flashback the data, we need to get all the latest visible keys first by
-    // scanning every unique key in `CF_WRITE`.
-    let keys_result = reader.scan_latest_user_keys(
-        Some(&next_write_key),
-        end_key,
-        |key, latest_commit_ts| {
-            // There is no any other write could happen after the flashback begins.
-            assert!(latest_commit_ts <= flashback_commit_ts);
-            // - Skip the `start_key` which as prewrite key.
-            // - No need to find an old version for the key if its latest `commit_ts` is
-            // smaller than or equal to the flashback version.
-            // - No need to flashback a key twice if its latest `commit_ts` is equal to the
-            //   flashback `commit_ts`.
-            key != start_key
-                && latest_commit_ts > flashback_version
-                && latest_commit_ts < flashback_commit_ts
-        },
-        FLASHBACK_BATCH_SIZE,
-    );
-    let (keys, _) = keys_result?;
-    Ok(keys)
+    exclude_prefixes: &[Key],
+    deadline: &Deadline,
+) -> TxnResult<(Vec<Key>, Option<(Key, TimeStamp)>)> {
+    // No other write should happen after the flashback begins since the
+    // prewrite lock blocks them, but a lagging apply or a misbehaving client
+    // may still sneak one in, so instead of asserting, the first write newer
+    // than `flashback_commit_ts` is recorded and returned to let the caller
+    // decide whether to abort.
+    let newer_write = RefCell::new(None);
+    let point_reader = RefCell::new(point_reader);
+    let filter = |key: &Key, latest_commit_ts: TimeStamp| {
+        if latest_commit_ts > flashback_commit_ts {
+            newer_write
+                .borrow_mut()
+                .get_or_insert_with(|| (key.clone(), latest_commit_ts));
+        }
+        // - Skip the `start_key` which as prewrite key.
+        // - Skip the keys under an excluded prefix, which keep their current
+        //   value instead of being flashed back.
+        // - No need to find an old version for the key if its latest `commit_ts` is
+        // smaller than or equal to the flashback version.
+        // - No need to flashback a key twice if its latest `commit_ts` is equal to the
+        //   flashback `commit_ts`.
+        // - No need to rewrite a key whose visible value already equals its
+        //   value at the flashback version. This check is the expensive one,
+        //   so it runs last and only for the keys passing everything above.
+        key != start_key
+            && !key_is_excluded(key, exclude_prefixes)
+            && latest_commit_ts > flashback_version
+            && latest_commit_ts < flashback_commit_ts
+            && !key_unchanged_since_version(
+                &mut **point_reader.borrow_mut(),
+                key,
+                flashback_version,
+            )
+    };
+    // To flashback the data, we need to get all the latest visible keys first
+    // by scanning every unique key in `CF_WRITE`, chunk by chunk with a
+    // deadline check in between like `flashback_to_version_read_lock`.
+    let mut keys: Vec<Key> = Vec::with_capacity(FLASHBACK_BATCH_SIZE);
+    let mut next_write_key = next_write_key;
+    loop {
+        let chunk_limit = FLASHBACK_DEADLINE_CHECK_INTERVAL.min(FLASHBACK_BATCH_SIZE - keys.len());
+        let (chunk, _) =
+            reader.scan_latest_user_keys(Some(&next_write_key), end_key, &filter, chunk_limit)?;
+        let exhausted = chunk.len() < chunk_limit;
+        keys.extend(chunk);
+        if exhausted || keys.len() >= FLASHBACK_BATCH_SIZE || deadline.check().is_err() {
+            break;
+        }
+        next_write_key = keys.pop().unwrap();
+    }
+    Ok((keys, newer_write.into_inner()))
+}
+
+// The reverse version of `flashback_to_version_read_write`: scan the latest
+// visible keys backward from `next_write_key` toward `start_key` and return
+// them in descending key order. `next_write_key` is `None` only for the first
+// batch, which starts the scan from `end_key`. Note that `start_key` is still
+// skipped as the prewrite key.
+pub fn flashback_to_version_read_write_reverse(
+    reader: &mut MvccReader<impl Snapshot>,
+    point_reader: &mut MvccReader<impl Snapshot>,
+    next_write_key: Option<&Key>,
+    start_key: &Key,
+    end_key: Option<&Key>,
+    flashback_version: TimeStamp,
+    flashback_commit_ts: TimeStamp,
+    exclude_prefixes: &[Key],
+    deadline: &Deadline,
+) -> TxnResult<(Vec<Key>, Option<(Key, TimeStamp)>)> {
+    // Like the forward scan, record the first write newer than
+    // `flashback_commit_ts` instead of asserting it never happens.
+    let newer_write = RefCell::new(None);
+    let point_reader = RefCell::new(point_reader);
+    let filter = |key: &Key, latest_commit_ts: TimeStamp| {
+        if latest_commit_ts > flashback_commit_ts {
+            newer_write
+                .borrow_mut()
+                .get_or_insert_with(|| (key.clone(), latest_commit_ts));
+        }
+        // - Skip the `start_key` which as prewrite key.
+        // - Skip `end_key` itself, since the reverse scan takes its upper bound
+        //   inclusively.
+        // - Skip the keys under an excluded prefix, which keep their current
+        //   value instead of being flashed back.
+        // - No need to find an old version for the key if its latest `commit_ts` is
+        // smaller than or equal to the flashback version.
+        // - No need to flashback a key twice if its latest `commit_ts` is equal to the
+        //   flashback `commit_ts`.
+        // - No need to rewrite a key whose visible value already equals its
+        //   value at the flashback version, like the forward scan.
+        key != start_key
+            && end_key.map_or(true, |end_key| key < end_key)
+            && !key_is_excluded(key, exclude_prefixes)
+            && latest_commit_ts > flashback_version
+            && latest_commit_ts < flashback_commit_ts
+            && !key_unchanged_since_version(
+                &mut **point_reader.borrow_mut(),
+                key,
+                flashback_version,
+            )
+    };
+    let mut keys: Vec<Key> = Vec::with_capacity(FLASHBACK_BATCH_SIZE);
+    let mut next_write_key = next_write_key.cloned();
+    loop {
+        let chunk_limit = FLASHBACK_DEADLINE_CHECK_INTERVAL.min(FLASHBACK_BATCH_SIZE - keys.len());
+        let (chunk, _) = reader.scan_latest_user_keys_reverse(
+            Some(start_key),
+            next_write_key.as_ref().or(end_key),
+            &filter,
+            chunk_limit,
+        )?;
+        let exhausted = chunk.len() < chunk_limit;
+        keys.extend(chunk);
+        if exhausted || keys.len() >= FLASHBACK_BATCH_SIZE || deadline.check().is_err() {
+            break;
+        }
+        // The popped smallest key becomes the inclusive upper bound the next
+        // chunk resumes from, mirroring the forward scan.
+        next_write_key = keys.pop();
+    }
+    Ok((keys, newer_write.into_inner()))
 }
 
 // At the very first beginning of flashback, we need to rollback all locks in
@@ -92,6 +539,14 @@ pub fn rollback_locks(
 //   - If a key exists and is visible at `flashback_version`, it will be put the
 //     exact same record in `CF_WRITE` and `CF_DEFAULT` with `self.commit_ts`
 //     and `self.start_ts`.
+//
+// With `preserve_history` set, the first case is skipped instead: the keys
+// created after `flashback_version` keep their latest value, so the result is
+// no longer an exact snapshot of `flashback_version`, but no data is hidden
+// behind a `WriteType::Delete` record either. In both modes the flashback
+// only appends new MVCC records, so the versions committed between
+// `flashback_version` and `flashback_commit_ts` stay readable below the
+// flashback `commit_ts` until the GC collects them.
 pub fn flashback_to_version_write(
     txn: &mut MvccTxn,
     reader: &mut MvccReader<impl Snapshot>,
@@ -99,6 +554,7 @@ pub fn flashback_to_version_write(
     flashback_version: TimeStamp,
     flashback_start_ts: TimeStamp,
     flashback_commit_ts: TimeStamp,
+    preserve_history: bool,
 ) -> TxnResult<Option<Key>> {
     for key in keys {
         #[cfg(feature = "failpoints")]
@@ -131,6 +587,13 @@ pub fn flashback_to_version_write(
                 old_write.short_value.clone(),
             )
         } else {
+            // In the preserve-history mode, a key without an old version at
+            // `flashback_version` is left as it is: a `WriteType::Delete`
+            // record would both hide its latest value and become a point the
+            // GC is allowed to collapse the whole history behind.
+            if preserve_history {
+                continue;
+            }
             // If the old write doesn't exist, we should put a `WriteType::Delete` record to
             // delete the current key when needed.
             Write::new(WriteType::Delete, flashback_start_ts, None)
@@ -140,6 +603,123 @@ pub fn flashback_to_version_write(
     Ok(None)
 }
 
+/// A page of the modifications a flashback over a range would produce,
+/// collected by [`flashback_to_version_dry_run`] without writing anything.
+pub struct FlashbackDryRun {
+    /// The write records the flashback would append, keyed by the user key
+    /// they would be written at with the flashback `commit_ts`.
+    pub writes: Vec<(Key, Write)>,
+    /// The locks the flashback would roll back first.
+    pub key_locks: Vec<(Key, Lock)>,
+    /// The key the next page resumes from, or `None` once the range has been
+    /// exhausted.
+    pub next_key: Option<Key>,
+}
+
+/// Collect a page of the modifications flashing `[next_key, end_key)` back to
+/// `flashback_version` would make, without applying any of them, so recovery
+/// tooling is able to diff the proposed writes against the current state
+/// before committing to a real flashback. Both collections are bounded by
+/// [`FLASHBACK_BATCH_SIZE`] — walk a huge range page by page by passing the
+/// returned cursor back in as `next_key`. Every key below the returned cursor
+/// is reported exactly once across the pages.
+///
+/// Note that the dry run takes no lock, so the records it returns only match
+/// the ones a real flashback would emit as long as no write lands in between.
+/// The prewrite anchor is not special-cased either: committing the anchor
+/// restores the same record [`flashback_to_version_write`] would compute, so
+/// it is reported like any other key.
+pub fn flashback_to_version_dry_run(
+    snapshot: impl Snapshot,
+    next_key: Key,
+    end_key: Option<&Key>,
+    flashback_version: TimeStamp,
+    flashback_start_ts: TimeStamp,
+    flashback_commit_ts: TimeStamp,
+    exclude_prefixes: &[Key],
+    preserve_history: bool,
+    deadline: &Deadline,
+) -> TxnResult<FlashbackDryRun> {
+    let mut reader = MvccReader::new(snapshot.clone(), Some(ScanMode::Forward), false);
+    let mut point_reader = MvccReader::new(snapshot, None, false);
+    let mut key_locks = flashback_to_version_read_lock(
+        &mut reader,
+        next_key.clone(),
+        end_key,
+        flashback_start_ts,
+        exclude_prefixes,
+        deadline,
+    )?;
+    // There is no prewrite key to skip: the dry run reports the record the
+    // anchor would be rewritten with like any other key.
+    let (mut keys, _) = flashback_to_version_read_write(
+        &mut reader,
+        &mut point_reader,
+        next_key,
+        &Key::from_raw(b""),
+        end_key,
+        flashback_version,
+        flashback_commit_ts,
+        exclude_prefixes,
+        deadline,
+    )?;
+    // When either scan fills its batch, the page is cut off below the smaller
+    // of the two resume keys, so the two collections stay consistent with
+    // each other and nothing is reported twice across the pages.
+    let lock_cursor = (key_locks.len() >= FLASHBACK_BATCH_SIZE).then(|| key_locks.pop().unwrap().0);
+    let write_cursor = (keys.len() >= FLASHBACK_BATCH_SIZE).then(|| keys.pop().unwrap());
+    let next_key = match (lock_cursor, write_cursor) {
+        (Some(lock_cursor), Some(write_cursor)) => Some(lock_cursor.min(write_cursor)),
+        (cursor, None) | (None, cursor) => cursor,
+    };
+    if let Some(next_key) = &next_key {
+        key_locks.retain(|(key, _)| key < next_key);
+        keys.retain(|key| key < next_key);
+    }
+    // Rolling a lock back leaves a `Rollback` record behind, which makes its
+    // key pass the write-phase filter afterwards, so a key only traced by its
+    // lock gets a flashback record as well — unless its committed value
+    // already matches the flashback version, in which case the write phase
+    // skips it like any other unchanged key.
+    for (key, lock) in &key_locks {
+        if lock.ts > flashback_version
+            && lock.ts < flashback_commit_ts
+            && !keys.contains(key)
+            && !key_unchanged_since_version(&mut point_reader, key, flashback_version)
+        {
+            keys.push(key.clone());
+        }
+    }
+    keys.sort();
+    // The keys merged in above are visited out of the scan order, which the
+    // point-get reader handles fine, like
+    // `truncate_flashback_batch_by_bytes` requires.
+    let mut writes = Vec::with_capacity(keys.len());
+    for key in keys {
+        let old_write = point_reader.get_write(&key, flashback_version, None)?;
+        let new_write = if let Some(old_write) = old_write {
+            Write::new(
+                old_write.write_type,
+                flashback_start_ts,
+                old_write.short_value,
+            )
+        } else {
+            // A key without an old version is skipped in the preserve-history
+            // mode, exactly like `flashback_to_version_write` does.
+            if preserve_history {
+                continue;
+            }
+            Write::new(WriteType::Delete, flashback_start_ts, None)
+        };
+        writes.push((key, new_write));
+    }
+    Ok(FlashbackDryRun {
+        writes,
+        key_locks,
+        next_key,
+    })
+}
+
 // Prewrite the `key_to_lock`, namely the `self.start_key`, to do a special 2PC
 // transaction.
 pub fn prewrite_flashback_key(
@@ -219,6 +799,15 @@ pub fn commit_flashback_key(
             flashback_commit_ts,
         );
     } else {
+        // The lock is gone: a retried commit lands here after a previous
+        // attempt already committed the key. Recognize our own commit record
+        // and treat the retry as a successful no-op so the scheduler is free
+        // to resend the final commit after a transient error.
+        if let Some((commit_ts, write)) = reader.seek_write(key_to_commit, flashback_commit_ts)? {
+            if commit_ts == flashback_commit_ts && write.start_ts == flashback_start_ts {
+                return Ok(());
+            }
+        }
         return Err(txn::Error::from_mvcc(mvcc::ErrorInner::TxnLockNotFound {
             start_ts: flashback_start_ts,
             commit_ts: flashback_commit_ts,
@@ -275,17 +864,26 @@ pub fn check_flashback_commit(
     )))
 }
 
+/// Resolve the first user key of the range, which the flashback 2PC takes as
+/// its prewrite anchor. The exclusions are applied here as well: the anchor
+/// is rewritten with its old value when the flashback commits, so anchoring
+/// on an excluded key would modify it despite the exclusion. When the range
+/// starts inside an excluded prefix, the anchor thus moves past it to the
+/// first key the flashback is actually going to rewrite.
 pub fn get_first_user_key(
     reader: &mut MvccReader<impl Snapshot>,
     start_key: &Key,
     end_key: Option<&Key>,
     flashback_version: TimeStamp,
+    exclude_prefixes: &[Key],
 ) -> TxnResult<Option<Key>> {
     let (mut keys_result, _) = reader.scan_latest_user_keys(
         Some(start_key),
         end_key,
         // Make sure we will get the same first user key each time.
-        |_, latest_commit_ts| latest_commit_ts > flashback_version,
+        |key, latest_commit_ts| {
+            latest_commit_ts > flashback_version && !key_is_excluded(key, exclude_prefixes)
+        },
         1,
     )?;
     Ok(keys_result.pop())
@@ -307,6 +905,7 @@ pub mod tests {
                 commit::tests::must_succeed as must_commit,
                 tests::{must_prewrite_delete, must_prewrite_put, must_rollback},
             },
+            scheduler::DEFAULT_EXECUTION_DURATION_LIMIT,
             tests::{must_acquire_pessimistic_lock, must_pessimistic_prewrite_put_err},
         },
         Engine, TestEngineBuilder,
@@ -323,9 +922,15 @@ pub mod tests {
         let ctx = Context::default();
         let snapshot = engine.snapshot(Default::default()).unwrap();
         let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
-        let key_locks =
-            flashback_to_version_read_lock(&mut reader, key, Some(next_key).as_ref(), start_ts)
-                .unwrap();
+        let key_locks = flashback_to_version_read_lock(
+            &mut reader,
+            key,
+            Some(next_key).as_ref(),
+            start_ts,
+            &[],
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
         let cm = ConcurrencyManager::new(TimeStamp::zero());
         let mut txn = MvccTxn::new(start_ts, cm);
         rollback_locks(&mut txn, snapshot, key_locks).unwrap();
@@ -351,6 +956,7 @@ pub mod tests {
             &Key::from_raw(key),
             Some(Key::from_raw(b"z")).as_ref(),
             version,
+            &[],
         )
         .unwrap()
         {
@@ -377,21 +983,34 @@ pub mod tests {
         let (version, start_ts, commit_ts) = (version.into(), start_ts.into(), commit_ts.into());
         let ctx = Context::default();
         let snapshot = engine.snapshot(Default::default()).unwrap();
-        let mut reader = MvccReader::new_with_ctx(snapshot, Some(ScanMode::Forward), &ctx);
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
         // Flashback the writes.
-        let keys = flashback_to_version_read_write(
+        let (keys, newer_write) = flashback_to_version_read_write(
             &mut reader,
+            &mut point_reader,
             key,
             &Key::from_raw(b""),
             next_key.as_ref(),
             version,
             commit_ts,
+            &[],
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
         )
         .unwrap();
+        assert!(newer_write.is_none());
         let cm = ConcurrencyManager::new(TimeStamp::zero());
         let mut txn = MvccTxn::new(start_ts, cm);
-        flashback_to_version_write(&mut txn, &mut reader, keys, version, start_ts, commit_ts)
-            .unwrap();
+        flashback_to_version_write(
+            &mut txn,
+            &mut reader,
+            keys,
+            version,
+            start_ts,
+            commit_ts,
+            false,
+        )
+        .unwrap();
         let rows = txn.modifies.len();
         write(engine, &ctx, txn.into_modifies());
         rows
@@ -415,6 +1034,7 @@ pub mod tests {
             &Key::from_raw(key),
             Some(Key::from_raw(b"z")).as_ref(),
             version,
+            &[],
         )
         .unwrap()
         .unwrap();
@@ -463,16 +1083,18 @@ pub mod tests {
             1
         );
         must_get(&mut engine, k, *ts.incr(), v1);
-        // Flashback to version 5 with start_ts = 20, commit_ts = 21.
+        // Flashback to version 5 with start_ts = 20, commit_ts = 21. The key
+        // already reads as `v1`, so no write is produced at all.
         assert_eq!(
             must_flashback_write_to_version(&mut engine, k, 5, *ts.incr(), *ts.incr()),
-            1
+            0
         );
         must_get(&mut engine, k, *ts.incr(), v1);
-        // Flashback to version 7 with start_ts = 23, commit_ts = 24.
+        // Flashback to version 7 with start_ts = 23, commit_ts = 24, another
+        // no-op since the visible value is still `v1`.
         assert_eq!(
             must_flashback_write_to_version(&mut engine, k, 7, *ts.incr(), *ts.incr()),
-            1
+            0
         );
         must_get(&mut engine, k, *ts.incr(), v1);
         // Flashback to version 10 with start_ts = 26, commit_ts = 27.
@@ -514,6 +1136,303 @@ pub mod tests {
         must_get_none(&mut engine, k, ts);
     }
 
+    #[test]
+    fn test_flashback_write_to_version_created_after() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (k, v) = (b"k", b"v");
+        let version = *ts.incr();
+        // `k` is only created after `version`, so flashing back must remove it
+        // with a new `WriteType::Delete` rather than leaving it visible.
+        must_prewrite_put(&mut engine, k, v, k, *ts.incr());
+        must_commit(&mut engine, k, ts, *ts.incr());
+        must_get(&mut engine, k, ts, v);
+        assert_eq!(
+            must_flashback_write_to_version(&mut engine, k, version, *ts.incr(), *ts.incr()),
+            1
+        );
+        must_get_none(&mut engine, k, ts);
+    }
+
+    #[test]
+    fn test_flashback_write_to_version_excluded_prefix() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (v1, v2) = (b"v1", b"v2");
+        let keys: [&[u8]; 4] = [b"a", b"b", b"x1", b"x2"];
+        for k in keys {
+            must_prewrite_put(&mut engine, k, v1, k, *ts.incr());
+            must_commit(&mut engine, k, ts, *ts.incr());
+        }
+        let version = ts;
+        for k in keys {
+            must_prewrite_put(&mut engine, k, v2, k, *ts.incr());
+            must_commit(&mut engine, k, ts, *ts.incr());
+        }
+        let (flashback_start_ts, flashback_commit_ts) = (*ts.incr(), *ts.incr());
+        // The exclusion prefixes are matched against the encoded form of the
+        // keys, so a single raw byte covers every key starting with it.
+        let exclude_prefixes = vec![Key::from_encoded(b"x".to_vec())];
+        let ctx = Context::default();
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
+        let (batch_keys, newer_write) = flashback_to_version_read_write(
+            &mut reader,
+            &mut point_reader,
+            Key::from_raw(b""),
+            &Key::from_raw(b""),
+            None,
+            version,
+            flashback_commit_ts,
+            &exclude_prefixes,
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
+        assert!(newer_write.is_none());
+        assert_eq!(batch_keys, vec![Key::from_raw(b"a"), Key::from_raw(b"b")]);
+        // The anchor resolution applies the exclusions as well: a range fully
+        // covered by an excluded prefix has no key to anchor the 2PC on.
+        assert_eq!(
+            get_first_user_key(
+                &mut reader,
+                &Key::from_raw(b"x"),
+                None,
+                version,
+                &exclude_prefixes
+            )
+            .unwrap(),
+            None
+        );
+        let cm = ConcurrencyManager::new(TimeStamp::zero());
+        let mut txn = MvccTxn::new(flashback_start_ts, cm);
+        flashback_to_version_write(
+            &mut txn,
+            &mut reader,
+            batch_keys,
+            version,
+            flashback_start_ts,
+            flashback_commit_ts,
+            false,
+        )
+        .unwrap();
+        write(&mut engine, &ctx, txn.into_modifies());
+        // The non-excluded keys are flashed back to `v1` while the excluded
+        // ones keep their current value.
+        let read_ts = *ts.incr();
+        must_get(&mut engine, b"a", read_ts, v1);
+        must_get(&mut engine, b"b", read_ts, v1);
+        must_get(&mut engine, b"x1", read_ts, v2);
+        must_get(&mut engine, b"x2", read_ts, v2);
+    }
+
+    #[test]
+    fn test_flashback_skip_unchanged_key() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (v1, v2) = (b"v1", b"v2");
+        // `a`, `b` and `c` all hold `v1` at `version`.
+        for k in [b"a", b"b", b"c"] {
+            must_prewrite_put(&mut engine, k, v1, k, *ts.incr());
+            must_commit(&mut engine, k, ts, *ts.incr());
+        }
+        let version = ts;
+        // `b` is rewritten with the identical value, so only its `commit_ts`
+        // differs from the version to flash back to, while `c` actually
+        // changes.
+        must_prewrite_put(&mut engine, b"b", v1, b"b", *ts.incr());
+        must_commit(&mut engine, b"b", ts, *ts.incr());
+        must_prewrite_put(&mut engine, b"c", v2, b"c", *ts.incr());
+        must_commit(&mut engine, b"c", ts, *ts.incr());
+        let (flashback_start_ts, flashback_commit_ts) = (*ts.incr(), *ts.incr());
+        let ctx = Context::default();
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
+        let (batch_keys, newer_write) = flashback_to_version_read_write(
+            &mut reader,
+            &mut point_reader,
+            Key::from_raw(b""),
+            &Key::from_raw(b""),
+            None,
+            version,
+            flashback_commit_ts,
+            &[],
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
+        assert!(newer_write.is_none());
+        // `a` is untouched since `version` and `b` still reads as `v1`, so
+        // only `c` is collected and no write is produced for the others.
+        assert_eq!(batch_keys, vec![Key::from_raw(b"c")]);
+        let cm = ConcurrencyManager::new(TimeStamp::zero());
+        let mut txn = MvccTxn::new(flashback_start_ts, cm);
+        flashback_to_version_write(
+            &mut txn,
+            &mut reader,
+            batch_keys,
+            version,
+            flashback_start_ts,
+            flashback_commit_ts,
+            false,
+        )
+        .unwrap();
+        assert_eq!(txn.modifies.len(), 1);
+        write(&mut engine, &ctx, txn.into_modifies());
+        let read_ts = *ts.incr();
+        must_get(&mut engine, b"a", read_ts, v1);
+        must_get(&mut engine, b"b", read_ts, v1);
+        must_get(&mut engine, b"c", read_ts, v1);
+    }
+
+    #[test]
+    fn test_flashback_dry_run() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (v1, v2) = (b"v1", b"v2");
+        // `a`, `b` and `c` exist at `version`, then `b` is overwritten, `d` is
+        // created and `e` is left locked.
+        let keys: [&[u8]; 3] = [b"a", b"b", b"c"];
+        for k in keys {
+            must_prewrite_put(&mut engine, k, v1, k, *ts.incr());
+            must_commit(&mut engine, k, ts, *ts.incr());
+        }
+        let version = ts;
+        must_prewrite_put(&mut engine, b"b", v2, b"b", *ts.incr());
+        must_commit(&mut engine, b"b", ts, *ts.incr());
+        must_prewrite_put(&mut engine, b"d", v2, b"d", *ts.incr());
+        must_commit(&mut engine, b"d", ts, *ts.incr());
+        let lock_ts = *ts.incr();
+        must_prewrite_put(&mut engine, b"e", v2, b"e", lock_ts);
+        let (start_ts, commit_ts) = (*ts.incr(), *ts.incr());
+        let dry_run = flashback_to_version_dry_run(
+            engine.snapshot(Default::default()).unwrap(),
+            Key::from_raw(b"a"),
+            Some(Key::from_raw(b"z")).as_ref(),
+            version,
+            start_ts,
+            commit_ts,
+            &[],
+            false,
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
+        assert!(dry_run.next_key.is_none());
+        // Only the lock on `e` is to be rolled back.
+        assert_eq!(dry_run.key_locks.len(), 1);
+        assert_eq!(dry_run.key_locks[0].0, Key::from_raw(b"e"));
+        assert_eq!(dry_run.key_locks[0].1.ts, lock_ts);
+        // `a` and `c` are untouched since `version`, so is `e` once its lock
+        // is rolled back (it has no committed value at all), while `b` is
+        // restored to `v1` and `d` is deleted.
+        assert_eq!(
+            dry_run.writes,
+            vec![
+                (
+                    Key::from_raw(b"b"),
+                    Write::new(WriteType::Put, start_ts, Some(v1.to_vec()))
+                ),
+                (
+                    Key::from_raw(b"d"),
+                    Write::new(WriteType::Delete, start_ts, None)
+                ),
+            ]
+        );
+        // Apply a real flashback over the same range and check every record
+        // the dry run proposed is exactly what landed in `CF_WRITE`.
+        assert_eq!(must_rollback_lock(&mut engine, b"e", start_ts), 2);
+        let ctx = Context::default();
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
+        let (batch_keys, newer_write) = flashback_to_version_read_write(
+            &mut reader,
+            &mut point_reader,
+            Key::from_raw(b"a"),
+            &Key::from_raw(b""),
+            Some(Key::from_raw(b"z")).as_ref(),
+            version,
+            commit_ts,
+            &[],
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
+        assert!(newer_write.is_none());
+        let cm = ConcurrencyManager::new(TimeStamp::zero());
+        let mut txn = MvccTxn::new(start_ts, cm);
+        flashback_to_version_write(
+            &mut txn,
+            &mut reader,
+            batch_keys,
+            version,
+            start_ts,
+            commit_ts,
+            false,
+        )
+        .unwrap();
+        write(&mut engine, &ctx, txn.into_modifies());
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
+        for (key, expected) in &dry_run.writes {
+            let (applied_ts, applied) = reader.seek_write(key, commit_ts).unwrap().unwrap();
+            assert_eq!(applied_ts, commit_ts);
+            assert_eq!(&applied, expected);
+        }
+    }
+
+    #[test]
+    fn test_flashback_dry_run_pagination() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (v1, v2) = (b"v1", b"v2");
+        let key_count = FLASHBACK_BATCH_SIZE + 1;
+        for i in 0..key_count {
+            let k = format!("k{:04}", i).into_bytes();
+            must_prewrite_put(&mut engine, &k, v1, &k, *ts.incr());
+            must_commit(&mut engine, &k, ts, *ts.incr());
+        }
+        let version = ts;
+        for i in 0..key_count {
+            let k = format!("k{:04}", i).into_bytes();
+            must_prewrite_put(&mut engine, &k, v2, &k, *ts.incr());
+            must_commit(&mut engine, &k, ts, *ts.incr());
+        }
+        let (start_ts, commit_ts) = (*ts.incr(), *ts.incr());
+        // Walk the range page by page: every key is proposed exactly once and
+        // each page stays below the batch size.
+        let mut all_writes = Vec::new();
+        let mut next_key = Key::from_raw(b"");
+        loop {
+            let page = flashback_to_version_dry_run(
+                engine.snapshot(Default::default()).unwrap(),
+                next_key,
+                Some(Key::from_raw(b"z")).as_ref(),
+                version,
+                start_ts,
+                commit_ts,
+                &[],
+                false,
+                &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+            )
+            .unwrap();
+            assert!(page.key_locks.is_empty());
+            assert!(page.writes.len() < FLASHBACK_BATCH_SIZE);
+            all_writes.extend(page.writes);
+            match page.next_key {
+                Some(key) => next_key = key,
+                None => break,
+            }
+        }
+        assert_eq!(all_writes.len(), key_count);
+        for (i, (key, write_record)) in all_writes.iter().enumerate() {
+            assert_eq!(*key, Key::from_raw(format!("k{:04}", i).as_bytes()));
+            assert_eq!(
+                *write_record,
+                Write::new(WriteType::Put, start_ts, Some(v1.to_vec()))
+            );
+        }
+    }
+
     #[test]
     fn test_flashback_write_to_version_pessimistic() {
         let mut engine = TestEngineBuilder::new().build().unwrap();
@@ -595,6 +1514,109 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_duplicated_commit_flashback_key() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        let (k, v1, v2) = (b"k", b"v1", b"v2");
+        must_prewrite_put(&mut engine, k, v1, k, *ts.incr());
+        must_commit(&mut engine, k, ts, *ts.incr());
+        let version = ts;
+        must_prewrite_put(&mut engine, k, v2, k, *ts.incr());
+        must_commit(&mut engine, k, ts, *ts.incr());
+
+        let (flashback_start_ts, flashback_commit_ts) = (*ts.incr(), *ts.incr());
+        assert_eq!(must_rollback_lock(&mut engine, k, flashback_start_ts), 0);
+        assert_eq!(
+            must_prewrite_flashback_key(&mut engine, k, version, flashback_start_ts),
+            1
+        );
+        // Put commit record and Unlock.
+        assert_eq!(
+            must_commit_flashback_key(
+                &mut engine,
+                k,
+                version,
+                flashback_start_ts,
+                flashback_commit_ts
+            ),
+            2
+        );
+        must_get(&mut engine, k, *ts.incr(), v1);
+        // Committing again with the same `start_ts` and `commit_ts` is a
+        // successful no-op: the retried commit recognizes its own commit
+        // record instead of failing with a lock-not-found error.
+        assert_eq!(
+            must_commit_flashback_key(
+                &mut engine,
+                k,
+                version,
+                flashback_start_ts,
+                flashback_commit_ts
+            ),
+            0
+        );
+        must_get(&mut engine, k, *ts.incr(), v1);
+    }
+
+    #[test]
+    fn test_flashback_batch_byte_budget() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        let mut ts = TimeStamp::zero();
+        // Write each key twice: first with the largest possible short value,
+        // then with a tiny one, so flashing back to the first round has to
+        // restore a large write record for every key.
+        let old_value = [u8::MAX; SHORT_VALUE_MAX_LEN];
+        let keys: Vec<_> = (0..FLASHBACK_BATCH_SIZE)
+            .map(|i| format!("k{:03}", i).into_bytes())
+            .collect();
+        for key in &keys {
+            must_prewrite_put(&mut engine, key, &old_value, key, *ts.incr());
+            must_commit(&mut engine, key, ts, *ts.incr());
+        }
+        let version = ts;
+        for key in &keys {
+            must_prewrite_put(&mut engine, key, b"v", key, *ts.incr());
+            must_commit(&mut engine, key, ts, *ts.incr());
+        }
+        let flashback_commit_ts = *ts.incr();
+        let ctx = Context::default();
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &ctx);
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &ctx);
+        let (batch_keys, _) = flashback_to_version_read_write(
+            &mut reader,
+            &mut point_reader,
+            Key::from_raw(b"k"),
+            &Key::from_raw(b""),
+            Some(Key::from_raw(b"l")).as_ref(),
+            version,
+            flashback_commit_ts,
+            &[],
+            &Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+        )
+        .unwrap();
+        assert_eq!(batch_keys.len(), FLASHBACK_BATCH_SIZE);
+        // The old writes to restore exceed `FLASHBACK_BATCH_MAX_BYTES` far
+        // before the key count limit, so the batch is cut off by the byte
+        // budget instead.
+        let mut truncated_keys = batch_keys.clone();
+        truncate_flashback_batch_by_bytes(&mut point_reader, &mut truncated_keys, version).unwrap();
+        assert!(!truncated_keys.is_empty());
+        assert!(truncated_keys.len() < FLASHBACK_BATCH_SIZE);
+        assert_eq!(
+            truncated_keys,
+            batch_keys[..truncated_keys.len()].to_vec(),
+            "the byte budget must keep a prefix of the batch"
+        );
+        // Flashing back to the very beginning restores nothing but small
+        // `WriteType::Delete` records, which never hit the byte budget.
+        let mut all_keys = batch_keys;
+        truncate_flashback_batch_by_bytes(&mut point_reader, &mut all_keys, TimeStamp::zero())
+            .unwrap();
+        assert_eq!(all_keys.len(), FLASHBACK_BATCH_SIZE);
+    }
+
     #[test]
     fn test_prewrite_with_special_key() {
         let mut engine = TestEngineBuilder::new().build().unwrap();
@@ -616,6 +1638,7 @@ pub mod tests {
             &Key::from_raw(b""),
             Some(Key::from_raw(b"z")).as_ref(),
             flashback_version,
+            &[],
         )
         .unwrap_or_else(|_| Some(Key::from_raw(b"")))
         .unwrap();
@@ -679,7 +1702,7 @@ pub mod tests {
         must_get_none(&mut engine, k, ts);
         // case 3: for last region, end_key will be None, prewrite key will be valid.
         assert_eq!(
-            get_first_user_key(&mut reader, &Key::from_raw(b"a"), None, flashback_version)
+            get_first_user_key(&mut reader, &Key::from_raw(b"a"), None, flashback_version, &[])
                 .unwrap()
                 .unwrap(),
             Key::from_raw(prewrite_key)
@@ -0,0 +1,348 @@
+// Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
+
+// #[PerformanceCriticalPath]
+use std::{fmt, mem, ops::Bound, sync::Arc};
+
+use txn_types::{Key, TimeStamp};
+
+use crate::storage::{
+    mvcc::MvccReader,
+    txn::{
+        commands::{Command, CommandExt, ProcessResult, ReadCommand, TypedCommand},
+        flashback_to_version_read_lock, flashback_to_version_read_write,
+        sched_pool::tls_collect_keyread_histogram_vec,
+        Result, FLASHBACK_BATCH_SIZE,
+    },
+    types::FlashbackEstimateResult,
+    Context, ScanMode, Snapshot, Statistics,
+};
+
+#[derive(Debug)]
+pub enum FlashbackEstimateState {
+    ScanLock { next_lock_key: Key },
+    ScanWrite { next_write_key: Key },
+}
+
+/// A partial estimate emitted by a streaming estimate each time its scan
+/// cursor crosses one of the caller-supplied boundaries, covering the counts
+/// accumulated since the previous emission. The lock scan and the write scan
+/// walk the range one after the other, so every segment is reported exactly
+/// twice, once per scan phase and in key order within each; consumers
+/// aggregate the emissions by `end_key` to build the per-region histogram.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlashbackEstimatePartial {
+    /// The exclusive end of the covered segment, `None` for the final
+    /// segment of an unbounded range.
+    pub end_key: Option<Key>,
+    pub lock_keys: usize,
+    pub write_keys: usize,
+    pub approximate_bytes: usize,
+}
+
+/// The callback a streaming estimate reports its partial counts through.
+#[derive(Clone)]
+pub struct FlashbackEstimateSink(Arc<dyn Fn(FlashbackEstimatePartial) + Send + Sync>);
+
+impl fmt::Debug for FlashbackEstimateSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FlashbackEstimateSink")
+    }
+}
+
+impl FlashbackEstimateSink {
+    pub fn new(sink: impl Fn(FlashbackEstimatePartial) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    fn emit(&self, partial: FlashbackEstimatePartial) {
+        (self.0)(partial);
+    }
+}
+
+pub fn new_flashback_estimate_cmd(
+    version: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    ctx: Context,
+) -> TypedCommand<FlashbackEstimateResult> {
+    FlashbackEstimate::new(
+        version,
+        start_key.clone(),
+        end_key,
+        FlashbackEstimateState::ScanLock {
+            next_lock_key: start_key,
+        },
+        FlashbackEstimateResult::default(),
+        Vec::new(),
+        0,
+        FlashbackEstimatePartial::default(),
+        None,
+        ctx,
+    )
+}
+
+/// Build an estimate that additionally emits partial counts through `sink`
+/// while it scans, segmented at `boundaries` (typically the region split
+/// keys strictly inside the range, in ascending order), so an estimate over
+/// a huge range can be observed incrementally instead of waiting for its
+/// completion. The final result still carries the full totals, which always
+/// equal the sum of all the emitted partials.
+pub fn new_flashback_estimate_streaming_cmd(
+    version: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    boundaries: Vec<Key>,
+    sink: FlashbackEstimateSink,
+    ctx: Context,
+) -> TypedCommand<FlashbackEstimateResult> {
+    FlashbackEstimate::new(
+        version,
+        start_key.clone(),
+        end_key,
+        FlashbackEstimateState::ScanLock {
+            next_lock_key: start_key,
+        },
+        FlashbackEstimateResult::default(),
+        boundaries,
+        0,
+        FlashbackEstimatePartial::default(),
+        Some(sink),
+        ctx,
+    )
+}
+
+command! {
+    FlashbackEstimate:
+        cmd_ty => FlashbackEstimateResult,
+        display => {
+            "kv::command::flashback_estimate -> {} | {:?}",
+            (version, ctx),
+        }
+        content => {
+            version: TimeStamp,
+            start_key: Key,
+            end_key: Option<Key>,
+            state: FlashbackEstimateState,
+            estimate: FlashbackEstimateResult,
+            boundaries: Vec<Key>,
+            next_boundary_idx: usize,
+            partial: FlashbackEstimatePartial,
+            sink: Option<FlashbackEstimateSink>,
+        }
+        in_heap => {
+            start_key,
+            end_key,
+            boundaries,
+        }
+}
+
+impl CommandExt for FlashbackEstimate {
+    ctx!();
+    request_type!(KvFlashbackToVersion);
+    property!(readonly);
+    tag!(flashback_estimate);
+    gen_lock!(empty);
+
+    fn write_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// A read-only pre-flight check of a flashback: count how many locks would be
+/// rolled back and how many latest user keys would be overwritten by a
+/// flashback to `self.version` over [`self.start_key`, `self.end_key`),
+/// without writing anything. Like the read phase of a real flashback, the
+/// scan is processed in batches of `FLASHBACK_BATCH_SIZE`, chaining itself
+/// with the accumulated counts until the whole range has been visited, so a
+/// single batch never occupies the scheduler for too long and the command
+/// deadline is checked between the batches.
+impl<S: Snapshot> ReadCommand<S> for FlashbackEstimate {
+    fn process_read(self, snapshot: S, statistics: &mut Statistics) -> Result<ProcessResult> {
+        let tag = self.tag().get_str();
+        let mut reader =
+            MvccReader::new_with_ctx(snapshot.clone(), Some(ScanMode::Forward), &self.ctx);
+        reader.set_allow_in_flashback(true);
+        // The write scan below checks the candidate keys for unchanged
+        // values out of the scan order, which needs a point-get reader.
+        let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &self.ctx);
+        point_reader.set_allow_in_flashback(true);
+        // Filter out the SST that does not have a newer version than
+        // `self.version` in `CF_WRITE`, just like the flashback read phase
+        // does.
+        reader.set_hint_min_ts(Some(Bound::Excluded(self.version)));
+        let mut estimate = self.estimate;
+        let mut partial = self.partial;
+        let mut next_boundary_idx = self.next_boundary_idx;
+        let next_state = match self.state {
+            FlashbackEstimateState::ScanLock { next_lock_key } => {
+                let mut key_locks = flashback_to_version_read_lock(
+                    &mut reader,
+                    next_lock_key,
+                    self.end_key.as_ref(),
+                    // The estimate runs before the flashback is prepared, so
+                    // there is no prewrite lock to skip.
+                    TimeStamp::zero(),
+                    // The estimate always counts the whole range, prefix
+                    // exclusions only apply to a real flashback.
+                    &[],
+                    &self.deadline,
+                )?;
+                // Only a full batch or one cut short by the deadline may have
+                // remaining locks, in which case carry the last key over as
+                // the start of the next batch without counting it twice. A
+                // chained command with an expired deadline is then rejected
+                // by the scheduler, like the batch hand-over always was.
+                let next_lock_key = if key_locks.len() == FLASHBACK_BATCH_SIZE
+                    || self.deadline.check().is_err()
+                {
+                    key_locks.pop().map(|(key, _)| key)
+                } else {
+                    None
+                };
+                tls_collect_keyread_histogram_vec(tag, key_locks.len() as f64);
+                estimate.lock_keys += key_locks.len();
+                estimate.approximate_bytes += key_locks
+                    .iter()
+                    .map(|(key, _)| key.as_encoded().len())
+                    .sum::<usize>();
+                // Attribute each counted lock to its boundary segment and
+                // emit the segments the cursor has fully crossed.
+                if let Some(sink) = self.sink.as_ref() {
+                    for (key, _) in &key_locks {
+                        while next_boundary_idx < self.boundaries.len()
+                            && *key >= self.boundaries[next_boundary_idx]
+                        {
+                            sink.emit(FlashbackEstimatePartial {
+                                end_key: Some(self.boundaries[next_boundary_idx].clone()),
+                                ..mem::take(&mut partial)
+                            });
+                            next_boundary_idx += 1;
+                        }
+                        partial.lock_keys += 1;
+                        partial.approximate_bytes += key.as_encoded().len();
+                    }
+                }
+                match next_lock_key {
+                    Some(next_lock_key) => FlashbackEstimateState::ScanLock { next_lock_key },
+                    None => {
+                        // The lock scan is done: flush its trailing segments,
+                        // including the (empty) ones without any lock, and
+                        // restart the boundary tracking for the write scan
+                        // below walking the range from the beginning again.
+                        if let Some(sink) = self.sink.as_ref() {
+                            while next_boundary_idx < self.boundaries.len() {
+                                sink.emit(FlashbackEstimatePartial {
+                                    end_key: Some(self.boundaries[next_boundary_idx].clone()),
+                                    ..mem::take(&mut partial)
+                                });
+                                next_boundary_idx += 1;
+                            }
+                            sink.emit(FlashbackEstimatePartial {
+                                end_key: self.end_key.clone(),
+                                ..mem::take(&mut partial)
+                            });
+                            next_boundary_idx = 0;
+                        }
+                        // All the locks have been counted, continue to count
+                        // the writes from the very beginning of the range.
+                        FlashbackEstimateState::ScanWrite {
+                            next_write_key: self.start_key.clone(),
+                        }
+                    }
+                }
+            }
+            FlashbackEstimateState::ScanWrite { next_write_key } => {
+                // The `commit_ts` passed below is `TimeStamp::max()`, so the
+                // newer-write detection never triggers for an estimate.
+                let (mut keys, _) = flashback_to_version_read_write(
+                    &mut reader,
+                    &mut point_reader,
+                    next_write_key,
+                    // Pass the raw range start as the prewrite key to exclude.
+                    // Unless the range starts exactly at a user key, nothing
+                    // is excluded, which matches a real flashback modifying
+                    // the prewrite key as well in the end.
+                    &self.start_key,
+                    self.end_key.as_ref(),
+                    self.version,
+                    // The flashback `commit_ts` is not allocated yet, so any
+                    // version newer than `self.version` counts.
+                    TimeStamp::max(),
+                    // The estimate always counts the whole range, prefix
+                    // exclusions only apply to a real flashback.
+                    &[],
+                    &self.deadline,
+                )?;
+                let next_write_key =
+                    if keys.len() == FLASHBACK_BATCH_SIZE || self.deadline.check().is_err() {
+                        keys.pop()
+                    } else {
+                        None
+                    };
+                tls_collect_keyread_histogram_vec(tag, keys.len() as f64);
+                estimate.write_keys += keys.len();
+                estimate.approximate_bytes += keys
+                    .iter()
+                    .map(|key| key.as_encoded().len())
+                    .sum::<usize>();
+                // Like the lock scan above, attribute each counted key to
+                // its boundary segment and emit the crossed segments.
+                if let Some(sink) = self.sink.as_ref() {
+                    for key in &keys {
+                        while next_boundary_idx < self.boundaries.len()
+                            && *key >= self.boundaries[next_boundary_idx]
+                        {
+                            sink.emit(FlashbackEstimatePartial {
+                                end_key: Some(self.boundaries[next_boundary_idx].clone()),
+                                ..mem::take(&mut partial)
+                            });
+                            next_boundary_idx += 1;
+                        }
+                        partial.write_keys += 1;
+                        partial.approximate_bytes += key.as_encoded().len();
+                    }
+                }
+                match next_write_key {
+                    Some(next_write_key) => FlashbackEstimateState::ScanWrite { next_write_key },
+                    None => {
+                        // Flush the trailing segments of the write scan as
+                        // well before finishing with the full totals.
+                        if let Some(sink) = self.sink.as_ref() {
+                            while next_boundary_idx < self.boundaries.len() {
+                                sink.emit(FlashbackEstimatePartial {
+                                    end_key: Some(self.boundaries[next_boundary_idx].clone()),
+                                    ..mem::take(&mut partial)
+                                });
+                                next_boundary_idx += 1;
+                            }
+                            sink.emit(FlashbackEstimatePartial {
+                                end_key: self.end_key.clone(),
+                                ..mem::take(&mut partial)
+                            });
+                        }
+                        statistics.add(&reader.statistics);
+                        statistics.add(&point_reader.statistics);
+                        return Ok(ProcessResult::FlashbackEstimate { estimate });
+                    }
+                }
+            }
+        };
+        statistics.add(&reader.statistics);
+        statistics.add(&point_reader.statistics);
+        Ok(ProcessResult::NextCommand {
+            cmd: Command::FlashbackEstimate(FlashbackEstimate {
+                ctx: self.ctx,
+                deadline: self.deadline,
+                version: self.version,
+                start_key: self.start_key,
+                end_key: self.end_key,
+                state: next_state,
+                estimate,
+                boundaries: self.boundaries,
+                next_boundary_idx,
+                partial,
+                sink: self.sink,
+            }),
+        })
+    }
+}
@@ -1,8 +1,10 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
 // #[PerformanceCriticalPath]
-use std::mem;
+use std::{mem, sync::Arc};
 
+use engine_traits::CfName;
+use resource_control::ResourceLimiter;
 use tikv_kv::ScanMode;
 use txn_types::{Key, TimeStamp};
 
@@ -13,21 +15,26 @@ use crate::storage::{
     mvcc::{MvccReader, MvccTxn},
     txn::{
         actions::flashback_to_version::{
-            commit_flashback_key, flashback_to_version_write, prewrite_flashback_key,
-            rollback_locks,
+            commit_flashback_key, delete_flashback_checkpoint, flashback_key_checksum,
+            flashback_range_checksum, flashback_to_version_write, prewrite_flashback_key,
+            rollback_locks, write_flashback_checkpoint, FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+            FLASHBACK_CHECKPOINT_ROLLBACK_LOCK,
         },
         commands::{
-            Command, CommandExt, FlashbackToVersionReadPhase, FlashbackToVersionState,
-            ReleasedLocks, ResponsePolicy, TypedCommand, WriteCommand, WriteContext, WriteResult,
+            Command, CommandExt, FlashbackCancelToken, FlashbackObserverRegistry,
+            FlashbackProgress, FlashbackRangeGuard, FlashbackShardGroup,
+            FlashbackToVersionReadPhase, FlashbackToVersionState, ReleasedLocks, ResponsePolicy,
+            TypedCommand, WriteCommand, WriteContext, WriteResult,
         },
         latch, Result,
     },
+    types::FlashbackResult,
     ProcessResult, Snapshot,
 };
 
 command! {
     FlashbackToVersion:
-        cmd_ty => (),
+        cmd_ty => FlashbackResult,
         display => {
             "kv::command::flashback_to_version -> {} | {} {} | {:?}",
             (version, start_ts, commit_ts, ctx),
@@ -39,10 +46,28 @@ command! {
             start_key: Key,
             end_key: Option<Key>,
             state: FlashbackToVersionState,
+            reverse: bool,
+            cf_filter: Option<CfName>,
+            exclude_prefixes: Vec<Key>,
+            force: bool,
+            preserve_history: bool,
+            progress: FlashbackProgress,
+            cancel_token: FlashbackCancelToken,
+            resource_limiter: Option<Arc<ResourceLimiter>>,
+            ranges: Vec<(Key, Key)>,
+            current_range_idx: usize,
+            stall_count: usize,
+            anchor: Option<Key>,
+            shard_group: Option<Arc<FlashbackShardGroup>>,
+            range_guard: Option<FlashbackRangeGuard>,
+            observers: Option<Arc<FlashbackObserverRegistry>>,
         }
         in_heap => {
             start_key,
             end_key,
+            exclude_prefixes,
+            ranges,
+            anchor,
         }
 }
 
@@ -133,20 +158,124 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for FlashbackToVersion {
                     self.version,
                     self.start_ts,
                     self.commit_ts,
+                    self.preserve_history,
                 )? {
                     *next_write_key = new_next_write_key;
                 }
             }
-            FlashbackToVersionState::Commit { ref key_to_commit } => commit_flashback_key(
-                &mut txn,
-                &mut reader,
-                key_to_commit,
-                self.start_ts,
-                self.commit_ts,
-            )?,
+            FlashbackToVersionState::Commit { ref key_to_commit } => {
+                commit_flashback_key(
+                    &mut txn,
+                    &mut reader,
+                    key_to_commit,
+                    self.start_ts,
+                    self.commit_ts,
+                )?;
+                // Recompute the checksum of the now-current range contents so
+                // the client can verify the flashback by comparing it against
+                // the one accumulated at `self.version` during the read
+                // phase. A multi-range flashback only restored the listed
+                // ranges, so the gaps between them must stay out of the
+                // comparison; a sharded flashback commits the shared anchor
+                // exactly once, here, so this single recomputation covers the
+                // whole sharded range.
+                if self.progress.checksum_enabled() {
+                    let mut checksum_reader = MvccReader::new_with_ctx(
+                        snapshot.clone(),
+                        Some(ScanMode::Forward),
+                        &self.ctx,
+                    );
+                    checksum_reader.set_allow_in_flashback(true);
+                    let mut point_reader = MvccReader::new_with_ctx(snapshot, None, &self.ctx);
+                    point_reader.set_allow_in_flashback(true);
+                    let spans: Vec<(&Key, Option<&Key>)> = if !self.ranges.is_empty() {
+                        self.ranges
+                            .iter()
+                            .map(|(start_key, end_key)| (start_key, Some(end_key)))
+                            .collect()
+                    } else if let Some(shard_group) = &self.shard_group {
+                        vec![shard_group.whole_range()]
+                    } else {
+                        vec![(&self.start_key, self.end_key.as_ref())]
+                    };
+                    let mut checksum = 0;
+                    for (span_start, span_end) in spans {
+                        checksum ^= flashback_range_checksum(
+                            &mut checksum_reader,
+                            &mut point_reader,
+                            span_start,
+                            span_end,
+                            TimeStamp::max(),
+                            &self.exclude_prefixes,
+                        )?;
+                    }
+                    // The anchor's own flashback commit only lives in this
+                    // command's write batch, so the snapshot still shows its
+                    // pre-flashback value: swap its contribution for the
+                    // value at `self.version` the pending commit makes
+                    // current.
+                    checksum ^= flashback_key_checksum(
+                        &mut point_reader,
+                        key_to_commit,
+                        TimeStamp::max(),
+                    )?;
+                    checksum ^= flashback_key_checksum(
+                        &mut point_reader,
+                        key_to_commit,
+                        self.version,
+                    )?;
+                    self.progress.add_post_checksum(checksum);
+                    context.statistics.add(&checksum_reader.statistics);
+                    context.statistics.add(&point_reader.statistics);
+                }
+            }
         }
         let rows = txn.modifies.len();
-        let mut write_data = WriteData::from_modifies(txn.into_modifies());
+        let mut modifies = txn.into_modifies();
+        // Checkpoint the progress at each batch boundary so an interrupted
+        // flashback is able to resume from where it stopped after a restart.
+        // Only the forward flashback is checkpointed since the first batch of
+        // a reverse scan never resumes from a recorded key. Multi-range and
+        // sharded flashbacks are not checkpointed either and always restart
+        // from scratch on retry.
+        if !self.reverse && self.ranges.is_empty() && self.shard_group.is_none() {
+            match self.state {
+                FlashbackToVersionState::RollbackLock {
+                    ref next_lock_key, ..
+                } => modifies.push(write_flashback_checkpoint(
+                    FLASHBACK_CHECKPOINT_ROLLBACK_LOCK,
+                    self.start_ts,
+                    self.version,
+                    &self.start_key,
+                    next_lock_key,
+                )),
+                FlashbackToVersionState::FlashbackWrite {
+                    ref next_write_key, ..
+                } => modifies.push(write_flashback_checkpoint(
+                    FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+                    self.start_ts,
+                    self.version,
+                    &self.start_key,
+                    next_write_key,
+                )),
+                // The checkpoint of the previous phase is no longer needed
+                // once the phase transition has been persisted.
+                FlashbackToVersionState::Prewrite { .. }
+                | FlashbackToVersionState::Commit { .. } => {
+                    modifies.push(delete_flashback_checkpoint(&self.start_key))
+                }
+            }
+        } else if matches!(
+            self.state,
+            FlashbackToVersionState::Prewrite { .. } | FlashbackToVersionState::Commit { .. }
+        ) {
+            // The modes that never checkpoint still delete here, so a
+            // checkpoint orphaned by an earlier abandoned flashback over the
+            // same start key is cleaned up by whichever flashback completes
+            // on it next, regardless of its mode.
+            modifies.push(delete_flashback_checkpoint(&self.start_key));
+        }
+        let mut write_data = WriteData::from_modifies(modifies);
         // To let the flashback modification could be proposed and applied successfully.
         write_data.extra.allowed_in_flashback = true;
         // To let the CDC treat the flashback modification as an 1PC transaction.
@@ -159,18 +288,65 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for FlashbackToVersion {
             to_be_write: write_data,
             rows,
             pr: (move || {
-                if matches!(
-                    self.state,
-                    FlashbackToVersionState::Prewrite { .. }
-                        | FlashbackToVersionState::Commit { .. }
-                ) {
-                    return ProcessResult::Res;
+                match self.state {
+                    FlashbackToVersionState::Prewrite { .. } => {
+                        // The prepare phase of a regular flashback ends here
+                        // and waits for a separate `FlashbackToVersion`
+                        // request to carry the `commit_ts`, while a
+                        // writes-only flashback already knows its `commit_ts`
+                        // and chains straight into the write phase.
+                        if self.commit_ts.is_zero() {
+                            return ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            };
+                        }
+                        // A multi-range flashback restarts the write phase
+                        // from its first range once the shared anchor has
+                        // been prewritten.
+                        if let Some((range_start, range_end)) = self.ranges.first().cloned() {
+                            self.current_range_idx = 0;
+                            self.start_key = range_start;
+                            self.end_key = Some(range_end);
+                        }
+                        self.state = FlashbackToVersionState::FlashbackWrite {
+                            next_write_key: self.start_key.clone(),
+                            keys: Vec::new(),
+                        };
+                    }
+                    FlashbackToVersionState::Commit { .. } => {
+                        // The prewrite anchor key committed here is flashed
+                        // back as well, so count it into the summary.
+                        self.progress.add_write_keys(1);
+                        // The rewritten history becomes visible with this
+                        // commit, so let the registered observers invalidate
+                        // whatever they derived from the old data. Like the
+                        // range registration, a multi-range or sharded
+                        // flashback conservatively reports its whole span.
+                        if let Some(observers) = &self.observers {
+                            let (start_key, end_key) = if let (Some(first), Some(last)) =
+                                (self.ranges.first(), self.ranges.last())
+                            {
+                                (&first.0, Some(&last.1))
+                            } else if let Some(shard_group) = &self.shard_group {
+                                shard_group.whole_range()
+                            } else {
+                                (&self.start_key, self.end_key.as_ref())
+                            };
+                            observers.notify_flashback_done(start_key, end_key);
+                        }
+                        return ProcessResult::FlashbackRes {
+                            result: self.progress.to_result(),
+                        };
+                    }
+                    _ => {}
                 }
 
                 #[cfg(feature = "failpoints")]
                 if matches!(self.state, FlashbackToVersionState::FlashbackWrite { .. }) {
                     fail_point!("flashback_failed_after_first_batch", |_| {
-                        ProcessResult::Res
+                        ProcessResult::FlashbackRes {
+                            result: self.progress.to_result(),
+                        }
                     });
                 }
 
@@ -184,6 +360,25 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for FlashbackToVersion {
                         start_key: self.start_key,
                         end_key: self.end_key,
                         state: self.state,
+                        reverse: self.reverse,
+                        cf_filter: self.cf_filter,
+                        exclude_prefixes: self.exclude_prefixes,
+                        force: self.force,
+                        preserve_history: self.preserve_history,
+                        progress: self.progress,
+                        cancel_token: self.cancel_token,
+                        resource_limiter: self.resource_limiter,
+                        ranges: self.ranges,
+                        current_range_idx: self.current_range_idx,
+                        stall_count: self.stall_count,
+                        // A write applied means the previous batch read went
+                        // through, so the next batch starts with a fresh
+                        // retry budget.
+                        read_retry_count: 0,
+                        anchor: self.anchor,
+                        shard_group: self.shard_group,
+                        range_guard: self.range_guard,
+                        observers: self.observers,
                     }),
                 }
             })(),
@@ -1,25 +1,99 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
 // #[PerformanceCriticalPath]
-use std::ops::Bound;
+use std::{
+    fmt,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
+use engine_traits::{CfName, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use file_system::IoBytes;
+use resource_control::ResourceLimiter;
+use tikv_util::{deadline::Deadline, time::Instant};
 use txn_types::{Key, Lock, TimeStamp};
 
 use crate::storage::{
-    metrics::{CommandKind, KV_COMMAND_COUNTER_VEC_STATIC},
-    mvcc::MvccReader,
+    metrics::{
+        CommandKind, FLASHBACK_BATCH_READ_DURATION_HISTOGRAM_VEC,
+        FLASHBACK_PROCESSED_KEYS_COUNTER_VEC, KV_COMMAND_COUNTER_VEC_STATIC,
+    },
+    kv::{Error as KvError, ErrorInner as KvErrorInner},
+    mvcc::{Error as MvccError, ErrorInner as MvccErrorInner, MvccReader},
     txn::{
-        actions::flashback_to_version::{check_flashback_commit, get_first_user_key},
+        actions::flashback_to_version::{
+            check_flashback_commit, check_flashback_version_has_data, flashback_key_successor,
+            flashback_range_checksum, get_first_user_key, load_flashback_checkpoint,
+            truncate_flashback_batch_by_bytes, FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+            FLASHBACK_CHECKPOINT_ROLLBACK_LOCK,
+        },
         commands::{
             Command, CommandExt, FlashbackToVersion, ProcessResult, ReadCommand, TypedCommand,
         },
-        flashback_to_version_read_lock, flashback_to_version_read_write,
+        flashback_to_version_read_lock, flashback_to_version_read_lock_reverse,
+        flashback_to_version_read_write, flashback_to_version_read_write_reverse,
         sched_pool::tls_collect_keyread_histogram_vec,
+        scheduler::DEFAULT_EXECUTION_DURATION_LIMIT,
         Error, ErrorInner, Result,
     },
+    types::FlashbackResult,
     Context, ScanMode, Snapshot, Statistics,
 };
 
+// The dead-loop guard below deliberately keeps a batch's only key as the
+// start of the next batch, so the resume key may legitimately repeat once.
+// Repeating this many batches in a row means the phase makes no progress at
+// all, and the flashback is aborted instead of looping forever.
+const FLASHBACK_MAX_STALLED_BATCHES: usize = 3;
+
+// A transient snapshot error does not abort the flashback right away: the
+// failed batch is retried against a fresh snapshot up to this many times in
+// a row, with an exponentially growing backoff in between.
+const FLASHBACK_MAX_READ_RETRIES: usize = 3;
+// The backoff before the first retry of a batch; it doubles on every further
+// retry of the same batch.
+const FLASHBACK_READ_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Chain `cmd` as the next command of the flashback, deferred by `delay`
+/// when the quota pacing asks for one. The scheduler awaits the delay
+/// asynchronously instead of blocking a worker thread on it.
+fn chain_next_command(cmd: Command, delay: Duration) -> ProcessResult {
+    if delay.is_zero() {
+        ProcessResult::NextCommand { cmd }
+    } else {
+        ProcessResult::NextCommandDelayed { cmd, delay }
+    }
+}
+
+/// Whether a batch read failed with a transient snapshot-level error that a
+/// retry against a fresh snapshot may well resolve: a leader change, a
+/// region split/merge or an overloaded store invalidates the snapshot
+/// without saying anything about the flashback itself. Everything else (a
+/// corrupted record, an invalid argument, ...) is genuine and fails the
+/// flashback directly.
+fn is_transient_read_error(err: &Error) -> bool {
+    let kv_err = match err {
+        Error(box ErrorInner::Engine(e)) => e,
+        Error(box ErrorInner::Mvcc(MvccError(box MvccErrorInner::Kv(e)))) => e,
+        _ => return false,
+    };
+    match kv_err {
+        KvError(box KvErrorInner::Request(header)) => {
+            header.has_not_leader()
+                || header.has_region_not_found()
+                || header.has_epoch_not_match()
+                || header.has_stale_command()
+                || header.has_server_is_busy()
+        }
+        KvError(box KvErrorInner::Timeout(_)) => true,
+        _ => false,
+    }
+}
+
 #[derive(Debug)]
 pub enum FlashbackToVersionState {
     RollbackLock {
@@ -38,14 +112,329 @@ pub enum FlashbackToVersionState {
     },
 }
 
+/// A cloneable handle tracking the approximate progress of a flashback.
+///
+/// Since a flashback is processed as a chain of read/write commands over
+/// multiple batches, the same handle is carried along the whole chain and
+/// accumulates the number of locks and writes processed so far, so the
+/// caller is able to report the progress while the flashback is running.
+/// The count is approximate: the key carried over as the start of the next
+/// batch will be scanned again and thus counted twice. The handle also
+/// accumulates the exact per-phase counts the final [`FlashbackResult`]
+/// summary is built from.
+#[derive(Clone, Debug)]
+pub struct FlashbackProgress {
+    processed_keys: Arc<AtomicUsize>,
+    lock_keys: Arc<AtomicUsize>,
+    write_keys: Arc<AtomicUsize>,
+    scanned_bytes: Arc<AtomicU64>,
+    start: Instant,
+    verify_checksum: bool,
+    pre_checksum: Arc<AtomicU64>,
+    post_checksum: Arc<AtomicU64>,
+}
+
+impl Default for FlashbackProgress {
+    fn default() -> Self {
+        Self {
+            processed_keys: Arc::default(),
+            lock_keys: Arc::default(),
+            write_keys: Arc::default(),
+            scanned_bytes: Arc::default(),
+            start: Instant::now(),
+            verify_checksum: false,
+            pre_checksum: Arc::default(),
+            post_checksum: Arc::default(),
+        }
+    }
+}
+
+impl FlashbackProgress {
+    /// Returns a progress handle that additionally accumulates a checksum of
+    /// the range contents at the flashback `version` during the read phase
+    /// and has the commit phase recompute the checksum of the now-current
+    /// range, so the client can verify the flashback by comparing the two in
+    /// the final [`FlashbackResult`]. The verification costs an extra value
+    /// lookup per key scanned plus a full range scan at commit time, and is
+    /// only meaningful for a flashback that runs to completion: a cancelled
+    /// one or one resumed from a persisted checkpoint reports the spans it
+    /// actually scanned, which will not match the full post checksum.
+    pub fn with_checksum() -> Self {
+        Self {
+            verify_checksum: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the total number of locks and writes processed so far.
+    pub fn processed_keys(&self) -> usize {
+        self.processed_keys.load(Ordering::Relaxed)
+    }
+
+    fn add_processed_keys(&self, count: usize) {
+        self.processed_keys.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn add_lock_keys(&self, count: usize) {
+        self.lock_keys.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(in crate::storage) fn add_write_keys(&self, count: usize) {
+        self.write_keys.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn add_scanned_bytes(&self, bytes: u64) {
+        self.scanned_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(in crate::storage) fn checksum_enabled(&self) -> bool {
+        self.verify_checksum
+    }
+
+    // The per-span checksums are folded in with XOR, so the order the spans
+    // of a reverse, multi-range or sharded flashback arrive in does not
+    // matter.
+    fn add_pre_checksum(&self, checksum: u64) {
+        self.pre_checksum.fetch_xor(checksum, Ordering::Relaxed);
+    }
+
+    pub(in crate::storage) fn add_post_checksum(&self, checksum: u64) {
+        self.post_checksum.fetch_xor(checksum, Ordering::Relaxed);
+    }
+
+    pub(in crate::storage) fn to_result(&self) -> FlashbackResult {
+        FlashbackResult {
+            writes_flashed: self.write_keys.load(Ordering::Relaxed),
+            locks_rolled_back: self.lock_keys.load(Ordering::Relaxed),
+            scanned_bytes: self.scanned_bytes.load(Ordering::Relaxed),
+            elapsed: self.start.saturating_elapsed(),
+            pre_checksum: self
+                .verify_checksum
+                .then(|| self.pre_checksum.load(Ordering::Relaxed)),
+            post_checksum: self
+                .verify_checksum
+                .then(|| self.post_checksum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FlashbackRange {
+    id: u64,
+    start_key: Vec<u8>,
+    end_key: Option<Vec<u8>>,
+}
+
+impl FlashbackRange {
+    fn overlaps(&self, start_key: &[u8], end_key: Option<&[u8]>) -> bool {
+        end_key.map_or(true, |end_key| self.start_key.as_slice() < end_key)
+            && self
+                .end_key
+                .as_ref()
+                .map_or(true, |own_end_key| start_key < own_end_key.as_slice())
+    }
+}
+
+/// The registry of all the flashback ranges a scheduler is still processing.
+/// Two flashbacks over overlapping ranges would interleave their writes and
+/// likely corrupt the data, so the scheduler registers the range of every
+/// incoming flashback here and rejects the ones conflicting with an active
+/// registration.
+#[derive(Debug, Default)]
+pub struct FlashbackRangeRegistry {
+    next_id: AtomicU64,
+    ranges: Mutex<Vec<FlashbackRange>>,
+}
+
+impl FlashbackRangeRegistry {
+    /// Registers `[start_key, end_key)` as an active flashback range, or
+    /// returns `None` when it intersects a range registered before. The
+    /// registration is released once the returned guard and all its clones
+    /// are dropped.
+    pub(in crate::storage) fn register(
+        self: &Arc<Self>,
+        start_key: &Key,
+        end_key: Option<&Key>,
+    ) -> Option<FlashbackRangeGuard> {
+        let start_key = start_key.as_encoded();
+        let end_key = end_key.map(|key| key.as_encoded());
+        let mut ranges = self.ranges.lock().unwrap();
+        if ranges
+            .iter()
+            .any(|range| range.overlaps(start_key, end_key.map(Vec::as_slice)))
+        {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        ranges.push(FlashbackRange {
+            id,
+            start_key: start_key.clone(),
+            end_key: end_key.cloned(),
+        });
+        Some(FlashbackRangeGuard {
+            registration: Arc::new(FlashbackRangeRegistration {
+                registry: self.clone(),
+                id,
+            }),
+        })
+    }
+
+    fn deregister(&self, id: u64) {
+        self.ranges
+            .lock()
+            .unwrap()
+            .retain(|range| range.id != id);
+    }
+}
+
+#[derive(Debug)]
+struct FlashbackRangeRegistration {
+    registry: Arc<FlashbackRangeRegistry>,
+    id: u64,
+}
+
+impl Drop for FlashbackRangeRegistration {
+    fn drop(&mut self) {
+        self.registry.deregister(self.id);
+    }
+}
+
+/// The coordination state shared by the shards of a sharded flashback write
+/// phase. Every shard decrements `remaining` once its whole sub-range is
+/// flashed back, and the shard taking the counter to zero commits the
+/// prewrite anchor, so the flashback only becomes visible once every shard
+/// is done.
+#[derive(Debug)]
+pub struct FlashbackShardGroup {
+    // the bounds of the whole flashback range, used by every shard to
+    // resolve the shared prewrite anchor
+    start_key: Key,
+    end_key: Option<Key>,
+    remaining: AtomicUsize,
+}
+
+impl FlashbackShardGroup {
+    /// Marks the calling shard as finished and returns whether it was the
+    /// last one, i.e. whether it has to commit the prewrite anchor.
+    fn finish_shard(&self) -> bool {
+        self.remaining.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    /// The bounds of the whole flashback range the shards were split from.
+    pub(in crate::storage) fn whole_range(&self) -> (&Key, Option<&Key>) {
+        (&self.start_key, self.end_key.as_ref())
+    }
+}
+
+/// A cloneable guard keeping a flashback range registered in the scheduler.
+/// It is carried along the whole chain of flashback commands and releases
+/// the registration once the last command of the chain is dropped, no matter
+/// whether the flashback completed or aborted halfway.
+#[derive(Clone, Debug)]
+pub struct FlashbackRangeGuard {
+    registration: Arc<FlashbackRangeRegistration>,
+}
+
+/// A cloneable handle used to abort an in-flight flashback.
+///
+/// Since a flashback is processed as a chain of read/write commands over
+/// multiple batches, the same handle is carried along the whole chain and
+/// checked at each batch boundary. Once cancelled, the flashback stops
+/// scanning and finalizes its 2PC on the prewrite key directly, so the range
+/// may be left only partially flashed back. This is intended for an emergency
+/// abort only: the caller set the token itself, so the command still finishes
+/// with an ordinary success result.
+#[derive(Clone, Debug, Default)]
+pub struct FlashbackCancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl FlashbackCancelToken {
+    /// Signals the flashback carrying this token to stop scanning and
+    /// finalize at the next batch boundary.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// An observer notified once a flashback has been committed. A flashback
+/// rewrites the history of its range, so any state derived from the old
+/// data, e.g. cached coprocessor results or statistics covering the range,
+/// is stale afterwards and should be invalidated here.
+pub trait FlashbackObserver: Send + Sync {
+    /// Called with the `[start_key, end_key)` range a finished flashback
+    /// covered, `None` meaning the range is unbounded at the end. A
+    /// multi-range or sharded flashback reports its whole conservative
+    /// span, including the gaps between its sub-ranges. A locks-only
+    /// flashback rewrites no history and never notifies.
+    fn on_flashback_done(&self, start_key: &Key, end_key: Option<&Key>);
+}
+
+/// The registry of the observers to notify once a flashback commits. It is
+/// held by the scheduler and handed to every flashback command it runs, so
+/// the commit phase at the end of the chain can reach the observers.
+#[derive(Default)]
+pub struct FlashbackObserverRegistry {
+    observers: Mutex<Vec<Arc<dyn FlashbackObserver>>>,
+}
+
+impl fmt::Debug for FlashbackObserverRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FlashbackObserverRegistry")
+    }
+}
+
+impl FlashbackObserverRegistry {
+    pub fn register(&self, observer: Arc<dyn FlashbackObserver>) {
+        self.observers.lock().unwrap().push(observer);
+    }
+
+    pub(in crate::storage) fn notify_flashback_done(
+        &self,
+        start_key: &Key,
+        end_key: Option<&Key>,
+    ) {
+        for observer in self.observers.lock().unwrap().iter() {
+            observer.on_flashback_done(start_key, end_key);
+        }
+    }
+}
+
+// Refuse to build a flashback whose `version` is below the GC safe point:
+// the history at such a version may already be (partially)
+// garbage-collected, so flashing back to it would resurrect a corrupt
+// snapshot. A zero safe point means it is unknown and the check is skipped,
+// e.g. on the gRPC path where the caller guarantees the version instead.
+fn check_flashback_version(version: TimeStamp, gc_safe_point: TimeStamp) -> Result<()> {
+    if !gc_safe_point.is_zero() && version < gc_safe_point {
+        return Err(Error::from(ErrorInner::FlashbackBelowGcSafePoint {
+            version,
+            safe_point: gc_safe_point,
+        }));
+    }
+    Ok(())
+}
+
 pub fn new_flashback_rollback_lock_cmd(
     start_ts: TimeStamp,
     version: TimeStamp,
+    gc_safe_point: TimeStamp,
     start_key: Key,
     end_key: Option<Key>,
+    reverse: bool,
+    cf_filter: Option<CfName>,
+    exclude_prefixes: Vec<Key>,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
     ctx: Context,
-) -> TypedCommand<()> {
-    FlashbackToVersionReadPhase::new(
+) -> Result<TypedCommand<FlashbackResult>> {
+    check_flashback_version(version, gc_safe_point)?;
+    Ok(FlashbackToVersionReadPhase::new(
         start_ts,
         TimeStamp::zero(),
         version,
@@ -55,19 +444,55 @@ pub fn new_flashback_rollback_lock_cmd(
             next_lock_key: start_key,
             key_locks: Vec::new(),
         },
+        reverse,
+        cf_filter,
+        exclude_prefixes,
+        // The prepare phase writes nothing but the prewrite anchor, so there
+        // is no data to guard against wiping yet and the preserve-history
+        // mode does not apply either.
+        false,
+        false,
+        progress,
+        cancel_token,
+        resource_limiter,
+        Vec::new(),
+        0,
+        0,
+        0,
+        None,
+        None,
+        // The range is registered by the scheduler once the command is run,
+        // and the observer registry is handed over there as well.
+        None,
+        None,
         ctx,
-    )
+    ))
 }
 
+/// Build the write-phase command of a flashback. With `preserve_history`
+/// set, the flashback runs in the audit-friendly mode: it only appends the
+/// restored old values as fresh writes and leaves the keys created after
+/// `version` untouched, see `flashback_to_version_write` for the exact
+/// MVCC implications.
 pub fn new_flashback_write_cmd(
     start_ts: TimeStamp,
     commit_ts: TimeStamp,
     version: TimeStamp,
+    gc_safe_point: TimeStamp,
     start_key: Key,
     end_key: Option<Key>,
+    reverse: bool,
+    cf_filter: Option<CfName>,
+    exclude_prefixes: Vec<Key>,
+    force: bool,
+    preserve_history: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
     ctx: Context,
-) -> TypedCommand<()> {
-    FlashbackToVersionReadPhase::new(
+) -> Result<TypedCommand<FlashbackResult>> {
+    check_flashback_version(version, gc_safe_point)?;
+    Ok(FlashbackToVersionReadPhase::new(
         start_ts,
         commit_ts,
         version,
@@ -77,13 +502,323 @@ pub fn new_flashback_write_cmd(
             next_write_key: start_key,
             keys: Vec::new(),
         },
+        reverse,
+        cf_filter,
+        exclude_prefixes,
+        force,
+        preserve_history,
+        progress,
+        cancel_token,
+        resource_limiter,
+        Vec::new(),
+        0,
+        0,
+        0,
+        None,
+        None,
+        // The range is registered by the scheduler once the command is run,
+        // and the observer registry is handed over there as well.
+        None,
+        None,
+        ctx,
+    ))
+}
+
+/// Like [`new_flashback_write_cmd`], but derives `commit_ts` from a fresh
+/// timestamp fetched through `ts_source` instead of taking it from the
+/// caller. Supplying a `commit_ts` below the current maximum committed
+/// timestamp would break the MVCC ordering of the rewritten history, and a
+/// fresh TSO allocation is by definition above every timestamp already
+/// persisted, so wiring `ts_source` to the TSO makes that mistake
+/// impossible. The explicit constructor stays available for callers that,
+/// e.g., share one `commit_ts` across several coordinated flashbacks.
+pub fn new_flashback_write_cmd_with_derived_ts(
+    start_ts: TimeStamp,
+    ts_source: impl FnOnce() -> TimeStamp,
+    version: TimeStamp,
+    gc_safe_point: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    reverse: bool,
+    cf_filter: Option<CfName>,
+    exclude_prefixes: Vec<Key>,
+    force: bool,
+    preserve_history: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
+    ctx: Context,
+) -> Result<TypedCommand<FlashbackResult>> {
+    let commit_ts = ts_source();
+    // The prewrite of the prepare phase happened at `start_ts`, so a source
+    // handing out a timestamp at or below it is stale (e.g. a cached
+    // allocation from before the prepare) and must be rejected before the
+    // command is even built.
+    if commit_ts <= start_ts {
+        return Err(Error::from(ErrorInner::InvalidTxnTso {
+            start_ts,
+            commit_ts,
+        }));
+    }
+    new_flashback_write_cmd(
+        start_ts,
+        commit_ts,
+        version,
+        gc_safe_point,
+        start_key,
+        end_key,
+        reverse,
+        cf_filter,
+        exclude_prefixes,
+        force,
+        preserve_history,
+        progress,
+        cancel_token,
+        resource_limiter,
         ctx,
     )
 }
 
+/// Build a command that only rolls back the locks in the range without
+/// rewriting any MVCC record, e.g. to clear the dangling locks left by a
+/// crashed transaction coordinator after a known point.
+///
+/// # Safety
+///
+/// Since no write will be overwritten, the command finishes right after the
+/// lock rollback without prewriting the flashback anchor key, so it does not
+/// block the `resolved_ts` from advancing. The caller has to make sure the
+/// rolled-back transactions will never commit, otherwise their data is lost.
+pub fn new_flashback_locks_only_cmd(
+    start_ts: TimeStamp,
+    version: TimeStamp,
+    gc_safe_point: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    reverse: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
+    ctx: Context,
+) -> Result<TypedCommand<FlashbackResult>> {
+    new_flashback_rollback_lock_cmd(
+        start_ts,
+        version,
+        gc_safe_point,
+        start_key,
+        end_key,
+        reverse,
+        Some(CF_LOCK),
+        Vec::new(),
+        progress,
+        cancel_token,
+        resource_limiter,
+        ctx,
+    )
+}
+
+/// Build a command that flashes back only the writes in the range, leaving
+/// every lock in place. Unlike the regular two-request flow, the lock
+/// rollback phase is skipped entirely and the prepare phase chains straight
+/// into the write phase, so a single command runs Prewrite -> FlashbackWrite
+/// -> Commit. Carrying a non-zero `commit_ts` into the prepare phase is what
+/// signals `FlashbackToVersion` to keep chaining after the prewrite.
+///
+/// # Safety
+///
+/// Any lock in the range survives the flashback, so the caller has to make
+/// sure no transaction is still running in the range, otherwise its lock
+/// would dangle over the flashed-back data once it commits.
+pub fn new_flashback_writes_only_cmd(
+    start_ts: TimeStamp,
+    commit_ts: TimeStamp,
+    version: TimeStamp,
+    gc_safe_point: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    reverse: bool,
+    force: bool,
+    preserve_history: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
+    ctx: Context,
+) -> Result<TypedCommand<FlashbackResult>> {
+    check_flashback_version(version, gc_safe_point)?;
+    Ok(FlashbackToVersionReadPhase::new(
+        start_ts,
+        commit_ts,
+        version,
+        start_key.clone(),
+        end_key,
+        FlashbackToVersionState::RollbackLock {
+            next_lock_key: start_key,
+            key_locks: Vec::new(),
+        },
+        reverse,
+        Some(CF_WRITE),
+        Vec::new(),
+        force,
+        preserve_history,
+        progress,
+        cancel_token,
+        resource_limiter,
+        Vec::new(),
+        0,
+        0,
+        0,
+        None,
+        None,
+        // The range is registered by the scheduler once the command is run,
+        // and the observer registry is handed over there as well.
+        None,
+        None,
+        ctx,
+    ))
+}
+
+/// Build a command that flashes back several disjoint key ranges in one go,
+/// sharing a single prewrite anchor and one commit across all of them. Like
+/// the writes-only flashback, carrying a non-zero `commit_ts` into the
+/// prepare phase makes the chain run all four phases under one command.
+///
+/// `ranges` must be sorted in ascending order and must not overlap. The
+/// phases are ordered as follows: the locks of every range are rolled back
+/// first in range order, then the anchor (the first user key across the
+/// ranges) is prewritten, the writes are flashed back range by range in
+/// ascending order, and the commit of the anchor at the very end finishes
+/// the whole flashback. An interrupted multi-range flashback thus never
+/// commits a partial result, but it is not checkpointed either: a retry
+/// rescans all the ranges from scratch.
+///
+/// # Panics
+///
+/// Panics if `ranges` is empty.
+pub fn new_flashback_multi_range_cmd(
+    start_ts: TimeStamp,
+    commit_ts: TimeStamp,
+    version: TimeStamp,
+    gc_safe_point: TimeStamp,
+    ranges: Vec<(Key, Key)>,
+    force: bool,
+    preserve_history: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
+    ctx: Context,
+) -> Result<TypedCommand<FlashbackResult>> {
+    check_flashback_version(version, gc_safe_point)?;
+    let (start_key, end_key) = ranges[0].clone();
+    Ok(FlashbackToVersionReadPhase::new(
+        start_ts,
+        commit_ts,
+        version,
+        start_key.clone(),
+        Some(end_key),
+        FlashbackToVersionState::RollbackLock {
+            next_lock_key: start_key,
+            key_locks: Vec::new(),
+        },
+        false,
+        None,
+        Vec::new(),
+        force,
+        preserve_history,
+        progress,
+        cancel_token,
+        resource_limiter,
+        ranges,
+        0,
+        0,
+        0,
+        None,
+        None,
+        // The ranges are registered by the scheduler once the command is run,
+        // and the observer registry is handed over there as well.
+        None,
+        None,
+        ctx,
+    ))
+}
+
+/// Build one write-phase command per shard of `[start_key, end_key)`, split
+/// at `shard_keys` (typically region boundaries strictly inside the range,
+/// in ascending order), so the FlashbackWrite phases of the shards can run
+/// in parallel. The range has to be prepared as usual first: every shard
+/// resolves the same prewrite anchor over the whole range, and whichever
+/// shard finishes last commits it, so the flashback only becomes visible
+/// once every shard is done. The shards share `progress`, which thus
+/// accumulates the merged progress across all of them. Like the multi-range
+/// flashback, a sharded one is not checkpointed: if any shard fails, the
+/// anchor stays locked and the whole write phase has to be retried.
+pub fn new_flashback_sharded_write_cmds(
+    start_ts: TimeStamp,
+    commit_ts: TimeStamp,
+    version: TimeStamp,
+    gc_safe_point: TimeStamp,
+    start_key: Key,
+    end_key: Option<Key>,
+    shard_keys: Vec<Key>,
+    preserve_history: bool,
+    progress: FlashbackProgress,
+    cancel_token: FlashbackCancelToken,
+    resource_limiter: Option<Arc<ResourceLimiter>>,
+    ctx: Context,
+) -> Result<Vec<TypedCommand<FlashbackResult>>> {
+    check_flashback_version(version, gc_safe_point)?;
+    let shard_group = Arc::new(FlashbackShardGroup {
+        start_key: start_key.clone(),
+        end_key: end_key.clone(),
+        remaining: AtomicUsize::new(shard_keys.len() + 1),
+    });
+    let mut bounds = Vec::with_capacity(shard_keys.len() + 1);
+    bounds.push(start_key);
+    bounds.extend(shard_keys);
+    Ok((0..bounds.len())
+        .map(|i| {
+            let shard_start = bounds[i].clone();
+            let shard_end = bounds.get(i + 1).cloned().or_else(|| end_key.clone());
+            FlashbackToVersionReadPhase::new(
+                start_ts,
+                commit_ts,
+                version,
+                shard_start.clone(),
+                shard_end,
+                FlashbackToVersionState::FlashbackWrite {
+                    next_write_key: shard_start,
+                    keys: Vec::new(),
+                },
+                false,
+                None,
+                Vec::new(),
+                // The shards are split at arbitrary boundaries, so a shard
+                // without any old data is expected and must not trip the
+                // no-data guard.
+                true,
+                preserve_history,
+                progress.clone(),
+                cancel_token.clone(),
+                resource_limiter.clone(),
+                Vec::new(),
+                0,
+                0,
+                0,
+                None,
+                Some(shard_group.clone()),
+                // Each shard registers its own sub-range with the scheduler
+                // once the command is run, and the observer registry is
+                // handed over there as well.
+                None,
+                None,
+                ctx.clone(),
+            )
+        })
+        .collect())
+}
+
 command! {
     FlashbackToVersionReadPhase:
-        cmd_ty => (),
+        cmd_ty => FlashbackResult,
         display => {
             "kv::command::flashback_to_version_read_phase -> {} | {} {} | {:?}",
             (version, start_ts, commit_ts, ctx),
@@ -95,13 +830,140 @@ command! {
             start_key: Key,
             end_key: Option<Key>,
             state: FlashbackToVersionState,
+            reverse: bool,
+            cf_filter: Option<CfName>,
+            exclude_prefixes: Vec<Key>,
+            force: bool,
+            preserve_history: bool,
+            progress: FlashbackProgress,
+            cancel_token: FlashbackCancelToken,
+            resource_limiter: Option<Arc<ResourceLimiter>>,
+            ranges: Vec<(Key, Key)>,
+            current_range_idx: usize,
+            stall_count: usize,
+            read_retry_count: usize,
+            anchor: Option<Key>,
+            shard_group: Option<Arc<FlashbackShardGroup>>,
+            range_guard: Option<FlashbackRangeGuard>,
+            observers: Option<Arc<FlashbackObserverRegistry>>,
         }
         in_heap => {
             start_key,
             end_key,
+            exclude_prefixes,
+            ranges,
+            anchor,
         }
 }
 
+impl FlashbackToVersionReadPhase {
+    /// Returns whether the data in the given CF should be flashed back under
+    /// `self.cf_filter`. Note that `CF_WRITE` and `CF_DEFAULT` are coupled:
+    /// whether an MVCC value lives in its write record in `CF_WRITE` or as a
+    /// separate entry in `CF_DEFAULT` depends only on its size, so a filter
+    /// targeting either of them restores both.
+    fn cf_applies(&self, cf: CfName) -> bool {
+        match self.cf_filter {
+            None => true,
+            Some(filter) if filter == cf => true,
+            Some(CF_DEFAULT) => cf == CF_WRITE,
+            _ => false,
+        }
+    }
+
+    /// Consume the bytes scanned by a batch from the resource limiter of the
+    /// group the flashback is associated with, and return how long the next
+    /// command in the chain has to be deferred once the group quota is
+    /// exhausted. Since a flashback already proceeds serially batch by batch,
+    /// pacing the hand-over to the next batch is enough to cap its throughput
+    /// with the existing group quotas. The wait is not slept here — this runs
+    /// on a shared scheduler worker — but awaited asynchronously by the
+    /// scheduler before the chained command is handed over.
+    fn consume_resource_quota(&self, scanned_bytes: u64) -> Duration {
+        let Some(limiter) = &self.resource_limiter else {
+            return Duration::ZERO;
+        };
+        limiter.consume(
+            Duration::ZERO,
+            IoBytes {
+                read: scanned_bytes,
+                write: 0,
+            },
+            true,
+        )
+    }
+
+    /// The deadline the next command in the chain runs under. A batch cut
+    /// short by the deadline is chained as usual, but the resumed command
+    /// gets a fresh execution budget, so the deadline bounds every single
+    /// batch instead of aborting a long flashback halfway. A pending delay
+    /// the scheduler awaits before handing the command over is granted on
+    /// top of the budget, so the deferral does not eat into the next batch.
+    fn next_deadline(&self, pending_delay: Duration) -> Deadline {
+        if pending_delay.is_zero() && self.deadline.check().is_ok() {
+            return self.deadline;
+        }
+        let execution_duration_limit = if self.ctx.max_execution_duration_ms == 0 {
+            DEFAULT_EXECUTION_DURATION_LIMIT
+        } else {
+            Duration::from_millis(self.ctx.max_execution_duration_ms)
+        };
+        Deadline::from_now(execution_duration_limit + pending_delay)
+    }
+
+    /// Watch whether the phase advanced past the key it resumed from, and
+    /// abort once [`FLASHBACK_MAX_STALLED_BATCHES`] consecutive batches made
+    /// no progress. The dead-loop guard keeping a batch's only key as the
+    /// next start legitimately repeats the resume key once, which stays well
+    /// below the threshold; only a scan stuck on the same key forever trips
+    /// the watchdog.
+    fn check_stalled_batch(&mut self, resume_key: &Key, next_key: &Key) -> Result<()> {
+        if next_key != resume_key {
+            self.stall_count = 0;
+            return Ok(());
+        }
+        self.stall_count += 1;
+        if self.stall_count >= FLASHBACK_MAX_STALLED_BATCHES {
+            return Err(Error::from(ErrorInner::FlashbackStalled {
+                key: next_key.to_raw()?,
+                batches: self.stall_count,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Turn a transient snapshot error into a bounded retry of the current
+    /// batch: the command chains itself again with the cursor it resumed
+    /// from, so the scheduler re-acquires a fresh snapshot and the scan
+    /// picks up where it left off. A genuine error, or a batch whose retry
+    /// budget is exhausted, still fails the flashback right away.
+    fn maybe_retry_read(mut self, err: Error) -> Result<ProcessResult> {
+        if !is_transient_read_error(&err) || self.read_retry_count >= FLASHBACK_MAX_READ_RETRIES {
+            return Err(err);
+        }
+        self.read_retry_count += 1;
+        warn!(
+            "flashback read hit a transient snapshot error, retrying the batch";
+            "region_id" => self.ctx.get_region_id(),
+            "start_ts" => self.start_ts,
+            "retries" => self.read_retry_count,
+            "err" => ?err,
+        );
+        // Back off before handing the command back to the scheduler, so a
+        // leader change or an overloaded store gets a chance to settle. The
+        // backoff is awaited asynchronously by the scheduler rather than
+        // slept here, keeping the worker thread free for other commands.
+        let backoff = FLASHBACK_READ_RETRY_BACKOFF * (1 << (self.read_retry_count - 1));
+        // Like a batch cut short by the deadline, the retried batch runs
+        // under a fresh execution budget, granted on top of the backoff.
+        self.deadline = self.next_deadline(backoff);
+        Ok(ProcessResult::NextCommandDelayed {
+            cmd: Command::FlashbackToVersionReadPhase(self),
+            delay: backoff,
+        })
+    }
+}
+
 impl CommandExt for FlashbackToVersionReadPhase {
     ctx!();
     request_type!(KvFlashbackToVersion);
@@ -157,25 +1019,208 @@ impl CommandExt for FlashbackToVersionReadPhase {
 ///     - Commit the first user key after `self.start_key` we write at the
 ///       second phase to finish the flashback.
 impl<S: Snapshot> ReadCommand<S> for FlashbackToVersionReadPhase {
-    fn process_read(self, snapshot: S, statistics: &mut Statistics) -> Result<ProcessResult> {
+    fn process_read(mut self, snapshot: S, statistics: &mut Statistics) -> Result<ProcessResult> {
+        // An empty range contains neither anything to flash back nor any key
+        // to prewrite, so finish directly without scanning at all.
+        if self
+            .end_key
+            .as_ref()
+            .map_or(false, |end_key| self.start_key >= *end_key)
+        {
+            return Ok(ProcessResult::FlashbackRes {
+                result: self.progress.to_result(),
+            });
+        }
         let tag = self.tag().get_str();
-        let mut reader = MvccReader::new_with_ctx(snapshot, Some(ScanMode::Forward), &self.ctx);
+        let begin_instant = Instant::now();
+        // The reverse scan needs a `ScanMode::Mixed` reader rather than a
+        // `ScanMode::Backward` one, since locating the prewrite key with
+        // `get_first_user_key` and checking the flashback commit record still
+        // seek forward.
+        let scan_mode = if self.reverse {
+            ScanMode::Mixed
+        } else {
+            ScanMode::Forward
+        };
+        let mut reader = MvccReader::new_with_ctx(snapshot.clone(), Some(scan_mode), &self.ctx);
         reader.set_allow_in_flashback(true);
         // Filter out the SST that does not have a newer version than `self.version` in
         // `CF_WRITE`, i.e, whose latest `commit_ts` <= `self.version` in the later
         // scan. By doing this, we can only flashback those keys that have version
         // changed since `self.version` as much as possible.
         reader.set_hint_min_ts(Some(Bound::Excluded(self.version)));
+        // An external cancellation only takes effect at a batch boundary: the
+        // scanning stops and the flashback 2PC is finalized directly, which
+        // may leave the range only partially flashed back.
+        let cancelled = self.cancel_token.is_cancelled();
+        if cancelled {
+            warn!(
+                "flashback is cancelled, finalizing early";
+                "region_id" => self.ctx.get_region_id(),
+                "start_ts" => self.start_ts,
+            );
+        }
         let mut start_key = self.start_key.clone();
+        // Instrument the batch with a tracing span carrying the phase and the
+        // key it resumes from, so a stuck flashback can be located through
+        // the tracing dump of the status server.
+        let span = match &self.state {
+            FlashbackToVersionState::RollbackLock { next_lock_key, .. } => tracing::debug_span!(
+                "flashback_rollback_lock",
+                region_id = self.ctx.get_region_id(),
+                next_lock_key = ?log_wrappers::Value::key(next_lock_key.as_encoded()),
+                batch_size = tracing::field::Empty,
+            ),
+            FlashbackToVersionState::FlashbackWrite { next_write_key, .. } => {
+                tracing::debug_span!(
+                    "flashback_write",
+                    region_id = self.ctx.get_region_id(),
+                    next_write_key = ?log_wrappers::Value::key(next_write_key.as_encoded()),
+                    batch_size = tracing::field::Empty,
+                )
+            }
+            _ => unreachable!(),
+        };
+        let _entered = span.enter();
+        // How long the scheduler should defer the chained command to respect
+        // the group quota, filled in once the batch has been consumed from it.
+        let mut defer_dur = Duration::ZERO;
         let next_state = match self.state {
             FlashbackToVersionState::RollbackLock { next_lock_key, .. } => {
-                let mut key_locks = flashback_to_version_read_lock(
-                    &mut reader,
-                    next_lock_key,
-                    self.end_key.as_ref(),
-                    self.start_ts,
-                )?;
+                let resume_key = next_lock_key.clone();
+                let scan_result = if cancelled || !self.cf_applies(CF_LOCK) {
+                    // Either the flashback has been cancelled or it is
+                    // restricted to another CF, leave the locks untouched.
+                    Ok(Vec::new())
+                } else if self.reverse {
+                    // `next_lock_key` equals to `self.start_key` only before the
+                    // first batch, in which case the reverse scan starts from
+                    // `self.end_key` instead.
+                    let next_lock_key = (next_lock_key != self.start_key).then_some(next_lock_key);
+                    flashback_to_version_read_lock_reverse(
+                        &mut reader,
+                        next_lock_key.as_ref(),
+                        &self.start_key,
+                        self.end_key.as_ref(),
+                        self.start_ts,
+                        &self.exclude_prefixes,
+                        &self.deadline,
+                    )
+                } else {
+                    // Before the first batch, check whether an interrupted
+                    // flashback has persisted a checkpoint to resume from.
+                    // Multi-range flashbacks are not checkpointed, a retry
+                    // always rescans them from scratch.
+                    let checkpoint = if self.ranges.is_empty() && next_lock_key == self.start_key {
+                        load_flashback_checkpoint(
+                            &snapshot,
+                            FLASHBACK_CHECKPOINT_ROLLBACK_LOCK,
+                            self.start_ts,
+                            self.version,
+                            &self.start_key,
+                        )
+                    } else {
+                        Ok(None)
+                    };
+                    checkpoint.and_then(|checkpoint_key| {
+                        flashback_to_version_read_lock(
+                            &mut reader,
+                            checkpoint_key.unwrap_or(next_lock_key),
+                            self.end_key.as_ref(),
+                            self.start_ts,
+                            &self.exclude_prefixes,
+                            &self.deadline,
+                        )
+                    })
+                };
+                let mut key_locks = match scan_result {
+                    Ok(key_locks) => key_locks,
+                    Err(err) => {
+                        statistics.add(&reader.statistics);
+                        // The cursor the batch resumed from is untouched, so
+                        // the retry rescans exactly this batch.
+                        self.state = FlashbackToVersionState::RollbackLock {
+                            next_lock_key: resume_key,
+                            key_locks: Vec::new(),
+                        };
+                        return self.maybe_retry_read(err);
+                    }
+                };
+                // The batch read went through, so the next transient failure
+                // gets a fresh retry budget again.
+                self.read_retry_count = 0;
+                span.record("batch_size", key_locks.len() as u64);
+                self.progress.add_processed_keys(key_locks.len());
+                FLASHBACK_BATCH_READ_DURATION_HISTOGRAM_VEC
+                    .with_label_values(&["flashback_lock"])
+                    .observe(begin_instant.saturating_elapsed_secs());
+                FLASHBACK_PROCESSED_KEYS_COUNTER_VEC
+                    .with_label_values(&["flashback_lock"])
+                    .inc_by(key_locks.len() as u64);
+                let scanned_bytes = key_locks
+                    .iter()
+                    .map(|(key, _)| key.as_encoded().len() as u64)
+                    .sum();
+                self.progress.add_scanned_bytes(scanned_bytes);
+                defer_dur = self.consume_resource_quota(scanned_bytes);
                 if key_locks.is_empty() {
+                    // A multi-range flashback rolls back the locks of every
+                    // range before prewriting the shared anchor, so move on to
+                    // the next range first.
+                    if self.current_range_idx + 1 < self.ranges.len() {
+                        self.current_range_idx += 1;
+                        let (range_start, range_end) =
+                            self.ranges[self.current_range_idx].clone();
+                        start_key = range_start.clone();
+                        self.end_key = Some(range_end);
+                        statistics.add(&reader.statistics);
+                        let deadline = self.next_deadline(defer_dur);
+                        return Ok(chain_next_command(
+                            Command::FlashbackToVersionReadPhase(FlashbackToVersionReadPhase {
+                                ctx: self.ctx,
+                                deadline,
+                                start_ts: self.start_ts,
+                                commit_ts: self.commit_ts,
+                                version: self.version,
+                                start_key,
+                                end_key: self.end_key,
+                                state: FlashbackToVersionState::RollbackLock {
+                                    next_lock_key: range_start,
+                                    key_locks: Vec::new(),
+                                },
+                                reverse: self.reverse,
+                                cf_filter: self.cf_filter,
+                                exclude_prefixes: self.exclude_prefixes,
+                                force: self.force,
+                                preserve_history: self.preserve_history,
+                                progress: self.progress,
+                                cancel_token: self.cancel_token,
+                                resource_limiter: self.resource_limiter,
+                                ranges: self.ranges,
+                                current_range_idx: self.current_range_idx,
+                                // the next range starts a fresh scan, so its
+                                // watchdog and retry budget start from
+                                // scratch as well.
+                                stall_count: 0,
+                                read_retry_count: 0,
+                                anchor: self.anchor,
+                                shard_group: self.shard_group,
+                                range_guard: self.range_guard,
+                                observers: self.observers,
+                            }),
+                            defer_dur,
+                        ));
+                    }
+                    // When the flashback is restricted to a CF other than the
+                    // write CF, no write will be overwritten later, so there is
+                    // no need to prewrite the start key to prevent the
+                    // `resolved_ts` from advancing either.
+                    if !self.cf_applies(CF_WRITE) {
+                        statistics.add(&reader.statistics);
+                        return Ok(ProcessResult::FlashbackRes {
+                            result: self.progress.to_result(),
+                        });
+                    }
                     // - No more locks to rollback, continue to the Prewrite Phase.
                     // - The start key from the client is actually a range which is used to limit
                     //   the upper bound of this flashback when scanning data, so it may not be a
@@ -185,27 +1230,64 @@ impl<S: Snapshot> ReadCommand<S> for FlashbackToVersionReadPhase {
                     //   completion of the 2pc.
                     // - To make sure the key locked in the latch is the same as the actual key
                     //   written, we pass it to the key in `process_write' after getting it.
-                    let key_to_lock = if let Some(first_key) = get_first_user_key(
-                        &mut reader,
-                        &self.start_key,
-                        self.end_key.as_ref(),
-                        self.version,
-                    )? {
-                        first_key
+                    let key_to_lock = if self.ranges.is_empty() {
+                        if let Some(first_key) = get_first_user_key(
+                            &mut reader,
+                            &self.start_key,
+                            self.end_key.as_ref(),
+                            self.version,
+                            &self.exclude_prefixes,
+                        )? {
+                            first_key
+                        } else {
+                            // If the key is None return directly
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        }
                     } else {
-                        // If the key is None return directly
-                        statistics.add(&reader.statistics);
-                        return Ok(ProcessResult::Res);
+                        // The shared anchor of a multi-range flashback is the
+                        // first user key across all the ranges in order.
+                        let mut first_key = None;
+                        for (range_start, range_end) in &self.ranges {
+                            first_key = get_first_user_key(
+                                &mut reader,
+                                range_start,
+                                Some(range_end),
+                                self.version,
+                                &self.exclude_prefixes,
+                            )?;
+                            if first_key.is_some() {
+                                break;
+                            }
+                        }
+                        if let Some(first_key) = first_key {
+                            self.anchor = Some(first_key.clone());
+                            first_key
+                        } else {
+                            // None of the ranges has any key to flash back.
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        }
                     };
                     FlashbackToVersionState::Prewrite { key_to_lock }
                 } else {
                     tls_collect_keyread_histogram_vec(tag, key_locks.len() as f64);
+                    let next_lock_key = if key_locks.len() > 1 {
+                        key_locks.pop().map(|(key, _)| key).unwrap()
+                    } else {
+                        key_locks.last().map(|(key, _)| key.clone()).unwrap()
+                    };
+                    self.check_stalled_batch(&resume_key, &next_lock_key)?;
+                    // Unlike the approximate overall progress above, the
+                    // summary only counts the locks actually rolled back by
+                    // this batch, i.e. after the boundary key has been popped.
+                    self.progress.add_lock_keys(key_locks.len());
                     FlashbackToVersionState::RollbackLock {
-                        next_lock_key: if key_locks.len() > 1 {
-                            key_locks.pop().map(|(key, _)| key).unwrap()
-                        } else {
-                            key_locks.last().map(|(key, _)| key.clone()).unwrap()
-                        },
+                        next_lock_key,
                         key_locks,
                     }
                 }
@@ -219,82 +1301,845 @@ impl<S: Snapshot> ReadCommand<S> for FlashbackToVersionReadPhase {
                         commit_ts: self.commit_ts,
                     }));
                 }
+                // Flashing back to a version newer than `self.commit_ts` would
+                // write MVCC records whose value is newer than their own
+                // `commit_ts`, corrupting the history, so reject it before any
+                // write is produced.
+                if self.version > self.commit_ts {
+                    return Err(Error::from(ErrorInner::InvalidFlashbackVersion {
+                        version: self.version,
+                        commit_ts: self.commit_ts,
+                    }));
+                }
+                // When the flashback is restricted to a CF other than the
+                // write CF, the write CF is left untouched and no key was
+                // prewritten by the preparing phase, so there is nothing to
+                // commit either.
+                if !self.cf_applies(CF_WRITE) {
+                    statistics.add(&reader.statistics);
+                    return Ok(ProcessResult::FlashbackRes {
+                        result: self.progress.to_result(),
+                    });
+                }
+                let mut is_first_batch = false;
                 if next_write_key == self.start_key {
-                    // The start key from the client is actually a range which is used to limit the
-                    // upper bound of this flashback when scanning data, so it may not be a real
-                    // key. In the Prewrite Phase, we make sure that the start
-                    // key is a real key and take this key as a lock for the
-                    // 2pc. So When overwriting the write, we skip the immediate
-                    // write of this key and instead put it after the completion
-                    // of the 2pc.
-                    next_write_key = if let Some(first_key) = get_first_user_key(
+                    is_first_batch = true;
+                    if let Some(group) = &self.shard_group {
+                        // Every shard resolves the same anchor over the whole
+                        // sharded range, so it can be skipped while scanning
+                        // and committed by the last shard to finish.
+                        let first_key = if let Some(first_key) = get_first_user_key(
+                            &mut reader,
+                            &group.start_key,
+                            group.end_key.as_ref(),
+                            self.version,
+                            &self.exclude_prefixes,
+                        )? {
+                            first_key
+                        } else {
+                            // There is nothing to flash back anywhere in the
+                            // sharded range, nor was any anchor prewritten.
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        };
+                        // If the anchor has already been committed by the
+                        // flashback, every shard of this retry returns
+                        // directly without committing it again.
+                        if check_flashback_commit(
+                            &mut reader,
+                            &first_key,
+                            self.start_ts,
+                            self.commit_ts,
+                            self.ctx.get_region_id(),
+                        )? {
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        }
+                        self.anchor = Some(first_key);
+                    } else if self.ranges.is_empty() {
+                        // The start key from the client is actually a range which is used to limit
+                        // the upper bound of this flashback when scanning data, so it may not be a
+                        // real key. In the Prewrite Phase, we make sure that the start
+                        // key is a real key and take this key as a lock for the
+                        // 2pc. So When overwriting the write, we skip the immediate
+                        // write of this key and instead put it after the completion
+                        // of the 2pc.
+                        next_write_key = if let Some(first_key) = get_first_user_key(
+                            &mut reader,
+                            &self.start_key,
+                            self.end_key.as_ref(),
+                            self.version,
+                            &self.exclude_prefixes,
+                        )? {
+                            first_key
+                        } else {
+                            // If the key is None return directly
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        };
+                        // Commit key needs to match the Prewrite key, which is set as the first
+                        // user key.
+                        start_key = next_write_key.clone();
+                        // If the key has already been committed by the flashback, it means that we
+                        // are in a retry. It's safe to just return directly.
+                        if check_flashback_commit(
+                            &mut reader,
+                            &start_key,
+                            self.start_ts,
+                            self.commit_ts,
+                            self.ctx.get_region_id(),
+                        )? {
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        }
+                        // Check whether an interrupted flashback has persisted a
+                        // checkpoint to resume from instead of rescanning from
+                        // the very first user key.
+                        if !self.reverse {
+                            if let Some(checkpoint_key) = load_flashback_checkpoint(
+                                &snapshot,
+                                FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+                                self.start_ts,
+                                self.version,
+                                &start_key,
+                            )? {
+                                next_write_key = checkpoint_key;
+                            }
+                        }
+                    } else if self.current_range_idx == 0 {
+                        // The shared anchor of a multi-range flashback was
+                        // already resolved while transitioning to the Prewrite
+                        // phase, so only the retry check on it is needed here.
+                        if let Some(anchor) = &self.anchor {
+                            if check_flashback_commit(
+                                &mut reader,
+                                anchor,
+                                self.start_ts,
+                                self.commit_ts,
+                                self.ctx.get_region_id(),
+                            )? {
+                                statistics.add(&reader.statistics);
+                                return Ok(ProcessResult::FlashbackRes {
+                                    result: self.progress.to_result(),
+                                });
+                            }
+                        }
+                    }
+                }
+                let resume_key = next_write_key.clone();
+                // The key skipped during the scan is the prewrite anchor,
+                // which for a multi-range flashback may live in an earlier
+                // range than the one being scanned.
+                let skip_key = self.anchor.as_ref().unwrap_or(&start_key);
+                // The unchanged-key check of the scans and the batch sizing
+                // below both visit keys out of the scan order, so a separate
+                // point-get reader is used instead of the scanning one.
+                let mut point_reader = MvccReader::new_with_ctx(snapshot.clone(), None, &self.ctx);
+                point_reader.set_allow_in_flashback(true);
+                let scan_result = if cancelled {
+                    // Stop scanning and commit the prewrite key directly to
+                    // finalize the flashback.
+                    Ok((Vec::new(), None))
+                } else if self.reverse {
+                    // The first batch of the reverse scan starts from
+                    // `self.end_key`, the later batches resume from the
+                    // checkpointed `next_write_key`.
+                    let next_write_key = (!is_first_batch).then_some(next_write_key);
+                    flashback_to_version_read_write_reverse(
                         &mut reader,
-                        &self.start_key,
+                        &mut point_reader,
+                        next_write_key.as_ref(),
+                        skip_key,
                         self.end_key.as_ref(),
                         self.version,
-                    )? {
-                        first_key
-                    } else {
-                        // If the key is None return directly
-                        statistics.add(&reader.statistics);
-                        return Ok(ProcessResult::Res);
-                    };
-                    // Commit key needs to match the Prewrite key, which is set as the first user
-                    // key.
-                    start_key = next_write_key.clone();
-                    // If the key has already been committed by the flashback, it means that we are
-                    // in a retry. It's safe to just return directly.
-                    if check_flashback_commit(
+                        self.commit_ts,
+                        &self.exclude_prefixes,
+                        &self.deadline,
+                    )
+                } else {
+                    flashback_to_version_read_write(
                         &mut reader,
-                        &start_key,
-                        self.start_ts,
+                        &mut point_reader,
+                        next_write_key,
+                        skip_key,
+                        self.end_key.as_ref(),
+                        self.version,
                         self.commit_ts,
-                        self.ctx.get_region_id(),
-                    )? {
+                        &self.exclude_prefixes,
+                        &self.deadline,
+                    )
+                };
+                let (mut keys, newer_write) = match scan_result {
+                    Ok(scanned) => scanned,
+                    Err(err) => {
                         statistics.add(&reader.statistics);
-                        return Ok(ProcessResult::Res);
+                        statistics.add(&point_reader.statistics);
+                        // A first batch may already have advanced its cursor
+                        // past the anchor resolution above, so it restarts
+                        // from `self.start_key` to replay it; the anchor
+                        // lookup and the commit and checkpoint checks are
+                        // all idempotent.
+                        self.state = FlashbackToVersionState::FlashbackWrite {
+                            next_write_key: if is_first_batch {
+                                self.start_key.clone()
+                            } else {
+                                resume_key
+                            },
+                            keys: Vec::new(),
+                        };
+                        return self.maybe_retry_read(err);
+                    }
+                };
+                // The batch read went through, so the next transient failure
+                // gets a fresh retry budget again.
+                self.read_retry_count = 0;
+                // The prewrite anchor is supposed to block every new commit in
+                // the range once the preparing phase is done, so a write
+                // committed above `self.commit_ts` means the anchor did not
+                // hold (e.g. it was resolved externally) and flashing back on
+                // top of it would leave a mixed history: some keys restored to
+                // `self.version`, some keeping the concurrent write. Abort the
+                // flashback before any write is produced unless the caller
+                // explicitly forces it.
+                if let Some((key, newer_commit_ts)) = newer_write {
+                    if !self.force {
+                        return Err(Error::from(ErrorInner::FlashbackNewerWrites {
+                            key: key.to_raw()?,
+                            commit_ts: newer_commit_ts,
+                        }));
                     }
+                    warn!(
+                        "flashback overlaps with a newer write, proceeding as forced";
+                        "key" => log_wrappers::Value::key(key.as_encoded()),
+                        "newer_commit_ts" => newer_commit_ts,
+                        "flashback_commit_ts" => self.commit_ts,
+                    );
                 }
-                let mut keys = flashback_to_version_read_write(
-                    &mut reader,
-                    next_write_key,
-                    &start_key,
-                    self.end_key.as_ref(),
-                    self.version,
-                    self.commit_ts,
-                )?;
+                // A batch full of large short values may hold much more memory
+                // than its key count suggests, so additionally cut it off by
+                // the serialized size of the old writes to restore.
+                truncate_flashback_batch_by_bytes(&mut point_reader, &mut keys, self.version)?;
+                span.record("batch_size", keys.len() as u64);
+                // A `version` that predates the creation of every key in the
+                // range would flash the whole range back to nothing. This is
+                // far more likely a mistyped `version` than an intent to wipe
+                // the data, so refuse it before the first write unless the
+                // caller explicitly forces the flashback. Only the first
+                // batch (including the prewritten anchor) is checked, which
+                // keeps the guard cheap while catching the common case where
+                // `version` predates all the data in the range.
+                if is_first_batch
+                    && !self.force
+                    && !cancelled
+                    && (self.ranges.is_empty() || self.current_range_idx == 0)
+                    && !check_flashback_version_has_data(
+                        &mut point_reader,
+                        self.anchor.as_ref().unwrap_or(&start_key),
+                        &keys,
+                        self.version,
+                    )?
+                {
+                    return Err(Error::from(ErrorInner::FlashbackNoData {
+                        version: self.version,
+                    }));
+                }
+                // Fold this batch's span into the pre checksum before the
+                // boundary key is popped below: the boundary is carried over
+                // and rescanned, so it is left to the next batch's span to
+                // count. The spans tile the scanned range exactly, including
+                // the unchanged and the excluded-from-flashback keys the
+                // batch itself filtered out, since the post checksum computed
+                // at commit time covers them as well. The values at
+                // `self.version` never change, so the rolling accumulation is
+                // unaffected by the writes applied between the batches.
+                if self.progress.checksum_enabled() && !cancelled {
+                    let mut checksum_reader = MvccReader::new_with_ctx(
+                        snapshot.clone(),
+                        Some(ScanMode::Forward),
+                        &self.ctx,
+                    );
+                    checksum_reader.set_allow_in_flashback(true);
+                    // A reverse scan visits its batch with inclusive bounds
+                    // in descending order, so its span is shifted by one key
+                    // to stay half-open.
+                    let (span_start, span_end) = if self.reverse {
+                        (
+                            keys.last()
+                                .map_or_else(|| start_key.clone(), flashback_key_successor),
+                            if is_first_batch {
+                                self.end_key.clone()
+                            } else {
+                                Some(flashback_key_successor(&resume_key))
+                            },
+                        )
+                    } else {
+                        (
+                            resume_key.clone(),
+                            keys.last().cloned().or_else(|| self.end_key.clone()),
+                        )
+                    };
+                    self.progress.add_pre_checksum(flashback_range_checksum(
+                        &mut checksum_reader,
+                        &mut point_reader,
+                        &span_start,
+                        span_end.as_ref(),
+                        self.version,
+                        &self.exclude_prefixes,
+                    )?);
+                    statistics.add(&checksum_reader.statistics);
+                }
+                statistics.add(&point_reader.statistics);
+                self.progress.add_processed_keys(keys.len());
+                FLASHBACK_BATCH_READ_DURATION_HISTOGRAM_VEC
+                    .with_label_values(&["flashback_write"])
+                    .observe(begin_instant.saturating_elapsed_secs());
+                FLASHBACK_PROCESSED_KEYS_COUNTER_VEC
+                    .with_label_values(&["flashback_write"])
+                    .inc_by(keys.len() as u64);
+                let scanned_bytes = keys.iter().map(|key| key.as_encoded().len() as u64).sum();
+                self.progress.add_scanned_bytes(scanned_bytes);
+                defer_dur = self.consume_resource_quota(scanned_bytes);
                 if keys.is_empty() {
-                    FlashbackToVersionState::Commit {
-                        key_to_commit: start_key.clone(),
+                    // The active range is fully flashed back; a multi-range
+                    // flashback moves on to the next range before the anchor
+                    // is committed.
+                    if !cancelled && self.current_range_idx + 1 < self.ranges.len() {
+                        self.current_range_idx += 1;
+                        let (range_start, range_end) =
+                            self.ranges[self.current_range_idx].clone();
+                        start_key = range_start.clone();
+                        self.end_key = Some(range_end);
+                        // the next range starts a fresh scan, so its watchdog
+                        // starts from scratch as well.
+                        self.stall_count = 0;
+                        FlashbackToVersionState::FlashbackWrite {
+                            next_write_key: range_start,
+                            keys: Vec::new(),
+                        }
+                    } else if let Some(group) = &self.shard_group {
+                        if group.finish_shard() {
+                            // The last shard to finish commits the anchor on
+                            // behalf of the whole group.
+                            FlashbackToVersionState::Commit {
+                                key_to_commit: self
+                                    .anchor
+                                    .clone()
+                                    .unwrap_or_else(|| start_key.clone()),
+                            }
+                        } else {
+                            // This shard is done, another one still running
+                            // will take care of the commit.
+                            statistics.add(&reader.statistics);
+                            return Ok(ProcessResult::FlashbackRes {
+                                result: self.progress.to_result(),
+                            });
+                        }
+                    } else {
+                        FlashbackToVersionState::Commit {
+                            key_to_commit: self
+                                .anchor
+                                .clone()
+                                .unwrap_or_else(|| start_key.clone()),
+                        }
                     }
                 } else {
                     tls_collect_keyread_histogram_vec(tag, keys.len() as f64);
+                    // DO NOT pop the last key as the next key when it's the only key to prevent
+                    // from making flashback fall into a dead loop.
+                    let next_write_key = if keys.len() > 1 {
+                        keys.pop().unwrap()
+                    } else {
+                        keys.last().unwrap().clone()
+                    };
+                    self.check_stalled_batch(&resume_key, &next_write_key)?;
+                    // Only count the writes actually flashed back by this
+                    // batch for the summary, excluding the popped boundary key.
+                    self.progress.add_write_keys(keys.len());
                     FlashbackToVersionState::FlashbackWrite {
-                        // DO NOT pop the last key as the next key when it's the only key to prevent
-                        // from making flashback fall into a dead loop.
-                        next_write_key: if keys.len() > 1 {
-                            keys.pop().unwrap()
-                        } else {
-                            keys.last().unwrap().clone()
-                        },
+                        next_write_key,
                         keys,
                     }
                 }
             }
             _ => unreachable!(),
         };
+        // Emit an event whenever the flashback moves on to another phase; the
+        // intermediate batches of a phase only refresh the span above.
+        match &next_state {
+            FlashbackToVersionState::Prewrite { key_to_lock } => tracing::debug!(
+                key_to_lock = ?log_wrappers::Value::key(key_to_lock.as_encoded()),
+                "flashback enters the Prewrite phase"
+            ),
+            FlashbackToVersionState::Commit { key_to_commit } => tracing::debug!(
+                key_to_commit = ?log_wrappers::Value::key(key_to_commit.as_encoded()),
+                "flashback enters the Commit phase"
+            ),
+            _ => {}
+        }
         statistics.add(&reader.statistics);
-        Ok(ProcessResult::NextCommand {
-            cmd: Command::FlashbackToVersion(FlashbackToVersion {
+        let deadline = self.next_deadline(defer_dur);
+        Ok(chain_next_command(
+            Command::FlashbackToVersion(FlashbackToVersion {
                 ctx: self.ctx,
-                deadline: self.deadline,
+                deadline,
                 start_ts: self.start_ts,
                 commit_ts: self.commit_ts,
                 version: self.version,
                 start_key,
                 end_key: self.end_key,
                 state: next_state,
+                reverse: self.reverse,
+                cf_filter: self.cf_filter,
+                exclude_prefixes: self.exclude_prefixes,
+                force: self.force,
+                preserve_history: self.preserve_history,
+                progress: self.progress,
+                cancel_token: self.cancel_token,
+                resource_limiter: self.resource_limiter,
+                ranges: self.ranges,
+                current_range_idx: self.current_range_idx,
+                stall_count: self.stall_count,
+                anchor: self.anchor,
+                shard_group: self.shard_group,
+                range_guard: self.range_guard,
+                observers: self.observers,
             }),
-        })
+            defer_dur,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use kvproto::errorpb;
+    use tracing::{
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+    use txn_types::Value;
+
+    use super::*;
+    use crate::storage::{
+        txn::{
+            actions::flashback_to_version::FLASHBACK_DEADLINE_CHECK_INTERVAL,
+            tests::{must_commit, must_prewrite_put},
+        },
+        Engine, TestEngineBuilder,
+    };
+
+    // A minimal subscriber collecting the names of the spans created and the
+    // number of events emitted while it is installed.
+    #[derive(Default)]
+    struct CapturingSubscriber {
+        next_id: AtomicU64,
+        spans: Mutex<Vec<&'static str>>,
+        events: Mutex<Vec<&'static str>>,
+    }
+
+    impl Subscriber for CapturingSubscriber {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.events.lock().unwrap().push(event.metadata().name());
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    // Wraps a snapshot to fail the first `failures` cursor creations before
+    // behaving normally, emulating snapshot errors during a batch scan.
+    #[derive(Clone)]
+    struct FailingSnapshot<S> {
+        snapshot: S,
+        failures: Arc<AtomicUsize>,
+        transient: bool,
+    }
+
+    impl<S> FailingSnapshot<S> {
+        fn error(&self) -> KvError {
+            if self.transient {
+                let mut header = errorpb::Error::default();
+                header.mut_not_leader().set_region_id(1);
+                KvError::from(KvErrorInner::Request(header))
+            } else {
+                KvError::from(KvErrorInner::EmptyRequest)
+            }
+        }
+    }
+
+    impl<S: Snapshot> Snapshot for FailingSnapshot<S> {
+        type Iter = S::Iter;
+        type Ext<'a> = S::Ext<'a> where S: 'a;
+
+        fn get(&self, key: &Key) -> tikv_kv::Result<Option<Value>> {
+            self.snapshot.get(key)
+        }
+
+        fn get_cf(&self, cf: CfName, key: &Key) -> tikv_kv::Result<Option<Value>> {
+            self.snapshot.get_cf(cf, key)
+        }
+
+        fn get_cf_opt(
+            &self,
+            opts: engine_traits::ReadOptions,
+            cf: CfName,
+            key: &Key,
+        ) -> tikv_kv::Result<Option<Value>> {
+            self.snapshot.get_cf_opt(opts, cf, key)
+        }
+
+        fn iter(
+            &self,
+            cf: CfName,
+            iter_opt: engine_traits::IterOptions,
+        ) -> tikv_kv::Result<Self::Iter> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(self.error());
+            }
+            self.snapshot.iter(cf, iter_opt)
+        }
+
+        fn ext(&self) -> Self::Ext<'_> {
+            self.snapshot.ext()
+        }
+    }
+
+    fn new_read_phase_cmd(state: FlashbackToVersionState) -> FlashbackToVersionReadPhase {
+        FlashbackToVersionReadPhase {
+            ctx: Context::default(),
+            deadline: Deadline::from_now(DEFAULT_EXECUTION_DURATION_LIMIT),
+            start_ts: 5.into(),
+            commit_ts: 6.into(),
+            version: TimeStamp::zero(),
+            start_key: Key::from_raw(b"a"),
+            end_key: Some(Key::from_raw(b"z")),
+            state,
+            reverse: false,
+            cf_filter: None,
+            exclude_prefixes: Vec::new(),
+            force: false,
+            preserve_history: false,
+            progress: FlashbackProgress::default(),
+            cancel_token: FlashbackCancelToken::default(),
+            resource_limiter: None,
+            ranges: Vec::new(),
+            current_range_idx: 0,
+            stall_count: 0,
+            read_retry_count: 0,
+            anchor: None,
+            shard_group: None,
+            range_guard: None,
+            observers: None,
+        }
+    }
+
+    #[test]
+    fn test_read_phase_tracing_spans() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        for key in [b"a", b"b", b"c"] {
+            must_prewrite_put(&mut engine, key, b"v@1", key, 1);
+            must_commit(&mut engine, key, 1, 2);
+        }
+        let subscriber = Arc::new(CapturingSubscriber::default());
+        let dispatch = tracing::Dispatch::from(subscriber.clone());
+        tracing::dispatcher::with_default(&dispatch, || {
+            let mut statistics = Statistics::default();
+            // The first RollbackLock batch finds no lock and transitions to
+            // the Prewrite phase.
+            let cmd = new_read_phase_cmd(FlashbackToVersionState::RollbackLock {
+                next_lock_key: Key::from_raw(b"a"),
+                key_locks: Vec::new(),
+            });
+            let snapshot = engine.snapshot(Default::default()).unwrap();
+            let pr = cmd.process_read(snapshot, &mut statistics).unwrap();
+            assert!(matches!(
+                pr,
+                ProcessResult::NextCommand {
+                    cmd: Command::FlashbackToVersion(_)
+                }
+            ));
+            // A non-first FlashbackWrite batch resuming from `b`, which skips
+            // the anchor resolution of the first batch.
+            let cmd = new_read_phase_cmd(FlashbackToVersionState::FlashbackWrite {
+                next_write_key: Key::from_raw(b"b"),
+                keys: Vec::new(),
+            });
+            let snapshot = engine.snapshot(Default::default()).unwrap();
+            cmd.process_read(snapshot, &mut statistics).unwrap();
+        });
+        let spans = subscriber.spans.lock().unwrap();
+        assert!(spans.contains(&"flashback_rollback_lock"), "{:?}", spans);
+        assert!(spans.contains(&"flashback_write"), "{:?}", spans);
+        // The transition into the Prewrite phase above must have emitted an
+        // event.
+        assert!(!subscriber.events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_phase_yields_on_deadline() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        // Write far more keys than a single deadline-check chunk holds.
+        for i in 0..100 {
+            let key = format!("k{:03}", i).into_bytes();
+            must_prewrite_put(&mut engine, &key, b"v", &key, 1);
+            must_commit(&mut engine, &key, 1, 2);
+        }
+        let mut statistics = Statistics::default();
+        // A non-first FlashbackWrite batch whose deadline has already
+        // elapsed: the batch must stop after the first chunk and yield
+        // instead of scanning the whole range.
+        let mut cmd = new_read_phase_cmd(FlashbackToVersionState::FlashbackWrite {
+            next_write_key: Key::from_raw(b"k000"),
+            keys: Vec::new(),
+        });
+        cmd.deadline = Deadline::from_now(Duration::ZERO);
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let pr = cmd.process_read(snapshot, &mut statistics).unwrap();
+        let cmd = match pr {
+            ProcessResult::NextCommand {
+                cmd: Command::FlashbackToVersion(cmd),
+            } => cmd,
+            pr => panic!("unexpected process result: {:?}", pr),
+        };
+        match &cmd.state {
+            FlashbackToVersionState::FlashbackWrite {
+                next_write_key,
+                keys,
+            } => {
+                // Only one chunk was collected before the deadline tripped,
+                // with its last key popped as the key to resume from.
+                assert_eq!(keys.len(), FLASHBACK_DEADLINE_CHECK_INTERVAL - 1);
+                assert_eq!(*next_write_key, Key::from_raw(b"k031"));
+            }
+            state => panic!("unexpected next state: {:?}", state),
+        }
+        // The resumed command gets a fresh execution budget rather than
+        // failing the whole flashback with a deadline error.
+        cmd.deadline.check().unwrap();
+    }
+
+    #[test]
+    fn test_read_phase_stall_watchdog() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        must_prewrite_put(&mut engine, b"b", b"v@1", b"b", 1);
+        must_commit(&mut engine, b"b", 1, 2);
+        let mut statistics = Statistics::default();
+        // Drive the read phase on its own without ever applying the write
+        // phase in between, so every batch re-reads the same single key `b`
+        // and keeps it as the next start: exactly the shape of a scan stuck
+        // on one key forever.
+        let mut state = FlashbackToVersionState::FlashbackWrite {
+            next_write_key: Key::from_raw(b"b"),
+            keys: Vec::new(),
+        };
+        let mut stall_count = 0;
+        let mut batches = 0;
+        let err = loop {
+            let mut cmd = new_read_phase_cmd(state);
+            cmd.stall_count = stall_count;
+            let snapshot = engine.snapshot(Default::default()).unwrap();
+            match cmd.process_read(snapshot, &mut statistics) {
+                Ok(ProcessResult::NextCommand {
+                    cmd: Command::FlashbackToVersion(next),
+                }) => {
+                    batches += 1;
+                    assert!(batches < 10, "the watchdog never fired");
+                    stall_count = next.stall_count;
+                    state = next.state;
+                }
+                Ok(pr) => panic!("unexpected process result: {:?}", pr),
+                Err(err) => break err,
+            }
+        };
+        // The repeated resume key is tolerated below the threshold and only
+        // aborts the flashback once it is reached.
+        assert_eq!(batches, FLASHBACK_MAX_STALLED_BATCHES - 1);
+        assert!(
+            matches!(&err, Error(box ErrorInner::FlashbackStalled { key, batches })
+                if key.as_slice() == b"b" && *batches == FLASHBACK_MAX_STALLED_BATCHES),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_read_phase_retries_transient_errors() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        for key in [b"a", b"b", b"c"] {
+            must_prewrite_put(&mut engine, key, b"v@1", key, 1);
+            must_commit(&mut engine, key, 1, 2);
+        }
+        let mut statistics = Statistics::default();
+        let new_write_cmd = || {
+            new_read_phase_cmd(FlashbackToVersionState::FlashbackWrite {
+                next_write_key: Key::from_raw(b"b"),
+                keys: Vec::new(),
+            })
+        };
+        // The scan fails once with a NotLeader error, which is transient:
+        // instead of failing the flashback, the command chains itself again
+        // with the cursor it resumed from.
+        let snapshot = FailingSnapshot {
+            snapshot: engine.snapshot(Default::default()).unwrap(),
+            failures: Arc::new(AtomicUsize::new(1)),
+            transient: true,
+        };
+        let cmd = match new_write_cmd()
+            .process_read(snapshot.clone(), &mut statistics)
+            .unwrap()
+        {
+            ProcessResult::NextCommandDelayed {
+                cmd: Command::FlashbackToVersionReadPhase(cmd),
+                delay,
+            } => {
+                // The first retry waits for the base backoff, awaited by the
+                // scheduler instead of slept on the worker.
+                assert_eq!(delay, FLASHBACK_READ_RETRY_BACKOFF);
+                cmd
+            }
+            pr => panic!("expected a retry of the read phase, got {:?}", pr),
+        };
+        assert_eq!(cmd.read_retry_count, 1);
+        assert!(matches!(
+            &cmd.state,
+            FlashbackToVersionState::FlashbackWrite { next_write_key, .. }
+                if *next_write_key == Key::from_raw(b"b")
+        ));
+        // The wrapped snapshot has used up its failure, so the retry goes
+        // through and the flashback completes the batch as usual.
+        assert!(matches!(
+            cmd.process_read(snapshot, &mut statistics).unwrap(),
+            ProcessResult::NextCommand {
+                cmd: Command::FlashbackToVersion(_)
+            }
+        ));
+        // A genuine error still fails the flashback right away.
+        let snapshot = FailingSnapshot {
+            snapshot: engine.snapshot(Default::default()).unwrap(),
+            failures: Arc::new(AtomicUsize::new(1)),
+            transient: false,
+        };
+        new_write_cmd()
+            .process_read(snapshot, &mut statistics)
+            .unwrap_err();
+        // So does a transient error recurring until the retry budget of the
+        // batch is exhausted.
+        let snapshot = FailingSnapshot {
+            snapshot: engine.snapshot(Default::default()).unwrap(),
+            failures: Arc::new(AtomicUsize::new(usize::MAX)),
+            transient: true,
+        };
+        let mut cmd = new_write_cmd();
+        for _ in 0..FLASHBACK_MAX_READ_RETRIES {
+            cmd = match cmd.process_read(snapshot.clone(), &mut statistics).unwrap() {
+                ProcessResult::NextCommandDelayed {
+                    cmd: Command::FlashbackToVersionReadPhase(cmd),
+                    ..
+                } => cmd,
+                pr => panic!("expected a retry of the read phase, got {:?}", pr),
+            };
+        }
+        cmd.process_read(snapshot, &mut statistics).unwrap_err();
+    }
+
+    #[test]
+    fn test_write_cmd_with_derived_commit_ts() {
+        let mut engine = TestEngineBuilder::new().build().unwrap();
+        for (key, start_ts, commit_ts) in [(b"a", 1, 2), (b"b", 11, 12), (b"c", 21, 22)] {
+            must_prewrite_put(&mut engine, key, b"v", key, start_ts);
+            must_commit(&mut engine, key, start_ts, commit_ts);
+        }
+        // A mock TSO handing out strictly increasing timestamps above every
+        // allocation so far, like PD does.
+        let tso = AtomicU64::new(23);
+        let start_ts = TimeStamp::from(tso.fetch_add(1, Ordering::Relaxed));
+        let cmd = new_flashback_write_cmd_with_derived_ts(
+            start_ts,
+            || tso.fetch_add(1, Ordering::Relaxed).into(),
+            1.into(),
+            TimeStamp::zero(),
+            Key::from_raw(b"a"),
+            Some(Key::from_raw(b"z")),
+            false,
+            None,
+            Vec::new(),
+            false,
+            false,
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap();
+        let commit_ts = match cmd.cmd {
+            Command::FlashbackToVersionReadPhase(ref cmd) => cmd.commit_ts,
+            ref cmd => panic!("unexpected command: {:?}", cmd),
+        };
+        // The derived `commit_ts` must sit above every write already
+        // committed in the range, verified against the engine rather than
+        // the constants above.
+        let snapshot = engine.snapshot(Default::default()).unwrap();
+        let mut reader = MvccReader::new(snapshot, Some(ScanMode::Forward), false);
+        for key in [b"a", b"b", b"c"] {
+            let (latest_commit_ts, _) = reader
+                .seek_write(&Key::from_raw(key), TimeStamp::max())
+                .unwrap()
+                .unwrap();
+            assert!(
+                commit_ts > latest_commit_ts,
+                "{} <= {}",
+                commit_ts,
+                latest_commit_ts
+            );
+        }
+        // A stale source whose timestamp does not exceed `start_ts` is
+        // rejected before the command is built.
+        let err = new_flashback_write_cmd_with_derived_ts(
+            start_ts,
+            || start_ts,
+            1.into(),
+            TimeStamp::zero(),
+            Key::from_raw(b"a"),
+            Some(Key::from_raw(b"z")),
+            false,
+            None,
+            Vec::new(),
+            false,
+            false,
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
+            Context::default(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error(box ErrorInner::InvalidTxnTso { .. })),
+            "{:?}",
+            err
+        );
     }
 }
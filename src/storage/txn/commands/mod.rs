@@ -12,6 +12,7 @@ pub(crate) mod check_txn_status;
 pub(crate) mod cleanup;
 pub(crate) mod commit;
 pub(crate) mod compare_and_swap;
+pub(crate) mod flashback_estimate;
 pub(crate) mod flashback_to_version;
 pub(crate) mod flashback_to_version_read_phase;
 pub(crate) mod flush;
@@ -44,9 +45,17 @@ pub use cleanup::Cleanup;
 pub use commit::Commit;
 pub use compare_and_swap::RawCompareAndSwap;
 use concurrency_manager::{ConcurrencyManager, KeyHandleGuard};
+pub use flashback_estimate::{
+    new_flashback_estimate_cmd, new_flashback_estimate_streaming_cmd, FlashbackEstimate,
+    FlashbackEstimatePartial, FlashbackEstimateSink, FlashbackEstimateState,
+};
 pub use flashback_to_version::FlashbackToVersion;
 pub use flashback_to_version_read_phase::{
-    new_flashback_rollback_lock_cmd, new_flashback_write_cmd, FlashbackToVersionReadPhase,
+    new_flashback_locks_only_cmd, new_flashback_multi_range_cmd, new_flashback_rollback_lock_cmd,
+    new_flashback_sharded_write_cmds, new_flashback_write_cmd,
+    new_flashback_write_cmd_with_derived_ts, new_flashback_writes_only_cmd,
+    FlashbackCancelToken, FlashbackObserver, FlashbackObserverRegistry, FlashbackProgress,
+    FlashbackRangeGuard, FlashbackRangeRegistry, FlashbackShardGroup, FlashbackToVersionReadPhase,
     FlashbackToVersionState,
 };
 pub use flush::Flush;
@@ -76,8 +85,8 @@ use crate::storage::{
     mvcc::{Lock as MvccLock, MvccReader, ReleasedLock, SnapshotReader},
     txn::{latch, txn_status_cache::TxnStatusCache, ProcessResult, Result},
     types::{
-        MvccInfo, PessimisticLockParameters, PessimisticLockResults, PrewriteResult,
-        SecondaryLocksStatus, StorageCallbackType, TxnStatus,
+        FlashbackResult, MvccInfo, PessimisticLockParameters, PessimisticLockResults,
+        PrewriteResult, SecondaryLocksStatus, StorageCallbackType, TxnStatus,
     },
     Result as StorageResult, Snapshot, Statistics,
 };
@@ -113,6 +122,7 @@ pub enum Command {
     RawAtomicStore(RawAtomicStore),
     FlashbackToVersionReadPhase(FlashbackToVersionReadPhase),
     FlashbackToVersion(FlashbackToVersion),
+    FlashbackEstimate(FlashbackEstimate),
     Flush(Flush),
 }
 
@@ -388,28 +398,53 @@ impl From<MvccGetByStartTsRequest> for TypedCommand<Option<(Key, MvccInfo)>> {
     }
 }
 
-impl From<PrepareFlashbackToVersionRequest> for TypedCommand<()> {
+impl From<PrepareFlashbackToVersionRequest> for TypedCommand<FlashbackResult> {
     fn from(mut req: PrepareFlashbackToVersionRequest) -> Self {
         new_flashback_rollback_lock_cmd(
             req.get_start_ts().into(),
             req.get_version().into(),
+            // The request does not carry the GC safe point, the gRPC caller
+            // guarantees the version instead. A zero safe point disables the
+            // check, so the construction cannot fail.
+            TimeStamp::zero(),
             Key::from_raw(req.get_start_key()),
             Key::from_raw_maybe_unbounded(req.get_end_key()),
+            false,
+            None,
+            Vec::new(),
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
             req.take_context(),
         )
+        .unwrap()
     }
 }
 
-impl From<FlashbackToVersionRequest> for TypedCommand<()> {
+impl From<FlashbackToVersionRequest> for TypedCommand<FlashbackResult> {
     fn from(mut req: FlashbackToVersionRequest) -> Self {
         new_flashback_write_cmd(
             req.get_start_ts().into(),
             req.get_commit_ts().into(),
             req.get_version().into(),
+            // Like the prepare request above, a zero safe point disables the
+            // check, so the construction cannot fail.
+            TimeStamp::zero(),
             Key::from_raw(req.get_start_key()),
             Key::from_raw_maybe_unbounded(req.get_end_key()),
+            false,
+            None,
+            Vec::new(),
+            false,
+            // The request has no preserve-history flag, it always asks for a
+            // regular flashback.
+            false,
+            FlashbackProgress::default(),
+            FlashbackCancelToken::default(),
+            None,
             req.take_context(),
         )
+        .unwrap()
     }
 }
 
@@ -704,6 +739,7 @@ impl Command {
             Command::RawAtomicStore(t) => t,
             Command::FlashbackToVersionReadPhase(t) => t,
             Command::FlashbackToVersion(t) => t,
+            Command::FlashbackEstimate(t) => t,
             Command::Flush(t) => t,
         }
     }
@@ -732,6 +768,7 @@ impl Command {
             Command::RawAtomicStore(t) => t,
             Command::FlashbackToVersionReadPhase(t) => t,
             Command::FlashbackToVersion(t) => t,
+            Command::FlashbackEstimate(t) => t,
             Command::Flush(t) => t,
         }
     }
@@ -747,6 +784,7 @@ impl Command {
             Command::MvccByKey(t) => t.process_read(snapshot, statistics),
             Command::MvccByStartTs(t) => t.process_read(snapshot, statistics),
             Command::FlashbackToVersionReadPhase(t) => t.process_read(snapshot, statistics),
+            Command::FlashbackEstimate(t) => t.process_read(snapshot, statistics),
             _ => panic!("unsupported read command"),
         }
     }
@@ -883,6 +921,7 @@ impl HeapSize for Command {
                 Command::RawAtomicStore(t) => t.approximate_heap_size(),
                 Command::FlashbackToVersionReadPhase(t) => t.approximate_heap_size(),
                 Command::FlashbackToVersion(t) => t.approximate_heap_size(),
+                Command::FlashbackEstimate(t) => t.approximate_heap_size(),
                 Command::Flush(t) => t.approximate_heap_size(),
             }
     }
@@ -13,7 +13,7 @@ mod latch;
 mod store;
 mod task;
 
-use std::{error::Error as StdError, io::Error as IoError};
+use std::{error::Error as StdError, io::Error as IoError, time::Duration};
 
 use error_code::{self, ErrorCode, ErrorCodeExt};
 use kvproto::kvrpcpb::LockInfo;
@@ -26,8 +26,13 @@ pub use self::{
         cleanup::cleanup,
         commit::commit,
         flashback_to_version::{
-            flashback_to_version_read_lock, flashback_to_version_read_write,
-            flashback_to_version_write, rollback_locks, FLASHBACK_BATCH_SIZE,
+            delete_flashback_checkpoint, flashback_checkpoint_key, flashback_to_version_dry_run,
+            flashback_to_version_read_lock, flashback_to_version_read_lock_reverse,
+            flashback_to_version_read_write, flashback_to_version_read_write_reverse,
+            flashback_to_version_write, load_flashback_checkpoint, rollback_locks,
+            truncate_flashback_batch_by_bytes, write_flashback_checkpoint, FlashbackDryRun,
+            FLASHBACK_BATCH_MAX_BYTES, FLASHBACK_BATCH_SIZE, FLASHBACK_CHECKPOINT_FLASHBACK_WRITE,
+            FLASHBACK_CHECKPOINT_ROLLBACK_LOCK,
         },
         gc::gc,
         prewrite::{prewrite, CommitKind, TransactionKind, TransactionProperties},
@@ -42,7 +47,10 @@ pub use self::{
 };
 use crate::storage::{
     mvcc::Error as MvccError,
-    types::{MvccInfo, PessimisticLockResults, PrewriteResult, SecondaryLocksStatus, TxnStatus},
+    types::{
+        FlashbackEstimateResult, FlashbackResult, MvccInfo, PessimisticLockResults,
+        PrewriteResult, SecondaryLocksStatus, TxnStatus,
+    },
     Error as StorageError, Result as StorageResult,
 };
 
@@ -72,6 +80,14 @@ pub enum ProcessResult {
     NextCommand {
         cmd: Command,
     },
+    /// Like [`ProcessResult::NextCommand`], but the scheduler waits `delay`
+    /// asynchronously before handing `cmd` over, so a chained command is able
+    /// to pace itself (e.g. against a resource quota) without blocking a
+    /// worker thread.
+    NextCommandDelayed {
+        cmd: Command,
+        delay: Duration,
+    },
     Failed {
         err: StorageError,
     },
@@ -85,6 +101,12 @@ pub enum ProcessResult {
         previous_value: Option<Value>,
         succeed: bool,
     },
+    FlashbackEstimate {
+        estimate: FlashbackEstimateResult,
+    },
+    FlashbackRes {
+        result: FlashbackResult,
+    },
 }
 
 impl ProcessResult {
@@ -124,6 +146,12 @@ pub enum ErrorInner {
         commit_ts: TimeStamp,
     },
 
+    #[error("Invalid flashback version:{version} with commit_ts:{commit_ts}")]
+    InvalidFlashbackVersion {
+        version: TimeStamp,
+        commit_ts: TimeStamp,
+    },
+
     #[error(
         "Request range exceeds bound, request range:[{}, {}), physical bound:[{}, {})",
         .start.as_ref().map(|x| &x[..]).map(log_wrappers::Value::key).map(|x| format!("{:?}", x)).unwrap_or_else(|| "(none)".to_owned()),
@@ -149,6 +177,44 @@ pub enum ErrorInner {
 
     #[error("region {0} not prepared the flashback")]
     FlashbackNotPrepared(u64),
+
+    #[error(
+        "Flashback range:[{}, {}) overlaps another flashback still in progress",
+        format!("{:?}", log_wrappers::Value::key(.start_key)),
+        .end_key.as_ref().map(|x| &x[..]).map(log_wrappers::Value::key).map(|x| format!("{:?}", x)).unwrap_or_else(|| "(none)".to_owned())
+    )]
+    FlashbackConflict {
+        start_key: Vec<u8>,
+        end_key: Option<Vec<u8>>,
+    },
+
+    #[error(
+        "no key in the flashback range has any MVCC version at or before version {version}, \
+        flashing back would wipe the whole range; set `force` to proceed anyway"
+    )]
+    FlashbackNoData { version: TimeStamp },
+
+    #[error(
+        "key {} was committed at {commit_ts} which is newer than the flashback commit_ts, \
+        flashing back on top of it would produce a mixed history; set `force` to proceed anyway",
+        format!("{:?}", log_wrappers::Value::key(.key))
+    )]
+    FlashbackNewerWrites { key: Vec<u8>, commit_ts: TimeStamp },
+
+    #[error(
+        "flashback version {version} is below the GC safe point {safe_point}, \
+        the history at that version may already be garbage-collected"
+    )]
+    FlashbackBelowGcSafePoint {
+        version: TimeStamp,
+        safe_point: TimeStamp,
+    },
+
+    #[error(
+        "flashback made no progress past key {} for {batches} consecutive batches",
+        format!("{:?}", log_wrappers::Value::key(.key))
+    )]
+    FlashbackStalled { key: Vec<u8>, batches: usize },
 }
 
 impl ErrorInner {
@@ -164,6 +230,9 @@ impl ErrorInner {
                 start_ts,
                 commit_ts,
             }),
+            ErrorInner::InvalidFlashbackVersion { version, commit_ts } => {
+                Some(ErrorInner::InvalidFlashbackVersion { version, commit_ts })
+            }
             ErrorInner::InvalidReqRange {
                 ref start,
                 ref end,
@@ -188,6 +257,35 @@ impl ErrorInner {
             ErrorInner::FlashbackNotPrepared(region_id) => {
                 Some(ErrorInner::FlashbackNotPrepared(region_id))
             }
+            ErrorInner::FlashbackConflict {
+                ref start_key,
+                ref end_key,
+            } => Some(ErrorInner::FlashbackConflict {
+                start_key: start_key.clone(),
+                end_key: end_key.clone(),
+            }),
+            ErrorInner::FlashbackNoData { version } => {
+                Some(ErrorInner::FlashbackNoData { version })
+            }
+            ErrorInner::FlashbackNewerWrites { ref key, commit_ts } => {
+                Some(ErrorInner::FlashbackNewerWrites {
+                    key: key.clone(),
+                    commit_ts,
+                })
+            }
+            ErrorInner::FlashbackBelowGcSafePoint {
+                version,
+                safe_point,
+            } => Some(ErrorInner::FlashbackBelowGcSafePoint {
+                version,
+                safe_point,
+            }),
+            ErrorInner::FlashbackStalled { ref key, batches } => {
+                Some(ErrorInner::FlashbackStalled {
+                    key: key.clone(),
+                    batches,
+                })
+            }
             ErrorInner::Other(_) | ErrorInner::ProtoBuf(_) | ErrorInner::Io(_) => None,
         }
     }
@@ -234,6 +332,7 @@ impl ErrorCodeExt for Error {
             ErrorInner::Other(_) => error_code::storage::UNKNOWN,
             ErrorInner::Io(_) => error_code::storage::IO,
             ErrorInner::InvalidTxnTso { .. } => error_code::storage::INVALID_TXN_TSO,
+            ErrorInner::InvalidFlashbackVersion { .. } => error_code::storage::INVALID_TXN_TSO,
             ErrorInner::InvalidReqRange { .. } => error_code::storage::INVALID_REQ_RANGE,
             ErrorInner::MaxTimestampNotSynced { .. } => {
                 error_code::storage::MAX_TIMESTAMP_NOT_SYNCED
@@ -242,6 +341,15 @@ impl ErrorCodeExt for Error {
                 error_code::storage::MAX_TIMESTAMP_NOT_SYNCED
             }
             ErrorInner::FlashbackNotPrepared(_) => error_code::storage::FLASHBACK_NOT_PREPARED,
+            ErrorInner::FlashbackConflict { .. } => error_code::storage::FLASHBACK_CONFLICT,
+            ErrorInner::FlashbackNoData { .. } => error_code::storage::FLASHBACK_NO_DATA,
+            ErrorInner::FlashbackBelowGcSafePoint { .. } => {
+                error_code::storage::FLASHBACK_BELOW_GC_SAFE_POINT
+            }
+            ErrorInner::FlashbackNewerWrites { .. } => {
+                error_code::storage::FLASHBACK_NEWER_WRITES
+            }
+            ErrorInner::FlashbackStalled { .. } => error_code::storage::FLASHBACK_STALLED,
         }
     }
 }
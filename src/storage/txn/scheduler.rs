@@ -79,7 +79,8 @@ use crate::{
         txn::{
             commands,
             commands::{
-                Command, RawExt, ReleasedLocks, ResponsePolicy, WriteContext, WriteResult,
+                Command, FlashbackObserver, FlashbackObserverRegistry, FlashbackRangeRegistry,
+                RawExt, ReleasedLocks, ResponsePolicy, WriteContext, WriteResult,
                 WriteResultLockInfo,
             },
             flow_controller::FlowController,
@@ -280,6 +281,12 @@ struct TxnSchedulerInner<L: LockManager> {
 
     txn_status_cache: Arc<TxnStatusCache>,
 
+    // the ranges of all the flashbacks this scheduler is still processing
+    flashback_ranges: Arc<FlashbackRangeRegistry>,
+
+    // the observers notified once a flashback run by this scheduler commits
+    flashback_observers: Arc<FlashbackObserverRegistry>,
+
     memory_quota: Arc<MemoryQuota>,
 
     in_memory_peer_size_limit: Arc<AtomicU64>,
@@ -478,6 +485,8 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
             quota_limiter,
             feature_gate,
             txn_status_cache,
+            flashback_ranges: Arc::new(FlashbackRangeRegistry::default()),
+            flashback_observers: Arc::new(FlashbackObserverRegistry::default()),
             memory_quota: Arc::new(MemoryQuota::new(config.memory_quota.0 as _)),
             in_memory_peer_size_limit: dynamic_configs.in_memory_peer_size_limit,
             in_memory_instance_size_limit: dynamic_configs.in_memory_instance_size_limit,
@@ -521,8 +530,43 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
         });
     }
 
-    pub(in crate::storage) fn run_cmd(&self, cmd: Command, callback: StorageCallback) {
+    pub(in crate::storage) fn run_cmd(&self, mut cmd: Command, callback: StorageCallback) {
         let tag = cmd.tag();
+        // A flashback whose range overlaps another one still in progress would
+        // interleave the writes of the two and likely corrupt the data, so
+        // register the range first and reject the command on a conflict. The
+        // registration travels with the command chain and is released when the
+        // last command of the chain is dropped, whether the flashback completed
+        // or aborted halfway. Chained commands re-enter the scheduler through
+        // `schedule_command` directly and thus are only registered once here.
+        if let Command::FlashbackToVersionReadPhase(cmd) = &mut cmd {
+            // Hand the observer registry over as well, so the commit phase at
+            // the end of the chain can notify the observers of the flashed
+            // range.
+            cmd.observers = Some(self.inner.flashback_observers.clone());
+            if cmd.range_guard.is_none() {
+                // A multi-range flashback conservatively registers the whole
+                // span from its first to its last range, including the gaps.
+                let (start_key, end_key) =
+                    if let (Some(first), Some(last)) = (cmd.ranges.first(), cmd.ranges.last()) {
+                        (&first.0, Some(&last.1))
+                    } else {
+                        (&cmd.start_key, cmd.end_key.as_ref())
+                    };
+                match self.inner.flashback_ranges.register(start_key, end_key) {
+                    Some(guard) => cmd.range_guard = Some(guard),
+                    None => {
+                        callback.execute(ProcessResult::Failed {
+                            err: StorageError::from(Error::from(ErrorInner::FlashbackConflict {
+                                start_key: start_key.as_encoded().clone(),
+                                end_key: end_key.map(|key| key.as_encoded().clone()),
+                            })),
+                        });
+                        return;
+                    }
+                }
+            }
+        }
         // write flow control
         //
         // TODO: Consider deprecating this write flow control. Reasons being:
@@ -544,6 +588,13 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
         }
     }
 
+    /// Register an observer notified with the covered range once a flashback
+    /// run by this scheduler has committed, e.g. to invalidate caches and
+    /// statistics derived from the overwritten history.
+    pub fn register_flashback_observer(&self, observer: Arc<dyn FlashbackObserver>) {
+        self.inner.flashback_observers.register(observer);
+    }
+
     /// Releases all the latches held by a command.
     fn release_latches(
         &self,
@@ -1226,7 +1277,7 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
 
             fail_point!("scheduler_process");
             if task.cmd().readonly() {
-                self.process_read(snapshot, task, &mut sched_details);
+                self.process_read(snapshot, task, &mut sched_details).await;
             } else {
                 self.process_write(snapshot, task, &mut sched_details).await;
             };
@@ -1247,7 +1298,12 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
 
     /// Processes a read command within a worker thread, then posts
     /// `ReadFinished` message back to the `TxnScheduler`.
-    fn process_read(self, snapshot: E::Snap, task: Task, sched_details: &mut SchedulerDetails) {
+    async fn process_read(
+        self,
+        snapshot: E::Snap,
+        task: Task,
+        sched_details: &mut SchedulerDetails,
+    ) {
         fail_point!("txn_before_process_read");
         let cid = task.cid();
         debug!("process read cmd in worker pool"; "cid" => cid);
@@ -1264,6 +1320,20 @@ impl<E: Engine, L: LockManager> TxnScheduler<E, L> {
         SCHED_PROCESSING_READ_HISTOGRAM_STATIC
             .get(tag)
             .observe(begin_instant.saturating_elapsed_secs());
+        // A command deferring its successor (e.g. a flashback batch pacing
+        // itself against its group quota) is held back here without tying up
+        // the worker thread.
+        let pr = match pr {
+            ProcessResult::NextCommandDelayed { cmd, delay } => {
+                GLOBAL_TIMER_HANDLE
+                    .delay(std::time::Instant::now() + delay)
+                    .compat()
+                    .await
+                    .unwrap();
+                ProcessResult::NextCommand { cmd }
+            }
+            pr => pr,
+        };
         self.on_read_finished(cid, pr, tag);
     }
 
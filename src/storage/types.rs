@@ -2,7 +2,7 @@
 
 //! Core data types.
 
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Duration};
 
 use kvproto::kvrpcpb;
 use txn_types::{Key, LastChange, Value};
@@ -415,6 +415,46 @@ impl SecondaryLocksStatus {
     }
 }
 
+/// The result of a `FlashbackEstimate` command, describing how much data a
+/// real flashback over the same range would touch.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FlashbackEstimateResult {
+    /// The number of the latest user keys whose version would be overwritten.
+    pub write_keys: usize,
+    /// The number of locks that would be rolled back.
+    pub lock_keys: usize,
+    /// The total encoded length of the keys counted above.
+    pub approximate_bytes: usize,
+}
+
+/// The summary of a finished flashback, returned as the confirmation receipt
+/// of both the preparing and the finishing flashback commands. Since a
+/// flashback is processed as a chain of read/write commands, the counts are
+/// accumulated across the whole chain through the shared progress handle.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FlashbackResult {
+    /// The number of keys whose writes were flashed back to the old version,
+    /// including the prewrite anchor key committed at the very end.
+    pub writes_flashed: usize,
+    /// The number of locks rolled back.
+    pub locks_rolled_back: usize,
+    /// The total encoded length of the keys scanned, including the batch
+    /// boundary keys scanned twice.
+    pub scanned_bytes: u64,
+    /// The wall time elapsed since the progress handle was created.
+    pub elapsed: Duration,
+    /// The checksum of the range contents resolved at the flashback
+    /// `version`, accumulated batch by batch during the read phase. `None`
+    /// unless the progress handle was created with checksum verification
+    /// enabled.
+    pub pre_checksum: Option<u64>,
+    /// The checksum of the range contents visible once the flashback
+    /// commits, recomputed by the commit phase. A complete flashback
+    /// restores the range to its contents at `version`, so the client can
+    /// verify it by comparing this against `pre_checksum`.
+    pub post_checksum: Option<u64>,
+}
+
 macro_rules! storage_callback {
     ($($variant: ident ( $cb_ty: ty ) $result_variant: pat => $result: expr,)*) => {
         pub enum StorageCallback {
@@ -453,6 +493,8 @@ storage_callback! {
     PessimisticLock(Result<PessimisticLockResults>) ProcessResult::PessimisticLockRes { res } => res,
     SecondaryLocksStatus(SecondaryLocksStatus) ProcessResult::SecondaryLocksStatus { status } => status,
     RawCompareAndSwap((Option<Value>, bool)) ProcessResult::RawCompareAndSwapRes { previous_value, succeed } => (previous_value, succeed),
+    FlashbackEstimate(FlashbackEstimateResult) ProcessResult::FlashbackEstimate { estimate } => estimate,
+    Flashback(FlashbackResult) ProcessResult::FlashbackRes { result } => result,
 }
 
 pub trait StorageCallbackType: Sized {